@@ -0,0 +1,60 @@
+/* C interface to the bencode crate, built with `--features ffi`. */
+
+#ifndef BENCODE_H
+#define BENCODE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+typedef enum BencodeStatus {
+  BencodeStatus_Ok = 0,
+  BencodeStatus_Truncated = 1,
+  BencodeStatus_InvalidCharacter = 2,
+  BencodeStatus_InvalidLength = 3,
+  BencodeStatus_OutOfOrderKey = 4,
+  BencodeStatus_NonCanonical = 5,
+  BencodeStatus_TooDeep = 6,
+  BencodeStatus_NullPointer = 7,
+  BencodeStatus_WrongKind = 8,
+  BencodeStatus_OutOfRange = 9,
+  BencodeStatus_TooLarge = 10,
+} BencodeStatus;
+
+typedef enum BencodeKind {
+  BencodeKind_Integer = 0,
+  BencodeKind_Bytes = 1,
+  BencodeKind_List = 2,
+  BencodeKind_Dict = 3,
+  BencodeKind_Null = 4,
+} BencodeKind;
+
+typedef struct BencodeValue BencodeValue;
+
+BencodeStatus bencode_decode(const uint8_t *buf, size_t len, BencodeValue **out);
+
+void bencode_free(BencodeValue *value);
+
+BencodeStatus bencode_encode(const BencodeValue *value, uint8_t **out, size_t *out_len);
+
+void bencode_buffer_free(uint8_t *buf, size_t len);
+
+BencodeKind bencode_kind(const BencodeValue *value);
+
+BencodeStatus bencode_as_i64(const BencodeValue *value, int64_t *out);
+
+BencodeStatus bencode_as_bytes(const BencodeValue *value, const uint8_t **out, size_t *out_len);
+
+size_t bencode_len(const BencodeValue *value);
+
+const BencodeValue *bencode_list_get(const BencodeValue *value, size_t index);
+
+const BencodeValue *bencode_dict_get(const BencodeValue *value,
+                                     const uint8_t *key,
+                                     size_t key_len);
+
+const BencodeValue *bencode_dict_entry(const BencodeValue *value,
+                                       size_t index,
+                                       const uint8_t **key,
+                                       size_t *key_len);
+
+#endif /* BENCODE_H */
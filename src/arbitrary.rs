@@ -0,0 +1,145 @@
+//! Structure-aware generation of documents from fuzzer input, enabled by
+//! the `arbitrary` feature.
+//!
+//! `Unstructured` and `Arbitrary` follow the shape of the `arbitrary`
+//! crate's types of the same names, so a fuzz target can turn its raw input
+//! into a `Bencode` with `Bencode::arbitrary(&mut Unstructured::new(data))`.
+//! Generated documents are always valid and canonical: integers have no
+//! leading zeros and dictionaries are sorted by construction. Running out
+//! of input is not an error; every choice then takes its smallest option.
+
+use std::collections::BTreeMap;
+
+use Bencode;
+
+/// Deepest nesting of a generated document, by default.
+pub const MAX_DEPTH: usize = 8;
+/// Most items in a generated list or entries in a generated dictionary, by
+/// default.
+pub const MAX_ITEMS: usize = 16;
+/// Longest generated byte string, by default.
+pub const MAX_BYTES: usize = 64;
+
+/// Size limits for generated documents.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Bounds {
+    pub max_depth: usize,
+    pub max_items: usize,
+    pub max_bytes: usize,
+}
+
+impl Default for Bounds {
+    fn default() -> Bounds {
+        Bounds { max_depth: MAX_DEPTH, max_items: MAX_ITEMS, max_bytes: MAX_BYTES }
+    }
+}
+
+impl Bounds {
+    /// Generates a document within these bounds.
+    pub fn generate(&self, u: &mut Unstructured) -> Bencode {
+        arbitrary_value(u, self, self.max_depth)
+    }
+}
+
+/// Fuzzer input, consumed from the front as choices are made.
+pub struct Unstructured<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Unstructured<'a> {
+    pub fn new(data: &'a [u8]) -> Unstructured<'a> {
+        Unstructured { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The next byte, or 0 once the input is used up.
+    pub fn byte(&mut self) -> u8 {
+        match self.data.split_first() {
+            Some((&byte, rest)) => {
+                self.data = rest;
+                byte
+            },
+            None => 0,
+        }
+    }
+
+    /// A number from 0 to `max` inclusive.
+    pub fn int_in_range(&mut self, max: usize) -> usize {
+        let mut value = 0usize;
+        let mut range = max;
+        while range > 0 && !self.is_empty() {
+            value = (value << 8) | usize::from(self.byte());
+            range >>= 8;
+        }
+        value % (max + 1)
+    }
+
+    /// Up to `len` bytes; fewer if the input runs out.
+    pub fn bytes(&mut self, len: usize) -> &'a [u8] {
+        let (head, rest) = self.data.split_at(::std::cmp::min(len, self.data.len()));
+        self.data = rest;
+        head
+    }
+}
+
+pub trait Arbitrary<'a>: Sized {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Self;
+}
+
+impl<'a> Arbitrary<'a> for Bencode {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Bencode {
+        Bounds::default().generate(u)
+    }
+}
+
+fn arbitrary_bytes(u: &mut Unstructured, bounds: &Bounds) -> Vec<u8> {
+    let len = u.int_in_range(bounds.max_bytes);
+    u.bytes(len).to_vec()
+}
+
+fn arbitrary_value(u: &mut Unstructured, bounds: &Bounds, depth: usize) -> Bencode {
+    // Containers are only offered while there is depth left.
+    let kinds = if depth > 1 { 4 } else { 2 };
+    match u.byte() % kinds {
+        0 => {
+            let mut buf = [0; 8];
+            let bytes = u.bytes(8);
+            buf[..bytes.len()].copy_from_slice(bytes);
+            Bencode::from(i64::from_le_bytes(buf))
+        },
+        1 => Bencode::Bytes(arbitrary_bytes(u, bounds)),
+        2 => {
+            let len = u.int_in_range(bounds.max_items);
+            Bencode::Array((0..len).map(|_| arbitrary_value(u, bounds, depth - 1)).collect())
+        },
+        _ => {
+            let len = u.int_in_range(bounds.max_items);
+            let mut dict = BTreeMap::new();
+            for _ in 0..len {
+                let key = arbitrary_bytes(u, bounds);
+                dict.insert(key, arbitrary_value(u, bounds, depth - 1));
+            }
+            Bencode::Object(dict)
+        },
+    }
+}
+
+#[test]
+fn test_arbitrary() {
+    let data: Vec<u8> = (0..4096u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+    for start in (0..4000).step_by(61) {
+        let value = Bencode::arbitrary(&mut Unstructured::new(&data[start..]));
+        let encoded = ::bencode_to_vec(&value);
+        assert_eq!(::validate_canonical(&encoded), Ok(()));
+        assert_eq!(::bdecode_slice(&encoded), Ok(value));
+    }
+    assert_eq!(Bencode::arbitrary(&mut Unstructured::new(&[])), Bencode::from(0i64));
+
+    let mut u = Unstructured::new(&[3, 1, 2]);
+    assert_eq!(u.int_in_range(2), 0);
+    assert_eq!(u.bytes(5), &[1, 2][..]);
+    assert_eq!((u.byte(), u.is_empty()), (0, true));
+}
@@ -0,0 +1,332 @@
+//! Inspects and converts bencoded documents, and works with `.torrent`
+//! files.
+
+extern crate bencode;
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use bencode::edit::TorrentEditor;
+use bencode::json::{from_json, to_json};
+use bencode::pretty::Pretty;
+use bencode::{ParseError, bdecode_slice, bencode_to_vec, validate, validate_canonical};
+
+const USAGE: &str = "\
+Usage: bencode <command> [ARGS]
+
+Documents:
+  show [FILE]          pretty-print a document
+  validate [FILE]      check that a document is well formed and canonical
+  to-json [FILE]       convert a document to JSON
+  from-json [FILE]     convert JSON to a document
+
+Torrents:
+  infohash FILE...     print the v1 and v2 infohashes of each torrent
+  magnet FILE          print a magnet link for a torrent
+  create PATH [-o OUT] [-t TRACKER]... [-w WEB_SEED]... [-c COMMENT]
+         [-l PIECE_LENGTH] [--private]
+                       build a torrent for a file or directory
+  edit-trackers [-n] EDIT... PATH...
+                       rewrite the trackers of torrents, or of every
+                       .torrent file in a directory; EDIT is one of
+                       --add URL, --remove URL or --replace OLD NEW,
+                       applied in order; -n only reports the changes
+
+FILE defaults to standard input, as does -. Output goes to standard
+output unless -o is given.";
+
+enum Failure {
+    Usage,
+    Error(String),
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Failure {
+        Failure::Error(message)
+    }
+}
+
+type CommandResult = Result<(), Failure>;
+
+fn read_input(path: Option<&str>) -> Result<Vec<u8>, String> {
+    match path {
+        None | Some("-") => {
+            let mut buf = Vec::new();
+            io::stdin().read_to_end(&mut buf).map_err(|err| format!("standard input: {}", err))?;
+            Ok(buf)
+        },
+        Some(path) => fs::read(path).map_err(|err| format!("{}: {}", path, err)),
+    }
+}
+
+fn write_output(buf: &[u8]) -> Result<(), String> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    stdout.write_all(buf).and_then(|()| stdout.flush())
+        .map_err(|err| format!("standard output: {}", err))
+}
+
+fn parse_error(err: ParseError) -> String {
+    format!("invalid document: {:?}", err)
+}
+
+/// The single optional FILE argument of the document commands.
+fn single_input(args: &[String]) -> Result<Vec<u8>, Failure> {
+    match args.len() {
+        0 | 1 => Ok(read_input(args.first().map(|arg| &arg[..]))?),
+        _ => Err(Failure::Usage),
+    }
+}
+
+fn show(args: &[String]) -> CommandResult {
+    let value = bdecode_slice(&single_input(args)?).map_err(parse_error)?;
+    Ok(write_output(format!("{}\n", Pretty(&value)).as_bytes())?)
+}
+
+fn check(args: &[String]) -> CommandResult {
+    let input = single_input(args)?;
+    validate(&input).map_err(parse_error)?;
+    let report = match validate_canonical(&input) {
+        Ok(()) => "ok: canonical\n".to_string(),
+        Err(err) => format!("ok: well formed, not canonical ({:?})\n", err),
+    };
+    Ok(write_output(report.as_bytes())?)
+}
+
+fn json_out(args: &[String]) -> CommandResult {
+    let value = bdecode_slice(&single_input(args)?).map_err(parse_error)?;
+    Ok(write_output(format!("{}\n", to_json(&value)).as_bytes())?)
+}
+
+fn json_in(args: &[String]) -> CommandResult {
+    let text = String::from_utf8(single_input(args)?)
+        .map_err(|_| "input is not UTF-8".to_string())?;
+    let value = from_json(&text).map_err(|err| format!("invalid JSON: {:?}", err))?;
+    Ok(write_output(&bencode_to_vec(&value))?)
+}
+
+#[cfg(any(feature = "sha1", feature = "sha256"))]
+fn load_torrent(path: &str) -> Result<(Vec<u8>, bencode::torrent::Metainfo), String> {
+    let buf = read_input(Some(path))?;
+    let metainfo = bencode::torrent::Metainfo::from_bytes(&buf)
+        .map_err(|err| format!("{}: not a torrent: {:?}", path, err))?;
+    Ok((buf, metainfo))
+}
+
+#[cfg(any(feature = "sha1", feature = "sha256"))]
+fn infohash(args: &[String]) -> CommandResult {
+    if args.is_empty() {
+        return Err(Failure::Usage);
+    }
+    let mut out = String::new();
+    for path in args.iter() {
+        let (buf, metainfo) = load_torrent(path)?;
+        // Hash `info` as it appears in the file, which is what peers see
+        // even if it is not canonical.
+        #[cfg(feature = "sha1")]
+        {
+            if metainfo.info.is_v1() {
+                let hash = bencode::infohash::info_hash_v1(&buf)
+                    .map_err(|err| format!("{}: {:?}", path, err))?;
+                out.push_str(&format!("{}  v1  {}\n", bencode::infohash::InfoHash::V1(hash), path));
+            }
+        }
+        #[cfg(feature = "sha256")]
+        {
+            if metainfo.info.is_v2() {
+                let hash = bencode::infohash::info_hash_v2(&buf)
+                    .map_err(|err| format!("{}: {:?}", path, err))?;
+                out.push_str(&format!("{}  v2  {}\n", bencode::infohash::InfoHash::V2(hash), path));
+            }
+        }
+    }
+    Ok(write_output(out.as_bytes())?)
+}
+
+#[cfg(any(feature = "sha1", feature = "sha256"))]
+fn magnet(args: &[String]) -> CommandResult {
+    let path = match *args {
+        [ref path] => path,
+        _ => return Err(Failure::Usage),
+    };
+    let (_, metainfo) = load_torrent(path)?;
+    let magnet = bencode::magnet::Magnet::from_metainfo(&metainfo)
+        .ok_or_else(|| format!("{}: no infohash can be computed in this build", path))?;
+    Ok(write_output(format!("{}\n", magnet).as_bytes())?)
+}
+
+#[cfg(not(any(feature = "sha1", feature = "sha256")))]
+fn infohash(_: &[String]) -> CommandResult {
+    Err(Failure::Error("built without the sha1 and sha256 features".to_string()))
+}
+
+#[cfg(not(any(feature = "sha1", feature = "sha256")))]
+fn magnet(_: &[String]) -> CommandResult {
+    infohash(&[])
+}
+
+#[cfg(feature = "sha1")]
+fn create(args: &[String]) -> CommandResult {
+    use bencode::builder::TorrentBuilder;
+
+    let mut path = None;
+    let mut output = None;
+    let mut options: Vec<(&str, &str)> = Vec::new();
+    let mut private = false;
+    let mut args = args.iter().map(|arg| &arg[..]);
+    while let Some(arg) = args.next() {
+        match arg {
+            "-o" | "-t" | "-w" | "-c" | "-l" => {
+                let value = args.next().ok_or(Failure::Usage)?;
+                if arg == "-o" {
+                    output = Some(value);
+                } else {
+                    options.push((arg, value));
+                }
+            },
+            "--private" => private = true,
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => return Err(Failure::Usage),
+        }
+    }
+    let mut builder = TorrentBuilder::new(path.ok_or(Failure::Usage)?).private(private);
+    for &(option, value) in options.iter() {
+        builder = match option {
+            "-t" => builder.tracker(value),
+            "-w" => builder.web_seed(value),
+            "-c" => builder.comment(value),
+            _ => builder.piece_length(value.parse()
+                .map_err(|_| format!("invalid piece length: {}", value))?),
+        };
+    }
+    let metainfo = builder.build().map_err(|err| format!("{}: {}", path.unwrap_or(""), err))?;
+    let buf = metainfo.to_bytes();
+    match output {
+        Some(output) => fs::write(output, buf).map_err(|err| format!("{}: {}", output, err))?,
+        None => write_output(&buf)?,
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "sha1"))]
+fn create(_: &[String]) -> CommandResult {
+    Err(Failure::Error("built without the sha1 feature".to_string()))
+}
+
+enum TrackerEdit<'a> {
+    Add(&'a str),
+    Remove(&'a str),
+    Replace(&'a str, &'a str),
+}
+
+/// The `.torrent` files named by `path`: itself, or the ones directly in
+/// it if it is a directory.
+fn torrent_files(path: &Path) -> Result<Vec<PathBuf>, String> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let entries = fs::read_dir(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|err| format!("{}: {}", path.display(), err))?.path();
+        if entry.extension().is_some_and(|ext| ext == "torrent") && entry.is_file() {
+            files.push(entry);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn edit_trackers(args: &[String]) -> CommandResult {
+    let mut edits = Vec::new();
+    let mut paths = Vec::new();
+    let mut dry_run = false;
+    let mut args = args.iter().map(|arg| &arg[..]);
+    while let Some(arg) = args.next() {
+        let edit = match arg {
+            "-n" => {
+                dry_run = true;
+                continue;
+            },
+            "--add" => args.next().map(TrackerEdit::Add),
+            "--remove" => args.next().map(TrackerEdit::Remove),
+            "--replace" => match (args.next(), args.next()) {
+                (Some(old), Some(new)) => Some(TrackerEdit::Replace(old, new)),
+                _ => None,
+            },
+            _ if !arg.starts_with('-') => {
+                paths.push(arg);
+                continue;
+            },
+            _ => None,
+        };
+        edits.push(edit.ok_or(Failure::Usage)?);
+    }
+    if edits.is_empty() || paths.is_empty() {
+        return Err(Failure::Usage);
+    }
+
+    let mut report = String::new();
+    for path in paths.iter() {
+        for file in torrent_files(Path::new(path))? {
+            let buf = fs::read(&file).map_err(|err| format!("{}: {}", file.display(), err))?;
+            let mut editor = TorrentEditor::from_bytes(&buf)
+                .map_err(|err| format!("{}: not a torrent: {:?}", file.display(), err))?;
+            let mut changed = false;
+            for edit in edits.iter() {
+                changed |= match *edit {
+                    TrackerEdit::Add(url) => editor.add_tracker(url),
+                    TrackerEdit::Remove(url) => editor.remove_tracker(url),
+                    TrackerEdit::Replace(old, new) => editor.replace_tracker(old, new),
+                };
+            }
+            if !changed {
+                continue;
+            }
+            if !dry_run {
+                let edited = editor.to_bytes()
+                    .map_err(|err| format!("{}: cannot edit: {:?}", file.display(), err))?;
+                fs::write(&file, edited).map_err(|err| format!("{}: {}", file.display(), err))?;
+            }
+            report.push_str(&format!("{} {}\n", if dry_run { "would update" } else { "updated" },
+                                     file.display()));
+        }
+    }
+    Ok(write_output(report.as_bytes())?)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command: fn(&[String]) -> CommandResult = match args.first().map(|arg| &arg[..]) {
+        Some("show") => show,
+        Some("validate") => check,
+        Some("to-json") => json_out,
+        Some("from-json") => json_in,
+        Some("infohash") => infohash,
+        Some("magnet") => magnet,
+        Some("create") => create,
+        Some("edit-trackers") => edit_trackers,
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            return;
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        },
+    };
+    match command(&args[1..]) {
+        Ok(()) => (),
+        Err(Failure::Usage) => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        },
+        Err(Failure::Error(message)) => {
+            eprintln!("bencode: {}", message);
+            process::exit(1);
+        },
+    }
+}
@@ -0,0 +1,210 @@
+//! A DOM that borrows from the input instead of copying it.
+//!
+//! Byte strings, integers and dictionary keys are slices of the document.
+//! Dictionaries are `DictMap`s: entries in a vector sorted by key and found
+//! by binary search, which saves a key allocation per entry and keeps a
+//! dictionary's entries next to each other in memory.
+
+use std::collections::BTreeMap;
+use std::slice;
+
+use {Bencode, ParseError, is_digit};
+use raw;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Value<'a> {
+    /// The digits of an integer, with any sign.
+    Integer(&'a [u8]),
+    Bytes(&'a [u8]),
+    List(Vec<Value<'a>>),
+    Dict(DictMap<'a>),
+}
+
+impl<'a> Value<'a> {
+    /// Decodes the value at the start of `buf`.
+    pub fn decode(buf: &'a [u8]) -> Result<Value<'a>, ParseError> {
+        decode_value(buf, 0, 1).map(|(value, _)| value)
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::Integer(digits) => ::std::str::from_utf8(digits).ok()?.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match *self {
+            Value::Bytes(buf) => Some(buf),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&'a str> {
+        self.as_bytes().and_then(|buf| ::std::str::from_utf8(buf).ok())
+    }
+
+    pub fn as_list(&self) -> Option<&[Value<'a>]> {
+        match *self {
+            Value::List(ref items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_dict(&self) -> Option<&DictMap<'a>> {
+        match *self {
+            Value::Dict(ref dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    /// Copies the value into the owned DOM.
+    pub fn to_bencode(&self) -> Bencode {
+        match *self {
+            Value::Integer(digits) => Bencode::Integer(digits.to_vec()),
+            Value::Bytes(buf) => Bencode::Bytes(buf.to_vec()),
+            Value::List(ref items) => Bencode::Array(items.iter().map(Value::to_bencode).collect()),
+            Value::Dict(ref dict) => {
+                let map: BTreeMap<Vec<u8>, Bencode> = dict.iter()
+                    .map(|&(key, ref value)| (key.to_vec(), value.to_bencode()))
+                    .collect();
+                Bencode::Object(map)
+            },
+        }
+    }
+}
+
+/// Dictionary entries sorted by key.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct DictMap<'a> {
+    entries: Vec<(&'a [u8], Value<'a>)>,
+}
+
+impl<'a> DictMap<'a> {
+    pub fn new() -> DictMap<'a> {
+        DictMap::default()
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&Value<'a>> {
+        self.entries.binary_search_by(|&(probe, _)| probe.cmp(key))
+            .ok()
+            .map(|index| &self.entries[index].1)
+    }
+
+    /// Inserts or replaces an entry, keeping the keys sorted, and returns
+    /// the value it replaced.
+    pub fn insert(&mut self, key: &'a [u8], value: Value<'a>) -> Option<Value<'a>> {
+        match self.entries.binary_search_by(|&(probe, _)| probe.cmp(key)) {
+            Ok(index) => Some(::std::mem::replace(&mut self.entries[index].1, value)),
+            Err(index) => {
+                self.entries.insert(index, (key, value));
+                None
+            },
+        }
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<Value<'a>> {
+        self.entries.binary_search_by(|&(probe, _)| probe.cmp(key))
+            .ok()
+            .map(|index| self.entries.remove(index).1)
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, (&'a [u8], Value<'a>)> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+fn decode_value(buf: &[u8], pos: usize, depth: usize)
+    -> Result<(Value<'_>, usize), ParseError> {
+    raw::check_depth(depth)?;
+    match buf.get(pos) {
+        Some(&b'i') => {
+            let end = raw::scan_integer(buf, pos)?;
+            Ok((Value::Integer(&buf[pos + 1..end - 1]), end))
+        },
+        Some(&b'l') => {
+            let mut items = Vec::new();
+            let mut pos = pos + 1;
+            loop {
+                match buf.get(pos) {
+                    Some(&b'e') => return Ok((Value::List(items), pos + 1)),
+                    Some(_) => {
+                        let (item, end) = decode_value(buf, pos, depth + 1)?;
+                        items.push(item);
+                        pos = end;
+                    },
+                    None => return Err(ParseError::Truncated),
+                }
+            }
+        },
+        Some(&b'd') => {
+            let mut entries: Vec<(&[u8], Value)> = Vec::new();
+            let mut pos = pos + 1;
+            loop {
+                match buf.get(pos) {
+                    Some(&b'e') => return Ok((Value::Dict(DictMap { entries }), pos + 1)),
+                    Some(_) => (),
+                    None => return Err(ParseError::Truncated),
+                }
+                let key = raw::scan_bytea(buf, pos)?;
+                let (value, end) = decode_value(buf, key.end, depth + 1)?;
+                let key = &buf[key];
+                // Input order is key order, so entries only ever append; a
+                // repeated key keeps its last value, as `bdecode` does.
+                match entries.last_mut() {
+                    Some(last) if last.0 > key => return Err(ParseError::OutOfOrderKey),
+                    Some(last) if last.0 == key => last.1 = value,
+                    _ => entries.push((key, value)),
+                }
+                pos = end;
+            }
+        },
+        Some(&val) if is_digit(val) => {
+            let span = raw::scan_bytea(buf, pos)?;
+            let end = span.end;
+            Ok((Value::Bytes(&buf[span]), end))
+        },
+        Some(_) => Err(ParseError::InvalidCharacter),
+        None => Err(ParseError::Truncated),
+    }
+}
+
+#[test]
+fn test_borrowed_value() {
+    let buf = b"d1:ali1e3:xyze1:bd1:ci-2eee";
+    let value = Value::decode(buf).unwrap();
+    let dict = value.as_dict().unwrap();
+    assert_eq!(dict.len(), 2);
+    let list = dict.get(b"a").and_then(Value::as_list).unwrap();
+    assert_eq!(list[0].as_i64(), Some(1));
+    assert_eq!(list[1].as_str(), Some("xyz"));
+    assert_eq!(dict.get(b"b").and_then(Value::as_dict).and_then(|b| b.get(b"c"))
+                   .and_then(Value::as_i64), Some(-2));
+    assert_eq!(Ok(value.to_bencode()), ::bdecode_slice(buf));
+
+    let mut dict = DictMap::new();
+    assert_eq!(dict.insert(b"z", Value::Bytes(b"1")), None);
+    assert_eq!(dict.insert(b"a", Value::Bytes(b"2")), None);
+    assert_eq!(dict.insert(b"z", Value::Bytes(b"3")), Some(Value::Bytes(b"1")));
+    let keys: Vec<&[u8]> = dict.iter().map(|&(key, _)| key).collect();
+    assert_eq!(keys, vec![&b"a"[..], b"z"]);
+    assert_eq!(dict.remove(b"a"), Some(Value::Bytes(b"2")));
+
+    assert_eq!(Value::decode(b"d1:b0:1:a0:e"), Err(ParseError::OutOfOrderKey));
+}
+
+#[test]
+fn test_borrowed_depth_limit() {
+    let deep = [&[b'l'; ::MAX_DEPTH][..], &[b'e'; ::MAX_DEPTH][..]].concat();
+    assert!(Value::decode(&deep).is_ok());
+    assert_eq!(Value::decode(&[b'l'; 10000]), Err(ParseError::TooDeep));
+    assert_eq!(Value::decode(&b"d1:a".repeat(10000)), Err(ParseError::TooDeep));
+}
@@ -0,0 +1,401 @@
+//! Creating v1 metainfo documents from files on disk.
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use sha1::Sha1;
+use torrent::{AnnounceList, Dict, FileEntry, Info, Metainfo};
+#[cfg(feature = "url")]
+use tracker_url::check_tracker_url;
+use validate::{self, Strictness};
+
+pub const DEFAULT_MIN_PIECE_LENGTH: u64 = 16 * 1024;
+pub const DEFAULT_MAX_PIECE_LENGTH: u64 = 16 * 1024 * 1024;
+pub const DEFAULT_TARGET_PIECE_COUNT: u64 = 2000;
+
+/// Picks the smallest power-of-two piece length within `[min, max]` that
+/// keeps the piece count at or below `target_pieces`. Content too large to
+/// fit gets `max` (rounded up to a power of two), and more pieces.
+pub fn select_piece_length(total_length: u64, min: u64, max: u64, target_pieces: u64) -> u64 {
+    let min = ::std::cmp::max(min, 1).next_power_of_two();
+    let max = ::std::cmp::max(max.next_power_of_two(), min);
+    let target_pieces = ::std::cmp::max(target_pieces, 1);
+
+    let mut piece_length = min;
+    while piece_length < max && total_length.div_ceil(piece_length) > target_pieces {
+        piece_length *= 2;
+    }
+    piece_length
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn file_name(path: &Path) -> io::Result<String> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.to_string())
+        .ok_or_else(|| invalid_data("file name is not valid UTF-8"))
+}
+
+/// Builds a `.torrent` for a file or a directory tree.
+#[derive(Clone, Debug)]
+pub struct TorrentBuilder {
+    path: PathBuf,
+    name: Option<String>,
+    piece_length: Option<u64>,
+    min_piece_length: u64,
+    max_piece_length: u64,
+    target_piece_count: u64,
+    strictness: Strictness,
+    private: bool,
+    source: Option<String>,
+    trackers: Vec<String>,
+    web_seeds: Vec<String>,
+    comment: Option<String>,
+    created_by: Option<String>,
+    creation_date: Option<i64>,
+}
+
+impl TorrentBuilder {
+    pub fn new<P: AsRef<Path>>(path: P) -> TorrentBuilder {
+        TorrentBuilder {
+            path: path.as_ref().to_path_buf(),
+            name: None,
+            piece_length: None,
+            min_piece_length: DEFAULT_MIN_PIECE_LENGTH,
+            max_piece_length: DEFAULT_MAX_PIECE_LENGTH,
+            target_piece_count: DEFAULT_TARGET_PIECE_COUNT,
+            strictness: Strictness::Strict,
+            private: false,
+            source: None,
+            trackers: Vec::new(),
+            web_seeds: Vec::new(),
+            comment: None,
+            created_by: None,
+            creation_date: None,
+        }
+    }
+
+    /// Overrides the torrent name, which defaults to the file or
+    /// directory name.
+    pub fn name<S: Into<String>>(mut self, name: S) -> TorrentBuilder {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Uses a fixed piece length instead of selecting one from the
+    /// content size.
+    pub fn piece_length(mut self, piece_length: u64) -> TorrentBuilder {
+        self.piece_length = Some(piece_length);
+        self
+    }
+
+    /// Bounds for the automatically selected piece length.
+    pub fn piece_length_range(mut self, min: u64, max: u64) -> TorrentBuilder {
+        self.min_piece_length = min;
+        self.max_piece_length = max;
+        self
+    }
+
+    /// The piece count automatic selection aims to stay under.
+    pub fn target_piece_count(mut self, count: u64) -> TorrentBuilder {
+        self.target_piece_count = count;
+        self
+    }
+
+    /// How the final piece length is checked; strict by default.
+    pub fn strictness(mut self, strictness: Strictness) -> TorrentBuilder {
+        self.strictness = strictness;
+        self
+    }
+
+    pub fn private(mut self, private: bool) -> TorrentBuilder {
+        self.private = private;
+        self
+    }
+
+    /// Sets the `source` tag expected by some private trackers.
+    pub fn source<S: Into<String>>(mut self, source: S) -> TorrentBuilder {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Adds a tracker. The first becomes `announce`; with more than one,
+    /// each also gets its own tier in `announce-list`.
+    pub fn tracker<S: Into<String>>(mut self, url: S) -> TorrentBuilder {
+        self.trackers.push(url.into());
+        self
+    }
+
+    /// Adds a BEP 19 web seed to `url-list`.
+    pub fn web_seed<S: Into<String>>(mut self, url: S) -> TorrentBuilder {
+        self.web_seeds.push(url.into());
+        self
+    }
+
+    pub fn comment<S: Into<String>>(mut self, comment: S) -> TorrentBuilder {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    pub fn created_by<S: Into<String>>(mut self, created_by: S) -> TorrentBuilder {
+        self.created_by = Some(created_by.into());
+        self
+    }
+
+    /// Sets `creation date`, in seconds since the Unix epoch.
+    pub fn creation_date(mut self, timestamp: i64) -> TorrentBuilder {
+        self.creation_date = Some(timestamp);
+        self
+    }
+
+    #[cfg(feature = "url")]
+    fn checked_trackers(&self) -> io::Result<Vec<String>> {
+        self.trackers.iter()
+            .map(|url| check_tracker_url(url).map_err(|err| invalid_data(&err.to_string())))
+            .collect()
+    }
+
+    #[cfg(not(feature = "url"))]
+    fn checked_trackers(&self) -> io::Result<Vec<String>> {
+        Ok(self.trackers.clone())
+    }
+
+    /// Walks the content, hashes it and assembles the metainfo.
+    pub fn build(&self) -> io::Result<Metainfo> {
+        let name = match self.name {
+            Some(ref name) => name.clone(),
+            None => file_name(&self.path)?,
+        };
+
+        let is_dir = fs::metadata(&self.path)?.is_dir();
+        let mut files = Vec::new();
+        if is_dir {
+            collect_files(&self.path, &mut Vec::new(), &mut files)?;
+            if files.is_empty() {
+                return Err(invalid_data("directory contains no files"));
+            }
+        } else {
+            files.push((self.path.clone(), Vec::new()));
+        }
+
+        let piece_length = match self.piece_length {
+            Some(piece_length) => piece_length,
+            None => {
+                let mut total_length = 0;
+                for (path, _) in files.iter() {
+                    total_length += fs::metadata(path)?.len();
+                }
+                select_piece_length(total_length, self.min_piece_length,
+                                    self.max_piece_length, self.target_piece_count)
+            },
+        };
+        if let Err(err) = validate::check_piece_length(piece_length, false, self.strictness) {
+            return Err(invalid_data(&format!("unusable piece length {}: {:?}",
+                                             piece_length, err)));
+        }
+
+        let mut hasher = PieceHasher::new(piece_length);
+        let mut entries = Vec::with_capacity(files.len());
+        for (path, components) in files {
+            let length = hasher.hash_file(&path)?;
+            entries.push(FileEntry {
+                length,
+                path: components,
+                attr: None,
+                extra: Dict::new(),
+            });
+        }
+        let (length, files) = if is_dir {
+            (None, Some(entries))
+        } else {
+            (Some(entries[0].length), None)
+        };
+
+        let mut info = Info {
+            name,
+            piece_length,
+            pieces: Some(hasher.finish()),
+            meta_version: None,
+            file_tree: None,
+            length,
+            files,
+            attr: None,
+            extra: Dict::new(),
+        };
+        info.set_private(self.private);
+        info.set_source(self.source.as_ref().map(|s| &s[..]));
+
+        let trackers = self.checked_trackers()?;
+        let announce_list = if trackers.len() > 1 {
+            Some(AnnounceList {
+                tiers: trackers.iter().map(|url| vec![url.clone()]).collect(),
+            })
+        } else {
+            None
+        };
+
+        Ok(Metainfo {
+            announce: trackers.first().cloned(),
+            announce_list,
+            url_list: self.web_seeds.clone(),
+            httpseeds: Vec::new(),
+            nodes: Vec::new(),
+            info,
+            info_bytes: None,
+            comment: self.comment.clone(),
+            created_by: self.created_by.clone(),
+            creation_date: self.creation_date,
+            extra: Dict::new(),
+        })
+    }
+}
+
+/// Collects the regular files below `dir` in a stable (sorted) order,
+/// along with their path components relative to the torrent root.
+fn collect_files(dir: &Path, prefix: &mut Vec<String>, out: &mut Vec<(PathBuf, Vec<String>)>)
+    -> io::Result<()> {
+
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        prefix.push(file_name(&path)?);
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(&path, prefix, out)?;
+        } else if file_type.is_file() {
+            out.push((path, prefix.clone()));
+        }
+        prefix.pop();
+    }
+    Ok(())
+}
+
+/// Hashes a byte stream, spanning file boundaries, into v1 pieces.
+struct PieceHasher {
+    piece_length: u64,
+    hasher: Sha1,
+    filled: u64,
+    pieces: Vec<u8>,
+}
+
+impl PieceHasher {
+    fn new(piece_length: u64) -> PieceHasher {
+        PieceHasher {
+            piece_length,
+            hasher: Sha1::new(),
+            filled: 0,
+            pieces: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let room = (self.piece_length - self.filled) as usize;
+            let take = ::std::cmp::min(room, data.len());
+            self.hasher.update(&data[..take]);
+            self.filled += take as u64;
+            data = &data[take..];
+            if self.filled == self.piece_length {
+                let hasher = ::std::mem::take(&mut self.hasher);
+                self.pieces.extend_from_slice(&hasher.finish());
+                self.filled = 0;
+            }
+        }
+    }
+
+    fn hash_file(&mut self, path: &Path) -> io::Result<u64> {
+        let mut file = File::open(path)?;
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut length = 0;
+        loop {
+            let read = match file.read(&mut buf) {
+                Ok(0) => return Ok(length),
+                Ok(read) => read,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            self.update(&buf[..read]);
+            length += read as u64;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.filled > 0 {
+            self.pieces.extend_from_slice(&self.hasher.finish());
+        }
+        self.pieces
+    }
+}
+
+#[test]
+fn test_select_piece_length() {
+    let (min, max) = (DEFAULT_MIN_PIECE_LENGTH, DEFAULT_MAX_PIECE_LENGTH);
+    assert_eq!(select_piece_length(0, min, max, 2000), min);
+    assert_eq!(select_piece_length(1 << 30, min, max, 2000), 1 << 20);
+    assert_eq!(select_piece_length(1 << 40, min, max, 2000), max);
+    assert_eq!(select_piece_length(1 << 30, 1 << 21, max, 2000), 1 << 21);
+    // Non-power-of-two bounds are rounded up.
+    assert_eq!(select_piece_length(10, 20000, 20000, 1), 32768);
+}
+
+#[test]
+fn test_build_directory() {
+    let root = ::std::env::temp_dir().join(format!("bencode-builder-{}", ::std::process::id()));
+    let content = root.join("content");
+    fs::create_dir_all(content.join("sub")).unwrap();
+    fs::write(content.join("b.txt"), vec![b'b'; 5]).unwrap();
+    fs::write(content.join("sub").join("a.txt"), vec![b'a'; 4]).unwrap();
+
+    let metainfo = TorrentBuilder::new(&content)
+        .piece_length(4)
+        .strictness(Strictness::Lenient)
+        .private(true)
+        .source("TRK")
+        .tracker("http://one.example/announce")
+        .tracker("http://two.example/announce")
+        .web_seed("http://seed.example/")
+        .comment("hi")
+        .creation_date(1234)
+        .build()
+        .unwrap();
+
+    let info = &metainfo.info;
+    assert_eq!(info.name, "content");
+    let files: Vec<(PathBuf, u64)> = info.files().map(|f| (f.path, f.length)).collect();
+    assert_eq!(files, vec![(["content", "b.txt"].iter().collect(), 5),
+                           (["content", "sub", "a.txt"].iter().collect(), 4)]);
+    let mut expected = Vec::new();
+    for chunk in b"bbbbbaaaa".chunks(4) {
+        expected.extend_from_slice(&Sha1::digest(chunk));
+    }
+    assert_eq!(info.pieces, Some(expected));
+    assert!(info.is_private());
+    assert_eq!(info.source(), Some("TRK"));
+    assert_eq!(metainfo.announce, Some("http://one.example/announce".to_string()));
+    assert_eq!(metainfo.trackers().flatten(),
+               vec!["http://one.example/announce", "http://two.example/announce"]);
+    assert_eq!(metainfo.url_list, vec!["http://seed.example/"]);
+
+    // The result is a canonical document that parses back identically.
+    let bytes = metainfo.to_bytes();
+    let decoded = Metainfo::from_bytes(&bytes).unwrap();
+    assert_eq!(decoded.info, metainfo.info);
+    assert_eq!(decoded.info_hash_v1(), metainfo.info_hash_v1());
+    assert_eq!(Metainfo { info_bytes: None, ..decoded }, metainfo);
+
+    let single = TorrentBuilder::new(content.join("b.txt")).build().unwrap();
+    assert_eq!(single.info.length, Some(5));
+    assert_eq!(single.info.name, "b.txt");
+    assert_eq!(single.info.piece_length, DEFAULT_MIN_PIECE_LENGTH);
+    assert!(TorrentBuilder::new(content.join("b.txt")).piece_length(4).build().is_err());
+    if cfg!(feature = "url") {
+        assert!(TorrentBuilder::new(content.join("b.txt")).tracker("ftp://x.example/").build().is_err());
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,169 @@
+//! Rewriting well-formed input into its canonical encoding.
+//!
+//! Hashes and signatures are only reproducible over the canonical bytes,
+//! but producers send unsorted keys, repeated keys and padded numbers.
+//! `canonicalize` accepts all of those and writes the one encoding that
+//! `validate_canonical` accepts, and `canonicalize_report` also says what
+//! it had to change.
+
+use std::mem;
+
+use {ParseError, is_digit};
+use raw;
+
+/// What `canonicalize_report` rewrote.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Report {
+    /// Integers written with leading zeros, or as `-0`.
+    pub integers: usize,
+    /// String lengths written with leading zeros.
+    pub lengths: usize,
+    /// Dictionaries whose keys were out of order.
+    pub unsorted_dicts: usize,
+    /// Entries dropped because a later entry had the same key. As with
+    /// `bdecode`, the last value wins.
+    pub duplicate_keys: usize,
+}
+
+impl Report {
+    /// Whether the input was already canonical.
+    pub fn is_unchanged(&self) -> bool {
+        *self == Report::default()
+    }
+}
+
+/// Returns the canonical encoding of the one value in `buf`.
+pub fn canonicalize(buf: &[u8]) -> Result<Vec<u8>, ParseError> {
+    canonicalize_report(buf).map(|(out, _)| out)
+}
+
+/// Like `canonicalize`, and also reports what was rewritten.
+pub fn canonicalize_report(buf: &[u8]) -> Result<(Vec<u8>, Report), ParseError> {
+    let mut out = Vec::with_capacity(buf.len());
+    let mut report = Report::default();
+    match write_value(buf, 0, 1, &mut out, &mut report)? {
+        end if end == buf.len() => Ok((out, report)),
+        _ => Err(ParseError::InvalidCharacter),
+    }
+}
+
+/// Writes `digits` without leading zeros, and `-0` as `0`.
+fn write_integer(digits: &[u8], out: &mut Vec<u8>, report: &mut Report) {
+    let (negative, magnitude) = match digits.split_first() {
+        Some((&b'-', magnitude)) => (true, magnitude),
+        _ => (false, digits),
+    };
+    let start = magnitude.iter().position(|&digit| digit != b'0').unwrap_or(magnitude.len());
+    let magnitude = &magnitude[start..];
+    out.push(b'i');
+    if magnitude.is_empty() {
+        out.push(b'0');
+    } else {
+        if negative {
+            out.push(b'-');
+        }
+        out.extend_from_slice(magnitude);
+    }
+    out.push(b'e');
+    if !raw::is_canonical_integer(digits) {
+        report.integers += 1;
+    }
+}
+
+fn write_bytea(buf: &[u8], pos: usize, out: &mut Vec<u8>, report: &mut Report)
+    -> Result<usize, ParseError> {
+    let span = raw::scan_bytea(buf, pos)?;
+    if raw::canonical_bytea(buf, pos).is_err() {
+        report.lengths += 1;
+    }
+    out.extend_from_slice(span.len().to_string().as_bytes());
+    out.push(b':');
+    out.extend_from_slice(&buf[span.clone()]);
+    Ok(span.end)
+}
+
+fn write_value(buf: &[u8], pos: usize, depth: usize, out: &mut Vec<u8>, report: &mut Report)
+    -> Result<usize, ParseError> {
+    raw::check_depth(depth)?;
+    match buf.get(pos) {
+        Some(&b'i') => {
+            let end = raw::scan_integer(buf, pos)?;
+            write_integer(&buf[pos + 1..end - 1], out, report);
+            Ok(end)
+        },
+        Some(&b'l') => {
+            out.push(b'l');
+            let mut pos = pos + 1;
+            loop {
+                match buf.get(pos) {
+                    Some(&b'e') => {
+                        out.push(b'e');
+                        return Ok(pos + 1);
+                    },
+                    Some(_) => pos = write_value(buf, pos, depth + 1, out, report)?,
+                    None => return Err(ParseError::Truncated),
+                }
+            }
+        },
+        Some(&b'd') => {
+            // Each entry is encoded on its own, then the entries are
+            // sorted by their raw key bytes and joined.
+            let mut entries: Vec<(&[u8], Vec<u8>)> = Vec::new();
+            let mut pos = pos + 1;
+            loop {
+                match buf.get(pos) {
+                    Some(&b'e') => break,
+                    Some(_) => (),
+                    None => return Err(ParseError::Truncated),
+                }
+                let key = raw::scan_bytea(buf, pos)?;
+                let mut entry = Vec::new();
+                let key_end = write_bytea(buf, pos, &mut entry, report)?;
+                pos = write_value(buf, key_end, depth + 1, &mut entry, report)?;
+                entries.push((&buf[key], entry));
+            }
+            if entries.windows(2).any(|pair| pair[0].0 > pair[1].0) {
+                report.unsorted_dicts += 1;
+            }
+            // Stable, so among equal keys the last one read stays last.
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push(b'd');
+            for index in 0..entries.len() {
+                if entries.get(index + 1).is_some_and(|next| next.0 == entries[index].0) {
+                    report.duplicate_keys += 1;
+                    continue;
+                }
+                out.extend_from_slice(&mem::take(&mut entries[index].1));
+            }
+            out.push(b'e');
+            Ok(pos + 1)
+        },
+        Some(&val) if is_digit(val) => write_bytea(buf, pos, out, report),
+        Some(_) => Err(ParseError::InvalidCharacter),
+        None => Err(ParseError::Truncated),
+    }
+}
+
+#[test]
+fn test_canonicalize() {
+    let canonical = b"d1:ali1ei-2e0:e1:bd1:xi0eee";
+    assert_eq!(canonicalize_report(canonical), Ok((canonical.to_vec(), Report::default())));
+
+    let messy = b"d1:bd1:xi-0ee1:ai9e1:ali01ei-002e00:ee";
+    let (out, report) = canonicalize_report(messy).unwrap();
+    assert_eq!(out, &canonical[..]);
+    assert_eq!(report, Report { integers: 3, lengths: 1, unsorted_dicts: 1, duplicate_keys: 1 });
+    assert!(!report.is_unchanged());
+    assert_eq!(::validate_canonical(&out), Ok(()));
+
+    assert_eq!(canonicalize(b"i1ei2e"), Err(ParseError::InvalidCharacter));
+    assert_eq!(canonicalize(b"d1:a"), Err(ParseError::Truncated));
+}
+
+#[test]
+fn test_canonicalize_depth_limit() {
+    let deep = [&[b'l'; ::MAX_DEPTH][..], &[b'e'; ::MAX_DEPTH][..]].concat();
+    assert_eq!(canonicalize(&deep), Ok(deep.clone()));
+    assert_eq!(canonicalize(&[b'l'; 10000]), Err(ParseError::TooDeep));
+    assert_eq!(canonicalize(&b"d1:a".repeat(10000)), Err(ParseError::TooDeep));
+}
@@ -0,0 +1,255 @@
+//! A decoder that keeps the buffers of values it is handed back, for hot
+//! loops that decode one message after another.
+//!
+//! Byte strings, keys and lists are built in buffers taken from the pools
+//! when any are available. Passing finished values to `recycle` refills
+//! the pools, so a loop that decodes messages of a similar shape soon stops
+//! allocating for them. Dictionary tree nodes are still allocated by
+//! `BTreeMap`.
+//!
+//! With `set_presize`, each document is first scanned to count the items
+//! of every list, so lists are allocated once at their final size rather
+//! than grown by doubling. This pays off for large flat lists.
+
+use std::collections::BTreeMap;
+
+use {Bencode, ParseError, is_digit};
+use raw;
+
+/// Upper bound on the buffers kept in each pool.
+const MAX_POOLED: usize = 4096;
+
+/// Buffers that have grown past this many bytes are freed, not pooled, so
+/// one huge message does not pin its memory for the life of the decoder.
+const MAX_POOLED_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Default)]
+pub struct Decoder {
+    bytes: Vec<Vec<u8>>,
+    lists: Vec<Vec<Bencode>>,
+    presize: bool,
+    /// List lengths from the pre-pass, consumed in order from `next_list`.
+    list_lengths: Vec<usize>,
+    next_list: usize,
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    /// Whether to scan each document for list lengths before decoding it.
+    pub fn set_presize(&mut self, presize: bool) {
+        self.presize = presize;
+    }
+
+    /// Decodes the value at the start of `buf`, as `bdecode_slice` does.
+    pub fn decode(&mut self, buf: &[u8]) -> Result<Bencode, ParseError> {
+        #[cfg(any(feature = "metrics", feature = "trace"))]
+        let start = ::std::time::Instant::now();
+        let result = self.decode_document(buf);
+        #[cfg(feature = "metrics")]
+        ::metrics::record(start, &result);
+        #[cfg(feature = "trace")]
+        ::trace::decoded("Decoder::decode", Some(buf), start, &result);
+        result
+    }
+
+    fn decode_document(&mut self, buf: &[u8]) -> Result<Bencode, ParseError> {
+        self.list_lengths.clear();
+        self.next_list = 0;
+        if self.presize {
+            raw::list_lengths(buf, 0, 1, &mut self.list_lengths)?;
+        }
+        self.decode_value(buf, 0, 1).map(|(value, _)| value)
+    }
+
+    /// Decodes into `out`, whose previous contents are recycled first, so
+    /// a caller holding one value and one decoder reaches a steady state
+    /// with no allocation beyond dictionary nodes. On error `out` is left
+    /// as an empty byte string.
+    pub fn decode_into(&mut self, buf: &[u8], out: &mut Bencode) -> Result<(), ParseError> {
+        let previous = out.take();
+        self.recycle(previous);
+        *out = self.decode(buf)?;
+        Ok(())
+    }
+
+    /// Returns the buffers of `value` to the pools.
+    pub fn recycle(&mut self, value: Bencode) {
+        match value {
+            Bencode::Integer(buf) | Bencode::Bytes(buf) => self.recycle_bytes(buf),
+            Bencode::Array(mut items) => {
+                for item in items.drain(..) {
+                    self.recycle(item);
+                }
+                if self.lists.len() < MAX_POOLED && items.capacity() > 0
+                    && items.capacity() * ::std::mem::size_of::<Bencode>() <= MAX_POOLED_BYTES {
+                    self.lists.push(items);
+                }
+            },
+            Bencode::Object(dict) => {
+                for (key, value) in dict {
+                    self.recycle_bytes(key);
+                    self.recycle(value);
+                }
+            },
+        }
+    }
+
+    fn recycle_bytes(&mut self, mut buf: Vec<u8>) {
+        if self.bytes.len() < MAX_POOLED && buf.capacity() > 0
+            && buf.capacity() <= MAX_POOLED_BYTES {
+            buf.clear();
+            self.bytes.push(buf);
+        }
+    }
+
+    fn bytes_from(&mut self, src: &[u8]) -> Vec<u8> {
+        let mut buf = self.bytes.pop().unwrap_or_default();
+        buf.extend_from_slice(src);
+        buf
+    }
+
+    fn decode_value(&mut self, buf: &[u8], pos: usize, depth: usize)
+        -> Result<(Bencode, usize), ParseError> {
+        raw::check_depth(depth)?;
+        match buf.get(pos) {
+            Some(&b'i') => {
+                let end = raw::scan_integer(buf, pos)?;
+                Ok((Bencode::Integer(self.bytes_from(&buf[pos + 1..end - 1])), end))
+            },
+            Some(&b'l') => {
+                let mut items = self.lists.pop().unwrap_or_default();
+                if let Some(&length) = self.list_lengths.get(self.next_list) {
+                    items.reserve_exact(length);
+                    self.next_list += 1;
+                }
+                let mut pos = pos + 1;
+                loop {
+                    match buf.get(pos) {
+                        Some(&b'e') => return Ok((Bencode::Array(items), pos + 1)),
+                        Some(_) => match self.decode_value(buf, pos, depth + 1) {
+                            Ok((item, end)) => {
+                                items.push(item);
+                                pos = end;
+                            },
+                            Err(err) => {
+                                self.recycle(Bencode::Array(items));
+                                return Err(err);
+                            },
+                        },
+                        None => {
+                            self.recycle(Bencode::Array(items));
+                            return Err(ParseError::Truncated);
+                        },
+                    }
+                }
+            },
+            Some(&b'd') => {
+                let mut dict = BTreeMap::new();
+                match self.decode_entries(buf, pos + 1, depth, &mut dict) {
+                    Ok(end) => Ok((Bencode::Object(dict), end)),
+                    Err(err) => {
+                        self.recycle(Bencode::Object(dict));
+                        Err(err)
+                    },
+                }
+            },
+            Some(&val) if is_digit(val) => {
+                let span = raw::scan_bytea(buf, pos)?;
+                let end = span.end;
+                Ok((Bencode::Bytes(self.bytes_from(&buf[span])), end))
+            },
+            Some(_) => Err(ParseError::InvalidCharacter),
+            None => Err(ParseError::Truncated),
+        }
+    }
+
+    fn decode_entries(&mut self, buf: &[u8], mut pos: usize, depth: usize,
+                      dict: &mut BTreeMap<Vec<u8>, Bencode>) -> Result<usize, ParseError> {
+        let mut prev_key: &[u8] = &[];
+        loop {
+            match buf.get(pos) {
+                Some(&b'e') => return Ok(pos + 1),
+                Some(_) => (),
+                None => return Err(ParseError::Truncated),
+            }
+            let key = raw::scan_bytea(buf, pos)?;
+            if buf[key.clone()] < *prev_key {
+                return Err(ParseError::OutOfOrderKey);
+            }
+            prev_key = &buf[key.clone()];
+            let (value, end) = self.decode_value(buf, key.end, depth + 1)?;
+            let key = self.bytes_from(prev_key);
+            dict.insert(key, value);
+            pos = end;
+        }
+    }
+}
+
+#[test]
+fn test_decoder_reuses_buffers() {
+    let document = b"d1:ali1ei2ee1:b5:helloe";
+    let mut decoder = Decoder::new();
+    let first = decoder.decode(document).unwrap();
+    assert_eq!(Ok(first.clone()), ::bdecode_slice(document));
+
+    decoder.recycle(first);
+    assert_eq!((decoder.bytes.len(), decoder.lists.len()), (5, 1));
+    let second = decoder.decode(document).unwrap();
+    assert_eq!(Ok(second), ::bdecode_slice(document));
+    assert_eq!((decoder.bytes.len(), decoder.lists.len()), (0, 0));
+
+    assert_eq!(decoder.decode(b"l3:abci1e"), Err(ParseError::Truncated));
+    assert_eq!((decoder.bytes.len(), decoder.lists.len()), (2, 1));
+}
+
+#[test]
+fn test_decode_into() {
+    let mut decoder = Decoder::new();
+    let mut value = Bencode::Bytes(Vec::new());
+    decoder.decode_into(b"l4:spami7ee", &mut value).unwrap();
+    let pointer = match value {
+        Bencode::Array(ref items) => items.as_ptr(),
+        _ => panic!("expected a list"),
+    };
+    decoder.decode_into(b"l4:eggsi8ee", &mut value).unwrap();
+    assert_eq!(value, Bencode::Array(vec![Bencode::from("eggs"), Bencode::from(8i64)]));
+    match value {
+        Bencode::Array(ref items) => assert_eq!(items.as_ptr(), pointer),
+        _ => panic!("expected a list"),
+    }
+
+    assert_eq!(decoder.decode_into(b"x", &mut value), Err(ParseError::InvalidCharacter));
+    assert_eq!(value, Bencode::Bytes(Vec::new()));
+}
+
+#[test]
+fn test_presize() {
+    let document = b"ll1:a1:b1:cei1eld1:xli1ei2eeee0:e";
+    let mut counts = Vec::new();
+    assert_eq!(raw::list_lengths(document, 0, 1, &mut counts), Ok(document.len()));
+    assert_eq!(counts, vec![4, 3, 1, 2]);
+
+    let mut decoder = Decoder::new();
+    decoder.set_presize(true);
+    let value = decoder.decode(document).unwrap();
+    assert_eq!(Ok(value.clone()), ::bdecode_slice(document));
+    match value {
+        Bencode::Array(ref items) => assert_eq!(items.capacity(), 4),
+        _ => panic!("expected a list"),
+    }
+    assert_eq!(decoder.decode(b"l1:ae1:"), Ok(Bencode::Array(vec![Bencode::from("a")])));
+    assert_eq!(decoder.decode(b"ll1:ae"), Err(ParseError::Truncated));
+}
+
+#[test]
+fn test_decoder_depth_limit() {
+    let mut decoder = Decoder::new();
+    let deep = [&[b'l'; ::MAX_DEPTH][..], &[b'e'; ::MAX_DEPTH][..]].concat();
+    assert_eq!(Ok(decoder.decode(&deep).unwrap()), ::bdecode_slice(&deep));
+    assert_eq!(decoder.decode(&[b'l'; 10000]), Err(ParseError::TooDeep));
+    assert_eq!(decoder.decode(&b"d1:a".repeat(10000)), Err(ParseError::TooDeep));
+    assert_eq!(::bdecode_slice(&[b'l'; 10000]), Err(ParseError::TooDeep));
+}
@@ -0,0 +1,70 @@
+//! Detached signatures over the canonical encoding of a value.
+//!
+//! The signature travels apart from the value, as BEP 44 items and most
+//! private extensions send it, so the value itself is left unchanged. The
+//! payload is always the canonical encoding, so two peers that decode and
+//! re-encode the same value, however it was first written, sign and check
+//! the same bytes. Keys are supplied through `signature::SigningKey` and
+//! `signature::VerifyingKey`.
+
+use Bencode;
+use digest::hash_canonical;
+use signature::{SigningKey, VerifyingKey};
+
+/// The bytes a detached signature over `value` covers.
+pub fn payload(value: &Bencode) -> Vec<u8> {
+    hash_canonical::<Vec<u8>>(value)
+}
+
+/// Follows `path` through nested dictionaries, as `lexer::lookup` does for
+/// encoded documents.
+pub fn subtree<'a>(value: &'a Bencode, path: &[&[u8]]) -> Option<&'a Bencode> {
+    path.iter().try_fold(value, |value, &key| match *value {
+        Bencode::Object(ref dict) => dict.get(key),
+        _ => None,
+    })
+}
+
+pub fn sign<K: SigningKey + ?Sized>(value: &Bencode, key: &K) -> Vec<u8> {
+    key.sign(&payload(value))
+}
+
+pub fn verify<K: VerifyingKey + ?Sized>(value: &Bencode, signature: &[u8], key: &K) -> bool {
+    key.verify(&payload(value), signature)
+}
+
+/// Signs the value at `path` within `value`, or returns `None` if there
+/// is nothing there.
+pub fn sign_at<K: SigningKey + ?Sized>(value: &Bencode, path: &[&[u8]], key: &K)
+    -> Option<Vec<u8>> {
+    subtree(value, path).map(|subtree| sign(subtree, key))
+}
+
+/// Checks a signature over the value at `path`; `false` if there is
+/// nothing there.
+pub fn verify_at<K: VerifyingKey + ?Sized>(value: &Bencode, path: &[&[u8]], signature: &[u8],
+                                           key: &K) -> bool {
+    subtree(value, path).is_some_and(|subtree| verify(subtree, signature, key))
+}
+
+#[test]
+fn test_detached_signatures() {
+    use signature::XorKey;
+
+    let value = ::bdecode_slice(b"d1:ad1:bi1e1:c3:xyze1:di2ee").unwrap();
+    let signature = sign(&value, &XorKey(5));
+    assert!(verify(&value, &signature, &XorKey(5)));
+    assert!(!verify(&value, &signature, &XorKey(6)));
+
+    // Padded integers decode to the same value, so they check the same.
+    let padded = ::bdecode_slice(b"d1:ad1:bi01e1:c3:xyze1:di002ee").unwrap();
+    assert_eq!(payload(&padded), ::bencode_to_vec(&value));
+    assert!(verify(&padded, &signature, &XorKey(5)));
+
+    let inner = sign_at(&value, &[b"a"], &XorKey(5)).unwrap();
+    assert_eq!(inner, sign(&::bdecode_slice(b"d1:bi1e1:c3:xyze").unwrap(), &XorKey(5)));
+    assert!(verify_at(&value, &[b"a"], &inner, &XorKey(5)));
+    assert!(!verify_at(&value, &[b"a", b"b", b"x"], &inner, &XorKey(5)));
+    assert_eq!(sign_at(&value, &[b"z"], &XorKey(5)), None);
+    assert_eq!(subtree(&value, &[b"a", b"c"]), Some(&Bencode::from("xyz")));
+}
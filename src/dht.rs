@@ -0,0 +1,839 @@
+//! KRPC messages of the BitTorrent DHT (BEP 5).
+
+use std::fmt;
+use std::net::SocketAddr;
+
+use {Bencode, ParseError, bencode};
+use fields::{put, take_bytes, take_dict, take_u64};
+use infohash::InfoHash;
+use random;
+use raw;
+use torrent::Dict;
+use tracker;
+
+pub const NODE_ID_LEN: usize = 20;
+
+/// Largest KRPC datagram `to_datagram` produces: the UDP payload that fits
+/// a 1500-byte Ethernet frame over IPv6 without fragmentation.
+pub const MAX_DATAGRAM_LEN: usize = 1452;
+
+/// Length of generated transaction ids.
+pub const TRANSACTION_ID_LEN: usize = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KrpcError {
+    Parse(ParseError),
+    /// A required key is absent.
+    Missing(&'static str),
+    /// A key holds a value of the wrong type or an unusable value.
+    Invalid(&'static str),
+}
+
+impl From<ParseError> for KrpcError {
+    fn from(err: ParseError) -> KrpcError {
+        KrpcError::Parse(err)
+    }
+}
+
+/// A key that held a value of the wrong type.
+impl From<&'static str> for KrpcError {
+    fn from(key: &'static str) -> KrpcError {
+        KrpcError::Invalid(key)
+    }
+}
+
+pub type KrpcResult<T> = Result<T, KrpcError>;
+
+fn require<T>(value: Option<T>, key: &'static str) -> KrpcResult<T> {
+    value.ok_or(KrpcError::Missing(key))
+}
+
+/// A 160-bit DHT node id.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub [u8; NODE_ID_LEN]);
+
+impl NodeId {
+    pub fn from_slice(bytes: &[u8]) -> Option<NodeId> {
+        if bytes.len() != NODE_ID_LEN {
+            return None;
+        }
+        let mut id = [0; NODE_ID_LEN];
+        id.copy_from_slice(bytes);
+        Some(NodeId(id))
+    }
+
+    fn take(dict: &mut Dict, key: &'static str) -> KrpcResult<NodeId> {
+        let id = require(take_bytes(dict, key)?, key)?;
+        NodeId::from_slice(&id).ok_or(KrpcError::Invalid(key))
+    }
+}
+
+impl fmt::Debug for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NodeId(")?;
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Length of one IPv4 compact node info entry: node id, address, port.
+pub const COMPACT_NODE_LEN: usize = NODE_ID_LEN + tracker::COMPACT_V4_LEN;
+/// Length of one IPv6 (BEP 32 `nodes6`) compact node info entry.
+pub const COMPACT_NODE6_LEN: usize = NODE_ID_LEN + tracker::COMPACT_V6_LEN;
+
+fn decode_nodes<A, F>(buf: &[u8], entry_len: usize, key: &'static str, decode_addr: F)
+    -> KrpcResult<Vec<(NodeId, SocketAddr)>>
+    where
+        F: Fn(&[u8]) -> tracker::TrackerResult<Vec<A>>,
+        A: Into<SocketAddr> {
+
+    if !buf.len().is_multiple_of(entry_len) {
+        return Err(KrpcError::Invalid(key));
+    }
+    let mut nodes = Vec::with_capacity(buf.len() / entry_len);
+    for entry in buf.chunks(entry_len) {
+        let id = NodeId::from_slice(&entry[..NODE_ID_LEN]).expect("length checked");
+        let addr = decode_addr(&entry[NODE_ID_LEN..]).map_err(|_| KrpcError::Invalid(key))?;
+        nodes.extend(addr.into_iter().map(|addr| (id, addr.into())));
+    }
+    Ok(nodes)
+}
+
+/// Decodes the 26-byte entries of a `nodes` string.
+pub fn decode_compact_nodes(buf: &[u8]) -> KrpcResult<Vec<(NodeId, SocketAddr)>> {
+    decode_nodes(buf, COMPACT_NODE_LEN, "nodes", tracker::decode_compact_peers)
+}
+
+/// Decodes the 38-byte entries of a `nodes6` string.
+pub fn decode_compact_nodes6(buf: &[u8]) -> KrpcResult<Vec<(NodeId, SocketAddr)>> {
+    decode_nodes(buf, COMPACT_NODE6_LEN, "nodes6", tracker::decode_compact_peers6)
+}
+
+/// Encodes the IPv4 nodes of `nodes` as a `nodes` string.
+pub fn encode_compact_nodes(nodes: &[(NodeId, SocketAddr)]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for &(ref id, addr) in nodes.iter() {
+        if let SocketAddr::V4(addr) = addr {
+            buf.extend_from_slice(&id.0);
+            buf.extend(tracker::encode_compact_peers(&[addr]));
+        }
+    }
+    buf
+}
+
+/// Encodes the IPv6 nodes of `nodes` as a `nodes6` string.
+pub fn encode_compact_nodes6(nodes: &[(NodeId, SocketAddr)]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for &(ref id, addr) in nodes.iter() {
+        if let SocketAddr::V6(addr) = addr {
+            buf.extend_from_slice(&id.0);
+            buf.extend(tracker::encode_compact_peers6(&[addr]));
+        }
+    }
+    buf
+}
+
+fn take_info_hash(dict: &mut Dict) -> KrpcResult<InfoHash> {
+    let hash = require(take_bytes(dict, "info_hash")?, "info_hash")?;
+    match InfoHash::from_slice(&hash) {
+        Some(hash) if hash.is_v1() => Ok(hash),
+        _ => Err(KrpcError::Invalid("info_hash")),
+    }
+}
+
+/// The method-specific arguments of a query.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum QueryKind {
+    Ping,
+    FindNode { target: NodeId },
+    /// `info_hash` is always 20 bytes; v2 torrents use their truncated
+    /// hash.
+    GetPeers { info_hash: InfoHash },
+    AnnouncePeer {
+        info_hash: InfoHash,
+        port: u16,
+        token: Vec<u8>,
+        /// Use the UDP source port instead of `port` (for peers behind
+        /// NAT).
+        implied_port: bool,
+    },
+    /// Any other method; its arguments are left in `Query::extra`.
+    Other(Vec<u8>),
+}
+
+/// A query (`y` = `q`).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Query {
+    /// The querying node's id, present in every query.
+    pub id: NodeId,
+    pub kind: QueryKind,
+    /// Arguments not covered by `kind`.
+    pub extra: Dict,
+}
+
+impl Query {
+    pub fn method(&self) -> &[u8] {
+        match self.kind {
+            QueryKind::Ping => b"ping",
+            QueryKind::FindNode { .. } => b"find_node",
+            QueryKind::GetPeers { .. } => b"get_peers",
+            QueryKind::AnnouncePeer { .. } => b"announce_peer",
+            QueryKind::Other(ref method) => method,
+        }
+    }
+
+    fn from_bencode(method: Vec<u8>, mut args: Dict) -> KrpcResult<Query> {
+        let id = NodeId::take(&mut args, "id")?;
+        let kind = match &method[..] {
+            b"ping" => QueryKind::Ping,
+            b"find_node" => QueryKind::FindNode { target: NodeId::take(&mut args, "target")? },
+            b"get_peers" => QueryKind::GetPeers { info_hash: take_info_hash(&mut args)? },
+            b"announce_peer" => {
+                let info_hash = take_info_hash(&mut args)?;
+                let port = require(take_u64(&mut args, "port")?, "port")?;
+                if port > u64::from(u16::MAX) {
+                    return Err(KrpcError::Invalid("port"));
+                }
+                QueryKind::AnnouncePeer {
+                    info_hash,
+                    port: port as u16,
+                    token: require(take_bytes(&mut args, "token")?, "token")?,
+                    implied_port: take_u64(&mut args, "implied_port")?.unwrap_or(0) != 0,
+                }
+            },
+            _ => QueryKind::Other(method),
+        };
+        Ok(Query { id, kind, extra: args })
+    }
+
+    fn args(&self) -> Dict {
+        let mut args = self.extra.clone();
+        put(&mut args, "id", Some(&self.id.0[..]));
+        match self.kind {
+            QueryKind::Ping | QueryKind::Other(_) => (),
+            QueryKind::FindNode { ref target } => put(&mut args, "target", Some(&target.0[..])),
+            QueryKind::GetPeers { ref info_hash } =>
+                put(&mut args, "info_hash", Some(info_hash.as_bytes())),
+            QueryKind::AnnouncePeer { ref info_hash, port, ref token, implied_port } => {
+                put(&mut args, "info_hash", Some(info_hash.as_bytes()));
+                put(&mut args, "port", Some(u64::from(port)));
+                put(&mut args, "token", Some(&token[..]));
+                if implied_port {
+                    put(&mut args, "implied_port", Some(1u64));
+                }
+            },
+        }
+        args
+    }
+}
+
+/// A response (`y` = `r`). Responses do not name their method, so this
+/// covers the fields of all standard responses.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Response {
+    pub id: NodeId,
+    /// The closest known nodes, from both `nodes` and `nodes6`.
+    pub nodes: Vec<(NodeId, SocketAddr)>,
+    /// Peers from `get_peers`, from 6-byte (IPv4) or 18-byte (IPv6)
+    /// compact strings.
+    pub values: Vec<SocketAddr>,
+    /// Token to present in a later `announce_peer`.
+    pub token: Option<Vec<u8>>,
+    pub extra: Dict,
+}
+
+impl Response {
+    pub fn new(id: NodeId) -> Response {
+        Response { id, nodes: Vec::new(), values: Vec::new(), token: None, extra: Dict::new() }
+    }
+
+    fn from_bencode(mut dict: Dict) -> KrpcResult<Response> {
+        let id = NodeId::take(&mut dict, "id")?;
+        let mut values = Vec::new();
+        match dict.remove(&b"values"[..]) {
+            None => (),
+            Some(Bencode::Array(items)) => for item in items {
+                let invalid = KrpcError::Invalid("values");
+                let buf = match item {
+                    Bencode::Bytes(buf) => buf,
+                    _ => return Err(invalid),
+                };
+                match buf.len() {
+                    tracker::COMPACT_V4_LEN => values.extend(
+                        tracker::decode_compact_peers(&buf).map_err(|_| invalid)?
+                            .into_iter().map(SocketAddr::V4)),
+                    tracker::COMPACT_V6_LEN => values.extend(
+                        tracker::decode_compact_peers6(&buf).map_err(|_| invalid)?
+                            .into_iter().map(SocketAddr::V6)),
+                    _ => return Err(invalid),
+                }
+            },
+            Some(_) => return Err(KrpcError::Invalid("values")),
+        }
+        let mut nodes = match take_bytes(&mut dict, "nodes")? {
+            Some(buf) => decode_compact_nodes(&buf)?,
+            None => Vec::new(),
+        };
+        if let Some(buf) = take_bytes(&mut dict, "nodes6")? {
+            nodes.extend(decode_compact_nodes6(&buf)?);
+        }
+        Ok(Response {
+            id,
+            nodes,
+            values,
+            token: take_bytes(&mut dict, "token")?,
+            extra: dict,
+        })
+    }
+
+    fn to_bencode(&self) -> Bencode {
+        let mut dict = self.extra.clone();
+        put(&mut dict, "id", Some(&self.id.0[..]));
+        let (nodes, nodes6) = (encode_compact_nodes(&self.nodes), encode_compact_nodes6(&self.nodes));
+        if !nodes.is_empty() {
+            put(&mut dict, "nodes", Some(nodes));
+        }
+        if !nodes6.is_empty() {
+            put(&mut dict, "nodes6", Some(nodes6));
+        }
+        put(&mut dict, "token", self.token.as_ref().map(|token| &token[..]));
+        if !self.values.is_empty() {
+            let values = self.values.iter().map(|addr| Bencode::Bytes(match *addr {
+                SocketAddr::V4(addr) => tracker::encode_compact_peers(&[addr]),
+                SocketAddr::V6(addr) => tracker::encode_compact_peers6(&[addr]),
+            })).collect();
+            put(&mut dict, "values", Some(Bencode::Array(values)));
+        }
+        Bencode::Object(dict)
+    }
+}
+
+/// KRPC error codes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorCode {
+    Generic,
+    Server,
+    /// A malformed packet, invalid arguments or a bad token.
+    Protocol,
+    MethodUnknown,
+    Other(u64),
+}
+
+impl ErrorCode {
+    pub fn from_code(code: u64) -> ErrorCode {
+        match code {
+            201 => ErrorCode::Generic,
+            202 => ErrorCode::Server,
+            203 => ErrorCode::Protocol,
+            204 => ErrorCode::MethodUnknown,
+            code => ErrorCode::Other(code),
+        }
+    }
+
+    pub fn code(&self) -> u64 {
+        match *self {
+            ErrorCode::Generic => 201,
+            ErrorCode::Server => 202,
+            ErrorCode::Protocol => 203,
+            ErrorCode::MethodUnknown => 204,
+            ErrorCode::Other(code) => code,
+        }
+    }
+
+    /// The message BEP 5 gives for the code.
+    pub fn message(&self) -> &'static str {
+        match *self {
+            ErrorCode::Generic => "Generic Error",
+            ErrorCode::Server => "Server Error",
+            ErrorCode::Protocol => "Protocol Error",
+            ErrorCode::MethodUnknown => "Method Unknown",
+            ErrorCode::Other(_) => "Error",
+        }
+    }
+}
+
+/// An error message (`y` = `e`): a code and a human-readable message.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ErrorMessage {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ErrorMessage {
+    /// An error with the standard message for `code`.
+    pub fn new(code: ErrorCode) -> ErrorMessage {
+        ErrorMessage { code, message: code.message().to_string() }
+    }
+
+    /// Decodes the `[code, message]` list of the `e` key.
+    pub fn from_bencode(value: &Bencode) -> KrpcResult<ErrorMessage> {
+        let invalid = KrpcError::Invalid("e");
+        let items = match *value {
+            Bencode::Array(ref items) => items,
+            _ => return Err(invalid),
+        };
+        match (items.first(), items.get(1)) {
+            (Some(code), Some(Bencode::Bytes(message))) => Ok(ErrorMessage {
+                code: ErrorCode::from_code(code.as_u64().ok_or(invalid)?),
+                message: String::from_utf8_lossy(message).into_owned(),
+            }),
+            _ => Err(invalid),
+        }
+    }
+
+    pub fn to_bencode(&self) -> Bencode {
+        Bencode::Array(vec![
+            Bencode::from(self.code.code()),
+            Bencode::from(&self.message[..]),
+        ])
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Body {
+    Query(Query),
+    Response(Response),
+    Error(ErrorMessage),
+}
+
+/// One KRPC datagram.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KrpcMessage {
+    /// Echoed back in the reply to match it to its query.
+    pub transaction_id: Vec<u8>,
+    /// The sender's `v` client version string, if any.
+    pub version: Option<Vec<u8>>,
+    pub body: Body,
+    pub extra: Dict,
+}
+
+impl KrpcMessage {
+    pub fn from_bencode(value: Bencode) -> KrpcResult<KrpcMessage> {
+        let mut dict = match value {
+            Bencode::Object(dict) => dict,
+            _ => return Err(KrpcError::Invalid("message")),
+        };
+        let transaction_id = require(take_bytes(&mut dict, "t")?, "t")?;
+        let version = take_bytes(&mut dict, "v")?;
+        let body = match &require(take_bytes(&mut dict, "y")?, "y")?[..] {
+            b"q" => {
+                let method = require(take_bytes(&mut dict, "q")?, "q")?;
+                let args = require(take_dict(&mut dict, "a")?, "a")?;
+                Body::Query(Query::from_bencode(method, args)?)
+            },
+            b"r" => Body::Response(Response::from_bencode(require(take_dict(&mut dict, "r")?, "r")?)?),
+            b"e" => {
+                let error = require(dict.remove(&b"e"[..]), "e")?;
+                Body::Error(ErrorMessage::from_bencode(&error)?)
+            },
+            _ => return Err(KrpcError::Invalid("y")),
+        };
+        Ok(KrpcMessage { transaction_id, version, body, extra: dict })
+    }
+
+    /// Decodes a datagram, which must hold exactly one message.
+    pub fn from_bytes(buf: &[u8]) -> KrpcResult<KrpcMessage> {
+        KrpcMessage::from_bencode(raw::decode_exact(buf)?)
+    }
+
+    pub fn to_bencode(&self) -> Bencode {
+        let mut dict = self.extra.clone();
+        put(&mut dict, "t", Some(&self.transaction_id[..]));
+        put(&mut dict, "v", self.version.as_ref().map(|v| &v[..]));
+        match self.body {
+            Body::Query(ref query) => {
+                put(&mut dict, "y", Some("q"));
+                put(&mut dict, "q", Some(query.method()));
+                put(&mut dict, "a", Some(Bencode::Object(query.args())));
+            },
+            Body::Response(ref response) => {
+                put(&mut dict, "y", Some("r"));
+                put(&mut dict, "r", Some(response.to_bencode()));
+            },
+            Body::Error(ref error) => {
+                put(&mut dict, "y", Some("e"));
+                put(&mut dict, "e", Some(error.to_bencode()));
+            },
+        }
+        Bencode::Object(dict)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(MAX_DATAGRAM_LEN);
+        bencode(&self.to_bencode(), &mut buf).expect("writing to a Vec cannot fail");
+        buf
+    }
+
+    /// Encodes the message for sending, failing if it would not fit in
+    /// `MAX_DATAGRAM_LEN` bytes.
+    pub fn to_datagram(&self) -> KrpcResult<Vec<u8>> {
+        let buf = self.to_bytes();
+        if buf.len() > MAX_DATAGRAM_LEN {
+            return Err(KrpcError::Invalid("message"));
+        }
+        Ok(buf)
+    }
+
+    /// A query with a fresh random transaction id.
+    pub fn query(id: NodeId, kind: QueryKind) -> KrpcMessage {
+        KrpcMessage {
+            transaction_id: transaction_id(),
+            version: None,
+            body: Body::Query(Query { id, kind, extra: Dict::new() }),
+            extra: Dict::new(),
+        }
+    }
+
+    /// The reply to `query`, echoing its transaction id.
+    pub fn reply(query: &KrpcMessage, body: Body) -> KrpcMessage {
+        KrpcMessage {
+            transaction_id: query.transaction_id.clone(),
+            version: None,
+            body,
+            extra: Dict::new(),
+        }
+    }
+}
+
+/// Generates a random transaction id.
+pub fn transaction_id() -> Vec<u8> {
+    let mut id = vec![0; TRANSACTION_ID_LEN];
+    random::fill(&mut id);
+    id
+}
+
+pub fn ping(id: NodeId) -> KrpcMessage {
+    KrpcMessage::query(id, QueryKind::Ping)
+}
+
+pub fn find_node(id: NodeId, target: NodeId) -> KrpcMessage {
+    KrpcMessage::query(id, QueryKind::FindNode { target })
+}
+
+/// Asks for peers of `info_hash`; a v2 hash is sent truncated to 20 bytes.
+pub fn get_peers(id: NodeId, info_hash: InfoHash) -> KrpcMessage {
+    KrpcMessage::query(id, QueryKind::GetPeers { info_hash: dht_info_hash(info_hash) })
+}
+
+/// Announces that we are a peer for `info_hash`, with the token from an
+/// earlier `get_peers` response. With `implied_port`, the receiver uses
+/// the UDP source port and `port` is only informational.
+pub fn announce_peer(id: NodeId, info_hash: InfoHash, port: u16, token: Vec<u8>,
+                     implied_port: bool) -> KrpcMessage {
+    KrpcMessage::query(id, QueryKind::AnnouncePeer {
+        info_hash: dht_info_hash(info_hash),
+        port,
+        token,
+        implied_port,
+    })
+}
+
+fn dht_info_hash(info_hash: InfoHash) -> InfoHash {
+    match info_hash {
+        InfoHash::V1(_) => info_hash,
+        InfoHash::V2(_) => InfoHash::V1(info_hash.truncated()),
+    }
+}
+
+/// Largest bencoded `v` a BEP 44 item may carry.
+pub const MAX_ITEM_VALUE_LEN: usize = 1000;
+/// Largest `salt` of a mutable item.
+pub const MAX_SALT_LEN: usize = 64;
+pub const PUBLIC_KEY_LEN: usize = 32;
+pub const SIGNATURE_LEN: usize = 64;
+
+fn encode_value(value: &Bencode) -> Vec<u8> {
+    let mut buf = Vec::new();
+    bencode(value, &mut buf).expect("writing to a Vec cannot fail");
+    buf
+}
+
+/// The exact bytes a BEP 44 mutable item signature covers: the bencoded
+/// `salt` (only if non-empty), `seq` and `v` entries, concatenated without
+/// the surrounding dictionary.
+pub fn signing_payload(salt: &[u8], seq: i64, value: &Bencode) -> Vec<u8> {
+    let mut buf = Vec::new();
+    if !salt.is_empty() {
+        buf.extend_from_slice(b"4:salt");
+        buf.extend(encode_value(&Bencode::from(salt)));
+    }
+    buf.extend_from_slice(format!("3:seqi{}e1:v", seq).as_bytes());
+    buf.extend(encode_value(value));
+    buf
+}
+
+/// Takes a required byte string of exactly `out.len()` bytes.
+fn take_fixed(dict: &mut Dict, key: &'static str, out: &mut [u8]) -> KrpcResult<()> {
+    let buf = require(take_bytes(dict, key)?, key)?;
+    if buf.len() != out.len() {
+        return Err(KrpcError::Invalid(key));
+    }
+    out.copy_from_slice(&buf);
+    Ok(())
+}
+
+fn take_value(dict: &mut Dict) -> KrpcResult<Bencode> {
+    let value = require(dict.remove(&b"v"[..]), "v")?;
+    if encode_value(&value).len() > MAX_ITEM_VALUE_LEN {
+        return Err(KrpcError::Invalid("v"));
+    }
+    Ok(value)
+}
+
+/// A BEP 44 immutable item, addressed by the SHA-1 of its bencoded value.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ImmutableItem {
+    pub value: Bencode,
+}
+
+impl ImmutableItem {
+    /// Takes `v` from the arguments of a `put` or the body of a `get`
+    /// response.
+    pub fn from_dict(dict: &mut Dict) -> KrpcResult<ImmutableItem> {
+        Ok(ImmutableItem { value: take_value(dict)? })
+    }
+
+    pub fn write_to(&self, dict: &mut Dict) {
+        put(dict, "v", Some(self.value.clone()));
+    }
+
+    #[cfg(feature = "sha1")]
+    pub fn target(&self) -> NodeId {
+        NodeId(::sha1::Sha1::digest(&encode_value(&self.value)))
+    }
+}
+
+/// A BEP 44 mutable item, signed by the owner of `public_key`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MutableItem {
+    pub public_key: [u8; PUBLIC_KEY_LEN],
+    pub salt: Vec<u8>,
+    pub seq: i64,
+    pub value: Bencode,
+    pub signature: [u8; SIGNATURE_LEN],
+}
+
+impl MutableItem {
+    /// Takes `k`, `salt`, `seq`, `sig` and `v` from the arguments of a
+    /// `put` or the body of a `get` response. The signature is not checked.
+    pub fn from_dict(dict: &mut Dict) -> KrpcResult<MutableItem> {
+        let mut public_key = [0; PUBLIC_KEY_LEN];
+        take_fixed(dict, "k", &mut public_key)?;
+        let mut signature = [0; SIGNATURE_LEN];
+        take_fixed(dict, "sig", &mut signature)?;
+        let salt = take_bytes(dict, "salt")?.unwrap_or_default();
+        if salt.len() > MAX_SALT_LEN {
+            return Err(KrpcError::Invalid("salt"));
+        }
+        let seq = match dict.remove(&b"seq"[..]) {
+            Some(seq) => seq.as_i64().ok_or(KrpcError::Invalid("seq"))?,
+            None => return Err(KrpcError::Missing("seq")),
+        };
+        Ok(MutableItem { public_key, salt, seq, value: take_value(dict)?, signature })
+    }
+
+    pub fn write_to(&self, dict: &mut Dict) {
+        put(dict, "k", Some(&self.public_key[..]));
+        if !self.salt.is_empty() {
+            put(dict, "salt", Some(&self.salt[..]));
+        }
+        put(dict, "seq", Some(self.seq));
+        put(dict, "sig", Some(&self.signature[..]));
+        put(dict, "v", Some(self.value.clone()));
+    }
+
+    /// The bytes `signature` must be a signature of.
+    pub fn signing_payload(&self) -> Vec<u8> {
+        signing_payload(&self.salt, self.seq, &self.value)
+    }
+
+    /// The DHT key: SHA-1 of the public key followed by the salt.
+    #[cfg(feature = "sha1")]
+    pub fn target(&self) -> NodeId {
+        let mut hasher = ::sha1::Sha1::new();
+        hasher.update(&self.public_key);
+        hasher.update(&self.salt);
+        NodeId(hasher.finish())
+    }
+}
+
+/// Checks a mutable item's signature against its public key.
+#[cfg(feature = "ed25519")]
+pub fn verify_mutable_item(item: &MutableItem) -> bool {
+    ::ed25519::verify(&item.public_key, &item.signing_payload(), &item.signature)
+}
+
+/// Builds a mutable item for `keypair`, signing the canonical payload.
+#[cfg(feature = "ed25519")]
+pub fn sign_mutable_item(keypair: &::ed25519::Keypair, salt: &[u8], seq: i64, value: Bencode)
+    -> MutableItem {
+
+    let signature = keypair.sign(&signing_payload(salt, seq, &value));
+    MutableItem {
+        public_key: keypair.public_key(),
+        salt: salt.to_vec(),
+        seq,
+        value,
+        signature,
+    }
+}
+
+#[cfg(test)]
+const GET_PEERS: &[u8] = b"d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz123456e\
+    1:q9:get_peers1:t2:aa1:y1:qe";
+
+#[test]
+fn test_queries() {
+    let message = KrpcMessage::from_bytes(GET_PEERS).unwrap();
+    assert_eq!(message.transaction_id, b"aa");
+    let query = match message.body {
+        Body::Query(ref query) => query,
+        _ => panic!("expected a query"),
+    };
+    assert_eq!(query.id, NodeId(*b"abcdefghij0123456789"));
+    assert_eq!(query.kind, QueryKind::GetPeers { info_hash: InfoHash::V1(*b"mnopqrstuvwxyz123456") });
+    assert_eq!(message.to_bytes(), GET_PEERS);
+
+    let announce = b"d1:ad2:id20:abcdefghij012345678912:implied_porti1e\
+        9:info_hash20:mnopqrstuvwxyz1234564:porti6881e5:token8:aoeusnthe\
+        1:q13:announce_peer1:t2:aa1:y1:qe";
+    let message = KrpcMessage::from_bytes(announce).unwrap();
+    match message.body {
+        Body::Query(Query { kind: QueryKind::AnnouncePeer { port, implied_port, .. }, .. }) =>
+            assert_eq!((port, implied_port), (6881, true)),
+        _ => panic!("expected announce_peer"),
+    }
+    assert_eq!(message.to_bytes(), &announce[..]);
+
+    assert_eq!(KrpcMessage::from_bytes(b"d1:ad2:id3:abce1:q4:ping1:t1:x1:y1:qe"),
+               Err(KrpcError::Invalid("id")));
+}
+
+#[test]
+fn test_responses_and_errors() {
+    let response = b"d1:rd2:id20:0123456789abcdefghij5:token8:aoeusnth\
+        6:valuesl6:axje.u6:idhtnmee1:t2:aa1:v4:UT011:y1:re";
+    let message = KrpcMessage::from_bytes(response).unwrap();
+    assert_eq!(message.version, Some(b"UT01".to_vec()));
+    match message.body {
+        Body::Response(ref r) => {
+            assert_eq!(r.token, Some(b"aoeusnth".to_vec()));
+            assert_eq!(r.values, vec!["97.120.106.101:11893".parse::<SocketAddr>().unwrap(),
+                                      "105.100.104.116:28269".parse().unwrap()]);
+        },
+        _ => panic!("expected a response"),
+    }
+    assert_eq!(message.to_bytes(), &response[..]);
+
+    let error = b"d1:eli201e23:A Generic Error Ocurrede1:t2:aa1:y1:ee";
+    let message = KrpcMessage::from_bytes(error).unwrap();
+    assert_eq!(message.body, Body::Error(ErrorMessage {
+        code: ErrorCode::Generic,
+        message: "A Generic Error Ocurred".to_string(),
+    }));
+    assert_eq!(message.to_bytes(), &error[..]);
+    let trailing = [&error[..], b"XX"].concat();
+    assert_eq!(KrpcMessage::from_bytes(&trailing), Err(KrpcError::Parse(ParseError::InvalidCharacter)));
+    let deep = [&b"d1:a"[..], &[b'l'; 8000][..]].concat();
+    assert_eq!(KrpcMessage::from_bytes(&deep), Err(KrpcError::Parse(ParseError::TooDeep)));
+
+    let unknown = ErrorMessage::new(ErrorCode::MethodUnknown);
+    assert_eq!(unknown.to_bencode(),
+               Bencode::Array(vec![Bencode::from(204u64), Bencode::from("Method Unknown")]));
+    assert_eq!(ErrorMessage::from_bencode(&unknown.to_bencode()), Ok(unknown));
+    let custom = Bencode::Array(vec![Bencode::from(299u64), Bencode::from("x")]);
+    assert_eq!(ErrorMessage::from_bencode(&custom).map(|e| e.code), Ok(ErrorCode::Other(299)));
+}
+
+#[test]
+fn test_constructors() {
+    let id = NodeId([1; 20]);
+    let query = get_peers(id, InfoHash::V2([7; 32]));
+    assert_eq!(query.transaction_id.len(), TRANSACTION_ID_LEN);
+    let datagram = query.to_datagram().unwrap();
+    let decoded = KrpcMessage::from_bytes(&datagram).unwrap();
+    assert_eq!(decoded, query);
+    match decoded.body {
+        Body::Query(Query { kind: QueryKind::GetPeers { info_hash }, .. }) =>
+            assert_eq!(info_hash, InfoHash::V1([7; 20])),
+        _ => panic!("expected get_peers"),
+    }
+
+    let mut response = Response::new(NodeId([2; 20]));
+    response.token = Some(b"tok".to_vec());
+    let reply = KrpcMessage::reply(&query, Body::Response(response));
+    assert_eq!(reply.transaction_id, query.transaction_id);
+
+    let mut huge = Response::new(id);
+    huge.nodes = vec![(id, "1.2.3.4:5".parse().unwrap()); MAX_DATAGRAM_LEN / COMPACT_NODE_LEN];
+    assert_eq!(KrpcMessage::reply(&query, Body::Response(huge)).to_datagram(),
+               Err(KrpcError::Invalid("message")));
+}
+
+#[test]
+fn test_compact_nodes() {
+    use std::net::{SocketAddrV4, SocketAddrV6};
+
+    let nodes = vec![
+        (NodeId([1; 20]), SocketAddr::V4(SocketAddrV4::new([10, 0, 0, 1].into(), 6881))),
+        (NodeId([2; 20]), SocketAddr::V6(SocketAddrV6::new([0, 0, 0, 0, 0, 0, 0, 1].into(), 80, 0, 0))),
+    ];
+    let v4 = encode_compact_nodes(&nodes);
+    let v6 = encode_compact_nodes6(&nodes);
+    assert_eq!((v4.len(), v6.len()), (COMPACT_NODE_LEN, COMPACT_NODE6_LEN));
+    assert_eq!(decode_compact_nodes(&v4), Ok(nodes[..1].to_vec()));
+    assert_eq!(decode_compact_nodes6(&v6), Ok(nodes[1..].to_vec()));
+    assert_eq!(decode_compact_nodes(&v4[1..]), Err(KrpcError::Invalid("nodes")));
+
+    let mut response = Response::new(NodeId([3; 20]));
+    response.nodes = nodes.clone();
+    let message = KrpcMessage {
+        transaction_id: b"t".to_vec(),
+        version: None,
+        body: Body::Response(response),
+        extra: Dict::new(),
+    };
+    assert_eq!(KrpcMessage::from_bytes(&message.to_bytes()), Ok(message));
+}
+
+#[test]
+fn test_signing_payload() {
+    // The example from BEP 44.
+    let value = Bencode::from("Hello World!");
+    assert_eq!(signing_payload(b"", 1, &value), b"3:seqi1e1:v12:Hello World!".to_vec());
+    assert_eq!(signing_payload(b"foobar", 1, &value),
+               b"4:salt6:foobar3:seqi1e1:v12:Hello World!".to_vec());
+
+    let item = MutableItem {
+        public_key: [7; PUBLIC_KEY_LEN],
+        salt: b"foobar".to_vec(),
+        seq: 4,
+        value,
+        signature: [9; SIGNATURE_LEN],
+    };
+    let mut dict = Dict::new();
+    item.write_to(&mut dict);
+    assert_eq!(MutableItem::from_dict(&mut dict.clone()), Ok(item.clone()));
+
+    dict.insert(b"v".to_vec(), Bencode::Bytes(vec![0; MAX_ITEM_VALUE_LEN]));
+    assert_eq!(MutableItem::from_dict(&mut dict), Err(KrpcError::Invalid("v")));
+}
+
+#[cfg(feature = "ed25519")]
+#[test]
+fn test_sign_mutable_item() {
+    use ed25519::Keypair;
+
+    let keypair = Keypair::from_seed(&[3; 32]);
+    let mut item = sign_mutable_item(&keypair, b"foobar", 4, Bencode::from("Hello World!"));
+    assert!(verify_mutable_item(&item));
+
+    let mut dict = Dict::new();
+    item.write_to(&mut dict);
+    assert_eq!(MutableItem::from_dict(&mut dict).unwrap(), item);
+
+    item.seq = 5;
+    assert!(!verify_mutable_item(&item));
+}
@@ -0,0 +1,285 @@
+//! Hashing encoded values as they are parsed.
+//!
+//! `bdecode_hashing` decodes a top-level dictionary and, in the same pass,
+//! feeds the exact input bytes of one of its values into a `Digest`. A
+//! torrent read from a file or socket can then be loaded and have its
+//! infohash computed without a second scan or a re-encode.
+//!
+//! `hash_canonical` goes the other way, hashing the canonical encoding of
+//! a value that is already in memory, again without building the bytes.
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+
+use {Bencode, ParseError, bdecode_bytea, bdecode_value};
+#[cfg(test)]
+use bdecode;
+#[cfg(feature = "sha1")]
+use sha1::{self, Sha1};
+#[cfg(feature = "sha256")]
+use sha256::{self, Sha256};
+
+/// A hash function fed incrementally.
+pub trait Digest {
+    type Output;
+
+    fn update(&mut self, data: &[u8]);
+    fn finish(self) -> Self::Output;
+}
+
+#[cfg(feature = "sha1")]
+impl Digest for Sha1 {
+    type Output = [u8; sha1::DIGEST_LEN];
+
+    fn update(&mut self, data: &[u8]) {
+        Sha1::update(self, data)
+    }
+
+    fn finish(self) -> [u8; sha1::DIGEST_LEN] {
+        Sha1::finish(self)
+    }
+}
+
+#[cfg(feature = "sha256")]
+impl Digest for Sha256 {
+    type Output = [u8; sha256::DIGEST_LEN];
+
+    fn update(&mut self, data: &[u8]) {
+        Sha256::update(self, data)
+    }
+
+    fn finish(self) -> [u8; sha256::DIGEST_LEN] {
+        Sha256::finish(self)
+    }
+}
+
+/// Collects the bytes themselves, which is mostly useful in tests.
+impl Digest for Vec<u8> {
+    type Output = Vec<u8>;
+
+    fn update(&mut self, data: &[u8]) {
+        self.extend_from_slice(data)
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self
+    }
+}
+
+const TAP_BUFFER: usize = 4096;
+
+/// Passes bytes through, copying them to the digest while `active` is set.
+/// Copies are batched, since digests are slow to feed a byte at a time.
+struct Tap<'a, I, D: 'a> {
+    inner: I,
+    active: &'a Cell<bool>,
+    pending: &'a RefCell<Vec<u8>>,
+    digest: &'a RefCell<&'a mut D>,
+}
+
+impl<'a, I: Iterator<Item=u8>, D: Digest> Iterator for Tap<'a, I, D> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.inner.next()?;
+        if self.active.get() {
+            let mut pending = self.pending.borrow_mut();
+            pending.push(byte);
+            if pending.len() == TAP_BUFFER {
+                self.digest.borrow_mut().update(&pending);
+                pending.clear();
+            }
+        }
+        Some(byte)
+    }
+}
+
+/// Decodes the dictionary at the start of `stream`, feeding the encoded
+/// bytes of the value under `key` to `digest` as they are read. Returns the
+/// document and whether `key` was present.
+pub fn bdecode_hashing<I, D>(stream: I, key: &[u8], digest: &mut D)
+    -> Result<(Bencode, bool), ParseError>
+    where
+        I: Iterator<Item=u8>,
+        D: Digest {
+
+    let active = Cell::new(false);
+    let pending = RefCell::new(Vec::new());
+    let digest = RefCell::new(digest);
+    let mut stream = Tap { inner: stream, active: &active, pending: &pending, digest: &digest }
+        .peekable();
+    let (dict, found) = decode_dict(&mut stream, key, &active)?;
+    digest.borrow_mut().update(&pending.borrow());
+    Ok((Bencode::Object(dict), found))
+}
+
+/// Mirrors `bdecode_dict`, switching the tap on around the value of `key`.
+/// Every value ends on a byte that has been consumed rather than peeked,
+/// so the tap sees its bytes exactly.
+fn decode_dict<I>(stream: &mut Peekable<I>, tapped: &[u8], active: &Cell<bool>)
+    -> Result<(BTreeMap<Vec<u8>, Bencode>, bool), ParseError>
+    where
+        I: Iterator<Item=u8> {
+
+    match stream.next() {
+        Some(b'd') => (),
+        Some(_) => return Err(ParseError::InvalidCharacter),
+        None => return Err(ParseError::Truncated),
+    }
+    let mut output = BTreeMap::new();
+    let mut prev_key = Vec::new();
+    let mut found = false;
+    loop {
+        match stream.peek() {
+            Some(&b'e') => {
+                stream.next();
+                return Ok((output, found));
+            },
+            Some(_) => (),
+            None => return Err(ParseError::Truncated),
+        }
+        let key = bdecode_bytea(stream)?;
+        if key < prev_key {
+            return Err(ParseError::OutOfOrderKey);
+        }
+        let hashed = key == tapped && !found;
+        active.set(hashed);
+        let value = bdecode_value(stream, 2);
+        active.set(false);
+        found |= hashed;
+        prev_key.clone_from(&key);
+        output.insert(key, value?);
+    }
+}
+
+/// Feeds the canonical encoding of `value` to a new `D` and returns the
+/// hash. Dictionaries are already in key order; integers are fed without
+/// leading zeros, and `-0` as `0`, as `canonical::canonicalize` writes them.
+pub fn hash_canonical<D>(value: &Bencode) -> D::Output
+    where
+        D: Digest + Default {
+
+    let mut digest = D::default();
+    update_canonical(&mut digest, value);
+    digest.finish()
+}
+
+fn update_length<D: Digest>(digest: &mut D, mut len: usize) {
+    let mut buf = [0; 21];
+    let mut start = buf.len() - 1;
+    buf[start] = b':';
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (len % 10) as u8;
+        len /= 10;
+        if len == 0 {
+            break;
+        }
+    }
+    digest.update(&buf[start..]);
+}
+
+fn update_canonical<D: Digest>(digest: &mut D, value: &Bencode) {
+    match *value {
+        Bencode::Integer(ref digits) => {
+            let (sign, magnitude): (&[u8], &[u8]) = match digits.split_first() {
+                Some((&b'-', magnitude)) => (b"-", magnitude),
+                _ => (b"", digits),
+            };
+            let start = magnitude.iter().position(|&digit| digit != b'0');
+            digest.update(b"i");
+            match start {
+                Some(start) => {
+                    digest.update(sign);
+                    digest.update(&magnitude[start..]);
+                },
+                None => digest.update(b"0"),
+            }
+            digest.update(b"e");
+        },
+        Bencode::Bytes(ref buf) => {
+            update_length(digest, buf.len());
+            digest.update(buf);
+        },
+        Bencode::Array(ref items) => {
+            digest.update(b"l");
+            for item in items.iter() {
+                update_canonical(digest, item);
+            }
+            digest.update(b"e");
+        },
+        Bencode::Object(ref dict) => {
+            digest.update(b"d");
+            for (key, item) in dict.iter() {
+                update_length(digest, key.len());
+                digest.update(key);
+                update_canonical(digest, item);
+            }
+            digest.update(b"e");
+        },
+    }
+}
+
+/// Decodes a metainfo document and its v1 infohash in one pass. The hash is
+/// `None` if there is no `info` key.
+#[cfg(feature = "sha1")]
+pub fn bdecode_with_info_hash_v1<I>(stream: I)
+    -> Result<(Bencode, Option<[u8; sha1::DIGEST_LEN]>), ParseError>
+    where
+        I: Iterator<Item=u8> {
+
+    let mut hasher = Sha1::new();
+    let (document, found) = bdecode_hashing(stream, b"info", &mut hasher)?;
+    Ok((document, if found { Some(hasher.finish()) } else { None }))
+}
+
+#[test]
+fn test_bdecode_hashing() {
+    let document = b"d8:announce1:a4:infod6:lengthi05e4:name1:xe1:zi1ee";
+    let mut tapped = Vec::new();
+    let (value, found) = bdecode_hashing(document.iter().cloned(), b"info", &mut tapped).unwrap();
+    assert!(found);
+    assert_eq!(&tapped[..], &b"d6:lengthi05e4:name1:xe"[..]);
+    assert_eq!(Ok(value), bdecode(&mut document.iter().cloned().peekable()));
+
+    let mut tapped = Vec::new();
+    let big: Vec<u8> = b"d1:a10000:".iter().cloned()
+        .chain(::std::iter::repeat_n(b'x', 10000)).chain(b"e".iter().cloned()).collect();
+    assert_eq!(bdecode_hashing(big.into_iter(), b"a", &mut tapped).map(|(_, found)| found), Ok(true));
+    assert_eq!(tapped.len(), 10006);
+
+    let mut tapped = Vec::new();
+    let (_, found) = bdecode_hashing(b"d1:ai1ee".iter().cloned(), b"info", &mut tapped).unwrap();
+    assert!(!found && tapped.is_empty());
+    assert_eq!(bdecode_hashing(b"li1ee".iter().cloned(), b"info", &mut tapped),
+               Err(ParseError::InvalidCharacter));
+    let deep = b"d4:info".iter().cloned().chain(::std::iter::repeat_n(b'l', 100_000));
+    assert_eq!(bdecode_hashing(deep, b"info", &mut tapped).map(|(_, found)| found),
+               Err(ParseError::TooDeep));
+}
+
+#[test]
+fn test_hash_canonical() {
+    let buf = b"d1:ali1ei-23e0:e1:b10:0123456789e";
+    let value = ::bdecode_slice(buf).unwrap();
+    assert_eq!(hash_canonical::<Vec<u8>>(&value), &buf[..]);
+    let padded = ::bdecode_slice(b"li007ei-0ei-010ei0ee").unwrap();
+    assert_eq!(hash_canonical::<Vec<u8>>(&padded), &b"li7ei0ei-10ei0ee"[..]);
+
+    #[cfg(feature = "sha1")]
+    assert_eq!(hash_canonical::<Sha1>(&value), {
+        let mut hasher = Sha1::new();
+        hasher.update(buf);
+        hasher.finish()
+    });
+}
+
+#[cfg(feature = "sha1")]
+#[test]
+fn test_info_hash_while_parsing() {
+    let document = b"d4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:ee";
+    let (value, hash) = bdecode_with_info_hash_v1(document.iter().cloned()).unwrap();
+    assert_eq!(hash, Some(::infohash::info_hash_v1(document).unwrap()));
+    assert!(::torrent::Metainfo::from_bencode(value).is_ok());
+}
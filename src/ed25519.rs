@@ -0,0 +1,437 @@
+//! A small, dependency-free Ed25519 (RFC 8032) implementation, enough to
+//! sign and verify BEP 44 mutable items.
+//!
+//! This favours brevity over speed and is not hardened against timing side
+//! channels; do not use it to sign with keys an attacker can time.
+
+use std::fmt;
+
+use sha512::Sha512;
+
+pub const SEED_LEN: usize = 32;
+pub const PUBLIC_KEY_LEN: usize = 32;
+pub const SIGNATURE_LEN: usize = 64;
+
+const MASK: u64 = (1 << 51) - 1;
+
+/// Little-endian limbs of the group order L = 2^252 + 27742317777372353535851937790883648493.
+const L: [u64; 4] = [0x5812631a5cf5d3ed, 0x14def9dea2f79cd6, 0, 0x1000000000000000];
+
+/// An element of GF(2^255 - 19) in five 51-bit limbs.
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+const ZERO: Fe = Fe([0, 0, 0, 0, 0]);
+const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+/// A 255-bit exponent of the form `low, 0xff.., high` in little-endian order.
+fn exponent(low: u8, high: u8) -> [u8; 32] {
+    let mut exp = [0xff; 32];
+    exp[0] = low;
+    exp[31] = high;
+    exp
+}
+
+fn carry(mut h: [u64; 5]) -> Fe {
+    for i in 0..4 {
+        h[i + 1] += h[i] >> 51;
+        h[i] &= MASK;
+    }
+    h[0] += 19 * (h[4] >> 51);
+    h[4] &= MASK;
+    Fe(h)
+}
+
+impl Fe {
+    /// Reads a little-endian encoding, ignoring the top bit.
+    fn from_bytes(bytes: &[u8; 32]) -> Fe {
+        let load = |i: usize| {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&bytes[i..i + 8]);
+            u64::from_le_bytes(word)
+        };
+        Fe([
+            load(0) & MASK,
+            (load(6) >> 3) & MASK,
+            (load(12) >> 6) & MASK,
+            (load(19) >> 1) & MASK,
+            (load(24) >> 12) & MASK,
+        ])
+    }
+
+    /// The canonical little-endian encoding.
+    fn to_bytes(self) -> [u8; 32] {
+        let mut h = carry(carry(self.0).0).0;
+        let mut q = (h[0] + 19) >> 51;
+        for limb in h.iter().skip(1) {
+            q = (limb + q) >> 51;
+        }
+        h[0] += 19 * q;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK;
+        }
+        h[4] &= MASK;
+
+        let mut out = [0u8; 32];
+        let mut acc: u128 = 0;
+        let mut bits = 0;
+        let mut index = 0;
+        for &limb in h.iter() {
+            acc |= u128::from(limb) << bits;
+            bits += 51;
+            while bits >= 8 {
+                out[index] = acc as u8;
+                acc >>= 8;
+                bits -= 8;
+                index += 1;
+            }
+        }
+        out[index] = acc as u8;
+        out
+    }
+
+    fn add(self, other: Fe) -> Fe {
+        let mut h = self.0;
+        for (limb, other) in h.iter_mut().zip(other.0.iter()) {
+            *limb += other;
+        }
+        carry(h)
+    }
+
+    fn sub(self, other: Fe) -> Fe {
+        // Adding 16p keeps every limb positive.
+        let mut h = [
+            self.0[0] + 0x7ffffffffffed0,
+            self.0[1] + 0x7ffffffffffff0,
+            self.0[2] + 0x7ffffffffffff0,
+            self.0[3] + 0x7ffffffffffff0,
+            self.0[4] + 0x7ffffffffffff0,
+        ];
+        for (limb, other) in h.iter_mut().zip(other.0.iter()) {
+            *limb -= other;
+        }
+        carry(h)
+    }
+
+    fn neg(self) -> Fe {
+        ZERO.sub(self)
+    }
+
+    fn mul(self, other: Fe) -> Fe {
+        let a = self.0;
+        let b = other.0;
+        let m = |x: u64, y: u64| u128::from(x) * u128::from(y);
+        let b1 = b[1] * 19;
+        let b2 = b[2] * 19;
+        let b3 = b[3] * 19;
+        let b4 = b[4] * 19;
+
+        let mut r = [
+            m(a[0], b[0]) + m(a[1], b4) + m(a[2], b3) + m(a[3], b2) + m(a[4], b1),
+            m(a[0], b[1]) + m(a[1], b[0]) + m(a[2], b4) + m(a[3], b3) + m(a[4], b2),
+            m(a[0], b[2]) + m(a[1], b[1]) + m(a[2], b[0]) + m(a[3], b4) + m(a[4], b3),
+            m(a[0], b[3]) + m(a[1], b[2]) + m(a[2], b[1]) + m(a[3], b[0]) + m(a[4], b4),
+            m(a[0], b[4]) + m(a[1], b[3]) + m(a[2], b[2]) + m(a[3], b[1]) + m(a[4], b[0]),
+        ];
+        for i in 0..4 {
+            r[i + 1] += r[i] >> 51;
+            r[i] &= u128::from(MASK);
+        }
+        r[0] += 19 * (r[4] >> 51);
+        r[4] &= u128::from(MASK);
+
+        let mut h = [r[0] as u64, r[1] as u64, r[2] as u64, r[3] as u64, r[4] as u64];
+        h[1] += h[0] >> 51;
+        h[0] &= MASK;
+        Fe(h)
+    }
+
+    fn square(self) -> Fe {
+        self.mul(self)
+    }
+
+    fn pow(self, exp: &[u8; 32]) -> Fe {
+        let mut result = ONE;
+        for i in (0..256).rev() {
+            result = result.square();
+            if (exp[i / 8] >> (i % 8)) & 1 == 1 {
+                result = result.mul(self);
+            }
+        }
+        result
+    }
+
+    fn invert(self) -> Fe {
+        self.pow(&exponent(0xeb, 0x7f))
+    }
+
+    fn is_negative(self) -> bool {
+        self.to_bytes()[0] & 1 == 1
+    }
+
+    fn is_zero(self) -> bool {
+        self.to_bytes() == [0; 32]
+    }
+
+    fn equals(self, other: Fe) -> bool {
+        self.to_bytes() == other.to_bytes()
+    }
+}
+
+fn edwards_d() -> Fe {
+    Fe([121665, 0, 0, 0, 0]).neg().mul(Fe([121666, 0, 0, 0, 0]).invert())
+}
+
+fn sqrt_m1() -> Fe {
+    Fe([2, 0, 0, 0, 0]).pow(&exponent(0xfb, 0x1f))
+}
+
+/// A point on the curve in extended coordinates.
+#[derive(Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+const IDENTITY: Point = Point { x: ZERO, y: ONE, z: ONE, t: ZERO };
+
+impl Point {
+    fn base() -> Point {
+        let mut encoded = [0x66; 32];
+        encoded[0] = 0x58;
+        Point::decompress(&encoded).expect("base point decodes")
+    }
+
+    /// Decodes a point as in RFC 8032, section 5.1.3.
+    fn decompress(bytes: &[u8; 32]) -> Option<Point> {
+        let sign = bytes[31] >> 7 == 1;
+        let mut unsigned = *bytes;
+        unsigned[31] &= 0x7f;
+        let y = Fe::from_bytes(&unsigned);
+        if y.to_bytes() != unsigned {
+            return None;
+        }
+
+        let y2 = y.square();
+        let u = y2.sub(ONE);
+        let v = edwards_d().mul(y2).add(ONE);
+        let v3 = v.square().mul(v);
+        let v7 = v3.square().mul(v);
+        let mut x = u.mul(v3).mul(u.mul(v7).pow(&exponent(0xfd, 0x0f)));
+
+        let vx2 = v.mul(x.square());
+        if !vx2.equals(u) {
+            if !vx2.equals(u.neg()) {
+                return None;
+            }
+            x = x.mul(sqrt_m1());
+        }
+        if x.is_zero() && sign {
+            return None;
+        }
+        if x.is_negative() != sign {
+            x = x.neg();
+        }
+        Some(Point { x, y, z: ONE, t: x.mul(y) })
+    }
+
+    fn compress(self) -> [u8; 32] {
+        let zinv = self.z.invert();
+        let x = self.x.mul(zinv);
+        let mut out = self.y.mul(zinv).to_bytes();
+        if x.is_negative() {
+            out[31] |= 0x80;
+        }
+        out
+    }
+
+    /// The unified addition formula, which also serves for doubling.
+    fn add(self, other: Point, d2: Fe) -> Point {
+        let a = self.y.sub(self.x).mul(other.y.sub(other.x));
+        let b = self.y.add(self.x).mul(other.y.add(other.x));
+        let c = self.t.mul(d2).mul(other.t);
+        let d = self.z.add(self.z).mul(other.z);
+        let e = b.sub(a);
+        let f = d.sub(c);
+        let g = d.add(c);
+        let h = b.add(a);
+        Point { x: e.mul(f), y: g.mul(h), z: f.mul(g), t: e.mul(h) }
+    }
+
+    fn neg(self) -> Point {
+        Point { x: self.x.neg(), y: self.y, z: self.z, t: self.t.neg() }
+    }
+
+    fn mul(self, scalar: &[u8; 32]) -> Point {
+        let d2 = edwards_d().add(edwards_d());
+        let mut result = IDENTITY;
+        for i in (0..256).rev() {
+            result = result.add(result, d2);
+            if (scalar[i / 8] >> (i % 8)) & 1 == 1 {
+                result = result.add(self, d2);
+            }
+        }
+        result
+    }
+}
+
+/// Reduces a little-endian number of any length modulo L.
+fn reduce(bytes: &[u8]) -> [u8; 32] {
+    let mut r = [0u64; 4];
+    for i in (0..bytes.len() * 8).rev() {
+        let mut bit = u64::from((bytes[i / 8] >> (i % 8)) & 1);
+        for limb in r.iter_mut() {
+            let next = *limb >> 63;
+            *limb = (*limb << 1) | bit;
+            bit = next;
+        }
+        if r.iter().rev().cmp(L.iter().rev()) != ::std::cmp::Ordering::Less {
+            let mut borrow = 0;
+            for (limb, l) in r.iter_mut().zip(L.iter()) {
+                let (value, under1) = limb.overflowing_sub(*l);
+                let (value, under2) = value.overflowing_sub(borrow);
+                *limb = value;
+                borrow = u64::from(under1 || under2);
+            }
+        }
+    }
+    let mut out = [0u8; 32];
+    for (chunk, limb) in out.chunks_mut(8).zip(r.iter()) {
+        chunk.copy_from_slice(&limb.to_le_bytes());
+    }
+    out
+}
+
+/// Computes `(a * b + c) mod L`.
+fn mul_add(a: &[u8; 32], b: &[u8; 32], c: &[u8; 32]) -> [u8; 32] {
+    let mut acc = [0u32; 65];
+    for (i, &x) in a.iter().enumerate() {
+        for (j, &y) in b.iter().enumerate() {
+            acc[i + j] += u32::from(x) * u32::from(y);
+        }
+    }
+    for (slot, &z) in acc.iter_mut().zip(c.iter()) {
+        *slot += u32::from(z);
+    }
+    let mut bytes = [0u8; 65];
+    let mut carry = 0;
+    for (byte, slot) in bytes.iter_mut().zip(acc.iter()) {
+        let value = slot + carry;
+        *byte = value as u8;
+        carry = value >> 8;
+    }
+    reduce(&bytes)
+}
+
+fn challenge(r: &[u8], public_key: &[u8; PUBLIC_KEY_LEN], message: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha512::new();
+    hasher.update(r);
+    hasher.update(public_key);
+    hasher.update(message);
+    reduce(&hasher.finish())
+}
+
+/// A signing key, derived from a 32-byte seed.
+#[derive(Clone)]
+pub struct Keypair {
+    scalar: [u8; 32],
+    prefix: [u8; 32],
+    public_key: [u8; PUBLIC_KEY_LEN],
+}
+
+impl fmt::Debug for Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keypair").field("public_key", &self.public_key).finish()
+    }
+}
+
+impl Keypair {
+    pub fn from_seed(seed: &[u8; SEED_LEN]) -> Keypair {
+        let hash = Sha512::digest(seed);
+        let mut scalar = [0u8; 32];
+        scalar.copy_from_slice(&hash[..32]);
+        scalar[0] &= 248;
+        scalar[31] &= 127;
+        scalar[31] |= 64;
+        let mut prefix = [0u8; 32];
+        prefix.copy_from_slice(&hash[32..]);
+        let public_key = Point::base().mul(&scalar).compress();
+        Keypair { scalar, prefix, public_key }
+    }
+
+    pub fn public_key(&self) -> [u8; PUBLIC_KEY_LEN] {
+        self.public_key
+    }
+
+    pub fn sign(&self, message: &[u8]) -> [u8; SIGNATURE_LEN] {
+        let mut hasher = Sha512::new();
+        hasher.update(&self.prefix);
+        hasher.update(message);
+        let r = reduce(&hasher.finish());
+        let big_r = Point::base().mul(&r).compress();
+        let k = challenge(&big_r, &self.public_key, message);
+        let s = mul_add(&k, &self.scalar, &r);
+
+        let mut signature = [0u8; SIGNATURE_LEN];
+        signature[..32].copy_from_slice(&big_r);
+        signature[32..].copy_from_slice(&s);
+        signature
+    }
+}
+
+/// Checks `signature` over `message` against `public_key`.
+pub fn verify(public_key: &[u8; PUBLIC_KEY_LEN], message: &[u8],
+              signature: &[u8; SIGNATURE_LEN]) -> bool {
+    let point = match Point::decompress(public_key) {
+        Some(point) => point,
+        None => return false,
+    };
+    let mut s = [0u8; 32];
+    s.copy_from_slice(&signature[32..]);
+    if reduce(&s) != s {
+        return false;
+    }
+    let k = challenge(&signature[..32], public_key, message);
+    let d2 = edwards_d().add(edwards_d());
+    let check = Point::base().mul(&s).add(point.neg().mul(&k), d2);
+    check.compress()[..] == signature[..32]
+}
+
+#[cfg(test)]
+fn from_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+}
+
+#[test]
+fn test_rfc8032_vectors() {
+    // Tests 1 and 2 of RFC 8032, section 7.1.
+    let cases = [
+        ("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+         "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+         "",
+         "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"),
+        ("4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+         "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+         "72",
+         "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00"),
+    ];
+    for &(seed, public_key, message, signature) in cases.iter() {
+        let mut seed_bytes = [0u8; SEED_LEN];
+        seed_bytes.copy_from_slice(&from_hex(seed));
+        let keypair = Keypair::from_seed(&seed_bytes);
+        assert_eq!(keypair.public_key()[..], from_hex(public_key)[..]);
+
+        let message = from_hex(message);
+        let signed = keypair.sign(&message);
+        assert_eq!(signed[..], from_hex(signature)[..]);
+        assert!(verify(&keypair.public_key(), &message, &signed));
+
+        let mut forged = signed;
+        forged[5] ^= 1;
+        assert!(!verify(&keypair.public_key(), &message, &forged));
+        assert!(!verify(&keypair.public_key(), b"other", &signed));
+    }
+}
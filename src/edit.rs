@@ -0,0 +1,255 @@
+//! High-level edits of a loaded `.torrent`: trackers, web seeds, comment
+//! and the `source` and `private` flags.
+//!
+//! Edits are applied with `patch::replace_keys`, so only the keys that
+//! changed are re-encoded. `info` is copied byte for byte unless `source`
+//! or `private` is set, and even then only those two keys of it are
+//! rewritten.
+
+use std::collections::BTreeMap;
+
+use Bencode;
+use patch;
+use raw;
+use torrent::{AnnounceList, Metainfo, MetainfoError, MetainfoResult};
+
+/// A torrent being edited. `to_bytes` produces the edited document.
+#[derive(Clone, Debug)]
+pub struct TorrentEditor {
+    document: Vec<u8>,
+    metainfo: Metainfo,
+    trackers: AnnounceList,
+    changes: BTreeMap<Vec<u8>, Option<Bencode>>,
+    info_changes: BTreeMap<Vec<u8>, Option<Bencode>>,
+}
+
+impl TorrentEditor {
+    /// Loads a torrent, which must be exactly one document: edits are
+    /// spliced into the original bytes, so nothing may follow it.
+    pub fn from_bytes(buf: &[u8]) -> MetainfoResult<TorrentEditor> {
+        if raw::skip_value(buf, 0, 1)? != buf.len() {
+            return Err(MetainfoError::Parse(::ParseError::InvalidCharacter));
+        }
+        let metainfo = Metainfo::from_bytes(buf)?;
+        Ok(TorrentEditor {
+            document: buf.to_vec(),
+            trackers: metainfo.trackers(),
+            metainfo,
+            changes: BTreeMap::new(),
+            info_changes: BTreeMap::new(),
+        })
+    }
+
+    /// The trackers as edited so far.
+    pub fn trackers(&self) -> &AnnounceList {
+        &self.trackers
+    }
+
+    pub fn web_seeds(&self) -> &[String] {
+        &self.metainfo.url_list
+    }
+
+    /// Replaces every tracker.
+    pub fn set_trackers(&mut self, trackers: AnnounceList) {
+        self.trackers = trackers;
+        self.trackers.tiers.retain(|tier| !tier.is_empty());
+        self.trackers_changed();
+    }
+
+    /// Adds a tracker in a tier of its own, after the existing ones.
+    /// Returns false if it is already present.
+    pub fn add_tracker(&mut self, url: &str) -> bool {
+        if self.has_tracker(url) {
+            return false;
+        }
+        self.trackers.tiers.push(vec![url.to_string()]);
+        self.trackers_changed();
+        true
+    }
+
+    /// Appends a tracker to tier `tier`, or to a new last tier if `tier` is
+    /// past the end. Returns false if it is already present.
+    pub fn add_tracker_to_tier(&mut self, tier: usize, url: &str) -> bool {
+        if self.has_tracker(url) {
+            return false;
+        }
+        match self.trackers.tiers.get_mut(tier) {
+            Some(tier) => tier.push(url.to_string()),
+            None => self.trackers.tiers.push(vec![url.to_string()]),
+        }
+        self.trackers_changed();
+        true
+    }
+
+    /// Removes a tracker from every tier, dropping tiers left empty.
+    /// Returns false if it was not present.
+    pub fn remove_tracker(&mut self, url: &str) -> bool {
+        if !self.has_tracker(url) {
+            return false;
+        }
+        for tier in self.trackers.tiers.iter_mut() {
+            tier.retain(|existing| existing != url);
+        }
+        self.trackers.tiers.retain(|tier| !tier.is_empty());
+        self.trackers_changed();
+        true
+    }
+
+    /// Replaces a tracker where it stands, keeping its tier and position.
+    /// Returns false if `old` was not present.
+    pub fn replace_tracker(&mut self, old: &str, new: &str) -> bool {
+        if !self.has_tracker(old) {
+            return false;
+        }
+        for tier in self.trackers.tiers.iter_mut() {
+            for url in tier.iter_mut().filter(|url| *url == old) {
+                *url = new.to_string();
+            }
+        }
+        self.trackers.dedup();
+        self.trackers_changed();
+        true
+    }
+
+    /// Removes every tracker.
+    pub fn clear_trackers(&mut self) {
+        self.set_trackers(AnnounceList::default());
+    }
+
+    fn has_tracker(&self, url: &str) -> bool {
+        self.trackers.flatten().contains(&url)
+    }
+
+    /// Writes `announce` as the first tracker, and `announce-list` only
+    /// when there is more than one.
+    fn trackers_changed(&mut self) {
+        let urls = self.trackers.flatten();
+        let announce = urls.first().map(|url| Bencode::from(*url));
+        let announce_list = if urls.len() > 1 {
+            Some(self.trackers.to_bencode())
+        } else {
+            None
+        };
+        self.changes.insert(b"announce".to_vec(), announce);
+        self.changes.insert(b"announce-list".to_vec(), announce_list);
+    }
+
+    /// Adds a BEP 19 web seed. Returns false if it is already present.
+    pub fn add_web_seed(&mut self, url: &str) -> bool {
+        if self.metainfo.url_list.iter().any(|seed| seed == url) {
+            return false;
+        }
+        self.metainfo.url_list.push(url.to_string());
+        self.web_seeds_changed();
+        true
+    }
+
+    /// Removes a web seed. Returns false if it was not present.
+    pub fn remove_web_seed(&mut self, url: &str) -> bool {
+        let before = self.metainfo.url_list.len();
+        self.metainfo.url_list.retain(|seed| seed != url);
+        if self.metainfo.url_list.len() == before {
+            return false;
+        }
+        self.web_seeds_changed();
+        true
+    }
+
+    fn web_seeds_changed(&mut self) {
+        let urls = &self.metainfo.url_list;
+        let value = if urls.is_empty() {
+            None
+        } else {
+            Some(Bencode::Array(urls.iter().map(|url| Bencode::from(&url[..])).collect()))
+        };
+        self.changes.insert(b"url-list".to_vec(), value);
+    }
+
+    pub fn set_comment(&mut self, comment: Option<&str>) {
+        self.metainfo.comment = comment.map(String::from);
+        self.changes.insert(b"comment".to_vec(), comment.map(Bencode::from));
+    }
+
+    /// Sets or clears the `source` tag. This changes the infohash.
+    pub fn set_source(&mut self, source: Option<&str>) {
+        self.metainfo.info.set_source(source);
+        self.info_changes.insert(b"source".to_vec(), source.map(Bencode::from));
+    }
+
+    /// Sets or clears the `private` flag. This changes the infohash.
+    pub fn set_private(&mut self, private: bool) {
+        self.metainfo.info.set_private(private);
+        let value = if private { Some(Bencode::from(1i64)) } else { None };
+        self.info_changes.insert(b"private".to_vec(), value);
+    }
+
+    /// Whether the edits so far leave the infohash as it was.
+    pub fn preserves_info(&self) -> bool {
+        self.info_changes.is_empty()
+    }
+
+    /// The edited document. This fails only for documents that decode but
+    /// cannot be patched in place, such as one whose `info` key repeats.
+    pub fn to_bytes(&self) -> MetainfoResult<Vec<u8>> {
+        let document = patch::replace_keys(&self.document, &self.changes)?;
+        if self.info_changes.is_empty() {
+            return Ok(document);
+        }
+        // The patched `info` is spliced in as written, so the rest of it
+        // keeps its original bytes.
+        let span = raw::dict_value_span(&document, b"info")?
+            .ok_or(MetainfoError::Missing("info"))?;
+        let info = patch::replace_keys(&document[span.clone()], &self.info_changes)?;
+        Ok([&document[..span.start], &info[..], &document[span.end..]].concat())
+    }
+}
+
+#[test]
+fn test_edit_trackers() {
+    // Non-canonical `info` (a zero-padded length) must survive untouched.
+    let document = b"d8:announce5:old/a7:comment1:c4:infod6:lengthi05e4:name1:a\
+        12:piece lengthi1e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+    let mut editor = TorrentEditor::from_bytes(document).unwrap();
+    assert!(editor.add_tracker("new/b"));
+    assert!(!editor.add_tracker("old/a"));
+    assert!(editor.add_tracker_to_tier(0, "new/c"));
+    assert!(editor.replace_tracker("old/a", "new/a"));
+    assert!(!editor.remove_tracker("old/a"));
+    assert!(editor.add_web_seed("http://seed/"));
+    editor.set_comment(None);
+    assert_eq!(editor.trackers().tiers,
+               vec![vec!["new/a".to_string(), "new/c".to_string()], vec!["new/b".to_string()]]);
+    assert!(editor.preserves_info());
+
+    let edited = editor.to_bytes().unwrap();
+    assert_eq!(&edited[..], &b"d8:announce5:new/a13:announce-listll5:new/a5:new/cel5:new/bee\
+        4:infod6:lengthi05e4:name1:a12:piece lengthi1e6:pieces20:aaaaaaaaaaaaaaaaaaaae\
+        8:url-listl12:http://seed/ee"[..]);
+
+    editor.clear_trackers();
+    assert!(editor.remove_web_seed("http://seed/"));
+    assert_eq!(&editor.to_bytes().unwrap()[..], &b"d4:infod6:lengthi05e4:name1:a\
+        12:piece lengthi1e6:pieces20:aaaaaaaaaaaaaaaaaaaaee"[..]);
+}
+
+#[test]
+fn test_edit_info_flags() {
+    // The zero-padded string length must survive the rewrite of `info`.
+    let document = b"d4:infod6:lengthi05e4:name01:a12:piece lengthi1e\
+        6:pieces20:aaaaaaaaaaaaaaaaaaaa6:sourcei1eee";
+    let mut editor = TorrentEditor::from_bytes(document).unwrap();
+    editor.set_private(true);
+    editor.set_source(Some("TRK"));
+    editor.set_comment(Some("c"));
+    assert!(!editor.preserves_info());
+    assert_eq!(&editor.to_bytes().unwrap()[..], &b"d7:comment1:c4:infod6:lengthi05e4:name01:a\
+        12:piece lengthi1e6:pieces20:aaaaaaaaaaaaaaaaaaaa7:privatei1e6:source3:TRKee"[..]);
+
+    let trailing = [&document[..], b"XX"].concat();
+    assert_eq!(TorrentEditor::from_bytes(&trailing).err(),
+               Some(MetainfoError::Parse(::ParseError::InvalidCharacter)));
+    let repeated = [&b"d4:infoi1e"[..], &document[1..]].concat();
+    let mut editor = TorrentEditor::from_bytes(&repeated).unwrap();
+    editor.set_private(true);
+    assert_eq!(editor.to_bytes(), Err(MetainfoError::Parse(::ParseError::InvalidCharacter)));
+}
@@ -0,0 +1,207 @@
+//! The extension protocol handshake (BEP 10).
+
+use std::collections::BTreeMap;
+
+use {Bencode, ParseError, bencode};
+use fields::{put, take_string, take_u64};
+use raw;
+use torrent::Dict;
+
+/// Extended message id of the handshake itself.
+pub const HANDSHAKE_ID: u8 = 0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExtensionError {
+    Parse(ParseError),
+    /// A key holds a value of the wrong type or an unusable value.
+    Invalid(&'static str),
+}
+
+impl From<ParseError> for ExtensionError {
+    fn from(err: ParseError) -> ExtensionError {
+        ExtensionError::Parse(err)
+    }
+}
+
+/// A key that held a value of the wrong type.
+impl From<&'static str> for ExtensionError {
+    fn from(key: &'static str) -> ExtensionError {
+        ExtensionError::Invalid(key)
+    }
+}
+
+pub type ExtensionResult<T> = Result<T, ExtensionError>;
+
+/// The extended handshake dictionary.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Handshake {
+    /// Extension names mapped to the message ids the sender wants to
+    /// receive them under. An id of 0 disables the extension.
+    pub extensions: BTreeMap<String, u8>,
+    /// `v`: the client name and version.
+    pub client: Option<String>,
+    /// `p`: the sender's listening port.
+    pub port: Option<u16>,
+    /// `metadata_size`: the size of the info dictionary (BEP 9).
+    pub metadata_size: Option<u64>,
+    /// `reqq`: how many outstanding requests the sender will queue.
+    pub reqq: Option<u64>,
+    pub extra: Dict,
+}
+
+impl Handshake {
+    pub fn new() -> Handshake {
+        Handshake::default()
+    }
+
+    pub fn from_bencode(value: Bencode) -> ExtensionResult<Handshake> {
+        let mut dict = match value {
+            Bencode::Object(dict) => dict,
+            _ => return Err(ExtensionError::Invalid("handshake")),
+        };
+        let mut extensions = BTreeMap::new();
+        match dict.remove(&b"m"[..]) {
+            None => (),
+            Some(Bencode::Object(map)) => for (name, id) in map {
+                let name = String::from_utf8(name).map_err(|_| ExtensionError::Invalid("m"))?;
+                let id = id.as_u64()
+                    .filter(|&id| id <= u64::from(u8::MAX))
+                    .ok_or(ExtensionError::Invalid("m"))?;
+                extensions.insert(name, id as u8);
+            },
+            Some(_) => return Err(ExtensionError::Invalid("m")),
+        }
+        let port = match take_u64(&mut dict, "p")? {
+            Some(port) if port > u64::from(u16::MAX) => return Err(ExtensionError::Invalid("p")),
+            port => port.map(|port| port as u16),
+        };
+        Ok(Handshake {
+            extensions,
+            client: take_string(&mut dict, "v")?,
+            port,
+            metadata_size: take_u64(&mut dict, "metadata_size")?,
+            reqq: take_u64(&mut dict, "reqq")?,
+            extra: dict,
+        })
+    }
+
+    /// Decodes the payload of an extended message with id `HANDSHAKE_ID`,
+    /// which must hold exactly one dictionary.
+    pub fn from_bytes(buf: &[u8]) -> ExtensionResult<Handshake> {
+        Handshake::from_bencode(raw::decode_exact(buf)?)
+    }
+
+    pub fn to_bencode(&self) -> Bencode {
+        let mut dict = self.extra.clone();
+        let map = self.extensions.iter()
+            .map(|(name, &id)| (name.as_bytes().to_vec(), Bencode::from(u64::from(id))))
+            .collect();
+        put(&mut dict, "m", Some(Bencode::Object(map)));
+        put(&mut dict, "v", self.client.as_ref().map(|v| &v[..]));
+        put(&mut dict, "p", self.port.map(u64::from));
+        put(&mut dict, "metadata_size", self.metadata_size);
+        put(&mut dict, "reqq", self.reqq);
+        Bencode::Object(dict)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        bencode(&self.to_bencode(), &mut buf).expect("writing to a Vec cannot fail");
+        buf
+    }
+
+    /// The id `name` should be sent under to the peer that sent this
+    /// handshake, or `None` if it does not support the extension.
+    pub fn id(&self, name: &str) -> Option<u8> {
+        self.extensions.get(name).cloned().filter(|&id| id != 0)
+    }
+
+    /// The extension the sender of this handshake receives under `id`.
+    pub fn name(&self, id: u8) -> Option<&str> {
+        if id == HANDSHAKE_ID {
+            return None;
+        }
+        self.extensions.iter()
+            .find(|&(_, &value)| value == id)
+            .map(|(name, _)| &name[..])
+    }
+
+    /// Enables `name`, assigning it the lowest unused id unless it already
+    /// has one, and returns its id.
+    pub fn enable(&mut self, name: &str) -> u8 {
+        if let Some(id) = self.id(name) {
+            return id;
+        }
+        let id = (1..=u8::MAX)
+            .find(|&id| self.name(id).is_none())
+            .expect("fewer than 255 extensions are enabled");
+        self.extensions.insert(name.to_string(), id);
+        id
+    }
+
+    /// Marks `name` as disabled, as a later handshake announces it.
+    pub fn disable(&mut self, name: &str) {
+        self.extensions.insert(name.to_string(), 0);
+    }
+
+    /// Applies a later handshake from the same peer: extensions it lists
+    /// are updated, those it disables are dropped, and any other keys it
+    /// carries replace the earlier ones.
+    pub fn update(&mut self, later: Handshake) {
+        for (name, id) in later.extensions {
+            if id == 0 {
+                self.extensions.remove(&name);
+            } else {
+                self.extensions.insert(name, id);
+            }
+        }
+        self.client = later.client.or(self.client.take());
+        self.port = later.port.or(self.port);
+        self.metadata_size = later.metadata_size.or(self.metadata_size);
+        self.reqq = later.reqq.or(self.reqq);
+        self.extra.extend(later.extra);
+    }
+}
+
+#[test]
+fn test_handshake() {
+    // The example from BEP 10, plus the BEP 9 and reqq keys.
+    let buf = b"d1:md11:LT_metadatai1e7:\xc2\xb5T_PEXi2ee13:metadata_sizei31235e\
+        1:pi6881e4:reqqi250e1:v13:\xc2\xb5Torrent 1.2e";
+    let handshake = Handshake::from_bytes(buf).unwrap();
+    assert_eq!(handshake.id("LT_metadata"), Some(1));
+    assert_eq!(handshake.name(2), Some("\u{b5}T_PEX"));
+    assert_eq!(handshake.client, Some("\u{b5}Torrent 1.2".to_string()));
+    assert_eq!(handshake.port, Some(6881));
+    assert_eq!(handshake.metadata_size, Some(31235));
+    assert_eq!(handshake.reqq, Some(250));
+    assert_eq!(handshake.to_bytes(), &buf[..]);
+
+    assert_eq!(Handshake::from_bytes(b"d1:md1:ai256eee"), Err(ExtensionError::Invalid("m")));
+    assert_eq!(Handshake::from_bytes(b"d1:pi65536ee"), Err(ExtensionError::Invalid("p")));
+    assert_eq!(Handshake::from_bytes(b"d1:pi1eeXX"),
+               Err(ExtensionError::Parse(ParseError::InvalidCharacter)));
+    let deep = [&b"d1:x"[..], &vec![b'l'; 100_000]].concat();
+    assert_eq!(Handshake::from_bytes(&deep), Err(ExtensionError::Parse(ParseError::TooDeep)));
+}
+
+#[test]
+fn test_negotiation() {
+    let mut local = Handshake::new();
+    assert_eq!(local.enable("ut_metadata"), 1);
+    assert_eq!(local.enable("ut_pex"), 2);
+    assert_eq!(local.enable("ut_metadata"), 1);
+
+    let mut remote = Handshake::from_bytes(b"d1:md11:ut_metadatai3e6:ut_pexi0eee").unwrap();
+    assert_eq!(remote.id("ut_metadata"), Some(3));
+    assert_eq!(remote.id("ut_pex"), None);
+
+    let mut later = Handshake::new();
+    later.disable("ut_metadata");
+    later.extensions.insert("ut_pex".to_string(), 7);
+    later.port = Some(51413);
+    remote.update(later);
+    assert_eq!(remote.id("ut_metadata"), None);
+    assert_eq!(remote.id("ut_pex"), Some(7));
+    assert_eq!(remote.port, Some(51413));
+}
@@ -0,0 +1,328 @@
+//! A C API, enabled by the `ffi` feature; `include/bencode.h` declares it.
+//!
+//! Documents are decoded into opaque `BencodeValue` handles that the caller
+//! frees with `bencode_free`. Accessors on a handle return borrowed
+//! pointers into it, valid until the root handle is freed. Functions report
+//! failure with a `BencodeStatus`, or a null pointer where they return one.
+//! Build the library with
+//! `cargo rustc --release --features ffi --crate-type staticlib` (or
+//! `cdylib`).
+
+use std::ptr;
+use std::slice;
+
+use {Bencode, ParseError, bdecode_slice, bencode_to_vec};
+
+/// A decoded value. Only ever handled through pointers.
+#[repr(transparent)]
+pub struct BencodeValue(Bencode);
+
+impl BencodeValue {
+    fn wrap(value: &Bencode) -> *const BencodeValue {
+        value as *const Bencode as *const BencodeValue
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BencodeStatus {
+    Ok = 0,
+    Truncated = 1,
+    InvalidCharacter = 2,
+    InvalidLength = 3,
+    OutOfOrderKey = 4,
+    NonCanonical = 5,
+    TooDeep = 6,
+    /// A required pointer argument was null.
+    NullPointer = 7,
+    /// The value is not of the kind the accessor reads.
+    WrongKind = 8,
+    /// The integer does not fit the output type.
+    OutOfRange = 9,
+    TooLarge = 10,
+}
+
+impl From<ParseError> for BencodeStatus {
+    fn from(err: ParseError) -> BencodeStatus {
+        match err {
+            ParseError::Truncated => BencodeStatus::Truncated,
+            ParseError::InvalidCharacter => BencodeStatus::InvalidCharacter,
+            ParseError::InvalidLength => BencodeStatus::InvalidLength,
+            ParseError::OutOfOrderKey => BencodeStatus::OutOfOrderKey,
+            ParseError::NonCanonical => BencodeStatus::NonCanonical,
+            ParseError::TooDeep => BencodeStatus::TooDeep,
+            ParseError::TooLarge => BencodeStatus::TooLarge,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BencodeKind {
+    Integer = 0,
+    Bytes = 1,
+    List = 2,
+    Dict = 3,
+    /// The handle was null, as `bencode_list_get` and `bencode_dict_get`
+    /// return for a missing item.
+    Null = 4,
+}
+
+unsafe fn bytes<'a>(buf: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(buf, len)
+    }
+}
+
+/// Decodes the value at the start of `buf` and stores a new handle in
+/// `*out`.
+///
+/// # Safety
+///
+/// `buf` must point to `len` readable bytes, and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_decode(buf: *const u8, len: usize, out: *mut *mut BencodeValue)
+    -> BencodeStatus {
+
+    if (buf.is_null() && len > 0) || out.is_null() {
+        return BencodeStatus::NullPointer;
+    }
+    match bdecode_slice(bytes(buf, len)) {
+        Ok(value) => {
+            *out = Box::into_raw(Box::new(BencodeValue(value)));
+            BencodeStatus::Ok
+        },
+        Err(err) => err.into(),
+    }
+}
+
+/// Frees a handle returned by `bencode_decode`. Null is ignored.
+///
+/// # Safety
+///
+/// `value` must be null or a root handle that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_free(value: *mut BencodeValue) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
+
+/// Encodes `value` into a new buffer, stored in `*out` and `*out_len`, to
+/// be freed with `bencode_buffer_free`.
+///
+/// # Safety
+///
+/// `value` must be a live handle, and `out` and `out_len` writable.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_encode(value: *const BencodeValue, out: *mut *mut u8,
+                                        out_len: *mut usize) -> BencodeStatus {
+    if value.is_null() || out.is_null() || out_len.is_null() {
+        return BencodeStatus::NullPointer;
+    }
+    let buf = bencode_to_vec(&(*value).0).into_boxed_slice();
+    *out_len = buf.len();
+    *out = Box::into_raw(buf) as *mut u8;
+    BencodeStatus::Ok
+}
+
+/// Frees a buffer returned by `bencode_encode`. Null is ignored.
+///
+/// # Safety
+///
+/// `buf` and `len` must be exactly as `bencode_encode` returned them.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_buffer_free(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buf, len)));
+    }
+}
+
+/// The kind of `value`, or `BencodeKind_Null` if it is null.
+///
+/// # Safety
+///
+/// `value` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_kind(value: *const BencodeValue) -> BencodeKind {
+    match value.as_ref().map(|value| &value.0) {
+        Some(Bencode::Integer(_)) => BencodeKind::Integer,
+        Some(Bencode::Bytes(_)) => BencodeKind::Bytes,
+        Some(Bencode::Array(_)) => BencodeKind::List,
+        Some(Bencode::Object(_)) => BencodeKind::Dict,
+        None => BencodeKind::Null,
+    }
+}
+
+/// Reads an integer into `*out`.
+///
+/// # Safety
+///
+/// `value` must be a live handle and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_as_i64(value: *const BencodeValue, out: *mut i64)
+    -> BencodeStatus {
+
+    if value.is_null() || out.is_null() {
+        return BencodeStatus::NullPointer;
+    }
+    match (*value).0 {
+        Bencode::Integer(_) => match (*value).0.as_i64() {
+            Some(integer) => {
+                *out = integer;
+                BencodeStatus::Ok
+            },
+            None => BencodeStatus::OutOfRange,
+        },
+        _ => BencodeStatus::WrongKind,
+    }
+}
+
+/// Stores a pointer to a byte string's contents in `*out` and its length
+/// in `*out_len`. The contents are not NUL-terminated.
+///
+/// # Safety
+///
+/// `value` must be a live handle, and `out` and `out_len` writable.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_as_bytes(value: *const BencodeValue, out: *mut *const u8,
+                                          out_len: *mut usize) -> BencodeStatus {
+    if value.is_null() || out.is_null() || out_len.is_null() {
+        return BencodeStatus::NullPointer;
+    }
+    match (*value).0 {
+        Bencode::Bytes(ref buf) => {
+            *out = buf.as_ptr();
+            *out_len = buf.len();
+            BencodeStatus::Ok
+        },
+        _ => BencodeStatus::WrongKind,
+    }
+}
+
+/// The number of items in a list or entries in a dictionary; 0 for other
+/// values and for null.
+///
+/// # Safety
+///
+/// `value` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_len(value: *const BencodeValue) -> usize {
+    match value.as_ref().map(|value| &value.0) {
+        Some(Bencode::Array(items)) => items.len(),
+        Some(Bencode::Object(dict)) => dict.len(),
+        _ => 0,
+    }
+}
+
+/// The `index`th item of a list, or null. A null `value` gives null, so
+/// lookups can be chained.
+///
+/// # Safety
+///
+/// `value` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_list_get(value: *const BencodeValue, index: usize)
+    -> *const BencodeValue {
+
+    match value.as_ref().map(|value| &value.0) {
+        Some(Bencode::Array(items)) => items.get(index).map_or(ptr::null(), BencodeValue::wrap),
+        _ => ptr::null(),
+    }
+}
+
+/// The value under `key` in a dictionary, or null. A null `value` gives
+/// null, so lookups can be chained.
+///
+/// # Safety
+///
+/// `value` must be null or a live handle, and `key` must point to
+/// `key_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_dict_get(value: *const BencodeValue, key: *const u8,
+                                          key_len: usize) -> *const BencodeValue {
+    if key.is_null() && key_len > 0 {
+        return ptr::null();
+    }
+    match value.as_ref().map(|value| &value.0) {
+        Some(Bencode::Object(dict)) => {
+            dict.get(bytes(key, key_len)).map_or(ptr::null(), BencodeValue::wrap)
+        },
+        _ => ptr::null(),
+    }
+}
+
+/// The `index`th entry of a dictionary in key order: its key is stored in
+/// `*key` and `*key_len`, and its value returned. Null if out of range or
+/// `value` is null.
+///
+/// # Safety
+///
+/// `value` must be null or a live handle, and `key` and `key_len`
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn bencode_dict_entry(value: *const BencodeValue, index: usize,
+                                            key: *mut *const u8, key_len: *mut usize)
+    -> *const BencodeValue {
+
+    if key.is_null() || key_len.is_null() {
+        return ptr::null();
+    }
+    match value.as_ref().map(|value| &value.0) {
+        Some(Bencode::Object(dict)) => match dict.iter().nth(index) {
+            Some((entry_key, entry)) => {
+                *key = entry_key.as_ptr();
+                *key_len = entry_key.len();
+                BencodeValue::wrap(entry)
+            },
+            None => ptr::null(),
+        },
+        _ => ptr::null(),
+    }
+}
+
+#[test]
+fn test_ffi() {
+    let document = b"d1:ali-3ee1:b3:xyze";
+    unsafe {
+        let mut root = ptr::null_mut();
+        assert_eq!(bencode_decode(document.as_ptr(), document.len(), &mut root), BencodeStatus::Ok);
+        assert_eq!(bencode_kind(root), BencodeKind::Dict);
+        assert_eq!(bencode_len(root), 2);
+
+        let list = bencode_dict_get(root, b"a".as_ptr(), 1);
+        let mut integer = 0;
+        assert_eq!(bencode_as_i64(bencode_list_get(list, 0), &mut integer), BencodeStatus::Ok);
+        assert_eq!(integer, -3);
+        assert!(bencode_list_get(list, 1).is_null());
+        assert_eq!(bencode_as_i64(list, &mut integer), BencodeStatus::WrongKind);
+
+        let (mut key, mut key_len) = (ptr::null(), 0);
+        let b = bencode_dict_entry(root, 1, &mut key, &mut key_len);
+        assert_eq!(bytes(key, key_len), b"b");
+        let (mut buf, mut len) = (ptr::null(), 0);
+        assert_eq!(bencode_as_bytes(b, &mut buf, &mut len), BencodeStatus::Ok);
+        assert_eq!(bytes(buf, len), b"xyz");
+
+        let (mut encoded, mut encoded_len) = (ptr::null_mut(), 0);
+        assert_eq!(bencode_encode(root, &mut encoded, &mut encoded_len), BencodeStatus::Ok);
+        assert_eq!(bytes(encoded, encoded_len), &document[..]);
+        bencode_buffer_free(encoded, encoded_len);
+
+        // Missing values come back as null, and null is accepted again.
+        let missing = bencode_dict_get(root, b"x".as_ptr(), 1);
+        assert!(missing.is_null());
+        assert_eq!(bencode_kind(missing), BencodeKind::Null);
+        assert_eq!(bencode_len(missing), 0);
+        assert!(bencode_list_get(missing, 0).is_null());
+        assert!(bencode_dict_get(missing, b"a".as_ptr(), 1).is_null());
+        assert!(bencode_dict_entry(missing, 0, &mut key, &mut key_len).is_null());
+        bencode_free(root);
+
+        let mut bad = ptr::null_mut();
+        assert_eq!(bencode_decode(b"l".as_ptr(), 1, &mut bad), BencodeStatus::Truncated);
+        assert!(bad.is_null());
+    }
+}
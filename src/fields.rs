@@ -0,0 +1,79 @@
+//! Typed access to dictionary keys, shared by the protocol models.
+//!
+//! A key holding a value of the wrong type is reported as the key itself,
+//! which each model turns into its own `Invalid` variant through
+//! `From<&'static str>`.
+
+use Bencode;
+use torrent::Dict;
+
+/// The key that held a value of the wrong type.
+pub(crate) type FieldResult<T> = Result<T, &'static str>;
+
+pub(crate) fn take_bytes(dict: &mut Dict, key: &'static str) -> FieldResult<Option<Vec<u8>>> {
+    match dict.remove(key.as_bytes()) {
+        None => Ok(None),
+        Some(Bencode::Bytes(buf)) => Ok(Some(buf)),
+        Some(_) => Err(key),
+    }
+}
+
+/// Reads a byte string that must be UTF-8.
+pub(crate) fn take_string(dict: &mut Dict, key: &'static str) -> FieldResult<Option<String>> {
+    match take_bytes(dict, key)? {
+        None => Ok(None),
+        Some(buf) => String::from_utf8(buf).map(Some).map_err(|_| key),
+    }
+}
+
+pub(crate) fn take_i64(dict: &mut Dict, key: &'static str) -> FieldResult<Option<i64>> {
+    match dict.remove(key.as_bytes()) {
+        None => Ok(None),
+        Some(value) => value.as_i64().map(Some).ok_or(key),
+    }
+}
+
+pub(crate) fn take_u64(dict: &mut Dict, key: &'static str) -> FieldResult<Option<u64>> {
+    match dict.remove(key.as_bytes()) {
+        None => Ok(None),
+        Some(value) => value.as_u64().map(Some).ok_or(key),
+    }
+}
+
+pub(crate) fn take_dict(dict: &mut Dict, key: &'static str) -> FieldResult<Option<Dict>> {
+    match dict.remove(key.as_bytes()) {
+        None => Ok(None),
+        Some(Bencode::Object(map)) => Ok(Some(map)),
+        Some(_) => Err(key),
+    }
+}
+
+pub(crate) fn take_list(dict: &mut Dict, key: &'static str) -> FieldResult<Option<Vec<Bencode>>> {
+    match dict.remove(key.as_bytes()) {
+        None => Ok(None),
+        Some(Bencode::Array(items)) => Ok(Some(items)),
+        Some(_) => Err(key),
+    }
+}
+
+pub(crate) fn put<V: Into<Bencode>>(dict: &mut Dict, key: &str, value: Option<V>) {
+    if let Some(value) = value {
+        dict.insert(key.as_bytes().to_vec(), value.into());
+    }
+}
+
+#[test]
+fn test_fields() {
+    let mut dict = Dict::new();
+    put(&mut dict, "a", Some("x"));
+    put(&mut dict, "b", Some(7u64));
+    put(&mut dict, "c", None::<u64>);
+    assert_eq!(dict.len(), 2);
+
+    assert_eq!(take_u64(&mut dict, "a"), Err("a"));
+    assert_eq!(take_string(&mut dict, "a"), Ok(None));
+    assert_eq!(take_u64(&mut dict, "b"), Ok(Some(7)));
+
+    dict.insert(b"s".to_vec(), Bencode::Bytes(vec![0xff]));
+    assert_eq!(take_string(&mut dict, "s"), Err("s"));
+}
@@ -0,0 +1,172 @@
+//! Mapping between pieces and the files they cover.
+//!
+//! The content is treated as the files laid end to end in order, padding
+//! files included, which is the v1 piece layout. Hybrid torrents pad their
+//! files to piece boundaries, so the same mapping holds for their v2 view.
+
+use std::ops::Range;
+
+use torrent::{Info, TorrentFile};
+
+/// The part of a file covered by a piece or byte range.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Extent {
+    /// Index into `FileMap::files`.
+    pub file: usize,
+    /// Offset within the file.
+    pub offset: u64,
+    pub length: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FileMap {
+    files: Vec<TorrentFile>,
+    /// Offset of each file's first byte within the content.
+    starts: Vec<u64>,
+    piece_length: u64,
+    total_length: u64,
+}
+
+impl FileMap {
+    /// Lays out the files of `info`. `None` if their lengths add up to
+    /// more than a `u64` holds, which only a hostile torrent does; every
+    /// offset within a map then fits.
+    pub fn new(info: &Info) -> Option<FileMap> {
+        let files: Vec<TorrentFile> = info.files().collect();
+        let mut starts = Vec::with_capacity(files.len());
+        let mut total_length = 0u64;
+        for file in files.iter() {
+            starts.push(total_length);
+            total_length = total_length.checked_add(file.length)?;
+        }
+        Some(FileMap { files, starts, piece_length: info.piece_length, total_length })
+    }
+
+    pub fn files(&self) -> &[TorrentFile] {
+        &self.files
+    }
+
+    pub fn total_length(&self) -> u64 {
+        self.total_length
+    }
+
+    /// The number of pieces needed to cover the content.
+    pub fn piece_count(&self) -> usize {
+        if self.piece_length == 0 {
+            return 0;
+        }
+        self.total_length.div_ceil(self.piece_length) as usize
+    }
+
+    /// The byte range of piece `index` within the content.
+    pub fn piece_range(&self, index: usize) -> Option<Range<u64>> {
+        if index >= self.piece_count() {
+            return None;
+        }
+        let start = index as u64 * self.piece_length;
+        let end = ::std::cmp::min(start.saturating_add(self.piece_length), self.total_length);
+        Some(start..end)
+    }
+
+    /// The byte range of file `file` within the content.
+    pub fn file_range(&self, file: usize) -> Option<Range<u64>> {
+        let start = *self.starts.get(file)?;
+        Some(start..start + self.files[file].length)
+    }
+
+    /// The file extents covering `range`, in order. Bytes past the end of
+    /// the content are ignored, and empty files never appear.
+    pub fn extents(&self, range: Range<u64>) -> Vec<Extent> {
+        let end = ::std::cmp::min(range.end, self.total_length);
+        let mut extents = Vec::new();
+        if range.start >= end {
+            return extents;
+        }
+        let first = self.starts.iter().zip(self.files.iter())
+            .position(|(start, file)| start + file.length > range.start)
+            .expect("range.start is within the content");
+        for (index, file) in self.files.iter().enumerate().skip(first) {
+            let start = self.starts[index];
+            if start >= end {
+                break;
+            }
+            if file.length == 0 {
+                continue;
+            }
+            let offset = range.start.saturating_sub(start);
+            let stop = ::std::cmp::min(end, start + file.length);
+            extents.push(Extent { file: index, offset, length: stop - start - offset });
+        }
+        extents
+    }
+
+    /// The file extents covered by piece `index`.
+    pub fn piece_extents(&self, index: usize) -> Option<Vec<Extent>> {
+        self.piece_range(index).map(|range| self.extents(range))
+    }
+
+    /// The pieces overlapping file `file`. Empty for an empty file.
+    pub fn file_pieces(&self, file: usize) -> Option<Range<usize>> {
+        let range = self.file_range(file)?;
+        if self.piece_length == 0 {
+            return None;
+        }
+        if range.start == range.end {
+            let piece = (range.start / self.piece_length) as usize;
+            return Some(piece..piece);
+        }
+        let first = range.start / self.piece_length;
+        let last = (range.end - 1) / self.piece_length;
+        Some(first as usize..last as usize + 1)
+    }
+
+    /// The piece holding byte `offset` of file `file`, and the offset of
+    /// that byte within the piece.
+    pub fn locate(&self, file: usize, offset: u64) -> Option<(usize, u64)> {
+        let range = self.file_range(file)?;
+        if offset >= range.end - range.start || self.piece_length == 0 {
+            return None;
+        }
+        let position = range.start + offset;
+        Some(((position / self.piece_length) as usize, position % self.piece_length))
+    }
+}
+
+#[test]
+fn test_file_map() {
+    let info = Info::from_bencode(::bdecode(&mut b"d5:filesld6:lengthi5e4:pathl1:aeed6:lengthi0e\
+        4:pathl1:beed6:lengthi6e4:pathl1:ceee4:name1:r12:piece lengthi4e\
+        6:pieces60:aaaaaaaaaaaaaaaaaaaabbbbbbbbbbbbbbbbbbbbcccccccccccccccccccce"
+        .iter().cloned().peekable()).unwrap()).unwrap();
+    let mut info = info;
+    let map = FileMap::new(&info).unwrap();
+    assert_eq!(map.total_length(), 11);
+    assert_eq!(map.piece_count(), 3);
+    assert_eq!(map.piece_range(2), Some(8..11));
+    assert_eq!(map.piece_range(3), None);
+
+    assert_eq!(map.piece_extents(0), Some(vec![Extent { file: 0, offset: 0, length: 4 }]));
+    assert_eq!(map.piece_extents(1), Some(vec![Extent { file: 0, offset: 4, length: 1 },
+                                               Extent { file: 2, offset: 0, length: 3 }]));
+    assert_eq!(map.piece_extents(2), Some(vec![Extent { file: 2, offset: 3, length: 3 }]));
+    assert_eq!(map.extents(3..100), vec![Extent { file: 0, offset: 3, length: 2 },
+                                         Extent { file: 2, offset: 0, length: 6 }]);
+    assert_eq!(map.extents(11..12), vec![]);
+
+    assert_eq!(map.file_pieces(0), Some(0..2));
+    assert_eq!(map.file_pieces(1), Some(1..1));
+    assert_eq!(map.file_pieces(2), Some(1..3));
+    assert_eq!(map.file_pieces(3), None);
+    assert_eq!(map.locate(2, 4), Some((2, 1)));
+    assert_eq!(map.locate(2, 6), None);
+
+    for file in info.files.as_mut().unwrap().iter_mut() {
+        file.length = 9_000_000_000_000_000_000;
+    }
+    assert_eq!(FileMap::new(&info), None);
+    info.files.as_mut().unwrap().truncate(2);
+    info.piece_length = u64::MAX;
+    let map = FileMap::new(&info).unwrap();
+    assert_eq!(map.file_range(1), Some(9_000_000_000_000_000_000..18_000_000_000_000_000_000));
+    assert_eq!(map.piece_range(0), Some(0..18_000_000_000_000_000_000));
+}
@@ -0,0 +1,210 @@
+//! Typed model of the BEP 52 `file tree`.
+//!
+//! Directories are dictionaries keyed by path component. A file is a
+//! dictionary holding a single empty key, whose value carries the file's
+//! `length` and `pieces root`.
+
+use std::collections::btree_map::{self, BTreeMap};
+
+use Bencode;
+use torrent::{Dict, FileEntry, MetainfoError, MetainfoResult};
+
+/// Length of a `pieces root` hash.
+pub const PIECES_ROOT_LEN: usize = 32;
+
+const INVALID: MetainfoError = MetainfoError::Invalid("file tree");
+
+/// A file leaf of the tree.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FileNode {
+    pub length: u64,
+    /// Merkle root of the file's 16 KiB blocks; absent for empty files.
+    pub pieces_root: Option<[u8; PIECES_ROOT_LEN]>,
+    /// BEP 47 attributes.
+    pub attr: Option<String>,
+    pub extra: Dict,
+}
+
+impl FileNode {
+    fn from_bencode(value: Bencode) -> MetainfoResult<FileNode> {
+        let mut dict = match value {
+            Bencode::Object(dict) => dict,
+            _ => return Err(INVALID),
+        };
+        let length = dict.remove(&b"length"[..])
+            .and_then(|length| length.as_u64())
+            .ok_or(INVALID)?;
+        let pieces_root = match dict.remove(&b"pieces root"[..]) {
+            None => None,
+            Some(Bencode::Bytes(ref buf)) if buf.len() == PIECES_ROOT_LEN => {
+                let mut root = [0; PIECES_ROOT_LEN];
+                root.copy_from_slice(buf);
+                Some(root)
+            },
+            Some(_) => return Err(INVALID),
+        };
+        let attr = match dict.remove(&b"attr"[..]) {
+            None => None,
+            Some(Bencode::Bytes(buf)) => Some(String::from_utf8(buf).map_err(|_| INVALID)?),
+            Some(_) => return Err(INVALID),
+        };
+        Ok(FileNode { length, pieces_root, attr, extra: dict })
+    }
+
+    fn to_bencode(&self) -> Bencode {
+        let mut dict = self.extra.clone();
+        if let Some(ref attr) = self.attr {
+            dict.insert(b"attr".to_vec(), Bencode::from(&attr[..]));
+        }
+        dict.insert(b"length".to_vec(), Bencode::from(self.length));
+        if let Some(ref root) = self.pieces_root {
+            dict.insert(b"pieces root".to_vec(), Bencode::from(&root[..]));
+        }
+        Bencode::Object(dict)
+    }
+
+    /// Whether this is a BEP 47 padding file (`p` in `attr`).
+    pub fn is_padding(&self) -> bool {
+        self.attr.as_ref().is_some_and(|attr| attr.contains('p'))
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Node {
+    File(FileNode),
+    Directory(FileTree),
+}
+
+/// A directory of the tree; the `file tree` value itself is the root.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct FileTree {
+    pub entries: BTreeMap<String, Node>,
+}
+
+impl FileTree {
+    pub fn from_bencode(value: Bencode) -> MetainfoResult<FileTree> {
+        let dict = match value {
+            Bencode::Object(dict) => dict,
+            _ => return Err(INVALID),
+        };
+        let mut entries = BTreeMap::new();
+        for (name, child) in dict {
+            if name.is_empty() {
+                return Err(INVALID);
+            }
+            let name = String::from_utf8(name).map_err(|_| INVALID)?;
+            let mut child = match child {
+                Bencode::Object(child) => child,
+                _ => return Err(INVALID),
+            };
+            let node = match child.remove(&b""[..]) {
+                Some(_) if !child.is_empty() => return Err(INVALID),
+                Some(file) => Node::File(FileNode::from_bencode(file)?),
+                None => Node::Directory(FileTree::from_bencode(Bencode::Object(child))?),
+            };
+            entries.insert(name, node);
+        }
+        Ok(FileTree { entries })
+    }
+
+    pub fn to_bencode(&self) -> Bencode {
+        let dict = self.entries.iter().map(|(name, node)| {
+            let value = match *node {
+                Node::File(ref file) => {
+                    let mut leaf = Dict::new();
+                    leaf.insert(Vec::new(), file.to_bencode());
+                    Bencode::Object(leaf)
+                },
+                Node::Directory(ref tree) => tree.to_bencode(),
+            };
+            (name.as_bytes().to_vec(), value)
+        }).collect();
+        Bencode::Object(dict)
+    }
+
+    /// Iterates over every file in path order, depth first.
+    pub fn walk(&self) -> Walk<'_> {
+        Walk { stack: vec![self.entries.iter()], path: Vec::new() }
+    }
+
+    pub fn total_length(&self) -> u64 {
+        self.checked_total_length().unwrap_or(u64::MAX)
+    }
+
+    /// Like `total_length`, but `None` if the sum overflows.
+    pub fn checked_total_length(&self) -> Option<u64> {
+        self.walk().try_fold(0u64, |total, (_, file)| total.checked_add(file.length))
+    }
+
+    /// Whether the tree holds a single file at its top level, which is how
+    /// single-file v2 torrents are laid out.
+    pub fn is_single_file(&self) -> bool {
+        self.entries.len() == 1 && self.entries.values().all(|node| match *node {
+            Node::File(_) => true,
+            Node::Directory(_) => false,
+        })
+    }
+
+    /// Flattens the tree into the v1 `files` representation.
+    pub fn to_file_entries(&self) -> Vec<FileEntry> {
+        self.walk().map(|(path, file)| FileEntry {
+            length: file.length,
+            path: path.into_iter().map(String::from).collect(),
+            attr: file.attr.clone(),
+            extra: Dict::new(),
+        }).collect()
+    }
+}
+
+/// Depth-first iterator over the files of a tree, yielding each file with
+/// its path components.
+pub struct Walk<'a> {
+    stack: Vec<btree_map::Iter<'a, String, Node>>,
+    path: Vec<&'a str>,
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = (Vec<&'a str>, &'a FileNode);
+
+    fn next(&mut self) -> Option<(Vec<&'a str>, &'a FileNode)> {
+        loop {
+            let entry = self.stack.last_mut()?.next();
+            match entry {
+                None => {
+                    self.stack.pop();
+                    self.path.pop();
+                },
+                Some((name, Node::File(file))) => {
+                    let mut path = self.path.clone();
+                    path.push(name);
+                    return Some((path, file));
+                },
+                Some((name, Node::Directory(tree))) => {
+                    self.path.push(name);
+                    self.stack.push(tree.entries.iter());
+                },
+            }
+        }
+    }
+}
+
+#[test]
+fn test_file_tree() {
+    let buf = b"d1:ad1:bd0:d6:lengthi3e11:pieces root32:rrrrrrrrrrrrrrrrrrrrrrrrrrrrrrrree\
+        1:cd0:d6:lengthi0eeee1:dd0:d4:attr1:p6:lengthi5eeee";
+    let value = ::bdecode(&mut buf.iter().cloned().peekable()).unwrap();
+    let tree = FileTree::from_bencode(value.clone()).unwrap();
+    assert_eq!(tree.to_bencode(), value);
+
+    let files: Vec<(Vec<&str>, u64)> = tree.walk().map(|(path, file)| (path, file.length)).collect();
+    assert_eq!(files, vec![(vec!["a", "b"], 3), (vec!["a", "c"], 0), (vec!["d"], 5)]);
+    assert_eq!(tree.total_length(), 8);
+    assert!(!tree.is_single_file());
+
+    let entries = tree.to_file_entries();
+    assert_eq!(entries[0].path, vec!["a".to_string(), "b".to_string()]);
+    assert!(entries[2].is_padding());
+
+    let bad = ::bdecode(&mut b"d1:ad0:d6:lengthi1ee1:xi1eee".iter().cloned().peekable()).unwrap();
+    assert_eq!(FileTree::from_bencode(bad), Err(INVALID));
+}
@@ -0,0 +1,113 @@
+//! A fast path for shallow dictionaries of strings and integers, the shape
+//! of tracker responses and KRPC messages.
+//!
+//! Values borrow from the input and only the top level is decoded: lists
+//! and dictionaries are scanned to find their end, to at most `MAX_DEPTH`,
+//! and kept as their encoded bytes, to be decoded by the caller only if
+//! they are needed.
+
+use {ParseError, is_digit};
+use raw::{self, DictEntries};
+
+/// A value of a `FlatDict`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FlatValue<'a> {
+    /// The digits of an integer, with any sign.
+    Integer(&'a [u8]),
+    Bytes(&'a [u8]),
+    /// An encoded list or dictionary.
+    Nested(&'a [u8]),
+}
+
+impl<'a> FlatValue<'a> {
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            FlatValue::Integer(digits) => ::std::str::from_utf8(digits).ok()?.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match *self {
+            FlatValue::Bytes(buf) => Some(buf),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&'a str> {
+        self.as_bytes().and_then(|buf| ::std::str::from_utf8(buf).ok())
+    }
+}
+
+/// The entries of a dictionary in key order. A repeated key keeps its last
+/// value, as `bdecode` does.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct FlatDict<'a> {
+    entries: Vec<(&'a [u8], FlatValue<'a>)>,
+}
+
+impl<'a> FlatDict<'a> {
+    pub fn get(&self, key: &[u8]) -> Option<&FlatValue<'a>> {
+        self.entries.binary_search_by(|&(probe, _)| probe.cmp(key))
+            .ok()
+            .map(|index| &self.entries[index].1)
+    }
+
+    pub fn bytes(&self, key: &[u8]) -> Option<&'a [u8]> {
+        self.get(key).and_then(FlatValue::as_bytes)
+    }
+
+    pub fn integer(&self, key: &[u8]) -> Option<i64> {
+        self.get(key).and_then(FlatValue::as_i64)
+    }
+
+    pub fn iter(&self) -> ::std::slice::Iter<'_, (&'a [u8], FlatValue<'a>)> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Decodes the dictionary at the start of `buf` one level deep.
+pub fn decode_flat_dict(buf: &[u8]) -> Result<FlatDict<'_>, ParseError> {
+    let mut entries: Vec<(&[u8], FlatValue)> = Vec::new();
+    for entry in DictEntries::new(buf, 0)? {
+        let (key, value) = entry?;
+        let (key, value) = (&buf[key], &buf[value]);
+        let value = match value[0] {
+            b'i' => FlatValue::Integer(&value[1..value.len() - 1]),
+            val if is_digit(val) => FlatValue::Bytes(&value[raw::scan_bytea(value, 0)?]),
+            _ => FlatValue::Nested(value),
+        };
+        match entries.last_mut() {
+            Some(last) if last.0 == key => last.1 = value,
+            _ => entries.push((key, value)),
+        }
+    }
+    Ok(FlatDict { entries })
+}
+
+#[test]
+fn test_decode_flat_dict() {
+    let buf = b"d8:completei5e10:incompletei-1e8:intervali1800e5:peers6:abcdef1:xld1:ai1eeee";
+    let dict = decode_flat_dict(buf).unwrap();
+    assert_eq!(dict.len(), 5);
+    assert_eq!(dict.integer(b"interval"), Some(1800));
+    assert_eq!(dict.integer(b"incomplete"), Some(-1));
+    assert_eq!(dict.bytes(b"peers"), Some(&b"abcdef"[..]));
+    assert_eq!(dict.get(b"x"), Some(&FlatValue::Nested(&b"ld1:ai1eee"[..])));
+    assert_eq!(dict.get(b"y"), None);
+
+    assert_eq!(decode_flat_dict(b"d1:a1:x1:a1:ye").unwrap().bytes(b"a"), Some(&b"y"[..]));
+    assert_eq!(decode_flat_dict(b"li1ee"), Err(ParseError::InvalidCharacter));
+    assert_eq!(decode_flat_dict(b"d1:b0:1:a0:e"), Err(ParseError::OutOfOrderKey));
+
+    let deep = [&b"d1:x"[..], &vec![b'l'; 100_000]].concat();
+    assert_eq!(decode_flat_dict(&deep), Err(ParseError::TooDeep));
+}
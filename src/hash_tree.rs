@@ -0,0 +1,141 @@
+//! BEP 30 merkle torrents: a SHA-1 hash tree over the piece hashes, of
+//! which only the root is stored in the info dictionary as `root hash`.
+//!
+//! Tree nodes are numbered breadth first from the root at 0, so the
+//! children of node `i` are `2i + 1` and `2i + 2`. Leaves past the last
+//! piece hold the all-zero hash.
+
+use std::collections::BTreeMap;
+
+use Bencode;
+use sha1::{self, Sha1};
+
+pub type Hash = [u8; sha1::DIGEST_LEN];
+
+const ZERO: Hash = [0; sha1::DIGEST_LEN];
+
+fn hash_pair(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha1::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finish()
+}
+
+/// Number of leaves in the tree over `piece_count` pieces.
+fn leaf_count(piece_count: usize) -> usize {
+    piece_count.next_power_of_two()
+}
+
+/// Computes every node of the tree, indexed by node number.
+fn build_tree(piece_hashes: &[Hash]) -> Vec<Hash> {
+    let leaves = leaf_count(piece_hashes.len());
+    let mut tree = vec![ZERO; 2 * leaves - 1];
+    tree[leaves - 1..leaves - 1 + piece_hashes.len()].copy_from_slice(piece_hashes);
+    for node in (0..leaves - 1).rev() {
+        tree[node] = hash_pair(&tree[2 * node + 1], &tree[2 * node + 2]);
+    }
+    tree
+}
+
+/// The `root hash` over a torrent's piece hashes.
+pub fn root_hash(piece_hashes: &[Hash]) -> Hash {
+    build_tree(piece_hashes)[0]
+}
+
+/// The hashes a seeder sends along with piece `index`: the piece's own
+/// hash and the sibling of every node on its path to the root, as
+/// `(node number, hash)` pairs. Returns `None` for an index out of range.
+pub fn hash_chain(piece_hashes: &[Hash], index: usize) -> Option<Vec<(usize, Hash)>> {
+    if index >= piece_hashes.len() {
+        return None;
+    }
+    let tree = build_tree(piece_hashes);
+    let mut node = leaf_count(piece_hashes.len()) - 1 + index;
+    let mut chain = vec![(node, tree[node])];
+    while node > 0 {
+        let sibling = if node % 2 == 1 { node + 1 } else { node - 1 };
+        chain.push((sibling, tree[sibling]));
+        node = (node - 1) / 2;
+    }
+    Some(chain)
+}
+
+/// Checks that `piece_hash` is the hash of piece `index` under `root`,
+/// using the sibling hashes in `chain`. Entries for nodes off the path,
+/// such as the root itself, are ignored.
+pub fn verify_chain(root: &Hash, piece_count: usize, index: usize, piece_hash: &Hash,
+                    chain: &[(usize, Hash)]) -> bool {
+    if index >= piece_count {
+        return false;
+    }
+    let chain: BTreeMap<usize, &Hash> = chain.iter().map(|(node, hash)| (*node, hash)).collect();
+    let mut node = leaf_count(piece_count) - 1 + index;
+    let mut hash = *piece_hash;
+    while node > 0 {
+        let sibling = if node % 2 == 1 { node + 1 } else { node - 1 };
+        let sibling_hash = match chain.get(&sibling) {
+            Some(hash) => **hash,
+            None => return false,
+        };
+        hash = if node % 2 == 1 {
+            hash_pair(&hash, &sibling_hash)
+        } else {
+            hash_pair(&sibling_hash, &hash)
+        };
+        node = (node - 1) / 2;
+    }
+    hash == *root
+}
+
+/// Encodes a chain as the list of `[node, hash]` pairs carried in the
+/// `hashes` key of a piece message.
+pub fn chain_to_bencode(chain: &[(usize, Hash)]) -> Bencode {
+    Bencode::Array(chain.iter()
+        .map(|(node, hash)| Bencode::Array(vec![Bencode::from(*node as u64), Bencode::from(&hash[..])]))
+        .collect())
+}
+
+pub fn chain_from_bencode(value: &Bencode) -> Option<Vec<(usize, Hash)>> {
+    let items = match *value {
+        Bencode::Array(ref items) => items,
+        _ => return None,
+    };
+    items.iter().map(|item| {
+        match *item {
+            Bencode::Array(ref pair) if pair.len() == 2 => {
+                let node = pair[0].as_u64()? as usize;
+                let hash = match pair[1] {
+                    Bencode::Bytes(ref buf) if buf.len() == sha1::DIGEST_LEN => buf,
+                    _ => return None,
+                };
+                let mut out = ZERO;
+                out.copy_from_slice(hash);
+                Some((node, out))
+            },
+            _ => None,
+        }
+    }).collect()
+}
+
+#[test]
+fn test_hash_chain() {
+    let pieces: Vec<Hash> = (0..5u8).map(|i| Sha1::digest(&[i])).collect();
+    let root = root_hash(&pieces);
+    let left = hash_pair(&hash_pair(&pieces[0], &pieces[1]), &hash_pair(&pieces[2], &pieces[3]));
+    let right = hash_pair(&hash_pair(&pieces[4], &ZERO), &hash_pair(&ZERO, &ZERO));
+    assert_eq!(root, hash_pair(&left, &right));
+
+    for index in 0..pieces.len() {
+        let chain = hash_chain(&pieces, index).unwrap();
+        assert_eq!(chain.len(), 4);
+        assert!(verify_chain(&root, pieces.len(), index, &pieces[index], &chain));
+        assert!(!verify_chain(&root, pieces.len(), index, &ZERO, &chain));
+
+        let decoded = chain_from_bencode(&chain_to_bencode(&chain)).unwrap();
+        assert_eq!(decoded, chain);
+    }
+    let chain = hash_chain(&pieces, 4).unwrap();
+    assert!(!verify_chain(&root, pieces.len(), 4, &pieces[4], &chain[..2]));
+    assert_eq!(hash_chain(&pieces, 5), None);
+    assert_eq!(root_hash(&pieces[..1]), pieces[0]);
+}
@@ -0,0 +1,128 @@
+//! Hex dumps of byte strings and encoded documents.
+//!
+//! `HexDump` is the classic view: an offset, sixteen bytes in hex and the
+//! same bytes as ASCII. `Annotated` walks an encoded document instead and
+//! prints one line per token, indented by depth and labelled, with the
+//! offset and bytes each token spans. When a capture is framed wrongly, it
+//! shows where the value really ends, what follows it and where parsing
+//! gives up.
+
+use std::fmt;
+use std::str;
+
+use lexer::{Lexer, Token};
+
+const BYTES_PER_LINE: usize = 16;
+
+/// Displays bytes as `offset  hex  |ascii|` lines, like `hexdump -C`.
+pub struct HexDump<'a>(pub &'a [u8]);
+
+/// Writes one line of a dump of `buf`, whose first byte is at `offset`.
+fn write_line(f: &mut fmt::Formatter, offset: usize, buf: &[u8]) -> fmt::Result {
+    write!(f, "{:08x} ", offset)?;
+    for index in 0..BYTES_PER_LINE {
+        if index == BYTES_PER_LINE / 2 {
+            write!(f, " ")?;
+        }
+        match buf.get(index) {
+            Some(byte) => write!(f, " {:02x}", byte)?,
+            None => write!(f, "   ")?,
+        }
+    }
+    write!(f, "  |")?;
+    for &byte in buf {
+        let ch = if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' };
+        write!(f, "{}", ch)?;
+    }
+    writeln!(f, "|")
+}
+
+fn write_dump(f: &mut fmt::Formatter, start: usize, buf: &[u8]) -> fmt::Result {
+    for (index, line) in buf.chunks(BYTES_PER_LINE).enumerate() {
+        write_line(f, start + index * BYTES_PER_LINE, line)?;
+    }
+    Ok(())
+}
+
+impl<'a> fmt::Display for HexDump<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_dump(f, 0, self.0)
+    }
+}
+
+/// Displays an encoded document token by token, then any bytes after the
+/// first value. A malformed document is annotated up to the error, and the
+/// rest is dumped from there.
+pub struct Annotated<'a>(pub &'a [u8]);
+
+impl<'a> fmt::Display for Annotated<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let buf = self.0;
+        let mut lexer = Lexer::new(buf);
+        let mut start = 0;
+        let mut depth = 0;
+        while let Some(token) = lexer.next() {
+            let token = match token {
+                Ok(token) => token,
+                Err(err) => {
+                    writeln!(f, "{:08x}  {:indent$}error: {:?}", start, "", err,
+                             indent = 2 * depth)?;
+                    return write_dump(f, start, &buf[start..]);
+                },
+            };
+            let end = lexer.position();
+            if token == Token::End {
+                depth -= 1;
+            }
+            write!(f, "{:08x}  {:indent$}", start, "", indent = 2 * depth)?;
+            match token {
+                Token::Integer(digits) => {
+                    write!(f, "int {}", str::from_utf8(digits).unwrap_or("?"))?
+                },
+                Token::Bytes(bytes) => write!(f, "bytes[{}]", bytes.len())?,
+                Token::List => write!(f, "list")?,
+                Token::Dict => write!(f, "dict")?,
+                Token::End => write!(f, "end")?,
+            }
+            write!(f, " ")?;
+            for byte in buf[start..end].iter().take(BYTES_PER_LINE) {
+                write!(f, " {:02x}", byte)?;
+            }
+            writeln!(f, "{}", if end - start > BYTES_PER_LINE { " …" } else { "" })?;
+            if let Token::List | Token::Dict = token {
+                depth += 1;
+            }
+            start = end;
+        }
+        if start < buf.len() {
+            writeln!(f, "{:08x}  trailing {} bytes", start, buf.len() - start)?;
+            write_dump(f, start, &buf[start..])?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_hexdump() {
+    assert_eq!(HexDump(b"d8:announce3:url4:infoi1ee\x00\xff").to_string(), "\
+00000000  64 38 3a 61 6e 6e 6f 75  6e 63 65 33 3a 75 72 6c  |d8:announce3:url|
+00000010  34 3a 69 6e 66 6f 69 31  65 65 00 ff              |4:infoi1ee..|
+");
+    assert_eq!(Annotated(b"d1:ali-7e0:ee1:x").to_string(), "\
+00000000  dict  64
+00000001    bytes[1]  31 3a 61
+00000004    list  6c
+00000005      int -7  69 2d 37 65
+00000009      bytes[0]  30 3a
+0000000b    end  65
+0000000c  end  65
+0000000d  trailing 3 bytes
+0000000d  31 3a 78                                          |1:x|
+");
+    assert_eq!(Annotated(b"li1e5:ab").to_string(), "\
+00000000  list  6c
+00000001    int 1  69 31 65
+00000004    error: Truncated
+00000004  35 3a 61 62                                       |5:ab|
+");
+}
@@ -0,0 +1,89 @@
+//! Reading tracker responses from HTTP bodies, enabled by the `http`
+//! feature.
+//!
+//! The helpers take any `Read`, so they work with whatever client is in
+//! use: a blocking `reqwest::Response` can be passed as is, and async
+//! bodies once adapted to a reader. Bodies are read up to a size limit,
+//! since a misbehaving tracker should not be able to exhaust memory, and
+//! a `failure reason` comes back as `TrackerError::Failure`.
+
+use std::io::{self, Read};
+
+use tracker::{AnnounceResponse, ScrapeResponse, TrackerError};
+
+/// A limit that comfortably fits announce responses and scrapes of a few
+/// thousand torrents.
+pub const DEFAULT_BODY_LIMIT: u64 = 1 << 20;
+
+#[derive(Debug)]
+pub enum BodyError {
+    Io(io::Error),
+    /// The body is longer than the limit.
+    TooLarge,
+    Tracker(TrackerError),
+}
+
+impl From<io::Error> for BodyError {
+    fn from(err: io::Error) -> BodyError {
+        BodyError::Io(err)
+    }
+}
+
+impl From<TrackerError> for BodyError {
+    fn from(err: TrackerError) -> BodyError {
+        BodyError::Tracker(err)
+    }
+}
+
+pub type BodyResult<T> = Result<T, BodyError>;
+
+/// Reads a whole body of at most `limit` bytes.
+pub fn read_body<R: Read>(body: R, limit: u64) -> BodyResult<Vec<u8>> {
+    let mut buf = Vec::new();
+    body.take(limit.saturating_add(1)).read_to_end(&mut buf)?;
+    if buf.len() as u64 > limit {
+        return Err(BodyError::TooLarge);
+    }
+    Ok(buf)
+}
+
+/// Reads and decodes the response to an announce.
+pub fn read_announce_response<R: Read>(body: R, limit: u64) -> BodyResult<AnnounceResponse> {
+    Ok(AnnounceResponse::from_bytes(&read_body(body, limit)?)?)
+}
+
+/// Reads and decodes the response to a scrape.
+pub fn read_scrape_response<R: Read>(body: R, limit: u64) -> BodyResult<ScrapeResponse> {
+    Ok(ScrapeResponse::from_bytes(&read_body(body, limit)?)?)
+}
+
+#[test]
+fn test_read_responses() {
+    let body = &b"d8:intervali1800e5:peers6:\x7f\x00\x00\x01\x1a\xe1e"[..];
+    let response = read_announce_response(body, DEFAULT_BODY_LIMIT).unwrap();
+    assert_eq!(response.interval_secs(), 1800);
+    assert_eq!(response.peers.len(), 1);
+    assert!(matches!(read_announce_response(body, body.len() as u64 - 1),
+                     Err(BodyError::TooLarge)));
+
+    let failure = &b"d14:failure reason20:unregistered torrente"[..];
+    match read_announce_response(failure, DEFAULT_BODY_LIMIT) {
+        Err(BodyError::Tracker(TrackerError::Failure(reason))) => {
+            assert_eq!(reason, "unregistered torrent")
+        },
+        other => panic!("expected a failure, got {:?}", other),
+    }
+
+    let scrape = &b"d5:filesd20:aaaaaaaaaaaaaaaaaaaad8:completei3e10:downloadedi9e\
+                   10:incompletei1eeee"[..];
+    assert_eq!(read_scrape_response(scrape, scrape.len() as u64).unwrap().files.len(), 1);
+    assert!(matches!(read_scrape_response(&b"d5:files"[..], DEFAULT_BODY_LIMIT),
+                     Err(BodyError::Tracker(TrackerError::Parse(_)))));
+
+    let deep = [&b"d8:intervali1e5:peers"[..], &vec![b'l'; 1_000_000]].concat();
+    assert!(matches!(read_announce_response(&deep[..], DEFAULT_BODY_LIMIT),
+                     Err(BodyError::Tracker(TrackerError::Parse(::ParseError::TooDeep)))));
+    let trailing = [body, b"XX"].concat();
+    assert!(matches!(read_announce_response(&trailing[..], DEFAULT_BODY_LIMIT),
+                     Err(BodyError::Tracker(TrackerError::Parse(::ParseError::InvalidCharacter)))));
+}
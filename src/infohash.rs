@@ -0,0 +1,385 @@
+//! Infohash computation over bencoded metainfo documents.
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+#[cfg(any(feature = "sha1", feature = "sha256"))]
+use std::ops::Range;
+use std::str::FromStr;
+
+#[cfg(any(feature = "sha1", feature = "sha256"))]
+use {Bencode, bencode, raw};
+use ParseError;
+#[cfg(feature = "sha1")]
+use sha1::{self, Sha1};
+#[cfg(feature = "sha256")]
+use sha256::{self, Sha256};
+
+pub const V1_LEN: usize = 20;
+pub const V2_LEN: usize = 32;
+
+/// Length of the base32 encoding of a v1 infohash.
+pub const BASE32_V1_LEN: usize = 32;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Length of the truncated v2 infohash used where a v1-sized hash is expected.
+pub const TRUNCATED_V2_LEN: usize = V1_LEN;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InfoHashError {
+    Parse(ParseError),
+    MissingInfo,
+}
+
+impl From<ParseError> for InfoHashError {
+    fn from(err: ParseError) -> InfoHashError {
+        InfoHashError::Parse(err)
+    }
+}
+
+/// A v1 (SHA-1) or v2 (SHA-256) infohash.
+///
+/// Formats as lowercase hex and parses from hex of either length, or from
+/// the 32-character base32 form of a v1 hash.
+/// Equality is evaluated in constant time.
+#[derive(Clone, Copy)]
+pub enum InfoHash {
+    V1([u8; V1_LEN]),
+    V2([u8; V2_LEN]),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParseInfoHashError {
+    InvalidLength,
+    InvalidCharacter,
+}
+
+impl InfoHash {
+    pub fn from_slice(bytes: &[u8]) -> Option<InfoHash> {
+        match bytes.len() {
+            V1_LEN => {
+                let mut buf = [0u8; V1_LEN];
+                buf.copy_from_slice(bytes);
+                Some(InfoHash::V1(buf))
+            },
+            V2_LEN => {
+                let mut buf = [0u8; V2_LEN];
+                buf.copy_from_slice(bytes);
+                Some(InfoHash::V2(buf))
+            },
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match *self {
+            InfoHash::V1(ref buf) => buf,
+            InfoHash::V2(ref buf) => buf,
+        }
+    }
+
+    pub fn is_v1(&self) -> bool {
+        match *self {
+            InfoHash::V1(_) => true,
+            InfoHash::V2(_) => false,
+        }
+    }
+
+    pub fn is_v2(&self) -> bool {
+        !self.is_v1()
+    }
+
+    /// Parses the legacy RFC 4648 base32 form of a v1 infohash, as found in
+    /// older magnet links. Both letter cases are accepted.
+    pub fn from_base32(s: &str) -> Result<InfoHash, ParseInfoHashError> {
+        let digits = s.as_bytes();
+        if digits.len() != BASE32_V1_LEN {
+            return Err(ParseInfoHashError::InvalidLength);
+        }
+        let mut buf = [0u8; V1_LEN];
+        // 8 base32 digits carry exactly 5 bytes.
+        for (out, group) in buf.chunks_mut(5).zip(digits.chunks(8)) {
+            let mut acc = 0u64;
+            for &digit in group {
+                acc = (acc << 5) | u64::from(base32_value(digit)?);
+            }
+            for (i, byte) in out.iter_mut().enumerate() {
+                *byte = (acc >> (32 - 8 * i)) as u8;
+            }
+        }
+        Ok(InfoHash::V1(buf))
+    }
+
+    /// Formats a v1 infohash in uppercase base32. Returns `None` for v2
+    /// hashes, which have no base32 form.
+    pub fn to_base32(&self) -> Option<String> {
+        let buf = match *self {
+            InfoHash::V1(ref buf) => buf,
+            InfoHash::V2(_) => return None,
+        };
+        let mut output = String::with_capacity(BASE32_V1_LEN);
+        for group in buf.chunks(5) {
+            let acc = group.iter().fold(0u64, |acc, &b| (acc << 8) | u64::from(b));
+            for i in 0..8 {
+                let index = (acc >> (35 - 5 * i)) & 0x1f;
+                output.push(BASE32_ALPHABET[index as usize] as char);
+            }
+        }
+        Some(output)
+    }
+
+    /// The 20-byte form of this hash: the v1 hash itself, or the first
+    /// 20 bytes of a v2 hash.
+    pub fn truncated(&self) -> [u8; TRUNCATED_V2_LEN] {
+        let mut output = [0u8; TRUNCATED_V2_LEN];
+        output.copy_from_slice(&self.as_bytes()[..TRUNCATED_V2_LEN]);
+        output
+    }
+}
+
+impl From<[u8; V1_LEN]> for InfoHash {
+    fn from(buf: [u8; V1_LEN]) -> InfoHash {
+        InfoHash::V1(buf)
+    }
+}
+
+impl From<[u8; V2_LEN]> for InfoHash {
+    fn from(buf: [u8; V2_LEN]) -> InfoHash {
+        InfoHash::V2(buf)
+    }
+}
+
+impl AsRef<[u8]> for InfoHash {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl PartialEq for InfoHash {
+    fn eq(&self, other: &InfoHash) -> bool {
+        let (lhs, rhs) = (self.as_bytes(), other.as_bytes());
+        if lhs.len() != rhs.len() {
+            return false;
+        }
+        lhs.iter().zip(rhs.iter()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+}
+
+impl Eq for InfoHash {}
+
+impl Hash for InfoHash {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_bytes().hash(state);
+    }
+}
+
+impl fmt::Display for InfoHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in self.as_bytes() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for InfoHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InfoHash::V1(_) => write!(f, "InfoHash::V1({})", self),
+            InfoHash::V2(_) => write!(f, "InfoHash::V2({})", self),
+        }
+    }
+}
+
+fn hex_value(digit: u8) -> Result<u8, ParseInfoHashError> {
+    match digit {
+        b'0'..=b'9' => Ok(digit - b'0'),
+        b'a'..=b'f' => Ok(digit - b'a' + 10),
+        b'A'..=b'F' => Ok(digit - b'A' + 10),
+        _ => Err(ParseInfoHashError::InvalidCharacter),
+    }
+}
+
+fn base32_value(digit: u8) -> Result<u8, ParseInfoHashError> {
+    match digit {
+        b'A'..=b'Z' => Ok(digit - b'A'),
+        b'a'..=b'z' => Ok(digit - b'a'),
+        b'2'..=b'7' => Ok(digit - b'2' + 26),
+        _ => Err(ParseInfoHashError::InvalidCharacter),
+    }
+}
+
+impl FromStr for InfoHash {
+    type Err = ParseInfoHashError;
+
+    fn from_str(s: &str) -> Result<InfoHash, ParseInfoHashError> {
+        let digits = s.as_bytes();
+        if digits.len() == BASE32_V1_LEN {
+            return InfoHash::from_base32(s);
+        }
+        if digits.len() != 2 * V1_LEN && digits.len() != 2 * V2_LEN {
+            return Err(ParseInfoHashError::InvalidLength);
+        }
+        let mut buf = [0u8; V2_LEN];
+        for (out, pair) in buf.iter_mut().zip(digits.chunks(2)) {
+            *out = (hex_value(pair[0])? << 4) | hex_value(pair[1])?;
+        }
+        Ok(InfoHash::from_slice(&buf[..digits.len() / 2])
+            .expect("length checked above"))
+    }
+}
+
+#[cfg(any(feature = "sha1", feature = "sha256"))]
+fn info_span(document: &[u8]) -> Result<Range<usize>, InfoHashError> {
+    let span = match raw::dict_value_span(document, b"info")? {
+        Some(span) => span,
+        None => return Err(InfoHashError::MissingInfo),
+    };
+    if document[span.start] != b'd' {
+        return Err(InfoHashError::MissingInfo);
+    }
+    Ok(span)
+}
+
+#[cfg(any(feature = "sha1", feature = "sha256"))]
+fn encode_info(document: &Bencode) -> Result<Vec<u8>, InfoHashError> {
+    let info = match *document {
+        Bencode::Object(ref map) => map.get(b"info" as &[u8]),
+        _ => None,
+    };
+    match info {
+        Some(info @ &Bencode::Object(_)) => {
+            let mut buf = Vec::new();
+            bencode(info, &mut buf).expect("writing to a Vec cannot fail");
+            Ok(buf)
+        },
+        _ => Err(InfoHashError::MissingInfo),
+    }
+}
+
+/// Locates the `info` dictionary within `document` and returns the SHA-1
+/// of its bytes exactly as they appear in the original document.
+#[cfg(feature = "sha1")]
+pub fn info_hash_v1(document: &[u8])
+    -> Result<[u8; sha1::DIGEST_LEN], InfoHashError> {
+
+    let span = info_span(document)?;
+    Ok(Sha1::digest(&document[span]))
+}
+
+/// Computes the v1 infohash of a decoded document by re-encoding its
+/// `info` dictionary canonically.
+#[cfg(feature = "sha1")]
+pub fn info_hash_v1_from(document: &Bencode)
+    -> Result<[u8; sha1::DIGEST_LEN], InfoHashError> {
+
+    Ok(Sha1::digest(&encode_info(document)?))
+}
+
+/// Locates the `info` dictionary within `document` and returns the BEP 52
+/// SHA-256 of its bytes exactly as they appear in the original document.
+#[cfg(feature = "sha256")]
+pub fn info_hash_v2(document: &[u8])
+    -> Result<[u8; sha256::DIGEST_LEN], InfoHashError> {
+
+    let span = info_span(document)?;
+    Ok(Sha256::digest(&document[span]))
+}
+
+/// Computes the v2 infohash of a decoded document by re-encoding its
+/// `info` dictionary canonically.
+#[cfg(feature = "sha256")]
+pub fn info_hash_v2_from(document: &Bencode)
+    -> Result<[u8; sha256::DIGEST_LEN], InfoHashError> {
+
+    Ok(Sha256::digest(&encode_info(document)?))
+}
+
+/// Truncates a v2 infohash to the 20 bytes used by trackers, the DHT and
+/// the peer wire protocol.
+#[cfg(feature = "sha256")]
+pub fn truncate_v2(hash: &[u8; sha256::DIGEST_LEN]) -> [u8; TRUNCATED_V2_LEN] {
+    InfoHash::V2(*hash).truncated()
+}
+
+#[cfg(all(test, any(feature = "sha1", feature = "sha256")))]
+const TEST_DOCUMENT: &[u8] = b"d8:announce9:http://x/4:infod6:lengthi5e4:name1:a12:piece lengthi16384e6:pieces0:ee";
+#[cfg(all(test, any(feature = "sha1", feature = "sha256")))]
+const TEST_INFO: &[u8] = b"d6:lengthi5e4:name1:a12:piece lengthi16384e6:pieces0:e";
+
+#[cfg(all(test, any(feature = "sha1", feature = "sha256")))]
+fn decode_test_document() -> Bencode {
+    let mut stream = TEST_DOCUMENT.iter().cloned().peekable();
+    ::bdecode(&mut stream).expect("failed to parse")
+}
+
+#[test]
+fn test_info_hash_hex() {
+    let text = "c12fe1c06bba254a9dc9f519b335aa7c1367a88a";
+    let hash: InfoHash = text.parse().unwrap();
+    assert!(hash.is_v1());
+    assert_eq!(hash.to_string(), text);
+    assert_eq!(text.to_uppercase().parse::<InfoHash>(), Ok(hash));
+    assert_eq!(format!("{:?}", hash), format!("InfoHash::V1({})", text));
+
+    let long = "d7c9b0b7d5b2b9a2e5c6f1a0b3e4d5c6a7b8c9d0e1f2a3b4c5d6e7f8a9b0c1d2";
+    let hash_v2: InfoHash = long.parse().unwrap();
+    assert!(hash_v2.is_v2());
+    assert_eq!(hash_v2.to_string(), long);
+    assert_eq!(&hash_v2.truncated()[..], &hash_v2.as_bytes()[..20]);
+    assert!(hash != hash_v2);
+
+    assert_eq!("abc".parse::<InfoHash>(), Err(ParseInfoHashError::InvalidLength));
+    assert_eq!("g12fe1c06bba254a9dc9f519b335aa7c1367a88a".parse::<InfoHash>(),
+               Err(ParseInfoHashError::InvalidCharacter));
+}
+
+#[test]
+fn test_info_hash_base32() {
+    let hash: InfoHash = "c12fe1c06bba254a9dc9f519b335aa7c1367a88a".parse().unwrap();
+    let encoded = "YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK";
+    assert_eq!(hash.to_base32().as_ref().map(|s| &s[..]), Some(encoded));
+    assert_eq!(InfoHash::from_base32(encoded), Ok(hash));
+    assert_eq!(encoded.to_lowercase().parse::<InfoHash>(), Ok(hash));
+
+    assert_eq!(InfoHash::V2([0; V2_LEN]).to_base32(), None);
+    assert_eq!(InfoHash::from_base32("YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKE1"),
+               Err(ParseInfoHashError::InvalidCharacter));
+    assert_eq!(InfoHash::from_base32("YEX6"),
+               Err(ParseInfoHashError::InvalidLength));
+}
+
+#[cfg(feature = "sha1")]
+#[test]
+fn test_info_hash_v1() {
+    assert_eq!(info_hash_v1(TEST_DOCUMENT), Ok(Sha1::digest(TEST_INFO)));
+    assert_eq!(info_hash_v1_from(&decode_test_document()),
+               Ok(Sha1::digest(TEST_INFO)));
+    assert_eq!(InfoHash::from(info_hash_v1(TEST_DOCUMENT).unwrap()),
+               InfoHash::V1(Sha1::digest(TEST_INFO)));
+
+    assert_eq!(info_hash_v1(b"d8:announce9:http://x/e"),
+               Err(InfoHashError::MissingInfo));
+    assert_eq!(info_hash_v1(b"d4:infoi1ee"),
+               Err(InfoHashError::MissingInfo));
+    assert_eq!(info_hash_v1(b"d4:infod"),
+               Err(InfoHashError::Parse(ParseError::Truncated)));
+
+    let deep = [&b"d4:infod1:a"[..], &vec![b'l'; 100_000], b"ee"].concat();
+    assert_eq!(info_hash_v1(&deep), Err(InfoHashError::Parse(ParseError::TooDeep)));
+}
+
+#[cfg(feature = "sha256")]
+#[test]
+fn test_info_hash_v2() {
+    let expected = Sha256::digest(TEST_INFO);
+    assert_eq!(info_hash_v2(TEST_DOCUMENT), Ok(expected));
+    assert_eq!(info_hash_v2_from(&decode_test_document()), Ok(expected));
+    assert_eq!(&truncate_v2(&expected)[..], &expected[..20]);
+
+    assert_eq!(info_hash_v2_from(&Bencode::Array(Vec::new())),
+               Err(InfoHashError::MissingInfo));
+
+    let deep = [&b"d4:infod1:a"[..], &b"d1:a".repeat(10000), b"ee"].concat();
+    assert_eq!(info_hash_v2(&deep), Err(InfoHashError::Parse(ParseError::TooDeep)));
+}
@@ -0,0 +1,359 @@
+//! Conversion between documents and JSON text.
+//!
+//! Integers become JSON numbers, digit for digit, and byte strings that
+//! are UTF-8 become JSON strings. Other byte strings become
+//! `{"$bytes": "<hex>"}`; dictionary keys that are not UTF-8, or that
+//! start with `$bytes`, become `"$bytes:<hex>"`. The mapping is lossless,
+//! so `from_json(&to_json(value))` gives `value` back. JSON without a
+//! bencode counterpart (`true`, `false`, `null` and non-integer numbers) is
+//! rejected.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::str;
+
+use {Bencode, MAX_DEPTH};
+
+const BYTES_KEY: &str = "$bytes";
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum JsonError {
+    /// Malformed JSON at this byte offset.
+    Syntax(usize),
+    /// Well-formed JSON that has no bencode equivalent.
+    Invalid(&'static str),
+}
+
+fn push_hex(out: &mut String, buf: &[u8]) {
+    for byte in buf.iter() {
+        write!(out, "{:02x}", byte).expect("writing to a String cannot fail");
+    }
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2)
+        .map(|pos| u8::from_str_radix(hex.get(pos..pos + 2)?, 16).ok())
+        .collect()
+}
+
+fn push_string(out: &mut String, text: &str) {
+    out.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => {
+                write!(out, "\\u{:04x}", ch as u32).expect("writing to a String cannot fail")
+            },
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+fn push_key(out: &mut String, key: &[u8]) {
+    match str::from_utf8(key) {
+        Ok(text) if !text.starts_with(BYTES_KEY) => push_string(out, text),
+        _ => {
+            let mut escaped = String::from(BYTES_KEY);
+            escaped.push(':');
+            push_hex(&mut escaped, key);
+            push_string(out, &escaped);
+        },
+    }
+}
+
+fn push_value(out: &mut String, value: &Bencode) {
+    match *value {
+        Bencode::Integer(ref digits) => out.push_str(&String::from_utf8_lossy(digits)),
+        Bencode::Bytes(ref buf) => match str::from_utf8(buf) {
+            Ok(text) => push_string(out, text),
+            Err(_) => {
+                out.push_str("{\"");
+                out.push_str(BYTES_KEY);
+                out.push_str("\":\"");
+                push_hex(out, buf);
+                out.push_str("\"}");
+            },
+        },
+        Bencode::Array(ref items) => {
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                push_value(out, item);
+            }
+            out.push(']');
+        },
+        Bencode::Object(ref dict) => {
+            out.push('{');
+            for (index, (key, item)) in dict.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                push_key(out, key);
+                out.push(':');
+                push_value(out, item);
+            }
+            out.push('}');
+        },
+    }
+}
+
+/// Renders `value` as compact JSON.
+pub fn to_json(value: &Bencode) -> String {
+    let mut out = String::new();
+    push_value(&mut out, value);
+    out
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+    /// Arrays and objects open around the current position.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn syntax<T>(&self) -> Result<T, JsonError> {
+        Err(JsonError::Syntax(self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.text.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.text.get(self.pos).cloned()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), JsonError> {
+        match self.peek() {
+            Some(found) if found == byte => {
+                self.pos += 1;
+                Ok(())
+            },
+            _ => self.syntax(),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self.text.get(self.pos..self.pos + 4)
+            .and_then(|digits| str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok());
+        match digits {
+            Some(value) => {
+                self.pos += 4;
+                Ok(value)
+            },
+            None => self.syntax(),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect(b'"')?;
+        let mut out = String::new();
+        loop {
+            let start = self.pos;
+            while let Some(&byte) = self.text.get(self.pos) {
+                if byte == b'"' || byte == b'\\' || byte < 0x20 {
+                    break;
+                }
+                self.pos += 1;
+            }
+            // The input is a `str` and runs stop at ASCII, so this is UTF-8.
+            out.push_str(str::from_utf8(&self.text[start..self.pos]).expect("input is UTF-8"));
+            match self.text.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                },
+                Some(b'\\') => self.pos += 1,
+                _ => return self.syntax(),
+            }
+            let escape = match self.text.get(self.pos) {
+                Some(&escape) => escape,
+                None => return self.syntax(),
+            };
+            self.pos += 1;
+            let ch = match escape {
+                b'"' => '"',
+                b'\\' => '\\',
+                b'/' => '/',
+                b'b' => '\u{8}',
+                b'f' => '\u{c}',
+                b'n' => '\n',
+                b'r' => '\r',
+                b't' => '\t',
+                b'u' => {
+                    let mut code = self.hex4()?;
+                    if (0xd800..0xdc00).contains(&code) {
+                        if self.text.get(self.pos..self.pos + 2) != Some(b"\\u") {
+                            return self.syntax();
+                        }
+                        self.pos += 2;
+                        let low = self.hex4()?;
+                        if !(0xdc00..0xe000).contains(&low) {
+                            return self.syntax();
+                        }
+                        code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                    }
+                    match ::std::char::from_u32(code) {
+                        Some(ch) => ch,
+                        None => return self.syntax(),
+                    }
+                },
+                _ => return self.syntax(),
+            };
+            out.push(ch);
+        }
+    }
+
+    fn integer(&mut self) -> Result<Bencode, JsonError> {
+        let start = self.pos;
+        if self.text.get(self.pos) == Some(&b'-') {
+            self.pos += 1;
+        }
+        let digits = self.pos;
+        while self.text.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        if self.pos == digits || (self.text[digits] == b'0' && self.pos - digits > 1) {
+            return self.syntax();
+        }
+        if let Some(b'.') | Some(b'e') | Some(b'E') = self.text.get(self.pos) {
+            return Err(JsonError::Invalid("non-integer number"));
+        }
+        if digits > start && self.text[digits] == b'0' {
+            // Bencode has no `i-0e`.
+            return Err(JsonError::Invalid("negative zero"));
+        }
+        Ok(Bencode::Integer(self.text[start..self.pos].to_vec()))
+    }
+
+    fn value(&mut self) -> Result<Bencode, JsonError> {
+        self.depth += 1;
+        let value = self.nested_value();
+        self.depth -= 1;
+        value
+    }
+
+    fn nested_value(&mut self) -> Result<Bencode, JsonError> {
+        if self.depth > MAX_DEPTH {
+            return Err(JsonError::Invalid("nesting depth"));
+        }
+        match self.peek() {
+            Some(b'"') => Ok(Bencode::from(self.string()?)),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Bencode::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Bencode::Array(items));
+                        },
+                        _ => return self.syntax(),
+                    }
+                }
+            },
+            Some(b'{') => {
+                self.pos += 1;
+                let mut dict = BTreeMap::new();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return object(dict);
+                }
+                loop {
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    let value = self.value()?;
+                    dict.insert(key, value);
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return object(dict);
+                        },
+                        _ => return self.syntax(),
+                    }
+                }
+            },
+            Some(b'-') | Some(b'0'..=b'9') => self.integer(),
+            Some(b't') | Some(b'f') | Some(b'n') => Err(JsonError::Invalid("true, false or null")),
+            _ => self.syntax(),
+        }
+    }
+}
+
+/// Turns a parsed JSON object back into a byte string or a dictionary,
+/// undoing the `$bytes` escapes.
+fn object(dict: BTreeMap<String, Bencode>) -> Result<Bencode, JsonError> {
+    if dict.len() == 1 {
+        if let Some(Bencode::Bytes(hex)) = dict.get(BYTES_KEY) {
+            return str::from_utf8(hex).ok().and_then(from_hex)
+                .map(Bencode::Bytes)
+                .ok_or(JsonError::Invalid("$bytes"));
+        }
+    }
+    let mut out = BTreeMap::new();
+    for (key, value) in dict {
+        let key = match key.strip_prefix(BYTES_KEY) {
+            Some(hex) => hex.strip_prefix(':').and_then(from_hex)
+                .ok_or(JsonError::Invalid("$bytes key"))?,
+            None => key.into_bytes(),
+        };
+        out.insert(key, value);
+    }
+    Ok(Bencode::Object(out))
+}
+
+/// Parses JSON text into a document.
+pub fn from_json(text: &str) -> Result<Bencode, JsonError> {
+    let mut parser = Parser { text: text.as_bytes(), pos: 0, depth: 0 };
+    let value = parser.value()?;
+    match parser.peek() {
+        None => Ok(value),
+        Some(_) => parser.syntax(),
+    }
+}
+
+#[test]
+fn test_json_roundtrip() {
+    let value = ::bdecode_slice(b"d7:$bytes!0:1:ali-1ei0e3:\"x\\e1:b2:\xff\x003:\xe2\x82\xaci12ee")
+        .unwrap();
+    let json = to_json(&value);
+    assert_eq!(json, "{\"$bytes:24627974657321\":\"\",\"a\":[-1,0,\"\\\"x\\\\\"],\
+                      \"b\":{\"$bytes\":\"ff00\"},\"€\":12}");
+    assert_eq!(from_json(&json), Ok(value));
+
+    let parsed = from_json(" { \"k\" : [ 1 , \"\\u00e9\\ud83d\\ude00\\n\" ] } ");
+    assert_eq!(parsed, Ok(::bdecode_slice(b"d1:kli1e7:\xc3\xa9\xf0\x9f\x98\x80\nee").unwrap()));
+    assert_eq!(from_json("[1.5]"), Err(JsonError::Invalid("non-integer number")));
+    assert_eq!(from_json("null"), Err(JsonError::Invalid("true, false or null")));
+    assert_eq!(from_json("[1,]"), Err(JsonError::Syntax(3)));
+    assert_eq!(from_json("01"), Err(JsonError::Syntax(2)));
+    assert_eq!(from_json("[-0]"), Err(JsonError::Invalid("negative zero")));
+    assert_eq!(from_json("[-10]"), Ok(::bdecode_slice(b"li-10ee").unwrap()));
+    assert_eq!(from_json("{\"$bytes\":\"f\"}"), Err(JsonError::Invalid("$bytes")));
+
+    let deep = "[".repeat(MAX_DEPTH) + &"]".repeat(MAX_DEPTH);
+    assert!(from_json(&deep).is_ok());
+    assert_eq!(from_json(&"[".repeat(100_000)), Err(JsonError::Invalid("nesting depth")));
+}
@@ -0,0 +1,187 @@
+//! An allocation-free lexer over slices, for targets without a heap.
+//!
+//! `Lexer` yields the same tokens as `stream::TokenReader`, but borrows
+//! every integer and byte string from the input and tracks nesting in two
+//! bitmasks, so it needs no memory beyond itself; documents may nest at most
+//! `LEXER_MAX_DEPTH` deep, well short of the crate-wide `MAX_DEPTH`. `lookup` builds on it to pull single fields, such as
+//! the `y` or `a.id` of a KRPC message, out of a document in static memory.
+//! Like `TokenReader`, it does not check dictionary key order.
+
+use {ParseError, is_digit};
+use raw;
+
+/// The deepest nesting a `Lexer` accepts, one bit of its masks per level.
+pub const LEXER_MAX_DEPTH: usize = 32;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Token<'a> {
+    /// The digits of an integer, with any sign.
+    Integer(&'a [u8]),
+    Bytes(&'a [u8]),
+    List,
+    Dict,
+    /// The end of the innermost list or dictionary.
+    End,
+}
+
+/// Yields the tokens of the value at the start of a slice, then `None`.
+/// An error ends the iteration.
+#[derive(Clone, Debug)]
+pub struct Lexer<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    depth: usize,
+    /// Bit `n` is set if level `n` is a dictionary.
+    dicts: u32,
+    /// Bit `n` is set if the dictionary at level `n` expects a key next.
+    keys: u32,
+    done: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(buf: &'a [u8]) -> Lexer<'a> {
+        Lexer { buf, pos: 0, depth: 0, dicts: 0, keys: 0, done: false }
+    }
+
+    /// The offset of the next unread byte.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Nesting depth after the last token.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn lex(&mut self) -> Result<Token<'a>, ParseError> {
+        let level = if self.depth > 0 { 1 << (self.depth - 1) } else { 0 };
+        let in_dict = self.dicts & level != 0;
+        let expect_key = self.keys & level != 0;
+        let val = match self.buf.get(self.pos) {
+            Some(&val) => val,
+            None => return Err(ParseError::Truncated),
+        };
+        if val == b'e' && self.depth > 0 && (!in_dict || expect_key) {
+            self.pos += 1;
+            self.depth -= 1;
+            return Ok(Token::End);
+        }
+        if expect_key && !is_digit(val) {
+            return Err(ParseError::InvalidCharacter);
+        }
+        if in_dict {
+            self.keys ^= level;
+        }
+        match val {
+            b'i' => {
+                let end = raw::scan_integer(self.buf, self.pos)?;
+                let digits = &self.buf[self.pos + 1..end - 1];
+                self.pos = end;
+                Ok(Token::Integer(digits))
+            },
+            b'l' | b'd' => {
+                if self.depth == LEXER_MAX_DEPTH {
+                    return Err(ParseError::TooDeep);
+                }
+                let level = 1 << self.depth;
+                if val == b'd' {
+                    self.dicts |= level;
+                    self.keys |= level;
+                } else {
+                    self.dicts &= !level;
+                    self.keys &= !level;
+                }
+                self.depth += 1;
+                self.pos += 1;
+                Ok(if val == b'd' { Token::Dict } else { Token::List })
+            },
+            val if is_digit(val) => {
+                let span = raw::scan_bytea(self.buf, self.pos)?;
+                self.pos = span.end;
+                Ok(Token::Bytes(&self.buf[span]))
+            },
+            _ => Err(ParseError::InvalidCharacter),
+        }
+    }
+
+    fn token(&mut self) -> Result<Token<'a>, ParseError> {
+        self.next().unwrap_or(Err(ParseError::Truncated))
+    }
+
+    /// Consumes the rest of a value whose first token was `token`.
+    fn finish_value(&mut self, token: Token<'a>) -> Result<(), ParseError> {
+        if let Token::List | Token::Dict = token {
+            let depth = self.depth - 1;
+            while self.depth > depth {
+                self.token()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Result<Token<'a>, ParseError>> {
+        if self.done {
+            return None;
+        }
+        let result = self.lex();
+        self.done = result.is_err() || self.depth == 0;
+        Some(result)
+    }
+}
+
+/// Follows `path` through nested dictionaries from the value at the start
+/// of `buf` and returns the encoded value it leads to, or `None` if a key
+/// is missing or a value on the way is not a dictionary. Only the part of
+/// the document before the value is checked.
+pub fn lookup<'a>(buf: &'a [u8], path: &[&[u8]]) -> Result<Option<&'a [u8]>, ParseError> {
+    let mut lexer = Lexer::new(buf);
+    for key in path.iter() {
+        let token = lexer.token()?;
+        if token != Token::Dict {
+            return Ok(None);
+        }
+        loop {
+            match lexer.token()? {
+                Token::End => return Ok(None),
+                Token::Bytes(probe) if probe == *key => break,
+                _ => {
+                    let value = lexer.token()?;
+                    lexer.finish_value(value)?;
+                },
+            }
+        }
+    }
+    let start = lexer.position();
+    let token = lexer.token()?;
+    lexer.finish_value(token)?;
+    Ok(Some(&buf[start..lexer.position()]))
+}
+
+#[test]
+fn test_lexer() {
+    let query = b"d1:ad2:id20:abcdefghij01234567896:target20:mnopqrstuvwxyz123456e\
+                  1:q9:find_node1:t2:aa1:y1:qe";
+    let tokens: Result<Vec<Token>, ParseError> = Lexer::new(b"d1:ali-3ee1:b0:e").collect();
+    assert_eq!(tokens, Ok(vec![
+        Token::Dict, Token::Bytes(b"a"), Token::List, Token::Integer(b"-3"), Token::End,
+        Token::Bytes(b"b"), Token::Bytes(b""), Token::End,
+    ]));
+    assert_eq!(lookup(query, &[b"y"]), Ok(Some(&b"1:q"[..])));
+    assert_eq!(lookup(query, &[b"a", b"id"]), Ok(Some(&b"20:abcdefghij0123456789"[..])));
+    assert_eq!(lookup(query, &[b"a"]).unwrap().map(|a| a.len()), Some(60));
+    assert_eq!(lookup(query, &[b"r", b"id"]), Ok(None));
+    assert_eq!(lookup(query, &[b"q", b"id"]), Ok(None));
+
+    let mut lexer = Lexer::new(b"i1ei2e");
+    assert_eq!(lexer.next(), Some(Ok(Token::Integer(b"1"))));
+    assert_eq!((lexer.next(), lexer.position()), (None, 3));
+    assert_eq!(Lexer::new(b"di1e0:e").last(), Some(Err(ParseError::InvalidCharacter)));
+    assert_eq!(Lexer::new(b"d1:ae").last(), Some(Err(ParseError::InvalidCharacter)));
+    assert_eq!(Lexer::new(b"l").last(), Some(Err(ParseError::Truncated)));
+    let deep = [b'l'; LEXER_MAX_DEPTH + 1];
+    assert_eq!(Lexer::new(&deep).last(), Some(Err(ParseError::TooDeep)));
+}
@@ -1,9 +1,126 @@
+//! Without the default `std` feature the crate is `no_std` and needs only
+//! `alloc`: `bdecode`, `bdecode_slice`, `bdecode_chunks`, the validators,
+//! `bencode_to_vec` and the allocation-free `lexer` remain, and everything
+//! built on `std::io` or the protocol modules is left out.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate core;
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::iter::Peekable;
+use core::ops::Index;
+#[cfg(feature = "std")]
 use std::io::{self, Write};
-use std::iter::Peekable;
-use std::collections::BTreeMap;
 
-
-#[derive(PartialEq, Eq, Debug)]
+mod raw;
+mod scan;
+#[cfg(feature = "sha1")]
+pub mod sha1;
+#[cfg(feature = "sha256")]
+pub mod sha256;
+#[cfg(feature = "ed25519")]
+mod sha512;
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
+#[cfg(feature = "std")]
+pub mod borrowed;
+#[cfg(feature = "sha1")]
+pub mod builder;
+#[cfg(feature = "std")]
+pub mod canonical;
+#[cfg(feature = "std")]
+pub mod decoder;
+#[cfg(feature = "std")]
+pub mod detached;
+#[cfg(feature = "std")]
+pub mod dht;
+#[cfg(feature = "std")]
+pub mod digest;
+#[cfg(feature = "ed25519")]
+pub mod ed25519;
+#[cfg(feature = "std")]
+pub mod edit;
+#[cfg(feature = "std")]
+pub mod extension;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+mod fields;
+#[cfg(feature = "std")]
+pub mod file_map;
+#[cfg(feature = "std")]
+pub mod file_tree;
+#[cfg(feature = "std")]
+pub mod flat;
+#[cfg(feature = "sha1")]
+pub mod hash_tree;
+#[cfg(feature = "std")]
+pub mod hexdump;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "std")]
+pub mod infohash;
+#[cfg(feature = "std")]
+pub mod json;
+pub mod lexer;
+#[cfg(feature = "std")]
+pub mod magnet;
+#[cfg(feature = "sha256")]
+pub mod merkle;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "std")]
+pub mod options;
+#[cfg(feature = "std")]
+pub mod patch;
+#[cfg(feature = "std")]
+pub mod peer_id;
+#[cfg(feature = "std")]
+pub mod percent;
+#[cfg(feature = "std")]
+pub mod pretty;
+#[cfg(feature = "property")]
+pub mod property;
+#[cfg(feature = "std")]
+mod random;
+#[cfg(feature = "std")]
+pub mod resume;
+#[cfg(feature = "std")]
+pub mod signature;
+#[cfg(feature = "std")]
+pub mod stream;
+#[cfg(feature = "std")]
+pub mod tape;
+#[cfg(feature = "std")]
+pub mod template;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+#[cfg(feature = "testvectors")]
+pub mod testvectors;
+#[cfg(feature = "std")]
+pub mod torrent;
+#[cfg(feature = "trace")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod tracker;
+#[cfg(feature = "url")]
+pub mod tracker_url;
+#[cfg(feature = "std")]
+pub mod ut_metadata;
+#[cfg(feature = "std")]
+pub mod validate;
+#[cfg(feature = "sha1")]
+pub mod verify;
+#[cfg(feature = "zeroize")]
+pub mod zeroize;
+
+
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Bencode {
     Integer(Vec<u8>),
     Bytes(Vec<u8>),
@@ -11,17 +128,131 @@ pub enum Bencode {
     Object(BTreeMap<Vec<u8>, Bencode>),
 }
 
+impl Bencode {
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Bencode::Integer(ref buf) => core::str::from_utf8(buf).ok()?.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Bencode::Integer(ref buf) => core::str::from_utf8(buf).ok()?.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Moves the value out, leaving an empty byte string, which costs no
+    /// allocation, in its place.
+    pub fn take(&mut self) -> Bencode {
+        core::mem::replace(self, Bencode::Bytes(Vec::new()))
+    }
+
+    /// Puts `value` in place of this one and returns the old value.
+    pub fn replace(&mut self, value: Bencode) -> Bencode {
+        core::mem::replace(self, value)
+    }
+
+    /// Whether this is the sentinel that indexing returns for a missing
+    /// key or item, as opposed to a value in a document.
+    pub fn is_missing(&self) -> bool {
+        core::ptr::eq(self, &MISSING)
+    }
+}
+
+/// What `document["key"]` and `document[index]` return when there is no
+/// such entry: an empty byte string, so accessors see an ordinary value of
+/// the wrong kind and return `None`, and further indexing stays missing.
+static MISSING: Bencode = Bencode::Bytes(Vec::new());
+
+impl<'a> Index<&'a str> for Bencode {
+    type Output = Bencode;
+
+    /// Looks a key up in a dictionary, giving the missing sentinel if the
+    /// key is absent or this is not a dictionary.
+    fn index(&self, key: &'a str) -> &Bencode {
+        match *self {
+            Bencode::Object(ref dict) => dict.get(key.as_bytes()).unwrap_or(&MISSING),
+            _ => &MISSING,
+        }
+    }
+}
+
+impl Index<usize> for Bencode {
+    type Output = Bencode;
+
+    /// Gets an item of a list, giving the missing sentinel if it is out of
+    /// range or this is not a list.
+    fn index(&self, index: usize) -> &Bencode {
+        match *self {
+            Bencode::Array(ref items) => items.get(index).unwrap_or(&MISSING),
+            _ => &MISSING,
+        }
+    }
+}
+
+impl From<i64> for Bencode {
+    fn from(value: i64) -> Bencode {
+        Bencode::Integer(value.to_string().into_bytes())
+    }
+}
+
+impl From<u64> for Bencode {
+    fn from(value: u64) -> Bencode {
+        Bencode::Integer(value.to_string().into_bytes())
+    }
+}
+
+impl From<Vec<u8>> for Bencode {
+    fn from(value: Vec<u8>) -> Bencode {
+        Bencode::Bytes(value)
+    }
+}
+
+impl<'a> From<&'a [u8]> for Bencode {
+    fn from(value: &'a [u8]) -> Bencode {
+        Bencode::Bytes(value.to_vec())
+    }
+}
+
+impl From<String> for Bencode {
+    fn from(value: String) -> Bencode {
+        Bencode::Bytes(value.into_bytes())
+    }
+}
+
+impl<'a> From<&'a str> for Bencode {
+    fn from(value: &'a str) -> Bencode {
+        Bencode::Bytes(value.as_bytes().to_vec())
+    }
+}
+
 type BencodeResult<T> = Result<T, ParseError>;
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum ParseError {
     Truncated,
     InvalidCharacter,
     InvalidLength,
     OutOfOrderKey,
+    /// Reported by `validate_canonical` for well-formed input that is not
+    /// in canonical form.
+    NonCanonical,
+    /// Reported for values nested deeper than `MAX_DEPTH`, or than
+    /// `lexer::Lexer` or `DecodeOptions` allow.
+    TooDeep,
+    /// Reported by `DecodeOptions` for documents over its size limit.
+    TooLarge,
 }
 
+/// Deepest nesting that any decoder accepts, where a bare integer or
+/// string has depth 1. Far deeper than real documents go, and shallow
+/// enough that a hostile one cannot exhaust the stack.
+pub const MAX_DEPTH: usize = 256;
+
 fn is_digit(val: u8) -> bool {
-    b'0' <= val && val <= b'9'
+    val.is_ascii_digit()
 }
 
 fn bdecode_extract_integer<I>(stream: &mut Peekable<I>)
@@ -44,7 +275,7 @@ fn bdecode_integer<I>(stream: &mut Peekable<I>) -> BencodeResult<Vec<u8>>
         I: Iterator<Item=u8> {
 
     let output = match stream.next() {
-        Some(b'i') => try!(bdecode_extract_integer(stream)),
+        Some(b'i') => bdecode_extract_integer(stream)?,
         Some(_) => return Err(ParseError::InvalidCharacter),
         None => return Err(ParseError::Truncated)
     };
@@ -55,28 +286,51 @@ fn bdecode_integer<I>(stream: &mut Peekable<I>) -> BencodeResult<Vec<u8>>
     }
 }
 
-fn bdecode_bytea<I>(stream: &mut Peekable<I>) -> BencodeResult<Vec<u8>>
+/// Adds `digit` to the decimal number `value`, failing on overflow.
+fn push_digit(value: usize, digit: u8) -> BencodeResult<usize> {
+    value.checked_mul(10)
+        .and_then(|value| value.checked_add(usize::from(digit - b'0')))
+        .ok_or(ParseError::InvalidLength)
+}
+
+/// Reads a byte string length prefix, accumulating its value as the digits
+/// are consumed.
+fn bdecode_length<I>(stream: &mut Peekable<I>) -> BencodeResult<usize>
     where
         I: Iterator<Item=u8> {
 
-    let intbuf = try!(bdecode_extract_integer(stream));
-    let length = std::str::from_utf8(&intbuf[..]).ok()
-        .expect("bdecode_extract_integer failed to hold invariant")
-        .parse::<usize>();
+    let mut length = None;
+    loop {
+        match stream.peek() {
+            Some(&val) if is_digit(val) => {
+                stream.next();
+                length = Some(push_digit(length.unwrap_or(0), val)?);
+            },
+            Some(_) => return length.ok_or(ParseError::InvalidLength),
+            None => return Err(ParseError::Truncated),
+        }
+    }
+}
 
-    let length = match length {
-        Ok(value) => value,
-        Err(_) => return Err(ParseError::InvalidLength),
-    };
+fn bdecode_bytea<I>(stream: &mut Peekable<I>) -> BencodeResult<Vec<u8>>
+    where
+        I: Iterator<Item=u8> {
 
+    let length = bdecode_length(stream)?;
     match stream.next() {
-        Some(b':') => Ok(stream.take(length).collect()),
-        Some(_) => return Err(ParseError::InvalidCharacter),
-        None => return Err(ParseError::Truncated)
+        Some(b':') => {
+            let bytea: Vec<u8> = stream.take(length).collect();
+            if bytea.len() < length {
+                return Err(ParseError::Truncated);
+            }
+            Ok(bytea)
+        },
+        Some(_) => Err(ParseError::InvalidCharacter),
+        None => Err(ParseError::Truncated)
     }
 }
 
-fn bdecode_list<I>(stream: &mut Peekable<I>) -> BencodeResult<Vec<Bencode>>
+fn bdecode_list<I>(stream: &mut Peekable<I>, depth: usize) -> BencodeResult<Vec<Bencode>>
     where
         I: Iterator<Item=u8> {
 
@@ -89,13 +343,13 @@ fn bdecode_list<I>(stream: &mut Peekable<I>) -> BencodeResult<Vec<Bencode>>
                 stream.next().expect("expected b'e'");
                 return Ok(output);
             },
-            Some(_) => output.push(try!(bdecode(stream))),
+            Some(_) => output.push(bdecode_value(stream, depth + 1)?),
             None => return Err(ParseError::Truncated)
         }
     }
 }
 
-fn bdecode_dict<I>(stream: &mut Peekable<I>)
+fn bdecode_dict<I>(stream: &mut Peekable<I>, depth: usize)
     -> BencodeResult<BTreeMap<Vec<u8>, Bencode>>
     where
         I: Iterator<Item=u8> {
@@ -108,82 +362,258 @@ fn bdecode_dict<I>(stream: &mut Peekable<I>)
 
     loop {
         match stream.peek() {
-            Some(&b'e') => return Ok(output),
+            Some(&b'e') => {
+                stream.next().expect("expected b'e'");
+                return Ok(output);
+            },
             Some(_) => (),
             None => return Err(ParseError::Truncated),
         }
-        let key = try!(bdecode_bytea(stream));
+        let key = bdecode_bytea(stream)?;
         if key < prev_key {
             return Err(ParseError::OutOfOrderKey);
         }
         prev_key.clear();
         prev_key.extend(key.iter().cloned());
 
-        let value = try!(bdecode(stream));
+        let value = bdecode_value(stream, depth + 1)?;
         output.insert(key, value);
     }
 }
 
 
-fn iter_bdecode<I>(stream: &mut Peekable<I>) -> Result<Bencode, ParseError>
+pub fn bdecode<I>(stream: &mut Peekable<I>) -> Result<Bencode, ParseError>
+    where
+        I: Iterator<Item=u8> {
+
+    #[cfg(any(feature = "metrics", feature = "trace"))]
+    let start = std::time::Instant::now();
+    let result = bdecode_value(stream, 1);
+    #[cfg(feature = "metrics")]
+    metrics::record(start, &result);
+    #[cfg(feature = "trace")]
+    trace::decoded("bdecode", None, start, &result);
+    result
+}
+
+/// Decodes the value at the front of `stream`, nested at `depth`.
+fn bdecode_value<I>(stream: &mut Peekable<I>, depth: usize) -> Result<Bencode, ParseError>
     where
         I: Iterator<Item=u8> {
 
     use Bencode::{Integer, Array, Object, Bytes};
+    raw::check_depth(depth)?;
     match stream.peek() {
-        Some(&b'i') => Ok(Integer(try!(bdecode_integer(stream)))),
-        Some(&b'l') => Ok(Array(try!(bdecode_list(stream)))),
-        Some(&b'd') => Ok(Object(try!(bdecode_dict(stream)))),
-        Some(&val) if is_digit(val) => Ok(Bytes(try!(bdecode_bytea(stream)))),
+        Some(&b'i') => Ok(Integer(bdecode_integer(stream)?)),
+        Some(&b'l') => Ok(Array(bdecode_list(stream, depth)?)),
+        Some(&b'd') => Ok(Object(bdecode_dict(stream, depth)?)),
+        Some(&val) if is_digit(val) => Ok(Bytes(bdecode_bytea(stream)?)),
         _ => Err(ParseError::InvalidCharacter),
     }
 }
 
+/// Decodes the value at the start of `buf`. Unlike `bdecode`, this can
+/// search a slice for delimiters in bulk rather than a byte at a time, so
+/// it is the faster choice when the whole document is in memory. Negative
+/// integers are accepted.
+pub fn bdecode_slice(buf: &[u8]) -> Result<Bencode, ParseError> {
+    #[cfg(any(feature = "metrics", feature = "trace"))]
+    let start = std::time::Instant::now();
+    let result = raw::decode_value(buf, 0, 1).map(|(value, _)| value);
+    #[cfg(feature = "metrics")]
+    metrics::record(start, &result);
+    #[cfg(feature = "trace")]
+    trace::decoded("bdecode_slice", Some(buf), start, &result);
+    result
+}
+
+/// Decodes a value spread across non-contiguous buffers, such as the
+/// `IoSlice`s of a vectored read (`slices.iter().map(|s| &s[..])`), without
+/// joining them first. Returns the value and the number of bytes it took,
+/// so whatever follows it can be kept for the next decode. As with
+/// `bdecode_slice`, values nested deeper than `MAX_DEPTH` are `TooDeep`.
+pub fn bdecode_chunks<'a, C>(chunks: C) -> Result<(Bencode, usize), ParseError>
+    where
+        C: IntoIterator<Item=&'a [u8]> {
+
+    let consumed = core::cell::Cell::new(0);
+    let mut stream = chunks.into_iter()
+        .flat_map(|chunk| chunk.iter().cloned())
+        .inspect(|_| consumed.set(consumed.get() + 1))
+        .peekable();
+    let value = bdecode(&mut stream)?;
+    // Every value ends on a consumed byte, so nothing past it was read.
+    Ok((value, consumed.get()))
+}
+
+/// Decodes many independent documents with `bdecode_slice`, spread over
+/// one scoped thread per available core. Results are in input order.
+/// Enabled by the `parallel` feature.
+#[cfg(feature = "parallel")]
+pub fn decode_batch<T>(documents: &[T]) -> Vec<Result<Bencode, ParseError>>
+    where
+        T: AsRef<[u8]> + Sync {
+
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let chunk_len = std::cmp::max(1, documents.len().div_ceil(threads));
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = documents.chunks(chunk_len).map(|chunk| {
+            scope.spawn(move || {
+                chunk.iter().map(|document| bdecode_slice(document.as_ref())).collect::<Vec<_>>()
+            })
+        }).collect();
+        workers.into_iter()
+            .flat_map(|worker| worker.join().expect("decoding does not panic"))
+            .collect()
+    })
+}
+
+/// Checks that `buf` holds exactly one well-formed value, nested no deeper
+/// than `MAX_DEPTH`, without building it or allocating.
+pub fn validate(buf: &[u8]) -> Result<(), ParseError> {
+    match raw::skip_value(buf, 0, 1)? {
+        end if end == buf.len() => Ok(()),
+        _ => Err(ParseError::InvalidCharacter),
+    }
+}
+
+/// Like `validate`, but also requires the canonical encoding: no leading
+/// zeros, no `-0` and strictly increasing dictionary keys.
+pub fn validate_canonical(buf: &[u8]) -> Result<(), ParseError> {
+    match raw::skip_canonical(buf, 0, 1)? {
+        end if end == buf.len() => Ok(()),
+        _ => Err(ParseError::InvalidCharacter),
+    }
+}
+
+#[cfg(feature = "std")]
 fn bencode_bytea<W>(bytea: &[u8], writer: &mut W) -> Result<(), io::Error>
     where
         W: Write {
 
-    try!(write!(writer, "{}:", bytea.len()));
-    try!(writer.write_all(bytea));
+    write!(writer, "{}:", bytea.len())?;
+    writer.write_all(bytea)?;
     Ok(())
 }
 
+#[cfg(feature = "std")]
 pub fn bencode<W>(document: &Bencode, writer: &mut W) -> Result<(), io::Error>
     where
         W: Write {
 
-    match document {
-        &Bencode::Integer(ref buf) => {
-            try!(writer.write_all(b"i"));
-            try!(writer.write_all(buf));
-            try!(writer.write_all(b"e"));
+    #[cfg(feature = "trace")]
+    let start = std::time::Instant::now();
+    let result = bencode_value(document, writer);
+    #[cfg(feature = "trace")]
+    trace::encoded("bencode", start, document, result.is_err());
+    result
+}
+
+#[cfg(feature = "std")]
+fn bencode_value<W>(document: &Bencode, writer: &mut W) -> Result<(), io::Error>
+    where
+        W: Write {
+
+    match *document {
+        Bencode::Integer(ref buf) => {
+            writer.write_all(b"i")?;
+            writer.write_all(buf)?;
+            writer.write_all(b"e")?;
         },
-        &Bencode::Bytes(ref buf) => try!(bencode_bytea(buf, writer)),
-        &Bencode::Array(ref items) => {
-            try!(writer.write_all(b"l"));
+        Bencode::Bytes(ref buf) => bencode_bytea(buf, writer)?,
+        Bencode::Array(ref items) => {
+            writer.write_all(b"l")?;
             for item in items.iter() {
-                try!(bencode(item, writer));
+                bencode_value(item, writer)?;
             }
-            try!(writer.write_all(b"e"));
+            writer.write_all(b"e")?;
         },
-        &Bencode::Object(ref map) => {
-            try!(writer.write_all(b"d"));
+        Bencode::Object(ref map) => {
+            writer.write_all(b"d")?;
             for (key, value) in map.iter() {
-                try!(bencode_bytea(key, writer));
-                try!(bencode(value, writer));
+                bencode_bytea(key, writer)?;
+                bencode_value(value, writer)?;
             }
-            try!(writer.write_all(b"e"));
+            writer.write_all(b"e")?;
         },
     };
     Ok(())
 }
 
+/// Returns `(encoded length, nodes, depth)` of `value`.
+#[cfg(any(feature = "metrics", feature = "trace"))]
+fn measure(value: &Bencode) -> (u64, u64, u64) {
+    fn bytea_len(len: usize) -> u64 {
+        (len.to_string().len() + 1 + len) as u64
+    }
+    match *value {
+        Bencode::Integer(ref digits) => (digits.len() as u64 + 2, 1, 1),
+        Bencode::Bytes(ref buf) => (bytea_len(buf.len()), 1, 1),
+        Bencode::Array(ref items) => {
+            items.iter().map(measure).fold((2, 1, 1), |(bytes, nodes, depth), item| {
+                (bytes + item.0, nodes + item.1, core::cmp::max(depth, item.2 + 1))
+            })
+        },
+        Bencode::Object(ref dict) => {
+            dict.iter().fold((2, 1, 1), |(bytes, nodes, depth), (key, value)| {
+                let value = measure(value);
+                (bytes + bytea_len(key.len()) + value.0, nodes + 1 + value.1,
+                 core::cmp::max(depth, value.2 + 1))
+            })
+        },
+    }
+}
+
+/// Encodes `document` into a new buffer. Unlike `bencode`, this is
+/// available without `std`.
+pub fn bencode_to_vec(document: &Bencode) -> Vec<u8> {
+    #[cfg(feature = "trace")]
+    let start = std::time::Instant::now();
+    let mut output = Vec::new();
+    bencode_into(document, &mut output);
+    #[cfg(feature = "trace")]
+    trace::encoded("bencode_to_vec", start, document, false);
+    output
+}
+
+fn bencode_into(document: &Bencode, output: &mut Vec<u8>) {
+    fn push_bytea(bytea: &[u8], output: &mut Vec<u8>) {
+        output.extend_from_slice(bytea.len().to_string().as_bytes());
+        output.push(b':');
+        output.extend_from_slice(bytea);
+    }
+
+    match *document {
+        Bencode::Integer(ref buf) => {
+            output.push(b'i');
+            output.extend_from_slice(buf);
+            output.push(b'e');
+        },
+        Bencode::Bytes(ref buf) => push_bytea(buf, output),
+        Bencode::Array(ref items) => {
+            output.push(b'l');
+            for item in items.iter() {
+                bencode_into(item, output);
+            }
+            output.push(b'e');
+        },
+        Bencode::Object(ref map) => {
+            output.push(b'd');
+            for (key, value) in map.iter() {
+                push_bytea(key, output);
+                bencode_into(value, output);
+            }
+            output.push(b'e');
+        },
+    }
+}
+
 #[test]
 fn it_works() {
     let document = b"d1:a3:eh?1:bl3:beeee";
 
     let mut peekable = document.iter().cloned().peekable();
-    let result = bdecode(&mut peekable).ok().expect("failed to parse");
+    let result = bdecode(&mut peekable).expect("failed to parse");
 
     let obj = match result {
         Bencode::Object(ref obj) => obj,
@@ -201,6 +631,122 @@ fn it_works() {
         ])));
 
     let mut reserialized = Vec::new();
-    bencode(&result, &mut reserialized).ok().expect("failed to serialize");
+    bencode(&result, &mut reserialized).expect("failed to serialize");
     assert_eq!(document, &reserialized[..]);
 }
+
+#[test]
+fn test_bdecode_slice() {
+    let document = b"d1:a3:eh?1:bli-3ei12ee1:cd0:0:ee";
+    let value = bdecode_slice(document).unwrap();
+    let mut reserialized = Vec::new();
+    bencode(&value, &mut reserialized).unwrap();
+    assert_eq!(&reserialized[..], &document[..]);
+    assert_eq!(bencode_to_vec(&value), reserialized);
+    assert_eq!(bdecode_slice(b"li1e3:ab"), Err(ParseError::Truncated));
+    assert_eq!(bdecode_slice(b"i1x2e"), Err(ParseError::InvalidCharacter));
+    assert_eq!(bdecode_slice(b"d1:b0:1:a0:e"), Err(ParseError::OutOfOrderKey));
+}
+
+#[test]
+fn test_depth_limit() {
+    let decode = |buf: &[u8]| bdecode(&mut buf.iter().cloned().peekable());
+    let deep = [&[b'l'; MAX_DEPTH][..], &[b'e'; MAX_DEPTH][..]].concat();
+    assert_eq!(decode(&deep), bdecode_slice(&deep));
+    assert!(decode(&deep).is_ok());
+    let deeper = vec![b'l'; 1_000_000];
+    assert_eq!(decode(&deeper), Err(ParseError::TooDeep));
+    assert_eq!(decode(&b"d1:a".repeat(10000)), Err(ParseError::TooDeep));
+    assert_eq!(bdecode_slice(&deeper), Err(ParseError::TooDeep));
+}
+
+#[test]
+fn test_length_prefix() {
+    let decode = |buf: &[u8]| bdecode(&mut buf.iter().cloned().peekable());
+    assert_eq!(decode(b"3:abc"), Ok(Bencode::from("abc")));
+    assert_eq!(decode(b"0:"), Ok(Bencode::from("")));
+    assert_eq!(decode(b"99999999999999999999999:"), Err(ParseError::InvalidLength));
+    assert_eq!(decode(b"d:e"), Err(ParseError::InvalidLength));
+    assert_eq!(decode(b"12"), Err(ParseError::Truncated));
+
+    assert_eq!(bdecode_slice(b"3:abc"), Ok(Bencode::from("abc")));
+    assert_eq!(bdecode_slice(b"99999999999999999999999:"), Err(ParseError::InvalidLength));
+}
+
+#[test]
+fn test_validate() {
+    assert_eq!(validate(b"d1:ali-1ei0ee1:b0:e"), Ok(()));
+    assert_eq!(validate(b"i1ei2e"), Err(ParseError::InvalidCharacter));
+    assert_eq!(validate(b"l1:a"), Err(ParseError::Truncated));
+    assert_eq!(validate(b"i03e"), Ok(()));
+
+    assert_eq!(validate_canonical(b"d1:ali-1ei0ee1:b0:e"), Ok(()));
+    for bad in [&b"i03e"[..], b"i-0e", b"02:ab", b"d1:a0:1:a0:e"].iter() {
+        assert_eq!(validate(bad), Ok(()));
+        assert_eq!(validate_canonical(bad), Err(ParseError::NonCanonical));
+    }
+    assert_eq!(validate_canonical(b"d1:b0:1:a0:e"), Err(ParseError::OutOfOrderKey));
+    assert_eq!(validate_canonical(b"di1e0:e"), Err(ParseError::InvalidCharacter));
+}
+
+#[test]
+fn test_bdecode_chunks() {
+    let chunks: [&[u8]; 4] = [b"d1:a", b"", b"li1ei", b"2ee1:b3:xyze4:next"];
+    let (value, consumed) = bdecode_chunks(chunks.iter().cloned()).unwrap();
+    assert_eq!(consumed, 21);
+    assert_eq!(Ok(value), bdecode_slice(b"d1:ali1ei2ee1:b3:xyze"));
+
+    let slices = [io::IoSlice::new(b"l3:ab"), io::IoSlice::new(b"ce")];
+    assert_eq!(bdecode_chunks(slices.iter().map(|s| &s[..])),
+               Ok((Bencode::Array(vec![Bencode::from("abc")]), 7)));
+    assert_eq!(bdecode_chunks(vec![&b"li1e"[..]]), Err(ParseError::Truncated));
+    assert_eq!(bdecode_chunks(vec![&b"l5:"[..], b"ab"]), Err(ParseError::Truncated));
+    assert_eq!(bdecode_chunks(vec![&b"5:ab"[..]]), Err(ParseError::Truncated));
+
+    let deep = vec![b'l'; 100_000];
+    assert_eq!(bdecode_chunks(deep.chunks(1000)), Err(ParseError::TooDeep));
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_decode_batch() {
+    let documents: Vec<Vec<u8>> = (0..100).map(|i| format!("li{}ee", i).into_bytes())
+        .chain(Some(b"l".to_vec()))
+        .collect();
+    let results = decode_batch(&documents);
+    assert_eq!(results.len(), 101);
+    for (i, result) in results[..100].iter().enumerate() {
+        assert_eq!(*result, Ok(Bencode::Array(vec![Bencode::from(i as i64)])));
+    }
+    assert_eq!(results[100], Err(ParseError::Truncated));
+    assert!(decode_batch::<&[u8]>(&[]).is_empty());
+}
+
+#[test]
+fn test_index() {
+    let document = bdecode_slice(b"d4:infod5:filesld6:lengthi42eeee1:xi0ee").unwrap();
+    assert_eq!(document["info"]["files"][0]["length"].as_i64(), Some(42));
+    assert!(!document["x"].is_missing());
+
+    for missing in [&document["nope"]["files"], &document["info"]["files"][1],
+                    &document["x"][0], &document["info"]["files"]["0"]].iter() {
+        assert!(missing.is_missing());
+        assert_eq!(missing["length"].as_i64(), None);
+    }
+    // An empty string in the document is not the sentinel.
+    assert!(!bdecode_slice(b"l0:e").unwrap()[0].is_missing());
+}
+
+#[test]
+fn test_take_and_replace() {
+    let mut document = bdecode_slice(b"d4:infod4:name1:ae1:xi1ee").unwrap();
+    let info = match document {
+        Bencode::Object(ref mut dict) => dict.get_mut(&b"info"[..]).unwrap().take(),
+        _ => unreachable!(),
+    };
+    assert_eq!(Ok(info), bdecode_slice(b"d4:name1:ae"));
+    assert_eq!(Ok(document.clone()), bdecode_slice(b"d4:info0:1:xi1ee"));
+
+    assert_eq!(document.replace(Bencode::from(7i64)), bdecode_slice(b"d4:info0:1:xi1ee").unwrap());
+    assert_eq!(document.as_i64(), Some(7));
+}
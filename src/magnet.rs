@@ -0,0 +1,536 @@
+//! Magnet URIs (BEP 9, with BEP 52 `btmh` hashes).
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use infohash::{self, InfoHash, ParseInfoHashError};
+use percent::{self, PercentDecodeError};
+use raw;
+use torrent::{AnnounceList, Dict, Info, Metainfo, MetainfoResult};
+#[cfg(any(feature = "sha1", feature = "sha256"))]
+use torrent::MetainfoError;
+#[cfg(any(feature = "sha1", feature = "sha256"))]
+use ut_metadata;
+
+/// Multihash prefix for a 32-byte SHA-256 digest.
+const SHA256_MULTIHASH_PREFIX: &str = "1220";
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ParseMagnetError {
+    /// The URI does not start with `magnet:?`.
+    InvalidScheme,
+    /// A query parameter has no `=`.
+    MalformedParameter,
+    InvalidEscape,
+    /// A decoded value is not valid UTF-8.
+    InvalidUtf8,
+    InvalidInfoHash(ParseInfoHashError),
+    /// No `urn:btih:` or `urn:btmh:` exact topic was present.
+    MissingInfoHash,
+    /// The BEP 53 `so` parameter is not a list of indices and ranges.
+    InvalidSelectOnly,
+    /// An `x.pe` value is not an `ip:port` or `[ipv6]:port` address.
+    InvalidPeerAddress,
+}
+
+impl From<PercentDecodeError> for ParseMagnetError {
+    fn from(_: PercentDecodeError) -> ParseMagnetError {
+        ParseMagnetError::InvalidEscape
+    }
+}
+
+impl From<ParseInfoHashError> for ParseMagnetError {
+    fn from(err: ParseInfoHashError) -> ParseMagnetError {
+        ParseMagnetError::InvalidInfoHash(err)
+    }
+}
+
+/// A set of file indices, as selected by the BEP 53 `so` parameter.
+///
+/// Stored as sorted, non-overlapping inclusive ranges so that wide ranges
+/// like `0-100000` stay cheap.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct FileIndices {
+    ranges: Vec<RangeInclusive<usize>>,
+}
+
+impl FileIndices {
+    pub fn new() -> FileIndices {
+        FileIndices::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.ranges.iter().any(|range| range.contains(&index))
+    }
+
+    pub fn insert(&mut self, index: usize) {
+        self.insert_range(index..=index);
+    }
+
+    pub fn insert_range(&mut self, range: RangeInclusive<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let (mut start, mut end) = range.into_inner();
+        // Merge with any overlapping or adjacent ranges.
+        self.ranges.retain(|existing| {
+            let touches = *existing.start() <= end.saturating_add(1)
+                && start <= existing.end().saturating_add(1);
+            if touches {
+                start = ::std::cmp::min(start, *existing.start());
+                end = ::std::cmp::max(end, *existing.end());
+            }
+            !touches
+        });
+        let pos = self.ranges.iter()
+            .position(|existing| *existing.start() > end)
+            .unwrap_or(self.ranges.len());
+        self.ranges.insert(pos, start..=end);
+    }
+
+    pub fn ranges(&self) -> &[RangeInclusive<usize>] {
+        &self.ranges
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=usize> + '_ {
+        self.ranges.iter().flat_map(|range| range.clone())
+    }
+}
+
+/// Formats as the `so` value, e.g. `0,2,4-7`.
+impl fmt::Display for FileIndices {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, range) in self.ranges.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            if range.start() == range.end() {
+                write!(f, "{}", range.start())?;
+            } else {
+                write!(f, "{}-{}", range.start(), range.end())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for FileIndices {
+    type Err = ParseMagnetError;
+
+    fn from_str(s: &str) -> Result<FileIndices, ParseMagnetError> {
+        fn index(s: &str) -> Result<usize, ParseMagnetError> {
+            // `usize::from_str` would also accept a leading `+`.
+            if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(ParseMagnetError::InvalidSelectOnly);
+            }
+            s.parse().map_err(|_| ParseMagnetError::InvalidSelectOnly)
+        }
+
+        let mut indices = FileIndices::new();
+        for item in s.split(',') {
+            match item.find('-') {
+                Some(idx) => {
+                    let (start, end) = (index(&item[..idx])?, index(&item[idx + 1..])?);
+                    if start > end {
+                        return Err(ParseMagnetError::InvalidSelectOnly);
+                    }
+                    indices.insert_range(start..=end);
+                },
+                None => indices.insert(index(item)?),
+            }
+        }
+        Ok(indices)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Magnet {
+    pub info_hashes: Vec<InfoHash>,
+    pub display_name: Option<String>,
+    pub trackers: Vec<String>,
+    pub web_seeds: Vec<String>,
+    /// Files to download, from the BEP 53 `so` parameter.
+    pub select_only: Option<FileIndices>,
+    /// Bootstrap peers, from `x.pe` parameters.
+    pub peers: Vec<SocketAddr>,
+}
+
+impl Magnet {
+    pub fn new(info_hash: InfoHash) -> Magnet {
+        Magnet {
+            info_hashes: vec![info_hash],
+            ..Magnet::default()
+        }
+    }
+
+    pub fn with_display_name<S: Into<String>>(mut self, name: S) -> Magnet {
+        self.display_name = Some(name.into());
+        self
+    }
+
+    pub fn with_tracker<S: Into<String>>(mut self, tracker: S) -> Magnet {
+        self.trackers.push(tracker.into());
+        self
+    }
+
+    pub fn with_web_seed<S: Into<String>>(mut self, web_seed: S) -> Magnet {
+        self.web_seeds.push(web_seed.into());
+        self
+    }
+
+    pub fn with_peer(mut self, peer: SocketAddr) -> Magnet {
+        self.peers.push(peer);
+        self
+    }
+
+    pub fn with_select_only(mut self, indices: FileIndices) -> Magnet {
+        self.select_only = Some(indices);
+        self
+    }
+
+    /// A magnet link for a torrent, with its name, trackers and web seeds.
+    /// `None` if none of its infohashes can be computed with the enabled
+    /// hash features.
+    #[cfg(any(feature = "sha1", feature = "sha256"))]
+    pub fn from_metainfo(metainfo: &Metainfo) -> Option<Magnet> {
+        let info_hashes = metainfo.info_hashes();
+        if info_hashes.is_empty() {
+            return None;
+        }
+        Some(Magnet {
+            info_hashes,
+            display_name: Some(metainfo.info.name.clone()),
+            trackers: metainfo.trackers().flatten().iter().map(|url| url.to_string()).collect(),
+            web_seeds: metainfo.url_list.clone(),
+            ..Magnet::default()
+        })
+    }
+}
+
+/// Formats the magnet as a `magnet:?` URI. v1 hashes are emitted as
+/// `urn:btih:` and v2 hashes as `urn:btmh:` multihashes.
+impl fmt::Display for Magnet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("magnet:")?;
+        let mut separator = '?';
+
+        for info_hash in self.info_hashes.iter() {
+            match *info_hash {
+                InfoHash::V1(_) => write!(f, "{}xt=urn:btih:{}", separator, info_hash)?,
+                InfoHash::V2(_) => write!(f, "{}xt=urn:btmh:{}{}",
+                                          separator, SHA256_MULTIHASH_PREFIX, info_hash)?,
+            }
+            separator = '&';
+        }
+        if let Some(ref name) = self.display_name {
+            write!(f, "{}dn={}", separator, percent::encode_component(name.as_bytes()))?;
+            separator = '&';
+        }
+        for tracker in self.trackers.iter() {
+            write!(f, "{}tr={}", separator, percent::encode_component(tracker.as_bytes()))?;
+            separator = '&';
+        }
+        for web_seed in self.web_seeds.iter() {
+            write!(f, "{}ws={}", separator, percent::encode_component(web_seed.as_bytes()))?;
+            separator = '&';
+        }
+        for peer in self.peers.iter() {
+            let peer = peer.to_string();
+            write!(f, "{}x.pe={}", separator, percent::encode_component(peer.as_bytes()))?;
+            separator = '&';
+        }
+        if let Some(ref indices) = self.select_only {
+            write!(f, "{}so={}", separator, indices)?;
+        }
+        Ok(())
+    }
+}
+
+fn decode_value(value: &str) -> Result<String, ParseMagnetError> {
+    let bytes = percent::decode(value)?;
+    String::from_utf8(bytes).map_err(|_| ParseMagnetError::InvalidUtf8)
+}
+
+fn parse_exact_topic(value: &str) -> Result<Option<InfoHash>, ParseMagnetError> {
+    let value = decode_value(value)?;
+    let (prefix, hash) = match value.rfind(':') {
+        Some(idx) => (&value[..idx + 1], &value[idx + 1..]),
+        None => return Ok(None),
+    };
+    if prefix.eq_ignore_ascii_case("urn:btih:") {
+        let info_hash: InfoHash = hash.parse()?;
+        if !info_hash.is_v1() {
+            return Err(ParseInfoHashError::InvalidLength.into());
+        }
+        Ok(Some(info_hash))
+    } else if prefix.eq_ignore_ascii_case("urn:btmh:") {
+        if !hash.starts_with(SHA256_MULTIHASH_PREFIX)
+            || hash.len() != SHA256_MULTIHASH_PREFIX.len() + 2 * infohash::V2_LEN {
+            return Err(ParseInfoHashError::InvalidLength.into());
+        }
+        Ok(Some(hash[SHA256_MULTIHASH_PREFIX.len()..].parse()?))
+    } else {
+        // Other networks' hashes (ed2k, sha1 file hashes, ...) are not ours.
+        Ok(None)
+    }
+}
+
+/// Strips the optional numeric suffix some clients append to repeated
+/// keys (`tr.1`, `xt.2`).
+fn base_key(key: &str) -> &str {
+    match key.rfind('.') {
+        Some(idx) if key[idx + 1..].bytes().all(|b| b.is_ascii_digit())
+            && idx + 1 < key.len() => &key[..idx],
+        _ => key,
+    }
+}
+
+/// Parses a `magnet:?` URI. Unknown parameters are ignored.
+impl FromStr for Magnet {
+    type Err = ParseMagnetError;
+
+    fn from_str(s: &str) -> Result<Magnet, ParseMagnetError> {
+        let scheme = "magnet:?";
+        match s.get(..scheme.len()) {
+            Some(prefix) if prefix.eq_ignore_ascii_case(scheme) => (),
+            _ => return Err(ParseMagnetError::InvalidScheme),
+        }
+
+        let mut magnet = Magnet::default();
+        for param in s[scheme.len()..].split('&') {
+            if param.is_empty() {
+                continue;
+            }
+            let (key, value) = match param.find('=') {
+                Some(idx) => (&param[..idx], &param[idx + 1..]),
+                None => return Err(ParseMagnetError::MalformedParameter),
+            };
+            match base_key(key) {
+                "xt" => {
+                    if let Some(info_hash) = parse_exact_topic(value)? {
+                        magnet.info_hashes.push(info_hash);
+                    }
+                },
+                "dn" => {
+                    // Form-style encoders write spaces in names as `+`.
+                    let name = decode_value(&value.replace('+', " "))?;
+                    magnet.display_name = Some(name);
+                },
+                "tr" => magnet.trackers.push(decode_value(value)?),
+                "ws" => magnet.web_seeds.push(decode_value(value)?),
+                "x.pe" => {
+                    let peer = decode_value(value)?.parse()
+                        .map_err(|_| ParseMagnetError::InvalidPeerAddress)?;
+                    magnet.peers.push(peer);
+                },
+                "so" => magnet.select_only = Some(decode_value(value)?.parse()?),
+                _ => (),
+            }
+        }
+
+        if magnet.info_hashes.is_empty() {
+            return Err(ParseMagnetError::MissingInfoHash);
+        }
+        Ok(magnet)
+    }
+}
+
+/// The parts of a metainfo document a magnet link provides, waiting for
+/// the info dictionary to arrive over BEP 9.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct MetainfoSkeleton {
+    pub info_hashes: Vec<InfoHash>,
+    pub display_name: Option<String>,
+    /// The `tr` trackers, one per tier in the order given.
+    pub trackers: AnnounceList,
+    pub web_seeds: Vec<String>,
+}
+
+impl MetainfoSkeleton {
+    /// Builds the document from the fetched info dictionary, without
+    /// checking it against `info_hashes`. `info` must hold exactly one
+    /// value, and is kept as `info_bytes`.
+    pub fn complete_unchecked(&self, info_bytes: &[u8]) -> MetainfoResult<Metainfo> {
+        let info = raw::decode_exact(info_bytes)?;
+        let urls = self.trackers.flatten();
+        let announce_list = if urls.len() > 1 { Some(self.trackers.clone()) } else { None };
+        Ok(Metainfo {
+            announce: urls.first().map(|url| url.to_string()),
+            announce_list,
+            url_list: self.web_seeds.clone(),
+            httpseeds: Vec::new(),
+            nodes: Vec::new(),
+            info: Info::from_bencode(info)?,
+            info_bytes: Some(info_bytes.to_vec()),
+            comment: None,
+            created_by: None,
+            creation_date: None,
+            extra: Dict::new(),
+        })
+    }
+
+    /// Builds the document once `info` is known to match one of the
+    /// magnet's infohashes.
+    #[cfg(any(feature = "sha1", feature = "sha256"))]
+    pub fn complete(&self, info: &[u8]) -> MetainfoResult<Metainfo> {
+        if !self.info_hashes.iter().any(|hash| ut_metadata::verify_metadata(info, hash)) {
+            return Err(MetainfoError::Invalid("info hash"));
+        }
+        self.complete_unchecked(info)
+    }
+}
+
+impl<'a> From<&'a Magnet> for MetainfoSkeleton {
+    fn from(magnet: &'a Magnet) -> MetainfoSkeleton {
+        MetainfoSkeleton {
+            info_hashes: magnet.info_hashes.clone(),
+            display_name: magnet.display_name.clone(),
+            trackers: AnnounceList {
+                tiers: magnet.trackers.iter().map(|url| vec![url.clone()]).collect(),
+            },
+            web_seeds: magnet.web_seeds.clone(),
+        }
+    }
+}
+
+#[test]
+fn test_magnet_display() {
+    let v1: InfoHash = "c12fe1c06bba254a9dc9f519b335aa7c1367a88a".parse().unwrap();
+    let magnet = Magnet::new(v1)
+        .with_display_name("Some Name")
+        .with_tracker("http://tracker.example/announce?a=1")
+        .with_web_seed("https://seed.example/files/");
+    assert_eq!(magnet.to_string(),
+               "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a\
+                &dn=Some%20Name\
+                &tr=http%3A%2F%2Ftracker.example%2Fannounce%3Fa%3D1\
+                &ws=https%3A%2F%2Fseed.example%2Ffiles%2F");
+
+    let mut hybrid = Magnet::new(v1);
+    hybrid.info_hashes.push(InfoHash::V2([0xab; 32]));
+    assert_eq!(hybrid.to_string(),
+               format!("magnet:?xt=urn:btih:{}&xt=urn:btmh:1220{}",
+                       v1, "ab".repeat(32)));
+}
+
+#[test]
+fn test_magnet_parse() {
+    let uri = "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a\
+               &dn=Some+Name%21\
+               &tr=http%3A%2F%2Fone.example%2Fannounce\
+               &tr.1=udp://two.example:80\
+               &ws=https%3A%2F%2Fseed.example%2F\
+               &x.unknown=1";
+    let magnet: Magnet = uri.parse().unwrap();
+    assert_eq!(magnet.info_hashes,
+               vec!["c12fe1c06bba254a9dc9f519b335aa7c1367a88a".parse().unwrap()]);
+    assert_eq!(magnet.display_name, Some("Some Name!".to_string()));
+    assert_eq!(magnet.trackers, vec!["http://one.example/announce",
+                                     "udp://two.example:80"]);
+    assert_eq!(magnet.web_seeds, vec!["https://seed.example/"]);
+
+    // Generation and parsing round-trip, including v2 and base32 hashes.
+    let mut hybrid = Magnet::new("YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK".parse().unwrap())
+        .with_display_name("a & b")
+        .with_tracker("http://x/?a=1&b=2");
+    hybrid.info_hashes.push(InfoHash::V2([0xcd; 32]));
+    assert_eq!(hybrid.to_string().parse::<Magnet>(), Ok(hybrid));
+
+    assert_eq!("magnét:?xt=".parse::<Magnet>(),
+               Err(ParseMagnetError::InvalidScheme));
+    assert_eq!("http://example/".parse::<Magnet>(),
+               Err(ParseMagnetError::InvalidScheme));
+    assert_eq!("magnet:?dn=x".parse::<Magnet>(),
+               Err(ParseMagnetError::MissingInfoHash));
+    assert_eq!("magnet:?xt".parse::<Magnet>(),
+               Err(ParseMagnetError::MalformedParameter));
+    assert_eq!("magnet:?xt=urn:btih:abc".parse::<Magnet>(),
+               Err(ParseMagnetError::InvalidInfoHash(ParseInfoHashError::InvalidLength)));
+    assert_eq!("magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a&dn=%ff"
+                   .parse::<Magnet>(),
+               Err(ParseMagnetError::InvalidUtf8));
+}
+
+#[test]
+fn test_magnet_select_only() {
+    let indices: FileIndices = "0,2,4-7,5,8".parse().unwrap();
+    assert_eq!(indices.ranges(), &[0..=0, 2..=2, 4..=8]);
+    assert_eq!(indices.to_string(), "0,2,4-8");
+    assert!(indices.contains(6) && !indices.contains(3));
+    assert_eq!(indices.iter().collect::<Vec<_>>(), vec![0, 2, 4, 5, 6, 7, 8]);
+
+    let uri = "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a&so=1,3-4";
+    let magnet: Magnet = uri.parse().unwrap();
+    assert_eq!(magnet.select_only.as_ref().map(|s| s.ranges()),
+               Some(&[1..=1, 3..=4][..]));
+    assert_eq!(magnet.to_string(), uri);
+
+    for bad in ["", "1,", "a", "3-1", "+1", "1-"].iter() {
+        assert_eq!(bad.parse::<FileIndices>(), Err(ParseMagnetError::InvalidSelectOnly));
+    }
+}
+
+#[test]
+fn test_magnet_peers() {
+    let uri = "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a\
+               &x.pe=10.0.0.1:6881&x.pe=%5B2001%3Adb8%3A%3A1%5D%3A51413";
+    let magnet: Magnet = uri.parse().unwrap();
+    assert_eq!(magnet.peers, vec!["10.0.0.1:6881".parse::<SocketAddr>().unwrap(),
+                                  "[2001:db8::1]:51413".parse().unwrap()]);
+    assert_eq!(magnet.to_string().parse::<Magnet>(), Ok(magnet));
+
+    assert_eq!("magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a&x.pe=[::1]"
+                   .parse::<Magnet>(),
+               Err(ParseMagnetError::InvalidPeerAddress));
+}
+
+#[test]
+fn test_metainfo_skeleton() {
+    let magnet: Magnet = "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a&dn=a\
+                          &tr=http%3A%2F%2Fone%2Fa&tr=http%3A%2F%2Ftwo%2Fa&ws=http%3A%2F%2Fseed%2F"
+        .parse().unwrap();
+    let skeleton = MetainfoSkeleton::from(&magnet);
+    assert_eq!(skeleton.display_name, Some("a".to_string()));
+    assert_eq!(skeleton.trackers.tiers.len(), 2);
+
+    let info = b"d6:lengthi1e4:name1:a12:piece lengthi1e6:pieces20:aaaaaaaaaaaaaaaaaaaae";
+    let metainfo = skeleton.complete_unchecked(info).unwrap();
+    assert_eq!(metainfo.announce, Some("http://one/a".to_string()));
+    assert_eq!(metainfo.trackers().flatten(), vec!["http://one/a", "http://two/a"]);
+    assert_eq!(metainfo.url_list, vec!["http://seed/"]);
+    assert_eq!(metainfo.info.name, "a");
+
+    use torrent::MetainfoError::Parse;
+    let trailing = [&info[..], b"XX"].concat();
+    assert_eq!(skeleton.complete_unchecked(&trailing), Err(Parse(::ParseError::InvalidCharacter)));
+    let deep = [&b"d1:x"[..], &vec![b'l'; 100_000]].concat();
+    assert_eq!(skeleton.complete_unchecked(&deep), Err(Parse(::ParseError::TooDeep)));
+}
+
+#[cfg(feature = "sha1")]
+#[test]
+fn test_complete_skeleton() {
+    let info = b"d6:lengthi1e4:name1:a12:piece lengthi1e6:pieces20:aaaaaaaaaaaaaaaaaaaae";
+    let skeleton = MetainfoSkeleton::from(&Magnet::new(InfoHash::V1(::sha1::Sha1::digest(info))));
+    assert_eq!(skeleton.complete(info).map(|m| m.info.name), Ok("a".to_string()));
+    assert_eq!(skeleton.complete(b"d4:name1:be"), Err(MetainfoError::Invalid("info hash")));
+}
+
+#[cfg(feature = "sha1")]
+#[test]
+fn test_magnet_from_metainfo() {
+    let metainfo = Metainfo::from_bytes(b"d8:announce5:http:4:infod6:lengthi1e4:name1:a\
+                                          12:piece lengthi1e6:pieces20:aaaaaaaaaaaaaaaaaaaae\
+                                          8:url-list6:https:e").unwrap();
+    let magnet = Magnet::from_metainfo(&metainfo).unwrap();
+    assert_eq!(magnet.info_hashes, vec![metainfo.info_hash_v1().unwrap()]);
+    assert_eq!(magnet.display_name, Some("a".to_string()));
+    assert_eq!(magnet.trackers, vec!["http:".to_string()]);
+    assert_eq!(magnet.web_seeds, vec!["https:".to_string()]);
+}
@@ -0,0 +1,252 @@
+//! BEP 52 merkle trees: per-file `pieces root` hashes and the top-level
+//! `piece layers` dictionary of v2 torrents.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use Bencode;
+use sha256::{self, Sha256};
+use torrent::Metainfo;
+
+/// Size of the data blocks hashed into merkle tree leaves.
+pub const BLOCK_LEN: usize = 16 * 1024;
+
+pub type Hash = [u8; sha256::DIGEST_LEN];
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MerkleError {
+    MissingFileTree,
+    /// A file larger than one piece has no `pieces root`.
+    InvalidFileTree,
+    InvalidPieceLayers,
+    /// v2 piece lengths must be a power of two of at least 16 KiB.
+    InvalidPieceLength,
+    /// A file larger than one piece has no entry in `piece layers`.
+    MissingLayer(PathBuf),
+    /// A piece layer does not hold one hash per piece of its file.
+    LayerLength(PathBuf),
+    /// A piece layer does not hash up to its file's `pieces root`.
+    RootMismatch(PathBuf),
+}
+
+fn hash_pair(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finish()
+}
+
+/// Hashes `data` into 16 KiB leaf hashes; the final block may be short.
+pub fn leaf_hashes(data: &[u8]) -> Vec<Hash> {
+    data.chunks(BLOCK_LEN).map(Sha256::digest).collect()
+}
+
+/// Reduces one layer of the tree to its root, padding the layer to a power
+/// of two with `pad`, the hash of an all-zero subtree at that height.
+fn reduce(layer: &[Hash], mut pad: Hash) -> Hash {
+    if layer.is_empty() {
+        return pad;
+    }
+    let mut layer = layer.to_vec();
+    layer.resize(layer.len().next_power_of_two(), pad);
+    while layer.len() > 1 {
+        layer = layer.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+        pad = hash_pair(&pad, &pad);
+    }
+    layer[0]
+}
+
+/// Computes the root over leaf hashes, padding with zero leaves.
+pub fn merkle_root(leaves: &[Hash]) -> Hash {
+    reduce(leaves, [0; sha256::DIGEST_LEN])
+}
+
+fn blocks_per_piece(piece_length: u64) -> Option<usize> {
+    let block_len = BLOCK_LEN as u64;
+    if piece_length < block_len || !piece_length.is_power_of_two() {
+        return None;
+    }
+    Some((piece_length / block_len) as usize)
+}
+
+/// The hash of a piece consisting entirely of padding leaves.
+fn zero_piece_hash(piece_length: u64) -> Option<Hash> {
+    let mut hash = [0; sha256::DIGEST_LEN];
+    let mut width = blocks_per_piece(piece_length)?;
+    while width > 1 {
+        hash = hash_pair(&hash, &hash);
+        width /= 2;
+    }
+    Some(hash)
+}
+
+/// Builds the piece layer of a file from its leaf hashes. Returns `None`
+/// for an invalid v2 piece length.
+pub fn piece_layer(leaves: &[Hash], piece_length: u64) -> Option<Vec<Hash>> {
+    let width = blocks_per_piece(piece_length)?;
+    Some(leaves.chunks(width).map(|chunk| {
+        let mut subtree = chunk.to_vec();
+        subtree.resize(width, [0; sha256::DIGEST_LEN]);
+        merkle_root(&subtree)
+    }).collect())
+}
+
+/// Computes a file's `pieces root` from its piece layer.
+pub fn root_from_piece_layer(layer: &[Hash], piece_length: u64) -> Option<Hash> {
+    Some(reduce(layer, zero_piece_hash(piece_length)?))
+}
+
+fn to_hash(buf: &[u8]) -> Option<Hash> {
+    if buf.len() != sha256::DIGEST_LEN {
+        return None;
+    }
+    let mut hash = [0; sha256::DIGEST_LEN];
+    hash.copy_from_slice(buf);
+    Some(hash)
+}
+
+/// The decoded `piece layers` dictionary, keyed by `pieces root`.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct PieceLayers {
+    pub layers: BTreeMap<Hash, Vec<Hash>>,
+}
+
+impl PieceLayers {
+    pub fn from_bencode(value: &Bencode) -> Result<PieceLayers, MerkleError> {
+        let map = match *value {
+            Bencode::Object(ref map) => map,
+            _ => return Err(MerkleError::InvalidPieceLayers),
+        };
+        let mut layers = BTreeMap::new();
+        for (root, layer) in map.iter() {
+            let root = to_hash(root).ok_or(MerkleError::InvalidPieceLayers)?;
+            let layer = match *layer {
+                Bencode::Bytes(ref buf) if buf.len().is_multiple_of(sha256::DIGEST_LEN) => buf,
+                _ => return Err(MerkleError::InvalidPieceLayers),
+            };
+            let hashes = layer.chunks(sha256::DIGEST_LEN)
+                .map(|chunk| to_hash(chunk).expect("chunk length checked"))
+                .collect();
+            layers.insert(root, hashes);
+        }
+        Ok(PieceLayers { layers })
+    }
+
+    /// Reads `piece layers` from a metainfo document; absent means empty.
+    pub fn from_metainfo(metainfo: &Metainfo) -> Result<PieceLayers, MerkleError> {
+        match metainfo.extra.get(&b"piece layers"[..]) {
+            Some(value) => PieceLayers::from_bencode(value),
+            None => Ok(PieceLayers::default()),
+        }
+    }
+
+    pub fn to_bencode(&self) -> Bencode {
+        let map = self.layers.iter()
+            .map(|(root, layer)| (root.to_vec(), Bencode::Bytes(layer.concat())))
+            .collect();
+        Bencode::Object(map)
+    }
+}
+
+/// Checks every file of a v2 torrent larger than one piece against
+/// `piece layers`: the layer must exist, hold one hash per piece, and hash
+/// up to the file's `pieces root`.
+pub fn validate_piece_layers(metainfo: &Metainfo) -> Result<(), MerkleError> {
+    let piece_length = metainfo.info.piece_length;
+    if blocks_per_piece(piece_length).is_none() {
+        return Err(MerkleError::InvalidPieceLength);
+    }
+    let file_tree = metainfo.info.file_tree.as_ref()
+        .ok_or(MerkleError::MissingFileTree)?;
+    let layers = PieceLayers::from_metainfo(metainfo)?;
+
+    for (path, file) in file_tree.walk() {
+        if file.length <= piece_length {
+            continue;
+        }
+        let path: PathBuf = path.iter().collect();
+        let root = file.pieces_root.ok_or(MerkleError::InvalidFileTree)?;
+        let layer = match layers.layers.get(&root) {
+            Some(layer) => layer,
+            None => return Err(MerkleError::MissingLayer(path)),
+        };
+        let expected = file.length.div_ceil(piece_length);
+        if layer.len() as u64 != expected {
+            return Err(MerkleError::LayerLength(path));
+        }
+        if root_from_piece_layer(layer, piece_length) != Some(root) {
+            return Err(MerkleError::RootMismatch(path));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_merkle_roots() {
+    let data: Vec<u8> = (0..40000u32).map(|x| x as u8).collect();
+    let leaves = leaf_hashes(&data);
+    assert_eq!(leaves.len(), 3);
+
+    let root = merkle_root(&leaves);
+    let zero = [0u8; 32];
+    assert_eq!(root, hash_pair(&hash_pair(&leaves[0], &leaves[1]),
+                                &hash_pair(&leaves[2], &zero)));
+
+    // Building from the piece layer must agree with building from leaves,
+    // for piece lengths both below and above the file size.
+    for &piece_length in [16384u64, 32768, 65536].iter() {
+        let layer = piece_layer(&leaves, piece_length).unwrap();
+        assert_eq!(root_from_piece_layer(&layer, piece_length), Some(root));
+    }
+    assert_eq!(piece_layer(&leaves, 10000), None);
+    assert_eq!(merkle_root(&leaves[..1]), leaves[0]);
+}
+
+#[test]
+fn test_validate_piece_layers() {
+    use torrent::Dict;
+
+    let data: Vec<u8> = (0..40000u32).map(|x| (x * 7) as u8).collect();
+    let leaves = leaf_hashes(&data);
+    let layer = piece_layer(&leaves, 16384).unwrap();
+    let root = merkle_root(&leaves);
+
+    let mut file = Dict::new();
+    file.insert(b"length".to_vec(), Bencode::from(40000u64));
+    file.insert(b"pieces root".to_vec(), Bencode::from(&root[..]));
+    let mut leaf = Dict::new();
+    leaf.insert(Vec::new(), Bencode::Object(file));
+    let mut tree = Dict::new();
+    tree.insert(b"data.bin".to_vec(), Bencode::Object(leaf));
+
+    let mut info = Dict::new();
+    info.insert(b"file tree".to_vec(), Bencode::Object(tree));
+    info.insert(b"meta version".to_vec(), Bencode::from(2u64));
+    info.insert(b"name".to_vec(), Bencode::from("data.bin"));
+    info.insert(b"piece length".to_vec(), Bencode::from(16384u64));
+
+    let mut layers = PieceLayers::default();
+    layers.layers.insert(root, layer.clone());
+    let mut document = Dict::new();
+    document.insert(b"info".to_vec(), Bencode::Object(info));
+    document.insert(b"piece layers".to_vec(), layers.to_bencode());
+
+    let mut metainfo = Metainfo::from_bencode(Bencode::Object(document)).unwrap();
+    assert_eq!(validate_piece_layers(&metainfo), Ok(()));
+
+    let mut bad = layer.clone();
+    bad[1][0] ^= 1;
+    layers.layers.insert(root, bad);
+    metainfo.extra.insert(b"piece layers".to_vec(), layers.to_bencode());
+    assert_eq!(validate_piece_layers(&metainfo),
+               Err(MerkleError::RootMismatch(PathBuf::from("data.bin"))));
+
+    layers.layers.insert(root, layer[..2].to_vec());
+    metainfo.extra.insert(b"piece layers".to_vec(), layers.to_bencode());
+    assert_eq!(validate_piece_layers(&metainfo),
+               Err(MerkleError::LayerLength(PathBuf::from("data.bin"))));
+
+    metainfo.extra.remove(&b"piece layers"[..]);
+    assert_eq!(validate_piece_layers(&metainfo),
+               Err(MerkleError::MissingLayer(PathBuf::from("data.bin"))));
+}
@@ -0,0 +1,85 @@
+//! Process-wide decode counters, enabled by the `metrics` feature.
+//!
+//! `bdecode`, `bdecode_slice` and `Decoder::decode` record every document
+//! they decode, and every failure. A service can poll `snapshot` and export the
+//! numbers to its own monitoring system.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use {Bencode, measure};
+
+static DOCUMENTS: AtomicU64 = AtomicU64::new(0);
+static ERRORS: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+static NODES: AtomicU64 = AtomicU64::new(0);
+static MAX_DEPTH: AtomicU64 = AtomicU64::new(0);
+static NANOS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Snapshot {
+    /// Documents decoded successfully.
+    pub documents: u64,
+    /// Decodes that failed.
+    pub errors: u64,
+    /// Encoded bytes of the decoded documents.
+    pub bytes: u64,
+    /// Values created, containers and leaves alike.
+    pub nodes: u64,
+    /// Deepest nesting seen; a bare integer or string has depth 1.
+    pub max_depth: u64,
+    /// Time spent decoding.
+    pub elapsed: Duration,
+}
+
+pub fn snapshot() -> Snapshot {
+    Snapshot {
+        documents: DOCUMENTS.load(Ordering::Relaxed),
+        errors: ERRORS.load(Ordering::Relaxed),
+        bytes: BYTES.load(Ordering::Relaxed),
+        nodes: NODES.load(Ordering::Relaxed),
+        max_depth: MAX_DEPTH.load(Ordering::Relaxed),
+        elapsed: Duration::from_nanos(NANOS.load(Ordering::Relaxed)),
+    }
+}
+
+/// Zeroes every counter.
+pub fn reset() {
+    for counter in [&DOCUMENTS, &ERRORS, &BYTES, &NODES, &MAX_DEPTH, &NANOS].iter() {
+        counter.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Records the outcome of one decode that started at `start`.
+pub(crate) fn record<E>(start: Instant, result: &Result<Bencode, E>) {
+    let elapsed = start.elapsed();
+    NANOS.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    match *result {
+        Ok(ref value) => {
+            let (bytes, nodes, depth) = measure(value);
+            DOCUMENTS.fetch_add(1, Ordering::Relaxed);
+            BYTES.fetch_add(bytes, Ordering::Relaxed);
+            NODES.fetch_add(nodes, Ordering::Relaxed);
+            MAX_DEPTH.fetch_max(depth, Ordering::Relaxed);
+        },
+        Err(_) => {
+            ERRORS.fetch_add(1, Ordering::Relaxed);
+        },
+    }
+}
+
+#[test]
+fn test_measure() {
+    let buf = b"d1:ali1e3:xyze1:bd1:ci-12eee";
+    let value = ::bdecode_slice(buf).unwrap();
+    assert_eq!(measure(&value), (buf.len() as u64, 9, 3));
+
+    // Other tests decode concurrently, so only lower bounds hold.
+    let before = snapshot();
+    let _ = ::bdecode_slice(b"x");
+    let _ = ::bdecode_slice(buf);
+    let after = snapshot();
+    assert!(after.errors > before.errors);
+    assert!(after.bytes >= before.bytes + buf.len() as u64);
+    assert!(after.max_depth >= 3);
+}
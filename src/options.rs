@@ -0,0 +1,202 @@
+//! Decoding with configurable strictness and limits.
+//!
+//! `bdecode_slice` accepts what most documents in the wild contain and puts
+//! no bound on size, and only the generous `MAX_DEPTH` on nesting.
+//! `DecodeOptions` makes each of those rules a setting, and its presets
+//! bundle the combinations most callers want: `strict_bep3` for checking
+//! that a document is exactly as BEP 3 writes it, `lenient_legacy` for old
+//! files from careless encoders, and `untrusted_network` for messages from
+//! peers and trackers.
+
+use std::collections::BTreeMap;
+
+use {Bencode, ParseError, is_digit};
+use raw;
+
+/// Nesting limit of `DecodeOptions::untrusted_network`.
+pub const UNTRUSTED_MAX_DEPTH: usize = 32;
+/// Size limit of `DecodeOptions::untrusted_network`.
+pub const UNTRUSTED_MAX_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DecodeOptions {
+    /// Reject dictionary keys that sort before the previous key, with
+    /// `OutOfOrderKey`. When off, entries are sorted as they are inserted.
+    pub sorted_keys: bool,
+    /// Accept a key that appears more than once, keeping its last value.
+    /// When off, a repeated key is `NonCanonical`.
+    pub duplicate_keys: bool,
+    /// Reject integers and string lengths with leading zeros, and `-0`, as
+    /// `NonCanonical`.
+    pub canonical_integers: bool,
+    /// Deepest nesting accepted, where a bare integer or string has depth
+    /// 1; deeper documents are `TooDeep`. `MAX_DEPTH` applies either way.
+    pub max_depth: Option<usize>,
+    /// Longest input accepted, in bytes; longer inputs are `TooLarge`.
+    pub max_size: Option<usize>,
+}
+
+impl Default for DecodeOptions {
+    /// The rules of `bdecode_slice`.
+    fn default() -> DecodeOptions {
+        DecodeOptions {
+            sorted_keys: true,
+            duplicate_keys: true,
+            canonical_integers: false,
+            max_depth: None,
+            max_size: None,
+        }
+    }
+}
+
+impl DecodeOptions {
+    /// Only the canonical encoding: sorted, unique keys and no leading
+    /// zeros. No limits beyond `MAX_DEPTH`.
+    pub fn strict_bep3() -> DecodeOptions {
+        DecodeOptions {
+            sorted_keys: true,
+            duplicate_keys: false,
+            canonical_integers: true,
+            max_depth: None,
+            max_size: None,
+        }
+    }
+
+    /// Anything well formed, including unsorted and repeated keys. No
+    /// limits beyond `MAX_DEPTH`.
+    pub fn lenient_legacy() -> DecodeOptions {
+        DecodeOptions {
+            sorted_keys: false,
+            duplicate_keys: true,
+            canonical_integers: false,
+            max_depth: None,
+            max_size: None,
+        }
+    }
+
+    /// Sorted, unique keys, with bounded size and nesting so a hostile
+    /// message cannot exhaust memory or the stack. Leading zeros are
+    /// tolerated, as some clients send them.
+    pub fn untrusted_network() -> DecodeOptions {
+        DecodeOptions {
+            sorted_keys: true,
+            duplicate_keys: false,
+            canonical_integers: false,
+            max_depth: Some(UNTRUSTED_MAX_DEPTH),
+            max_size: Some(UNTRUSTED_MAX_SIZE),
+        }
+    }
+
+    /// Decodes the value at the start of `buf`.
+    pub fn decode(&self, buf: &[u8]) -> Result<Bencode, ParseError> {
+        if self.max_size.is_some_and(|max_size| buf.len() > max_size) {
+            return Err(ParseError::TooLarge);
+        }
+        self.decode_value(buf, 0, 1).map(|(value, _)| value)
+    }
+
+    fn bytea(&self, buf: &[u8], pos: usize) -> Result<::std::ops::Range<usize>, ParseError> {
+        if self.canonical_integers {
+            raw::canonical_bytea(buf, pos)
+        } else {
+            raw::scan_bytea(buf, pos)
+        }
+    }
+
+    fn decode_value(&self, buf: &[u8], pos: usize, depth: usize)
+        -> Result<(Bencode, usize), ParseError> {
+        if self.max_depth.is_some_and(|max_depth| depth > max_depth) {
+            return Err(ParseError::TooDeep);
+        }
+        raw::check_depth(depth)?;
+        match buf.get(pos) {
+            Some(&b'i') => {
+                let end = raw::scan_integer(buf, pos)?;
+                let digits = &buf[pos + 1..end - 1];
+                if self.canonical_integers && !raw::is_canonical_integer(digits) {
+                    return Err(ParseError::NonCanonical);
+                }
+                Ok((Bencode::Integer(digits.to_vec()), end))
+            },
+            Some(&b'l') => {
+                let mut items = Vec::new();
+                let mut pos = pos + 1;
+                loop {
+                    match buf.get(pos) {
+                        Some(&b'e') => return Ok((Bencode::Array(items), pos + 1)),
+                        Some(_) => {
+                            let (item, end) = self.decode_value(buf, pos, depth + 1)?;
+                            items.push(item);
+                            pos = end;
+                        },
+                        None => return Err(ParseError::Truncated),
+                    }
+                }
+            },
+            Some(&b'd') => {
+                let mut dict = BTreeMap::new();
+                let mut pos = pos + 1;
+                let mut prev_key: &[u8] = &[];
+                loop {
+                    match buf.get(pos) {
+                        Some(&b'e') => return Ok((Bencode::Object(dict), pos + 1)),
+                        Some(_) => (),
+                        None => return Err(ParseError::Truncated),
+                    }
+                    let span = self.bytea(buf, pos)?;
+                    let key = &buf[span.clone()];
+                    if self.sorted_keys && key < prev_key {
+                        return Err(ParseError::OutOfOrderKey);
+                    }
+                    prev_key = key;
+                    let (value, end) = self.decode_value(buf, span.end, depth + 1)?;
+                    if dict.insert(key.to_vec(), value).is_some() && !self.duplicate_keys {
+                        return Err(ParseError::NonCanonical);
+                    }
+                    pos = end;
+                }
+            },
+            Some(&val) if is_digit(val) => {
+                let span = self.bytea(buf, pos)?;
+                let end = span.end;
+                Ok((Bencode::Bytes(buf[span].to_vec()), end))
+            },
+            Some(_) => Err(ParseError::InvalidCharacter),
+            None => Err(ParseError::Truncated),
+        }
+    }
+}
+
+#[test]
+fn test_decode_option_presets() {
+    let strict = DecodeOptions::strict_bep3();
+    let lenient = DecodeOptions::lenient_legacy();
+    let network = DecodeOptions::untrusted_network();
+
+    let canonical = b"d1:ali1e2:xye1:bi-2ee";
+    for options in [strict, lenient, network, DecodeOptions::default()].iter() {
+        assert_eq!(options.decode(canonical), ::bdecode_slice(canonical));
+    }
+
+    assert_eq!(lenient.decode(b"d1:bi2e1:ai1ee"), ::bdecode_slice(b"d1:ai1e1:bi2ee"));
+    assert_eq!(network.decode(b"d1:bi2e1:ai1ee"), Err(ParseError::OutOfOrderKey));
+    assert_eq!(lenient.decode(b"d1:ai1e1:ai2ee"), ::bdecode_slice(b"d1:ai2ee"));
+    assert_eq!(network.decode(b"d1:ai1e1:ai2ee"), Err(ParseError::NonCanonical));
+
+    assert_eq!(network.decode(b"li03e02:abe"), ::bdecode_slice(b"li03e02:abe"));
+    assert_eq!(strict.decode(b"li03ee"), Err(ParseError::NonCanonical));
+    assert_eq!(strict.decode(b"02:ab"), Err(ParseError::NonCanonical));
+
+    let deep = [&[b'l'; UNTRUSTED_MAX_DEPTH][..], &[b'e'; UNTRUSTED_MAX_DEPTH][..]].concat();
+    assert!(network.decode(&deep).is_ok());
+    let deeper = [&b"l"[..], &deep, b"e"].concat();
+    assert_eq!(network.decode(&deeper), Err(ParseError::TooDeep));
+    assert!(lenient.decode(&deeper).is_ok());
+    assert_eq!(lenient.decode(&[b'l'; 10000]), Err(ParseError::TooDeep));
+    let unbounded = DecodeOptions { max_depth: Some(usize::MAX), ..lenient };
+    assert_eq!(unbounded.decode(&[b'l'; ::MAX_DEPTH + 1]), Err(ParseError::TooDeep));
+
+    let limited = DecodeOptions { max_size: Some(4), ..DecodeOptions::default() };
+    assert!(limited.decode(b"i12e").is_ok());
+    assert_eq!(limited.decode(b"i123e"), Err(ParseError::TooLarge));
+}
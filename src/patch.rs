@@ -0,0 +1,290 @@
+//! Editing the top-level dictionary of an encoded document without
+//! re-encoding the values that are left alone.
+//!
+//! Untouched entries, `info` in particular, are copied byte for byte from
+//! the input, so the infohash of a patched `.torrent` cannot change even if
+//! the original encoding was not one this crate would produce.
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use {Bencode, ParseError, bencode, bencode_bytea, is_digit};
+use raw::{self, DictEntries};
+use torrent::AnnounceList;
+
+/// Replaces, inserts (`Some`) or removes (`None`) top-level keys and copies
+/// every other entry verbatim. The input must be a single dictionary with
+/// sorted keys.
+pub fn replace_keys(document: &[u8], replacements: &BTreeMap<Vec<u8>, Option<Bencode>>)
+    -> Result<Vec<u8>, ParseError> {
+
+    if raw::skip_value(document, 0, 1)? != document.len() {
+        return Err(ParseError::InvalidCharacter);
+    }
+
+    let mut out = Vec::with_capacity(document.len());
+    out.push(b'd');
+    let mut pending = replacements.iter().peekable();
+    for entry in DictEntries::new(document, 0)? {
+        let (key, value) = entry?;
+        let key = &document[key];
+        while let Some(&(new_key, new_value)) = pending.peek() {
+            if &new_key[..] > key {
+                break;
+            }
+            write_entry(&mut out, new_key, new_value.as_ref());
+            pending.next();
+            if &new_key[..] == key {
+                // Keep `key` out of the copy below.
+                break;
+            }
+        }
+        if replacements.contains_key(key) {
+            continue;
+        }
+        bencode_bytea(key, &mut out).expect("writing to a Vec cannot fail");
+        out.extend_from_slice(&document[value]);
+    }
+    for (new_key, new_value) in pending {
+        write_entry(&mut out, new_key, new_value.as_ref());
+    }
+    out.push(b'e');
+    Ok(out)
+}
+
+fn write_entry(out: &mut Vec<u8>, key: &[u8], value: Option<&Bencode>) {
+    if let Some(value) = value {
+        bencode_bytea(key, out).expect("writing to a Vec cannot fail");
+        bencode(value, out).expect("writing to a Vec cannot fail");
+    }
+}
+
+/// Sets the trackers of an encoded `.torrent`, leaving `info` and every
+/// other key exactly as they were. `None` removes the key.
+pub fn set_trackers(document: &[u8], announce: Option<&str>,
+                    announce_list: Option<&AnnounceList>)
+    -> Result<Vec<u8>, ParseError> {
+
+    let mut replacements = BTreeMap::new();
+    replacements.insert(b"announce".to_vec(), announce.map(Bencode::from));
+    replacements.insert(b"announce-list".to_vec(), announce_list.map(AnnounceList::to_bencode));
+    replace_keys(document, &replacements)
+}
+
+#[derive(Debug)]
+pub enum RewriteError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl From<io::Error> for RewriteError {
+    fn from(err: io::Error) -> RewriteError {
+        RewriteError::Io(err)
+    }
+}
+
+impl From<ParseError> for RewriteError {
+    fn from(err: ParseError) -> RewriteError {
+        RewriteError::Parse(err)
+    }
+}
+
+type RewriteResult<T> = Result<T, RewriteError>;
+
+/// Byte-at-a-time access to a buffered reader.
+struct Scanner<R> {
+    reader: R,
+}
+
+impl<R: BufRead> Scanner<R> {
+    fn peek(&mut self) -> RewriteResult<Option<u8>> {
+        Ok(self.reader.fill_buf()?.first().cloned())
+    }
+
+    fn byte(&mut self) -> RewriteResult<u8> {
+        match self.peek()? {
+            Some(byte) => {
+                self.reader.consume(1);
+                Ok(byte)
+            },
+            None => Err(ParseError::Truncated.into()),
+        }
+    }
+
+    /// Reads the rest of a byte string length prefix whose first digit is
+    /// `first`, through the colon.
+    fn length(&mut self, first: u8) -> RewriteResult<u64> {
+        let mut length = u64::from(first - b'0');
+        loop {
+            match self.byte()? {
+                b':' => return Ok(length),
+                digit if is_digit(digit) => {
+                    length = length.checked_mul(10)
+                        .and_then(|length| length.checked_add(u64::from(digit - b'0')))
+                        .ok_or(ParseError::InvalidLength)?;
+                },
+                _ => return Err(ParseError::InvalidCharacter.into()),
+            }
+        }
+    }
+
+    /// Copies `length` bytes to `out` without holding them all in memory.
+    fn copy_bytes<O: Write>(&mut self, length: u64, out: &mut O) -> RewriteResult<()> {
+        if io::copy(&mut self.reader.by_ref().take(length), out)? != length {
+            return Err(ParseError::Truncated.into());
+        }
+        Ok(())
+    }
+
+    fn read_bytes(&mut self) -> RewriteResult<Vec<u8>> {
+        let first = self.byte()?;
+        if !is_digit(first) {
+            return Err(ParseError::InvalidCharacter.into());
+        }
+        let length = self.length(first)?;
+        let mut buf = Vec::new();
+        self.copy_bytes(length, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Copies one complete value to `out`. Nesting is tracked with a
+    /// counter rather than recursion, and keys of nested dictionaries are
+    /// passed through without checking their order.
+    fn copy_value<O: Write>(&mut self, out: &mut O) -> RewriteResult<()> {
+        let mut depth = 0usize;
+        loop {
+            match self.byte()? {
+                b'i' => {
+                    out.write_all(b"i")?;
+                    let (mut digits, mut signed) = (0, false);
+                    loop {
+                        match self.byte()? {
+                            b'e' if digits > 0 => break,
+                            b'-' if digits == 0 && !signed => {
+                                out.write_all(b"-")?;
+                                signed = true;
+                            },
+                            digit if is_digit(digit) => {
+                                out.write_all(&[digit])?;
+                                digits += 1;
+                            },
+                            _ => return Err(ParseError::InvalidCharacter.into()),
+                        }
+                    }
+                    out.write_all(b"e")?;
+                },
+                open @ b'l' | open @ b'd' => {
+                    out.write_all(&[open])?;
+                    depth += 1;
+                    continue;
+                },
+                b'e' if depth > 0 => {
+                    out.write_all(b"e")?;
+                    depth -= 1;
+                },
+                digit if is_digit(digit) => {
+                    let length = self.length(digit)?;
+                    write!(out, "{}:", length)?;
+                    self.copy_bytes(length, out)?;
+                },
+                _ => return Err(ParseError::InvalidCharacter.into()),
+            }
+            if depth == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// The streaming counterpart of `replace_keys`: reads a document from
+/// `reader` and writes it to `writer` with the top-level keys in `rules`
+/// replaced, inserted or dropped. All other entries are passed through as
+/// they are read, so memory use does not depend on the document size.
+pub fn rewrite<R, W>(reader: R, mut writer: W, rules: &BTreeMap<Vec<u8>, Option<Bencode>>)
+    -> RewriteResult<()>
+    where
+        R: Read,
+        W: Write {
+
+    let mut scanner = Scanner { reader: BufReader::new(reader) };
+    if scanner.byte()? != b'd' {
+        return Err(ParseError::InvalidCharacter.into());
+    }
+    writer.write_all(b"d")?;
+
+    let mut pending = rules.iter().peekable();
+    let mut prev_key: Option<Vec<u8>> = None;
+    while scanner.peek()? != Some(b'e') {
+        let key = scanner.read_bytes()?;
+        if prev_key.as_ref().is_some_and(|prev| *prev >= key) {
+            return Err(ParseError::OutOfOrderKey.into());
+        }
+        while let Some(&(new_key, new_value)) = pending.peek() {
+            if *new_key > key {
+                break;
+            }
+            if let Some(ref value) = *new_value {
+                bencode_bytea(new_key, &mut writer)?;
+                bencode(value, &mut writer)?;
+            }
+            pending.next();
+        }
+        if rules.contains_key(&key) {
+            scanner.copy_value(&mut io::sink())?;
+        } else {
+            bencode_bytea(&key, &mut writer)?;
+            scanner.copy_value(&mut writer)?;
+        }
+        prev_key = Some(key);
+    }
+    scanner.byte()?;
+    for (new_key, new_value) in pending {
+        if let Some(ref value) = *new_value {
+            bencode_bytea(new_key, &mut writer)?;
+            bencode(value, &mut writer)?;
+        }
+    }
+    writer.write_all(b"e")?;
+
+    if scanner.peek()?.is_some() {
+        return Err(ParseError::InvalidCharacter.into());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_set_trackers() {
+    // Non-canonical `info` (a zero-padded integer) must survive untouched.
+    let document = b"d8:announce5:old/a7:comment1:c4:infod6:lengthi05eee";
+    let list = AnnounceList { tiers: vec![vec!["x".to_string()], vec!["y".to_string()]] };
+
+    let patched = set_trackers(document, Some("new"), Some(&list)).unwrap();
+    assert_eq!(&patched[..], &b"d8:announce3:new13:announce-listll1:xel1:yee\
+        7:comment1:c4:infod6:lengthi05eee"[..]);
+
+    let removed = set_trackers(&patched, None, None).unwrap();
+    assert_eq!(&removed[..], &b"d7:comment1:c4:infod6:lengthi05eee"[..]);
+
+    assert_eq!(set_trackers(b"d4:infodee", Some("a"), None).unwrap(),
+               b"d8:announce1:a4:infodee".to_vec());
+    assert_eq!(set_trackers(b"d4:infodeex", None, None), Err(ParseError::InvalidCharacter));
+    assert_eq!(set_trackers(b"le", None, None), Err(ParseError::InvalidCharacter));
+}
+
+#[test]
+fn test_rewrite() {
+    let document = &b"d8:announce5:old/a7:comment1:c4:infod6:lengthi05e5:piecel0:i-1eeee"[..];
+    let mut rules = BTreeMap::new();
+    rules.insert(b"announce".to_vec(), Some(Bencode::from("new")));
+    rules.insert(b"comment".to_vec(), None);
+    rules.insert(b"z".to_vec(), Some(Bencode::from(1i64)));
+
+    let mut out = Vec::new();
+    rewrite(document, &mut out, &rules).unwrap();
+    assert_eq!(&out[..], &b"d8:announce3:new4:infod6:lengthi05e5:piecel0:i-1eee1:zi1ee"[..]);
+    assert_eq!(replace_keys(document, &rules), Ok(out));
+
+    for bad in [&b"d1:bi1e1:ai1ee"[..], b"d4:infod", b"d1:ai1eex", b"d1:ai-e", b"d1:ai--1ee"].iter() {
+        assert!(rewrite(*bad, &mut Vec::new(), &rules).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+//! Peer ids and the client naming conventions encoded in them.
+
+use std::fmt;
+
+use random;
+
+pub const PEER_ID_LEN: usize = 20;
+
+const SUFFIX_ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// Known Azureus-style client codes.
+const AZUREUS_CLIENTS: &[(&[u8; 2], &str)] = &[
+    (b"AZ", "Vuze"),
+    (b"BI", "BiglyBT"),
+    (b"BT", "BitTorrent"),
+    (b"DE", "Deluge"),
+    (b"lt", "libtorrent (Rasterbar)"),
+    (b"LT", "libtorrent (Rakshasa)"),
+    (b"qB", "qBittorrent"),
+    (b"TR", "Transmission"),
+    (b"UT", "\u{b5}Torrent"),
+    (b"UM", "\u{b5}Torrent Mac"),
+    (b"WD", "WebTorrent Desktop"),
+    (b"WW", "WebTorrent"),
+];
+
+/// A 20-byte BitTorrent peer id.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PeerId(pub [u8; PEER_ID_LEN]);
+
+/// What a peer id says about the client that made it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ClientInfo {
+    /// The client code, e.g. `qB`, or `M` for Mainline.
+    pub code: String,
+    /// The client name, if the code is a known one.
+    pub name: Option<&'static str>,
+    /// The version as encoded in the id, e.g. `4.6.2.0`.
+    pub version: String,
+}
+
+impl PeerId {
+    pub fn from_slice(bytes: &[u8]) -> Option<PeerId> {
+        if bytes.len() != PEER_ID_LEN {
+            return None;
+        }
+        let mut id = [0; PEER_ID_LEN];
+        id.copy_from_slice(bytes);
+        Some(PeerId(id))
+    }
+
+    /// Generates an id starting with `prefix`, typically Azureus-style
+    /// `-XX1234-`, followed by random alphanumerics. A prefix longer than
+    /// the id is truncated.
+    pub fn generate(prefix: &[u8]) -> PeerId {
+        let mut id = [0; PEER_ID_LEN];
+        random::fill(&mut id);
+        for byte in id.iter_mut() {
+            *byte = SUFFIX_ALPHABET[*byte as usize % SUFFIX_ALPHABET.len()];
+        }
+        let len = ::std::cmp::min(prefix.len(), PEER_ID_LEN);
+        id[..len].copy_from_slice(&prefix[..len]);
+        PeerId(id)
+    }
+
+    /// Builds the Azureus-style prefix `-XXvvvv-` from a two-character
+    /// client code and a four-character version.
+    pub fn azureus_prefix(code: &[u8; 2], version: &[u8; 4]) -> [u8; 8] {
+        let mut prefix = [b'-'; 8];
+        prefix[1..3].copy_from_slice(code);
+        prefix[3..7].copy_from_slice(version);
+        prefix
+    }
+
+    pub fn as_bytes(&self) -> &[u8; PEER_ID_LEN] {
+        &self.0
+    }
+
+    /// Best-effort identification of the Azureus (`-qB4620-`) and
+    /// Mainline (`M7-4-3--`) conventions.
+    pub fn client(&self) -> Option<ClientInfo> {
+        let id = &self.0;
+        if id[0] == b'-' && id[7] == b'-' && id[1..3].iter().all(u8::is_ascii_alphanumeric)
+                && id[3..7].iter().all(u8::is_ascii_alphanumeric) {
+            let code = [id[1], id[2]];
+            let name = AZUREUS_CLIENTS.iter()
+                .find(|&&(known, _)| *known == code)
+                .map(|&(_, name)| name);
+            let version: Vec<String> = id[3..7].iter()
+                .map(|&byte| match byte {
+                    b'0'..=b'9' => (byte - b'0').to_string(),
+                    b'A'..=b'Z' => (byte - b'A' + 10).to_string(),
+                    _ => (byte as char).to_string(),
+                })
+                .collect();
+            return Some(ClientInfo {
+                code: String::from_utf8_lossy(&code).into_owned(),
+                name,
+                version: version.join("."),
+            });
+        }
+
+        // Mainline: a letter, then dash-separated version numbers padded
+        // with dashes to eight bytes.
+        let head = &id[..8];
+        if id[0].is_ascii_uppercase() && head[1..].iter().all(|&b| b == b'-' || b.is_ascii_digit())
+                && head[1].is_ascii_digit() && head[7] == b'-' {
+            let version: Vec<&str> = ::std::str::from_utf8(&head[1..]).ok()?
+                .split('-')
+                .filter(|part| !part.is_empty())
+                .collect();
+            if version.is_empty() {
+                return None;
+            }
+            return Some(ClientInfo {
+                code: (id[0] as char).to_string(),
+                name: if id[0] == b'M' { Some("BitTorrent (Mainline)") } else { None },
+                version: version.join("."),
+            });
+        }
+        None
+    }
+}
+
+impl From<[u8; PEER_ID_LEN]> for PeerId {
+    fn from(id: [u8; PEER_ID_LEN]) -> PeerId {
+        PeerId(id)
+    }
+}
+
+impl AsRef<[u8]> for PeerId {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for PeerId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PeerId(\"{}\")", self.0.escape_ascii())
+    }
+}
+
+#[test]
+fn test_peer_id() {
+    let prefix = PeerId::azureus_prefix(b"qB", b"4620");
+    assert_eq!(&prefix, b"-qB4620-");
+    let id = PeerId::generate(&prefix);
+    assert_eq!(&id.0[..8], b"-qB4620-");
+    assert!(id.0[8..].iter().all(u8::is_ascii_alphanumeric));
+    assert_eq!(id.client(), Some(ClientInfo {
+        code: "qB".to_string(),
+        name: Some("qBittorrent"),
+        version: "4.6.2.0".to_string(),
+    }));
+
+    let mainline = PeerId(*b"M7-10-3-abcdefghijkl");
+    assert_eq!(mainline.client().map(|info| info.version), Some("7.10.3".to_string()));
+    assert_eq!(PeerId([0xff; 20]).client(), None);
+    assert_eq!(format!("{:?}", PeerId(*b"-XX0001-abcdefghij\n\x00")),
+               "PeerId(\"-XX0001-abcdefghij\\n\\x00\")");
+}
@@ -0,0 +1,118 @@
+//! Percent-encoding of raw byte strings, as used for `info_hash` and
+//! `peer_id` in HTTP tracker requests, and a lighter escaping for logs.
+
+use std::fmt;
+
+const HEX_UPPER: &[u8; 16] = b"0123456789ABCDEF";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PercentDecodeError {
+    /// A `%` was not followed by two hex digits.
+    InvalidEscape,
+}
+
+/// Encodes every byte of `bytes` as `%XX`.
+///
+/// Trackers are inconsistent about which characters they accept
+/// unescaped, so nothing is left literal.
+pub fn encode(bytes: &[u8]) -> String {
+    let mut output = String::with_capacity(3 * bytes.len());
+    for &byte in bytes {
+        push_escape(&mut output, byte);
+    }
+    output
+}
+
+/// Encodes `bytes` for use as a URI query component, leaving the RFC 3986
+/// unreserved characters literal.
+pub fn encode_component(bytes: &[u8]) -> String {
+    let mut output = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9'
+                | b'-' | b'.' | b'_' | b'~' => output.push(byte as char),
+            _ => push_escape(&mut output, byte),
+        }
+    }
+    output
+}
+
+/// Displays a byte string with printable ASCII as is and every other byte,
+/// and `%` itself, as `%XX`. Infohashes, peer IDs and tokens stay readable
+/// where they are text, and `decode` turns the output back into the bytes.
+pub struct Escaped<'a>(pub &'a [u8]);
+
+impl<'a> fmt::Display for Escaped<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &byte in self.0 {
+            match byte {
+                b'%' => f.write_str("%25")?,
+                b' '..=b'~' => write!(f, "{}", byte as char)?,
+                _ => write!(f, "%{}{}", HEX_UPPER[(byte >> 4) as usize] as char,
+                            HEX_UPPER[(byte & 0xf) as usize] as char)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn push_escape(output: &mut String, byte: u8) {
+    output.push('%');
+    output.push(HEX_UPPER[(byte >> 4) as usize] as char);
+    output.push(HEX_UPPER[(byte & 0xf) as usize] as char);
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decodes `%XX` escapes in `input`; all other characters are taken
+/// literally.
+pub fn decode(input: &str) -> Result<Vec<u8>, PercentDecodeError> {
+    let input = input.as_bytes();
+    let mut output = Vec::with_capacity(input.len());
+    let mut pos = 0;
+    while pos < input.len() {
+        if input[pos] != b'%' {
+            output.push(input[pos]);
+            pos += 1;
+            continue;
+        }
+        let high = input.get(pos + 1).and_then(|&d| hex_value(d));
+        let low = input.get(pos + 2).and_then(|&d| hex_value(d));
+        match (high, low) {
+            (Some(high), Some(low)) => output.push((high << 4) | low),
+            _ => return Err(PercentDecodeError::InvalidEscape),
+        }
+        pos += 3;
+    }
+    Ok(output)
+}
+
+#[test]
+fn test_percent_roundtrip() {
+    let hash = b"\x12\x34\xab\xcd\xef\x00-~";
+    let encoded = encode(hash);
+    assert_eq!(encoded, "%12%34%AB%CD%EF%00%2D%7E");
+    assert_eq!(decode(&encoded).unwrap(), &hash[..]);
+
+    assert_eq!(encode_component(b"a b/c-d~"), "a%20b%2Fc-d~");
+
+    // Clients commonly leave unreserved characters literal.
+    assert_eq!(decode("%124Vx").unwrap(), b"\x12\x34\x56\x78");
+    assert_eq!(decode("%1"), Err(PercentDecodeError::InvalidEscape));
+    assert_eq!(decode("%zz"), Err(PercentDecodeError::InvalidEscape));
+}
+
+#[test]
+fn test_escaped() {
+    let peer_id = b"-TR3000-\x00\xff%abc 12\n";
+    let escaped = Escaped(peer_id).to_string();
+    assert_eq!(escaped, "-TR3000-%00%FF%25abc 12%0A");
+    assert_eq!(decode(&escaped).unwrap(), &peer_id[..]);
+}
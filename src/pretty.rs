@@ -0,0 +1,89 @@
+//! An indented, human-readable rendering of documents.
+//!
+//! `Pretty` lays a document out one entry per line, like JSON. Text byte
+//! strings are quoted, others are shown as their length and leading hex,
+//! so a `pieces` value takes one line instead of a screenful.
+
+use std::fmt;
+use std::str;
+
+use Bencode;
+
+/// Binary strings longer than this are shown truncated.
+const HEX_PREVIEW: usize = 16;
+
+/// Displays a value across several indented lines.
+pub struct Pretty<'a>(pub &'a Bencode);
+
+fn write_bytes(f: &mut fmt::Formatter, buf: &[u8]) -> fmt::Result {
+    match str::from_utf8(buf) {
+        Ok(text) if !text.chars().any(char::is_control) => write!(f, "{:?}", text),
+        _ => {
+            write!(f, "<{} bytes ", buf.len())?;
+            for byte in buf.iter().take(HEX_PREVIEW) {
+                write!(f, "{:02x}", byte)?;
+            }
+            if buf.len() > HEX_PREVIEW {
+                write!(f, "…")?;
+            }
+            write!(f, ">")
+        },
+    }
+}
+
+fn write_value(f: &mut fmt::Formatter, value: &Bencode, indent: usize) -> fmt::Result {
+    match *value {
+        Bencode::Integer(ref digits) => write!(f, "{}", String::from_utf8_lossy(digits)),
+        Bencode::Bytes(ref buf) => write_bytes(f, buf),
+        Bencode::Array(ref items) if items.is_empty() => write!(f, "[]"),
+        Bencode::Array(ref items) => {
+            writeln!(f, "[")?;
+            for (index, item) in items.iter().enumerate() {
+                write!(f, "{:1$}", "", indent + 2)?;
+                write_value(f, item, indent + 2)?;
+                writeln!(f, "{}", if index + 1 < items.len() { "," } else { "" })?;
+            }
+            write!(f, "{:1$}]", "", indent)
+        },
+        Bencode::Object(ref dict) if dict.is_empty() => write!(f, "{{}}"),
+        Bencode::Object(ref dict) => {
+            writeln!(f, "{{")?;
+            for (index, (key, item)) in dict.iter().enumerate() {
+                write!(f, "{:1$}", "", indent + 2)?;
+                write_bytes(f, key)?;
+                write!(f, ": ")?;
+                write_value(f, item, indent + 2)?;
+                writeln!(f, "{}", if index + 1 < dict.len() { "," } else { "" })?;
+            }
+            write!(f, "{:1$}}}", "", indent)
+        },
+    }
+}
+
+impl<'a> fmt::Display for Pretty<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_value(f, self.0, 0)
+    }
+}
+
+#[test]
+fn test_pretty() {
+    let value = ::bdecode_slice(b"d4:infod6:lengthi42e6:pieces20:\x00\x01\x02\x03\x04\x05\x06\x07\
+                                  \x08\x09\x0a\x0b\x0c\x0d\x0e\x0f\x10\x11\x12\x13e\
+                                  4:listli-1e4:a\"b\nlee3:nilde1:x3:\xff\x00\x01e").unwrap();
+    assert_eq!(Pretty(&value).to_string(), "\
+{
+  \"info\": {
+    \"length\": 42,
+    \"pieces\": <20 bytes 000102030405060708090a0b0c0d0e0f…>
+  },
+  \"list\": [
+    -1,
+    <4 bytes 6122620a>,
+    []
+  ],
+  \"nil\": {},
+  \"x\": <3 bytes ff0001>
+}");
+    assert_eq!(Pretty(&Bencode::from("a\"b")).to_string(), "\"a\\\"b\"");
+}
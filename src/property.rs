@@ -0,0 +1,137 @@
+//! Property testing over generated documents, enabled by the `property`
+//! feature.
+//!
+//! `check` runs a property against documents generated within `Bounds`
+//! from random input and, on the first failure, shrinks the document to a
+//! minimal one that still fails: dictionaries lose keys, lists lose items,
+//! containers are replaced by their children, strings are truncated and
+//! integers move towards zero.
+
+use arbitrary::{Bounds, Unstructured};
+use random;
+use Bencode;
+
+/// Bytes of random input behind each generated document.
+const INPUT_LEN: usize = 1024;
+
+/// Candidates simpler than `value`, roughly simplest first. Each one is a
+/// valid document.
+pub fn shrink(value: &Bencode) -> Vec<Bencode> {
+    let mut out = Vec::new();
+    match *value {
+        Bencode::Integer(_) => {
+            let integer = value.as_i64().unwrap_or(1);
+            if integer != 0 {
+                out.push(Bencode::from(0i64));
+            }
+            if integer / 2 != 0 {
+                out.push(Bencode::from(integer / 2));
+            }
+        },
+        Bencode::Bytes(ref buf) if !buf.is_empty() => {
+            out.push(Bencode::Bytes(Vec::new()));
+            if buf.len() > 2 {
+                out.push(Bencode::Bytes(buf[..buf.len() / 2].to_vec()));
+            }
+            out.push(Bencode::Bytes(buf[..buf.len() - 1].to_vec()));
+        },
+        Bencode::Bytes(_) => (),
+        Bencode::Array(ref items) if !items.is_empty() => {
+            out.push(Bencode::Array(Vec::new()));
+            out.extend(items.iter().cloned());
+            for index in 0..items.len() {
+                let mut fewer = items.clone();
+                fewer.remove(index);
+                out.push(Bencode::Array(fewer));
+            }
+            for (index, item) in items.iter().enumerate() {
+                for simpler in shrink(item) {
+                    let mut items = items.clone();
+                    items[index] = simpler;
+                    out.push(Bencode::Array(items));
+                }
+            }
+        },
+        Bencode::Array(_) => (),
+        Bencode::Object(ref dict) if !dict.is_empty() => {
+            out.push(Bencode::Object(Default::default()));
+            out.extend(dict.values().cloned());
+            for key in dict.keys() {
+                let mut fewer = dict.clone();
+                fewer.remove(key);
+                out.push(Bencode::Object(fewer));
+            }
+            for (key, entry) in dict.iter() {
+                for simpler in shrink(entry) {
+                    let mut dict = dict.clone();
+                    dict.insert(key.clone(), simpler);
+                    out.push(Bencode::Object(dict));
+                }
+            }
+        },
+        Bencode::Object(_) => (),
+    }
+    out
+}
+
+/// Shrinks a document that fails `property` for as long as some simpler
+/// candidate fails too.
+pub fn minimize<F>(mut value: Bencode, mut property: F) -> Bencode
+    where
+        F: FnMut(&Bencode) -> bool {
+
+    'shrinking: loop {
+        for candidate in shrink(&value) {
+            if !property(&candidate) {
+                value = candidate;
+                continue 'shrinking;
+            }
+        }
+        return value;
+    }
+}
+
+/// Runs `property` on `cases` generated documents. Returns the minimized
+/// counterexample if it fails on any.
+pub fn check<F>(cases: usize, bounds: &Bounds, mut property: F) -> Result<(), Bencode>
+    where
+        F: FnMut(&Bencode) -> bool {
+
+    let mut input = vec![0; INPUT_LEN];
+    for _ in 0..cases {
+        random::fill(&mut input);
+        let value = bounds.generate(&mut Unstructured::new(&input));
+        if !property(&value) {
+            return Err(minimize(value, property));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_check() {
+    let bounds = Bounds::default();
+    assert_eq!(check(100, &bounds, |value| {
+        ::bdecode_slice(&::bencode_to_vec(value)).as_ref() == Ok(value)
+    }), Ok(()));
+
+    fn short_strings(value: &Bencode) -> bool {
+        match *value {
+            Bencode::Bytes(ref buf) => buf.len() <= 3,
+            Bencode::Array(ref items) => items.iter().all(short_strings),
+            Bencode::Object(ref dict) => dict.values().all(short_strings),
+            Bencode::Integer(_) => true,
+        }
+    }
+    match check(1000, &bounds, short_strings) {
+        Err(Bencode::Bytes(buf)) => assert_eq!(buf.len(), 4),
+        other => panic!("expected a 4-byte string, got {:?}", other),
+    }
+
+    let dict = ::bdecode_slice(b"d1:ai10e1:bi0ee").unwrap();
+    let candidates = shrink(&dict);
+    assert_eq!(candidates[0], Bencode::Object(Default::default()));
+    assert!(candidates.contains(&::bdecode_slice(b"d1:bi0ee").unwrap()));
+    assert!(candidates.contains(&::bdecode_slice(b"d1:ai5e1:bi0ee").unwrap()));
+    assert!(shrink(&Bencode::from(0i64)).is_empty());
+}
@@ -0,0 +1,35 @@
+//! Non-cryptographic random bytes for identifiers such as peer ids and
+//! transaction ids, without pulling in an RNG crate.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+fn next_u64() -> u64 {
+    // Every `RandomState` is seeded with fresh per-process randomness; the
+    // counter and clock keep successive calls apart.
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_usize(COUNTER.fetch_add(1, Ordering::Relaxed));
+    if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
+        hasher.write_u128(now.as_nanos());
+    }
+    hasher.finish()
+}
+
+pub fn fill(buf: &mut [u8]) {
+    for chunk in buf.chunks_mut(8) {
+        let bytes = next_u64().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
+
+#[test]
+fn test_fill() {
+    let (mut a, mut b) = ([0u8; 20], [0u8; 20]);
+    fill(&mut a);
+    fill(&mut b);
+    assert_ne!(a, b);
+}
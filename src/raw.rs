@@ -0,0 +1,375 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use {Bencode, BencodeResult, MAX_DEPTH, ParseError, is_digit, push_digit};
+use scan;
+
+// Slice-based scanning that tracks byte offsets, so callers can refer back
+// to the exact bytes of a value in the original document.
+
+/// Finds the `delimiter` ending the digit run at `pos` and returns its
+/// offset. The delimiter is located with `scan::find_byte` and the bytes
+/// before it then checked in bulk, instead of testing byte by byte.
+fn scan_delimited(buf: &[u8], pos: usize, delimiter: u8) -> BencodeResult<usize> {
+    let rest = buf.get(pos..).unwrap_or(&[]);
+    match scan::find_byte(delimiter, rest) {
+        Some(0) => Err(ParseError::InvalidCharacter),
+        Some(end) if scan::digit_run(&rest[..end]) == end => Ok(pos + end),
+        Some(_) => Err(ParseError::InvalidCharacter),
+        None if scan::digit_run(rest) == rest.len() => Err(ParseError::Truncated),
+        None => Err(ParseError::InvalidCharacter),
+    }
+}
+
+pub fn scan_integer(buf: &[u8], mut pos: usize) -> BencodeResult<usize> {
+    // skip the leading b'i'
+    pos += 1;
+    if buf.get(pos) == Some(&b'-') {
+        pos += 1;
+    }
+    Ok(scan_delimited(buf, pos, b'e')? + 1)
+}
+
+pub fn scan_bytea(buf: &[u8], pos: usize) -> BencodeResult<Range<usize>> {
+    let colon = scan_delimited(buf, pos, b':')?;
+    let length = buf[pos..colon].iter().try_fold(0, |length, &digit| push_digit(length, digit))?;
+
+    let start = colon + 1;
+    match start.checked_add(length) {
+        Some(end) if end <= buf.len() => Ok(start..end),
+        Some(_) => Err(ParseError::Truncated),
+        None => Err(ParseError::InvalidLength),
+    }
+}
+
+/// The depth guard of the recursive decoders, called on entering each
+/// value at `depth`.
+pub fn check_depth(depth: usize) -> BencodeResult<()> {
+    if depth > MAX_DEPTH {
+        return Err(ParseError::TooDeep);
+    }
+    Ok(())
+}
+
+/// Decodes the value starting at `pos`, nested at `depth`, returning it
+/// with the offset one past its end.
+pub fn decode_value(buf: &[u8], pos: usize, depth: usize) -> BencodeResult<(Bencode, usize)> {
+    check_depth(depth)?;
+    match buf.get(pos) {
+        Some(&b'i') => {
+            let end = scan_integer(buf, pos)?;
+            Ok((Bencode::Integer(buf[pos + 1..end - 1].to_vec()), end))
+        },
+        Some(&b'l') => {
+            let mut items = Vec::new();
+            let mut pos = pos + 1;
+            loop {
+                match buf.get(pos) {
+                    Some(&b'e') => return Ok((Bencode::Array(items), pos + 1)),
+                    Some(_) => {
+                        let (item, end) = decode_value(buf, pos, depth + 1)?;
+                        items.push(item);
+                        pos = end;
+                    },
+                    None => return Err(ParseError::Truncated),
+                }
+            }
+        },
+        Some(&b'd') => {
+            let mut dict = BTreeMap::new();
+            let mut pos = pos + 1;
+            let mut prev_key: &[u8] = &[];
+            loop {
+                match buf.get(pos) {
+                    Some(&b'e') => return Ok((Bencode::Object(dict), pos + 1)),
+                    Some(_) => (),
+                    None => return Err(ParseError::Truncated),
+                }
+                let key = scan_bytea(buf, pos)?;
+                if buf[key.clone()] < *prev_key {
+                    return Err(ParseError::OutOfOrderKey);
+                }
+                prev_key = &buf[key.clone()];
+                let (value, end) = decode_value(buf, key.end, depth + 1)?;
+                dict.insert(prev_key.to_vec(), value);
+                pos = end;
+            }
+        },
+        Some(&val) if is_digit(val) => {
+            let span = scan_bytea(buf, pos)?;
+            let end = span.end;
+            Ok((Bencode::Bytes(buf[span].to_vec()), end))
+        },
+        Some(_) => Err(ParseError::InvalidCharacter),
+        None => Err(ParseError::Truncated),
+    }
+}
+
+/// Decodes `buf`, which must hold exactly one value. For untrusted input
+/// that should be nothing more than one document.
+#[cfg(feature = "std")]
+pub fn decode_exact(buf: &[u8]) -> BencodeResult<Bencode> {
+    match decode_value(buf, 0, 1)? {
+        (value, end) if end == buf.len() => Ok(value),
+        _ => Err(ParseError::InvalidCharacter),
+    }
+}
+
+/// Returns the offset one past the end of the value starting at `pos`,
+/// nested at `depth`.
+pub fn skip_value(buf: &[u8], pos: usize, depth: usize) -> BencodeResult<usize> {
+    check_depth(depth)?;
+    match buf.get(pos) {
+        Some(&b'i') => scan_integer(buf, pos),
+        Some(&b'l') => {
+            let mut pos = pos + 1;
+            loop {
+                match buf.get(pos) {
+                    Some(&b'e') => return Ok(pos + 1),
+                    Some(_) => pos = skip_value(buf, pos, depth + 1)?,
+                    None => return Err(ParseError::Truncated),
+                }
+            }
+        },
+        Some(&b'd') => {
+            let mut entries = DictEntries::nested(buf, pos, depth)?;
+            for entry in entries.by_ref() {
+                entry?;
+            }
+            Ok(entries.pos)
+        },
+        Some(&val) if is_digit(val) => Ok(scan_bytea(buf, pos)?.end),
+        Some(_) => Err(ParseError::InvalidCharacter),
+        None => Err(ParseError::Truncated),
+    }
+}
+
+pub fn is_canonical_integer(digits: &[u8]) -> bool {
+    match digits {
+        b"0" => true,
+        [b'-', b'0', ..] | [b'0', ..] => false,
+        _ => true,
+    }
+}
+
+/// Like `skip_value`, but also rejects encodings that are well formed yet
+/// not the unique canonical one: integers and lengths with leading zeros,
+/// `-0`, and dictionary keys that repeat.
+pub fn skip_canonical(buf: &[u8], pos: usize, depth: usize) -> BencodeResult<usize> {
+    check_depth(depth)?;
+    match buf.get(pos) {
+        Some(&b'i') => {
+            let end = scan_integer(buf, pos)?;
+            if !is_canonical_integer(&buf[pos + 1..end - 1]) {
+                return Err(ParseError::NonCanonical);
+            }
+            Ok(end)
+        },
+        Some(&b'l') => {
+            let mut pos = pos + 1;
+            loop {
+                match buf.get(pos) {
+                    Some(&b'e') => return Ok(pos + 1),
+                    Some(_) => pos = skip_canonical(buf, pos, depth + 1)?,
+                    None => return Err(ParseError::Truncated),
+                }
+            }
+        },
+        Some(&b'd') => {
+            let mut pos = pos + 1;
+            let mut prev_key: Option<&[u8]> = None;
+            loop {
+                match buf.get(pos) {
+                    Some(&b'e') => return Ok(pos + 1),
+                    Some(_) => (),
+                    None => return Err(ParseError::Truncated),
+                }
+                let key = canonical_bytea(buf, pos)?;
+                let (key_end, key) = (key.end, &buf[key]);
+                match prev_key {
+                    Some(prev) if key < prev => return Err(ParseError::OutOfOrderKey),
+                    Some(prev) if key == prev => return Err(ParseError::NonCanonical),
+                    _ => (),
+                }
+                prev_key = Some(key);
+                pos = skip_canonical(buf, key_end, depth + 1)?;
+            }
+        },
+        Some(&val) if is_digit(val) => Ok(canonical_bytea(buf, pos)?.end),
+        Some(_) => Err(ParseError::InvalidCharacter),
+        None => Err(ParseError::Truncated),
+    }
+}
+
+pub fn canonical_bytea(buf: &[u8], pos: usize) -> BencodeResult<Range<usize>> {
+    let span = scan_bytea(buf, pos)?;
+    // Any length prefix longer than "0:" that starts with a zero.
+    if buf[pos] == b'0' && span.start - pos > 2 {
+        return Err(ParseError::NonCanonical);
+    }
+    Ok(span)
+}
+
+/// Appends the number of items in every list of the value at `pos`,
+/// nested at `depth`, to `counts`, in the order the lists open, and returns
+/// the offset one past the value's end.
+#[cfg(feature = "std")]
+pub fn list_lengths(buf: &[u8], pos: usize, depth: usize, counts: &mut Vec<usize>)
+    -> BencodeResult<usize> {
+    check_depth(depth)?;
+    match buf.get(pos) {
+        Some(&b'l') => {
+            let slot = counts.len();
+            counts.push(0);
+            let mut pos = pos + 1;
+            loop {
+                match buf.get(pos) {
+                    Some(&b'e') => return Ok(pos + 1),
+                    Some(_) => {
+                        pos = list_lengths(buf, pos, depth + 1, counts)?;
+                        counts[slot] += 1;
+                    },
+                    None => return Err(ParseError::Truncated),
+                }
+            }
+        },
+        Some(&b'd') => {
+            let mut pos = pos + 1;
+            loop {
+                match buf.get(pos) {
+                    Some(&b'e') => return Ok(pos + 1),
+                    Some(_) => {
+                        let key = scan_bytea(buf, pos)?;
+                        pos = list_lengths(buf, key.end, depth + 1, counts)?;
+                    },
+                    None => return Err(ParseError::Truncated),
+                }
+            }
+        },
+        _ => skip_value(buf, pos, depth),
+    }
+}
+
+/// Iterates over the `(key, value)` byte ranges of the dictionary at `pos`.
+pub struct DictEntries<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    /// Depth of the dictionary itself; its values are one deeper.
+    depth: usize,
+    prev_key: Option<Range<usize>>,
+    done: bool,
+}
+
+impl<'a> DictEntries<'a> {
+    /// Iterates over a top-level dictionary.
+    #[cfg(feature = "std")]
+    pub fn new(buf: &'a [u8], pos: usize) -> BencodeResult<DictEntries<'a>> {
+        DictEntries::nested(buf, pos, 1)
+    }
+
+    /// Iterates over a dictionary nested at `depth`.
+    pub fn nested(buf: &'a [u8], pos: usize, depth: usize) -> BencodeResult<DictEntries<'a>> {
+        check_depth(depth)?;
+        match buf.get(pos) {
+            Some(&b'd') => (),
+            Some(_) => return Err(ParseError::InvalidCharacter),
+            None => return Err(ParseError::Truncated),
+        }
+        Ok(DictEntries {
+            buf,
+            pos: pos + 1,
+            depth,
+            prev_key: None,
+            done: false,
+        })
+    }
+
+    fn next_entry(&mut self) -> BencodeResult<Option<(Range<usize>, Range<usize>)>> {
+        match self.buf.get(self.pos) {
+            Some(&b'e') => {
+                self.pos += 1;
+                return Ok(None);
+            },
+            Some(_) => (),
+            None => return Err(ParseError::Truncated),
+        }
+        let key = scan_bytea(self.buf, self.pos)?;
+        if let Some(ref prev) = self.prev_key {
+            if self.buf[key.clone()] < self.buf[prev.clone()] {
+                return Err(ParseError::OutOfOrderKey);
+            }
+        }
+        let value_end = skip_value(self.buf, key.end, self.depth + 1)?;
+        let value = key.end..value_end;
+        self.pos = value_end;
+        self.prev_key = Some(key.clone());
+        Ok(Some((key, value)))
+    }
+}
+
+impl<'a> Iterator for DictEntries<'a> {
+    type Item = BencodeResult<(Range<usize>, Range<usize>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_entry() {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
+                self.done = true;
+                None
+            },
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            },
+        }
+    }
+}
+
+/// Finds the byte range of the value stored under `key` in the top-level
+/// dictionary of `buf`.
+#[cfg(feature = "std")]
+pub fn dict_value_span(buf: &[u8], key: &[u8])
+    -> BencodeResult<Option<Range<usize>>> {
+
+    for entry in DictEntries::new(buf, 0)? {
+        let (key_range, value_range) = entry?;
+        if &buf[key_range] == key {
+            return Ok(Some(value_range));
+        }
+    }
+    Ok(None)
+}
+
+#[test]
+fn test_dict_value_span() {
+    let document = b"d1:ai-12e4:infod1:xl1:yee1:zi0ee";
+    let span = dict_value_span(document, b"info").unwrap().unwrap();
+    assert_eq!(&document[span], b"d1:xl1:yee");
+    assert_eq!(dict_value_span(document, b"nope").unwrap(), None);
+
+    assert_eq!(dict_value_span(b"d4:infod", b"info"),
+               Err(ParseError::Truncated));
+    assert_eq!(dict_value_span(b"d1:bi1e1:ai2ee", b"info"),
+               Err(ParseError::OutOfOrderKey));
+}
+
+#[test]
+fn test_skip_depth_limit() {
+    let deep = [&[b'l'; MAX_DEPTH][..], &[b'e'; MAX_DEPTH][..]].concat();
+    assert_eq!(skip_value(&deep, 0, 1), Ok(deep.len()));
+    assert_eq!(skip_canonical(&deep, 0, 1), Ok(deep.len()));
+
+    let deeper = vec![b'l'; 1_000_000];
+    assert_eq!(skip_value(&deeper, 0, 1), Err(ParseError::TooDeep));
+    assert_eq!(skip_canonical(&deeper, 0, 1), Err(ParseError::TooDeep));
+    let mut counts = Vec::new();
+    assert_eq!(list_lengths(&deeper, 0, 1, &mut counts), Err(ParseError::TooDeep));
+    let dicts = b"d1:a".repeat(10000);
+    assert_eq!(dict_value_span(&dicts, b"a"), Err(ParseError::TooDeep));
+    assert_eq!(::validate(&deeper), Err(ParseError::TooDeep));
+    assert_eq!(::validate_canonical(&dicts), Err(ParseError::TooDeep));
+}
@@ -0,0 +1,495 @@
+//! libtorrent fastresume data: the bencoded state a client saves per
+//! torrent so it can restart without rechecking.
+
+use std::net::SocketAddr;
+
+use {Bencode, ParseError, bdecode, bencode};
+use fields::{self, put, take_bytes, take_string, take_u64};
+use infohash::InfoHash;
+use torrent::{AnnounceList, Dict};
+use tracker;
+
+/// Value of `file-format` in libtorrent resume files.
+pub const FILE_FORMAT: &str = "libtorrent resume file";
+
+/// Bit of a `pieces` byte set when the piece is downloaded.
+pub const PIECE_HAVE: u8 = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ResumeError {
+    Parse(ParseError),
+    /// A key holds a value of the wrong type or an unusable value.
+    Invalid(&'static str),
+}
+
+impl From<ParseError> for ResumeError {
+    fn from(err: ParseError) -> ResumeError {
+        ResumeError::Parse(err)
+    }
+}
+
+/// A key that held a value of the wrong type.
+impl From<&'static str> for ResumeError {
+    fn from(key: &'static str) -> ResumeError {
+        ResumeError::Invalid(key)
+    }
+}
+
+pub type ResumeResult<T> = Result<T, ResumeError>;
+
+fn take_list(dict: &mut Dict, key: &'static str) -> ResumeResult<Vec<Bencode>> {
+    Ok(fields::take_list(dict, key)?.unwrap_or_default())
+}
+
+fn take_strings(dict: &mut Dict, key: &'static str) -> ResumeResult<Vec<String>> {
+    take_list(dict, key)?.into_iter()
+        .map(|item| match item {
+            Bencode::Bytes(buf) => String::from_utf8(buf).map_err(|_| ResumeError::Invalid(key)),
+            _ => Err(ResumeError::Invalid(key)),
+        })
+        .collect()
+}
+
+fn take_info_hash(dict: &mut Dict, key: &'static str) -> ResumeResult<Option<InfoHash>> {
+    match take_bytes(dict, key)? {
+        None => Ok(None),
+        Some(buf) => InfoHash::from_slice(&buf).map(Some).ok_or(ResumeError::Invalid(key)),
+    }
+}
+
+fn take_peers(dict: &mut Dict, v4: &'static str, v6: &'static str) -> ResumeResult<Vec<SocketAddr>> {
+    let mut peers = Vec::new();
+    if let Some(buf) = take_bytes(dict, v4)? {
+        let addrs = tracker::decode_compact_peers(&buf).map_err(|_| ResumeError::Invalid(v4))?;
+        peers.extend(addrs.into_iter().map(SocketAddr::V4));
+    }
+    if let Some(buf) = take_bytes(dict, v6)? {
+        let addrs = tracker::decode_compact_peers6(&buf).map_err(|_| ResumeError::Invalid(v6))?;
+        peers.extend(addrs.into_iter().map(SocketAddr::V6));
+    }
+    Ok(peers)
+}
+
+fn put_peers(dict: &mut Dict, v4: &str, v6: &str, peers: &[SocketAddr]) {
+    let (mut peers4, mut peers6) = (Vec::new(), Vec::new());
+    for peer in peers {
+        match *peer {
+            SocketAddr::V4(addr) => peers4.push(addr),
+            SocketAddr::V6(addr) => peers6.push(addr),
+        }
+    }
+    if !peers4.is_empty() {
+        put(dict, v4, Some(tracker::encode_compact_peers(&peers4)));
+    }
+    if !peers6.is_empty() {
+        put(dict, v6, Some(tracker::encode_compact_peers6(&peers6)));
+    }
+}
+
+fn put_strings(dict: &mut Dict, key: &str, values: &[String]) {
+    if !values.is_empty() {
+        let list = values.iter().map(|value| Bencode::from(&value[..])).collect();
+        put(dict, key, Some(Bencode::Array(list)));
+    }
+}
+
+/// A partially downloaded piece: which of its 16 KiB blocks are present.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct UnfinishedPiece {
+    pub piece: u64,
+    /// One bit per block, most significant bit first.
+    pub bitmask: Vec<u8>,
+    pub extra: Dict,
+}
+
+impl UnfinishedPiece {
+    fn from_bencode(value: Bencode) -> ResumeResult<UnfinishedPiece> {
+        let mut dict = match value {
+            Bencode::Object(dict) => dict,
+            _ => return Err(ResumeError::Invalid("unfinished")),
+        };
+        let piece = take_u64(&mut dict, "piece")?.ok_or(ResumeError::Invalid("unfinished"))?;
+        let bitmask = take_bytes(&mut dict, "bitmask")?.unwrap_or_default();
+        Ok(UnfinishedPiece { piece, bitmask, extra: dict })
+    }
+
+    fn to_bencode(&self) -> Bencode {
+        let mut dict = self.extra.clone();
+        put(&mut dict, "bitmask", Some(&self.bitmask[..]));
+        put(&mut dict, "piece", Some(self.piece));
+        Bencode::Object(dict)
+    }
+
+    pub fn has_block(&self, block: usize) -> bool {
+        self.bitmask.get(block / 8).is_some_and(|byte| byte & (0x80 >> (block % 8)) != 0)
+    }
+}
+
+/// A libtorrent resume file. Keys without a field here, such as rate
+/// limits and flags, are kept in `extra`.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ResumeData {
+    pub info_hash: Option<InfoHash>,
+    /// `info-hash2`: the v2 infohash of hybrid and v2 torrents.
+    pub info_hash2: Option<InfoHash>,
+    pub name: Option<String>,
+    pub save_path: Option<String>,
+    /// One priority (0 to 7) per file.
+    pub file_priority: Vec<u64>,
+    /// One priority byte per piece.
+    pub piece_priority: Vec<u8>,
+    /// One byte per piece; see `PIECE_HAVE`.
+    pub pieces: Vec<u8>,
+    pub unfinished: Vec<UnfinishedPiece>,
+    /// `peers` and `peers6`.
+    pub peers: Vec<SocketAddr>,
+    /// `banned_peers` and `banned_peers6`.
+    pub banned_peers: Vec<SocketAddr>,
+    /// Renamed file paths, by file index; empty strings keep the original.
+    pub mapped_files: Vec<String>,
+    pub trackers: Option<AnnounceList>,
+    pub url_seeds: Vec<String>,
+    pub total_uploaded: Option<u64>,
+    pub total_downloaded: Option<u64>,
+    pub added_time: Option<u64>,
+    pub completed_time: Option<u64>,
+    /// The info dictionary, when the torrent was saved with it.
+    pub info: Option<Dict>,
+    pub extra: Dict,
+}
+
+impl ResumeData {
+    pub fn from_bencode(value: Bencode) -> ResumeResult<ResumeData> {
+        let mut dict = match value {
+            Bencode::Object(dict) => dict,
+            _ => return Err(ResumeError::Invalid("resume")),
+        };
+        let file_priority = take_list(&mut dict, "file_priority")?.iter()
+            .map(|item| item.as_u64().ok_or(ResumeError::Invalid("file_priority")))
+            .collect::<ResumeResult<_>>()?;
+        let unfinished = take_list(&mut dict, "unfinished")?.into_iter()
+            .map(UnfinishedPiece::from_bencode)
+            .collect::<ResumeResult<_>>()?;
+        let trackers = match dict.remove(&b"trackers"[..]) {
+            None => None,
+            Some(value) => Some(AnnounceList::from_bencode(&value)
+                .ok_or(ResumeError::Invalid("trackers"))?),
+        };
+        let info = match dict.remove(&b"info"[..]) {
+            None => None,
+            Some(Bencode::Object(info)) => Some(info),
+            Some(_) => return Err(ResumeError::Invalid("info")),
+        };
+
+        Ok(ResumeData {
+            info_hash: take_info_hash(&mut dict, "info-hash")?,
+            info_hash2: take_info_hash(&mut dict, "info-hash2")?,
+            name: take_string(&mut dict, "name")?,
+            save_path: take_string(&mut dict, "save_path")?,
+            file_priority,
+            piece_priority: take_bytes(&mut dict, "piece_priority")?.unwrap_or_default(),
+            pieces: take_bytes(&mut dict, "pieces")?.unwrap_or_default(),
+            unfinished,
+            peers: take_peers(&mut dict, "peers", "peers6")?,
+            banned_peers: take_peers(&mut dict, "banned_peers", "banned_peers6")?,
+            mapped_files: take_strings(&mut dict, "mapped_files")?,
+            trackers,
+            url_seeds: take_strings(&mut dict, "url-list")?,
+            total_uploaded: take_u64(&mut dict, "total_uploaded")?,
+            total_downloaded: take_u64(&mut dict, "total_downloaded")?,
+            added_time: take_u64(&mut dict, "added_time")?,
+            completed_time: take_u64(&mut dict, "completed_time")?,
+            info,
+            extra: dict,
+        })
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> ResumeResult<ResumeData> {
+        let mut stream = buf.iter().cloned().peekable();
+        ResumeData::from_bencode(bdecode(&mut stream)?)
+    }
+
+    pub fn to_bencode(&self) -> Bencode {
+        let mut dict = self.extra.clone();
+        put(&mut dict, "info-hash", self.info_hash.as_ref().map(InfoHash::as_bytes));
+        put(&mut dict, "info-hash2", self.info_hash2.as_ref().map(InfoHash::as_bytes));
+        put(&mut dict, "name", self.name.as_ref().map(|s| &s[..]));
+        put(&mut dict, "save_path", self.save_path.as_ref().map(|s| &s[..]));
+        if !self.file_priority.is_empty() {
+            let list = self.file_priority.iter().map(|&p| Bencode::from(p)).collect();
+            put(&mut dict, "file_priority", Some(Bencode::Array(list)));
+        }
+        if !self.piece_priority.is_empty() {
+            put(&mut dict, "piece_priority", Some(&self.piece_priority[..]));
+        }
+        if !self.pieces.is_empty() {
+            put(&mut dict, "pieces", Some(&self.pieces[..]));
+        }
+        if !self.unfinished.is_empty() {
+            let list = self.unfinished.iter().map(UnfinishedPiece::to_bencode).collect();
+            put(&mut dict, "unfinished", Some(Bencode::Array(list)));
+        }
+        put_peers(&mut dict, "peers", "peers6", &self.peers);
+        put_peers(&mut dict, "banned_peers", "banned_peers6", &self.banned_peers);
+        put_strings(&mut dict, "mapped_files", &self.mapped_files);
+        put(&mut dict, "trackers", self.trackers.as_ref().map(AnnounceList::to_bencode));
+        put_strings(&mut dict, "url-list", &self.url_seeds);
+        put(&mut dict, "total_uploaded", self.total_uploaded);
+        put(&mut dict, "total_downloaded", self.total_downloaded);
+        put(&mut dict, "added_time", self.added_time);
+        put(&mut dict, "completed_time", self.completed_time);
+        put(&mut dict, "info", self.info.clone().map(Bencode::Object));
+        Bencode::Object(dict)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        bencode(&self.to_bencode(), &mut buf).expect("writing to a Vec cannot fail");
+        buf
+    }
+
+    pub fn has_piece(&self, index: usize) -> bool {
+        self.pieces.get(index).is_some_and(|&byte| byte & PIECE_HAVE != 0)
+    }
+
+    /// Number of pieces marked as downloaded.
+    pub fn pieces_have(&self) -> usize {
+        self.pieces.iter().filter(|&&byte| byte & PIECE_HAVE != 0).count()
+    }
+
+    /// The path file `index` is stored under, if it was renamed.
+    pub fn mapped_file(&self, index: usize) -> Option<&str> {
+        self.mapped_files.get(index).map(|s| &s[..]).filter(|s| !s.is_empty())
+    }
+}
+
+fn get_string(dict: &Dict, key: &'static str) -> ResumeResult<Option<String>> {
+    match dict.get(key.as_bytes()) {
+        None => Ok(None),
+        Some(Bencode::Bytes(buf)) => String::from_utf8(buf.clone())
+            .map(Some)
+            .map_err(|_| ResumeError::Invalid(key)),
+        Some(_) => Err(ResumeError::Invalid(key)),
+    }
+}
+
+fn get_i64(dict: &Dict, key: &'static str) -> ResumeResult<Option<i64>> {
+    match dict.get(key.as_bytes()) {
+        None => Ok(None),
+        Some(value) => value.as_i64().map(Some).ok_or(ResumeError::Invalid(key)),
+    }
+}
+
+fn set<V: Into<Bencode>>(dict: &mut Dict, key: &str, value: Option<V>) {
+    match value {
+        Some(value) => dict.insert(key.as_bytes().to_vec(), value.into()),
+        None => dict.remove(key.as_bytes()),
+    };
+}
+
+fn bool_value(value: bool) -> Bencode {
+    Bencode::from(u64::from(value))
+}
+
+/// The `qBt-*` keys qBittorrent adds to libtorrent resume data. They live
+/// in `ResumeData::extra`, so keys not modelled here survive a rewrite.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct QbtFields {
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    /// `qBt-savePath`, overriding libtorrent's `save_path`.
+    pub save_path: Option<String>,
+    pub download_path: Option<String>,
+    /// `qBt-name`: a display name set by the user.
+    pub name: Option<String>,
+    /// `qBt-contentLayout`: `Original`, `Subfolder` or `NoSubfolder`.
+    pub content_layout: Option<String>,
+    pub first_last_piece_priority: Option<bool>,
+    pub queue_position: Option<i64>,
+    /// In thousandths; negative values select the global or no limit.
+    pub ratio_limit: Option<i64>,
+    /// In minutes; negative values select the global or no limit.
+    pub seeding_time_limit: Option<i64>,
+}
+
+impl ResumeData {
+    pub fn qbittorrent(&self) -> ResumeResult<QbtFields> {
+        let dict = &self.extra;
+        let tags = match dict.get(&b"qBt-tags"[..]) {
+            None => Vec::new(),
+            Some(Bencode::Array(items)) => items.iter()
+                .map(|item| match *item {
+                    Bencode::Bytes(ref buf) => String::from_utf8(buf.clone())
+                        .map_err(|_| ResumeError::Invalid("qBt-tags")),
+                    _ => Err(ResumeError::Invalid("qBt-tags")),
+                })
+                .collect::<ResumeResult<_>>()?,
+            Some(_) => return Err(ResumeError::Invalid("qBt-tags")),
+        };
+        Ok(QbtFields {
+            category: get_string(dict, "qBt-category")?,
+            tags,
+            save_path: get_string(dict, "qBt-savePath")?,
+            download_path: get_string(dict, "qBt-downloadPath")?,
+            name: get_string(dict, "qBt-name")?,
+            content_layout: get_string(dict, "qBt-contentLayout")?,
+            first_last_piece_priority: get_i64(dict, "qBt-firstLastPiecePriority")?.map(|v| v != 0),
+            queue_position: get_i64(dict, "qBt-queuePosition")?,
+            ratio_limit: get_i64(dict, "qBt-ratioLimit")?,
+            seeding_time_limit: get_i64(dict, "qBt-seedingTimeLimit")?,
+        })
+    }
+
+    /// Writes the modelled `qBt-*` keys, removing those that are unset.
+    pub fn set_qbittorrent(&mut self, fields: &QbtFields) {
+        let dict = &mut self.extra;
+        set(dict, "qBt-category", fields.category.as_ref().map(|s| &s[..]));
+        let tags = fields.tags.iter().map(|tag| Bencode::from(&tag[..])).collect();
+        set(dict, "qBt-tags", Some(Bencode::Array(tags)).filter(|_| !fields.tags.is_empty()));
+        set(dict, "qBt-savePath", fields.save_path.as_ref().map(|s| &s[..]));
+        set(dict, "qBt-downloadPath", fields.download_path.as_ref().map(|s| &s[..]));
+        set(dict, "qBt-name", fields.name.as_ref().map(|s| &s[..]));
+        set(dict, "qBt-contentLayout", fields.content_layout.as_ref().map(|s| &s[..]));
+        set(dict, "qBt-firstLastPiecePriority", fields.first_last_piece_priority.map(bool_value));
+        set(dict, "qBt-queuePosition", fields.queue_position);
+        set(dict, "qBt-ratioLimit", fields.ratio_limit);
+        set(dict, "qBt-seedingTimeLimit", fields.seeding_time_limit);
+    }
+}
+
+/// An rTorrent session file (`<infohash>.torrent.rtorrent`). Keys not
+/// modelled here, including the `custom` dictionary, are kept in `extra`.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct RtorrentSession {
+    pub directory: Option<String>,
+    pub tied_to_file: Option<String>,
+    pub loaded_file: Option<String>,
+    /// 1 when started, 0 when stopped.
+    pub state: Option<u64>,
+    pub complete: Option<bool>,
+    pub priority: Option<u64>,
+    pub total_uploaded: Option<u64>,
+    pub timestamp_started: Option<u64>,
+    pub timestamp_finished: Option<u64>,
+    /// `custom1` to `custom5`, the strings behind `d.custom1` and friends.
+    pub custom: [Option<String>; 5],
+    pub views: Vec<String>,
+    pub extra: Dict,
+}
+
+const RTORRENT_CUSTOM: [&str; 5] = ["custom1", "custom2", "custom3", "custom4", "custom5"];
+
+impl RtorrentSession {
+    pub fn from_bencode(value: Bencode) -> ResumeResult<RtorrentSession> {
+        let mut dict = match value {
+            Bencode::Object(dict) => dict,
+            _ => return Err(ResumeError::Invalid("session")),
+        };
+        let mut custom: [Option<String>; 5] = Default::default();
+        for (slot, &key) in custom.iter_mut().zip(RTORRENT_CUSTOM.iter()) {
+            *slot = take_string(&mut dict, key)?;
+        }
+        Ok(RtorrentSession {
+            directory: take_string(&mut dict, "directory")?,
+            tied_to_file: take_string(&mut dict, "tied_to_file")?,
+            loaded_file: take_string(&mut dict, "loaded_file")?,
+            state: take_u64(&mut dict, "state")?,
+            complete: take_u64(&mut dict, "complete")?.map(|v| v != 0),
+            priority: take_u64(&mut dict, "priority")?,
+            total_uploaded: take_u64(&mut dict, "total_uploaded")?,
+            timestamp_started: take_u64(&mut dict, "timestamp.started")?,
+            timestamp_finished: take_u64(&mut dict, "timestamp.finished")?,
+            custom,
+            views: take_strings(&mut dict, "views")?,
+            extra: dict,
+        })
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> ResumeResult<RtorrentSession> {
+        let mut stream = buf.iter().cloned().peekable();
+        RtorrentSession::from_bencode(bdecode(&mut stream)?)
+    }
+
+    pub fn to_bencode(&self) -> Bencode {
+        let mut dict = self.extra.clone();
+        put(&mut dict, "directory", self.directory.as_ref().map(|s| &s[..]));
+        put(&mut dict, "tied_to_file", self.tied_to_file.as_ref().map(|s| &s[..]));
+        put(&mut dict, "loaded_file", self.loaded_file.as_ref().map(|s| &s[..]));
+        put(&mut dict, "state", self.state);
+        put(&mut dict, "complete", self.complete.map(bool_value));
+        put(&mut dict, "priority", self.priority);
+        put(&mut dict, "total_uploaded", self.total_uploaded);
+        put(&mut dict, "timestamp.started", self.timestamp_started);
+        put(&mut dict, "timestamp.finished", self.timestamp_finished);
+        for (value, &key) in self.custom.iter().zip(RTORRENT_CUSTOM.iter()) {
+            put(&mut dict, key, value.as_ref().map(|s| &s[..]));
+        }
+        // rTorrent always writes `views`, even when empty.
+        let views = self.views.iter().map(|view| Bencode::from(&view[..])).collect();
+        put(&mut dict, "views", Some(Bencode::Array(views)));
+        Bencode::Object(dict)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        bencode(&self.to_bencode(), &mut buf).expect("writing to a Vec cannot fail");
+        buf
+    }
+}
+
+#[test]
+fn test_resume_data() {
+    let buf = b"d10:added_timei1600000000e11:file-format22:libtorrent resume file\
+        13:file_priorityli1ei0ei4ee9:info-hash20:aaaaaaaaaaaaaaaaaaaa\
+        12:mapped_filesl0:5:b.bine4:name4:demo6:pausedi1e\
+        5:peers6:\x7f\x00\x00\x01\x1a\xe1\
+        6:pieces3:\x01\x00\x03\
+        9:save_path4:/tmp8:trackersll5:udp:aee\
+        10:unfinishedld7:bitmask1:\xa05:piecei1eee\
+        8:url-listl6:http:xee";
+    let resume = ResumeData::from_bytes(buf).unwrap();
+    assert_eq!(resume.name, Some("demo".to_string()));
+    assert_eq!(resume.file_priority, vec![1, 0, 4]);
+    assert_eq!(resume.peers, vec!["127.0.0.1:6881".parse::<SocketAddr>().unwrap()]);
+    assert!(resume.has_piece(0) && !resume.has_piece(1) && resume.has_piece(2));
+    assert_eq!(resume.pieces_have(), 2);
+    assert_eq!(resume.mapped_file(0), None);
+    assert_eq!(resume.mapped_file(1), Some("b.bin"));
+    assert!(resume.unfinished[0].has_block(0) && !resume.unfinished[0].has_block(1));
+    assert_eq!(resume.trackers.as_ref().unwrap().flatten(), vec!["udp:a"]);
+    assert_eq!(resume.extra.get(&b"paused"[..]), Some(&Bencode::from(1u64)));
+    assert_eq!(resume.to_bytes(), &buf[..]);
+
+    assert_eq!(ResumeData::from_bytes(b"d5:peers5:abcdee"), Err(ResumeError::Invalid("peers")));
+}
+
+#[test]
+fn test_qbittorrent_fields() {
+    let buf = b"d12:qBt-category5:films17:qBt-contentLayout8:Original\
+        26:qBt-firstLastPiecePriorityi1e8:qBt-tagsl2:hde11:qBt-unknowni7ee";
+    let mut resume = ResumeData::from_bytes(buf).unwrap();
+    let mut fields = resume.qbittorrent().unwrap();
+    assert_eq!(fields.category, Some("films".to_string()));
+    assert_eq!(fields.tags, vec!["hd".to_string()]);
+    assert_eq!(fields.first_last_piece_priority, Some(true));
+
+    fields.category = None;
+    fields.queue_position = Some(3);
+    resume.set_qbittorrent(&fields);
+    assert_eq!(resume.to_bytes(), b"d17:qBt-contentLayout8:Original\
+        26:qBt-firstLastPiecePriorityi1e17:qBt-queuePositioni3e8:qBt-tagsl2:hde\
+        11:qBt-unknowni7ee".to_vec());
+}
+
+#[test]
+fn test_rtorrent_session() {
+    let buf = b"d8:completei1e6:customd3:foo3:bare7:custom15:movie\
+        9:directory4:/dl/8:priorityi2e5:statei1e17:timestamp.startedi1600000000e\
+        5:viewslee";
+    let session = RtorrentSession::from_bytes(buf).unwrap();
+    assert_eq!(session.custom[0], Some("movie".to_string()));
+    assert_eq!(session.directory, Some("/dl/".to_string()));
+    assert_eq!(session.complete, Some(true));
+    assert!(session.extra.contains_key(&b"custom"[..]));
+    assert_eq!(session.to_bytes(), &buf[..]);
+}
@@ -0,0 +1,119 @@
+//! Bulk byte scanning for the slice-based parser.
+//!
+//! Length prefixes and integers are runs of ASCII digits, and finding where
+//! a run ends dominates parse time for documents made mostly of short
+//! strings. On x86_64 and aarch64 the run is checked 16 bytes at a time
+//! with SSE2 or NEON, both part of the baseline for those targets; other
+//! targets use the scalar loop.
+
+use core::convert::TryInto;
+
+#[inline]
+fn scalar_digit_run(buf: &[u8]) -> usize {
+    buf.iter().position(|val| !val.is_ascii_digit()).unwrap_or(buf.len())
+}
+
+/// The number of leading ASCII digits in `buf`.
+#[cfg(target_arch = "x86_64")]
+pub fn digit_run(buf: &[u8]) -> usize {
+    use core::arch::x86_64::{
+        _mm_and_si128, _mm_cmpgt_epi8, _mm_cmplt_epi8, _mm_loadu_si128, _mm_movemask_epi8,
+        _mm_set1_epi8,
+    };
+
+    let mut pos = 0;
+    while pos + 16 <= buf.len() {
+        // SAFETY: SSE2 is always available on x86_64, and the unaligned load
+        // reads 16 bytes that are within `buf`.
+        let mask = unsafe {
+            let chunk = _mm_loadu_si128(buf.as_ptr().add(pos) as *const _);
+            // Bytes above 0x7f compare as negative, so never match.
+            let digits = _mm_and_si128(_mm_cmpgt_epi8(chunk, _mm_set1_epi8(b'0' as i8 - 1)),
+                                       _mm_cmplt_epi8(chunk, _mm_set1_epi8(b'9' as i8 + 1)));
+            _mm_movemask_epi8(digits) as u32
+        };
+        if mask != 0xffff {
+            return pos + (!mask).trailing_zeros() as usize;
+        }
+        pos += 16;
+    }
+    pos + scalar_digit_run(&buf[pos..])
+}
+
+/// The number of leading ASCII digits in `buf`.
+#[cfg(target_arch = "aarch64")]
+pub fn digit_run(buf: &[u8]) -> usize {
+    use core::arch::aarch64::{vandq_u8, vcgeq_u8, vcleq_u8, vdupq_n_u8, vld1q_u8, vminvq_u8};
+
+    let mut pos = 0;
+    while pos + 16 <= buf.len() {
+        // SAFETY: NEON is always available on aarch64, and the load reads
+        // 16 bytes that are within `buf`.
+        let all_digits = unsafe {
+            let chunk = vld1q_u8(buf.as_ptr().add(pos));
+            let digits = vandq_u8(vcgeq_u8(chunk, vdupq_n_u8(b'0')),
+                                  vcleq_u8(chunk, vdupq_n_u8(b'9')));
+            vminvq_u8(digits) == 0xff
+        };
+        if !all_digits {
+            return pos + scalar_digit_run(&buf[pos..pos + 16]);
+        }
+        pos += 16;
+    }
+    pos + scalar_digit_run(&buf[pos..])
+}
+
+/// The number of leading ASCII digits in `buf`.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn digit_run(buf: &[u8]) -> usize {
+    scalar_digit_run(buf)
+}
+
+/// The offset of the first `needle` in `haystack`, checking a word at a
+/// time: a byte of `word ^ pattern` is zero exactly where `needle` is.
+pub fn find_byte(needle: u8, haystack: &[u8]) -> Option<usize> {
+    const LO: u64 = 0x0101_0101_0101_0101;
+    const HI: u64 = 0x8080_8080_8080_8080;
+    let pattern = LO * u64::from(needle);
+
+    let mut chunks = haystack.chunks_exact(8);
+    let mut pos = 0;
+    for chunk in chunks.by_ref() {
+        let word = u64::from_le_bytes(chunk.try_into().expect("chunks are 8 bytes")) ^ pattern;
+        // Borrows only carry upwards, so the lowest flagged byte is exact.
+        let found = word.wrapping_sub(LO) & !word & HI;
+        if found != 0 {
+            return Some(pos + (found.trailing_zeros() / 8) as usize);
+        }
+        pos += 8;
+    }
+    chunks.remainder().iter().position(|&val| val == needle).map(|index| pos + index)
+}
+
+#[test]
+fn test_digit_run() {
+    let mut buf = b"0123456789012345678901234567890123456789".to_vec();
+    assert_eq!(digit_run(&buf), buf.len());
+    for end in 0..buf.len() {
+        for &stop in [b':', b'e', b'/', 0xb0].iter() {
+            let saved = buf[end];
+            buf[end] = stop;
+            assert_eq!(digit_run(&buf), end);
+            assert_eq!(digit_run(&buf[..end]), end);
+            buf[end] = saved;
+        }
+    }
+    assert_eq!(digit_run(b""), 0);
+}
+
+#[test]
+fn test_find_byte() {
+    let buf = b"0123456789:123456789e0123:";
+    for &needle in b":e0x\x01".iter() {
+        for start in 0..buf.len() {
+            assert_eq!(find_byte(needle, &buf[start..]),
+                       buf[start..].iter().position(|&val| val == needle));
+        }
+    }
+    assert_eq!(find_byte(0x80, &[0x7f, 0x00, 0xff, 0x01, 0x80, 0x81, 0, 0, 0]), Some(4));
+}
@@ -0,0 +1,142 @@
+//! A small, dependency-free SHA-1 implementation.
+
+pub const DIGEST_LEN: usize = 20;
+
+const BLOCK_LEN: usize = 64;
+
+#[derive(Clone)]
+pub struct Sha1 {
+    state: [u32; 5],
+    buffer: [u8; BLOCK_LEN],
+    buffered: usize,
+    length: u64,
+}
+
+impl Default for Sha1 {
+    fn default() -> Sha1 {
+        Sha1::new()
+    }
+}
+
+impl Sha1 {
+    pub fn new() -> Sha1 {
+        Sha1 {
+            state: [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0],
+            buffer: [0; BLOCK_LEN],
+            buffered: 0,
+            length: 0,
+        }
+    }
+
+    pub fn digest(data: &[u8]) -> [u8; DIGEST_LEN] {
+        let mut hasher = Sha1::new();
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length = self.length.wrapping_add(data.len() as u64);
+
+        if self.buffered > 0 {
+            let take = ::std::cmp::min(BLOCK_LEN - self.buffered, data.len());
+            self.buffer[self.buffered..self.buffered + take]
+                .copy_from_slice(&data[..take]);
+            self.buffered += take;
+            data = &data[take..];
+            if self.buffered < BLOCK_LEN {
+                return;
+            }
+            let block = self.buffer;
+            self.compress(&block);
+            self.buffered = 0;
+        }
+
+        while data.len() >= BLOCK_LEN {
+            let (block, rest) = data.split_at(BLOCK_LEN);
+            self.compress(block);
+            data = rest;
+        }
+
+        self.buffer[..data.len()].copy_from_slice(data);
+        self.buffered = data.len();
+    }
+
+    pub fn finish(mut self) -> [u8; DIGEST_LEN] {
+        let bit_length = self.length.wrapping_mul(8);
+
+        let mut padding = [0u8; BLOCK_LEN + 8];
+        padding[0] = 0x80;
+        let pad_len = if self.buffered < 56 {
+            56 - self.buffered
+        } else {
+            120 - self.buffered
+        };
+        padding[pad_len..pad_len + 8].copy_from_slice(&bit_length.to_be_bytes());
+        let length = self.length;
+        self.update(&padding[..pad_len + 8]);
+        self.length = length;
+        debug_assert_eq!(self.buffered, 0);
+
+        let mut output = [0u8; DIGEST_LEN];
+        for (chunk, word) in output.chunks_mut(4).zip(self.state.iter()) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        output
+    }
+
+    fn compress(&mut self, block: &[u8]) {
+        let mut w = [0u32; 80];
+        for (i, chunk) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = self.state;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        self.state[0] = self.state[0].wrapping_add(a);
+        self.state[1] = self.state[1].wrapping_add(b);
+        self.state[2] = self.state[2].wrapping_add(c);
+        self.state[3] = self.state[3].wrapping_add(d);
+        self.state[4] = self.state[4].wrapping_add(e);
+    }
+}
+
+#[test]
+fn test_sha1_vectors() {
+    assert_eq!(Sha1::digest(b""), [
+        0xda, 0x39, 0xa3, 0xee, 0x5e, 0x6b, 0x4b, 0x0d, 0x32, 0x55,
+        0xbf, 0xef, 0x95, 0x60, 0x18, 0x90, 0xaf, 0xd8, 0x07, 0x09,
+    ]);
+    assert_eq!(Sha1::digest(b"abc"), [
+        0xa9, 0x99, 0x3e, 0x36, 0x47, 0x06, 0x81, 0x6a, 0xba, 0x3e,
+        0x25, 0x71, 0x78, 0x50, 0xc2, 0x6c, 0x9c, 0xd0, 0xd8, 0x9d,
+    ]);
+
+    // Feeding in odd-sized chunks must match a one-shot digest.
+    let data: Vec<u8> = (0..1000u32).map(|x| x as u8).collect();
+    let mut hasher = Sha1::new();
+    for chunk in data.chunks(37) {
+        hasher.update(chunk);
+    }
+    assert_eq!(hasher.finish(), Sha1::digest(&data));
+}
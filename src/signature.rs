@@ -0,0 +1,291 @@
+//! Torrent signatures (BEP 35): the top-level `signatures` dictionary,
+//! keyed by signer identity.
+//!
+//! A signature covers the `info` dictionary exactly as encoded, followed by
+//! the signature's own `info` dictionary when it has one. The key type is
+//! left to the caller through `SigningKey` and `VerifyingKey`.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use {Bencode, ParseError, bdecode, bencode, raw};
+use patch;
+use torrent::Dict;
+
+pub const SIGNATURES_KEY: &[u8] = b"signatures";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SignatureError {
+    Parse(ParseError),
+    MissingInfo,
+    /// A key holds a value of the wrong type or an unusable value.
+    Invalid(&'static str),
+}
+
+impl From<ParseError> for SignatureError {
+    fn from(err: ParseError) -> SignatureError {
+        SignatureError::Parse(err)
+    }
+}
+
+pub type SignatureResult<T> = Result<T, SignatureError>;
+
+/// Produces signatures over a payload.
+pub trait SigningKey {
+    fn sign(&self, payload: &[u8]) -> Vec<u8>;
+}
+
+/// Checks signatures over a payload.
+pub trait VerifyingKey {
+    fn verify(&self, payload: &[u8], signature: &[u8]) -> bool;
+}
+
+#[cfg(feature = "ed25519")]
+impl SigningKey for ::ed25519::Keypair {
+    fn sign(&self, payload: &[u8]) -> Vec<u8> {
+        ::ed25519::Keypair::sign(self, payload).to_vec()
+    }
+}
+
+#[cfg(feature = "ed25519")]
+impl VerifyingKey for [u8; ::ed25519::PUBLIC_KEY_LEN] {
+    fn verify(&self, payload: &[u8], signature: &[u8]) -> bool {
+        if signature.len() != ::ed25519::SIGNATURE_LEN {
+            return false;
+        }
+        let mut buf = [0; ::ed25519::SIGNATURE_LEN];
+        buf.copy_from_slice(signature);
+        ::ed25519::verify(self, payload, &buf)
+    }
+}
+
+/// One entry of the `signatures` dictionary.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Signature {
+    /// The signer's X.509 certificate, if embedded.
+    pub certificate: Option<Vec<u8>>,
+    /// Extra signed data, appended to the payload.
+    pub info: Option<Dict>,
+    pub signature: Vec<u8>,
+    pub extra: Dict,
+}
+
+impl Signature {
+    pub fn from_bencode(value: Bencode) -> SignatureResult<Signature> {
+        let mut dict = match value {
+            Bencode::Object(dict) => dict,
+            _ => return Err(SignatureError::Invalid("signatures")),
+        };
+        let certificate = match dict.remove(&b"certificate"[..]) {
+            None => None,
+            Some(Bencode::Bytes(buf)) => Some(buf),
+            Some(_) => return Err(SignatureError::Invalid("certificate")),
+        };
+        let info = match dict.remove(&b"info"[..]) {
+            None => None,
+            Some(Bencode::Object(info)) => Some(info),
+            Some(_) => return Err(SignatureError::Invalid("info")),
+        };
+        let signature = match dict.remove(&b"signature"[..]) {
+            Some(Bencode::Bytes(buf)) => buf,
+            _ => return Err(SignatureError::Invalid("signature")),
+        };
+        Ok(Signature { certificate, info, signature, extra: dict })
+    }
+
+    pub fn to_bencode(&self) -> Bencode {
+        let mut dict = self.extra.clone();
+        if let Some(ref certificate) = self.certificate {
+            dict.insert(b"certificate".to_vec(), Bencode::from(&certificate[..]));
+        }
+        if let Some(ref info) = self.info {
+            dict.insert(b"info".to_vec(), Bencode::Object(info.clone()));
+        }
+        dict.insert(b"signature".to_vec(), Bencode::from(&self.signature[..]));
+        Bencode::Object(dict)
+    }
+}
+
+/// Reads the `signatures` dictionary of an encoded `.torrent`; absent
+/// means empty.
+pub fn signatures(document: &[u8]) -> SignatureResult<BTreeMap<Vec<u8>, Signature>> {
+    let span = match raw::dict_value_span(document, SIGNATURES_KEY)? {
+        Some(span) => span,
+        None => return Ok(BTreeMap::new()),
+    };
+    let mut stream = document[span].iter().cloned().peekable();
+    let map = match bdecode(&mut stream)? {
+        Bencode::Object(map) => map,
+        _ => return Err(SignatureError::Invalid("signatures")),
+    };
+    map.into_iter()
+        .map(|(identity, value)| Ok((identity, Signature::from_bencode(value)?)))
+        .collect()
+}
+
+/// The byte range of the `info` dictionary within `document`.
+pub fn info_range(document: &[u8]) -> SignatureResult<Range<usize>> {
+    match raw::dict_value_span(document, b"info")? {
+        Some(span) if document[span.start] == b'd' => Ok(span),
+        _ => Err(SignatureError::MissingInfo),
+    }
+}
+
+fn payload(document: &[u8], info: Option<&[u8]>) -> SignatureResult<Vec<u8>> {
+    let mut payload = document[info_range(document)?].to_vec();
+    if let Some(info) = info {
+        payload.extend_from_slice(info);
+    }
+    Ok(payload)
+}
+
+/// The bytes a signature with the given extra `info` must cover.
+pub fn signing_payload(document: &[u8], info: Option<&Dict>) -> SignatureResult<Vec<u8>> {
+    let info = info.map(|info| {
+        let mut buf = Vec::new();
+        bencode(&Bencode::Object(info.clone()), &mut buf).expect("writing to a Vec cannot fail");
+        buf
+    });
+    payload(document, info.as_ref().map(|info| &info[..]))
+}
+
+/// The payload of the signature stored under `identity`, using its `info`
+/// dictionary exactly as encoded.
+fn stored_payload(document: &[u8], identity: &[u8]) -> SignatureResult<Option<Vec<u8>>> {
+    let span = match raw::dict_value_span(document, SIGNATURES_KEY)? {
+        Some(span) => span,
+        None => return Ok(None),
+    };
+    let signatures = &document[span];
+    for entry in raw::DictEntries::new(signatures, 0)? {
+        let (key, value) = entry?;
+        if &signatures[key] != identity {
+            continue;
+        }
+        let entry = &signatures[value];
+        if entry.first() != Some(&b'd') {
+            return Err(SignatureError::Invalid("signatures"));
+        }
+        let info = raw::dict_value_span(entry, b"info")?.map(|span| &entry[span]);
+        return payload(document, info).map(Some);
+    }
+    Ok(None)
+}
+
+/// Signs `document` as `identity` and returns it with the signature added
+/// to `signatures`, replacing any earlier one from the same identity.
+/// `info`, the other signatures and every other key are kept byte for byte.
+pub fn attach<K: SigningKey + ?Sized>(document: &[u8], identity: &[u8], key: &K,
+                                      certificate: Option<Vec<u8>>, info: Option<Dict>)
+    -> SignatureResult<Vec<u8>> {
+
+    let signature = key.sign(&signing_payload(document, info.as_ref())?);
+    let signature = Signature { certificate, info, signature, extra: Dict::new() }.to_bencode();
+    let mut replacements = BTreeMap::new();
+
+    let span = match raw::dict_value_span(document, SIGNATURES_KEY)? {
+        Some(span) => span,
+        None => {
+            let mut signatures = Dict::new();
+            signatures.insert(identity.to_vec(), signature);
+            replacements.insert(SIGNATURES_KEY.to_vec(), Some(Bencode::Object(signatures)));
+            return Ok(patch::replace_keys(document, &replacements)?);
+        }
+    };
+    if raw::skip_value(document, 0, 1)? != document.len() {
+        return Err(SignatureError::Parse(ParseError::InvalidCharacter));
+    }
+    if document[span.start] != b'd' {
+        return Err(SignatureError::Invalid("signatures"));
+    }
+    // Only the entry for `identity` is written; the others are copied.
+    replacements.insert(identity.to_vec(), Some(signature));
+    let signatures = patch::replace_keys(&document[span.clone()], &replacements)?;
+    let mut out = Vec::with_capacity(document.len() + signatures.len() - span.len());
+    out.extend_from_slice(&document[..span.start]);
+    out.extend_from_slice(&signatures);
+    out.extend_from_slice(&document[span.end..]);
+    Ok(out)
+}
+
+/// Checks the signature stored under `identity`. Returns `Ok(false)` if
+/// there is none or it does not verify.
+pub fn verify<K: VerifyingKey + ?Sized>(document: &[u8], identity: &[u8], key: &K)
+    -> SignatureResult<bool> {
+
+    let payload = match stored_payload(document, identity)? {
+        Some(payload) => payload,
+        None => return Ok(false),
+    };
+    let signatures = signatures(document)?;
+    let signature = &signatures[identity];
+    Ok(key.verify(&payload, &signature.signature))
+}
+
+#[cfg(test)]
+pub(crate) struct XorKey(pub(crate) u8);
+
+#[cfg(test)]
+impl SigningKey for XorKey {
+    fn sign(&self, payload: &[u8]) -> Vec<u8> {
+        vec![payload.iter().fold(self.0, |acc, &b| acc ^ b)]
+    }
+}
+
+#[cfg(test)]
+impl VerifyingKey for XorKey {
+    fn verify(&self, payload: &[u8], signature: &[u8]) -> bool {
+        self.sign(payload) == signature
+    }
+}
+
+#[test]
+fn test_attach_and_verify() {
+    use torrent::AnnounceList;
+
+    let document = b"d8:announce3:foo4:infod4:name1:a12:piece lengthi16384e6:pieces0:ee";
+    let mut info = Dict::new();
+    info.insert(b"note".to_vec(), Bencode::from("hi"));
+    let signed = attach(&document[..], b"alice", &XorKey(7), None, Some(info)).unwrap();
+    let signed = attach(&signed, b"bob", &XorKey(9), Some(b"cert".to_vec()), None).unwrap();
+
+    assert_eq!(signatures(&signed).unwrap().len(), 2);
+    assert_eq!(verify(&signed, b"alice", &XorKey(7)), Ok(true));
+    assert_eq!(verify(&signed, b"bob", &XorKey(9)), Ok(true));
+    assert_eq!(verify(&signed, b"bob", &XorKey(7)), Ok(false));
+    assert_eq!(verify(&signed, b"carol", &XorKey(7)), Ok(false));
+
+    // Editing other keys keeps the signatures valid.
+    let list = AnnounceList { tiers: vec![vec!["bar".to_string()]] };
+    let edited = patch::set_trackers(&signed, Some("bar"), Some(&list)).unwrap();
+    assert_eq!(verify(&edited, b"alice", &XorKey(7)), Ok(true));
+    assert_eq!(info_range(b"d1:ai1ee"), Err(SignatureError::MissingInfo));
+}
+
+#[test]
+fn test_attach_keeps_existing_signatures() {
+    // `i07e` is not how this crate would encode the existing signature's
+    // extra key, so re-encoding it would change its bytes.
+    let document = b"d4:infod4:name1:a12:piece lengthi16384e6:pieces0:e\
+                     10:signaturesd5:aliced9:signature1:\x001:xi07eeee";
+    let signed = attach(&document[..], b"bob", &XorKey(9), None, None).unwrap();
+    let alice = b"5:aliced9:signature1:\x001:xi07ee";
+    assert!(signed.windows(alice.len()).any(|w| w == &alice[..]));
+    assert_eq!(verify(&signed, b"bob", &XorKey(9)), Ok(true));
+    assert_eq!(signatures(&signed).unwrap().len(), 2);
+
+    assert_eq!(attach(b"d4:infod4:name1:ae10:signaturesi1ee", b"bob", &XorKey(9), None, None),
+               Err(SignatureError::Invalid("signatures")));
+}
+
+#[cfg(feature = "ed25519")]
+#[test]
+fn test_ed25519_signature() {
+    use ed25519::Keypair;
+
+    let document = b"d4:infod4:name1:a12:piece lengthi16384e6:pieces0:ee";
+    let keypair = Keypair::from_seed(&[5; 32]);
+    let signed = attach(&document[..], b"me", &keypair, None, None).unwrap();
+    assert_eq!(verify(&signed, b"me", &keypair.public_key()), Ok(true));
+    assert_eq!(verify(&signed, b"me", &Keypair::from_seed(&[6; 32]).public_key()), Ok(false));
+}
@@ -0,0 +1,208 @@
+//! A pull parser over `BufRead` that yields one token at a time.
+//!
+//! Byte strings are announced by length and their contents are read
+//! through `TokenReader::bytes`, a `Read` bounded to the string. A 1 GB
+//! `pieces` value can then be hashed or copied out in pieces rather than
+//! held in memory. Dictionary keys are ordinary byte string tokens, and
+//! their order is not checked.
+
+use std::io::{self, BufRead, Read};
+
+use {ParseError, is_digit};
+
+#[derive(Debug)]
+pub enum StreamError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl From<io::Error> for StreamError {
+    fn from(err: io::Error) -> StreamError {
+        StreamError::Io(err)
+    }
+}
+
+impl From<ParseError> for StreamError {
+    fn from(err: ParseError) -> StreamError {
+        StreamError::Parse(err)
+    }
+}
+
+pub type StreamResult<T> = Result<T, StreamError>;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Token {
+    /// The digits of an integer, with any sign.
+    Integer(Vec<u8>),
+    /// A byte string of this many bytes, to be read with `bytes`. Any part
+    /// left unread is skipped by the next call to `next_token`.
+    Bytes(u64),
+    List,
+    Dict,
+    /// The end of the innermost list or dictionary.
+    End,
+}
+
+pub struct TokenReader<R> {
+    reader: R,
+    /// Unread bytes of the current byte string.
+    remaining: u64,
+    depth: usize,
+}
+
+impl<R: BufRead> TokenReader<R> {
+    pub fn new(reader: R) -> TokenReader<R> {
+        TokenReader { reader, remaining: 0, depth: 0 }
+    }
+
+    /// Nesting depth after the last token.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn peek(&mut self) -> StreamResult<Option<u8>> {
+        Ok(self.reader.fill_buf()?.first().cloned())
+    }
+
+    fn byte(&mut self) -> StreamResult<u8> {
+        match self.peek()? {
+            Some(byte) => {
+                self.reader.consume(1);
+                Ok(byte)
+            },
+            None => Err(ParseError::Truncated.into()),
+        }
+    }
+
+    /// The next token, or `None` at the end of input between top-level
+    /// values.
+    pub fn next_token(&mut self) -> StreamResult<Option<Token>> {
+        if self.remaining > 0 {
+            let remaining = self.remaining;
+            if io::copy(&mut self.bytes(), &mut io::sink())? != remaining {
+                return Err(ParseError::Truncated.into());
+            }
+        }
+        let first = match self.peek()? {
+            Some(first) => first,
+            None if self.depth == 0 => return Ok(None),
+            None => return Err(ParseError::Truncated.into()),
+        };
+        self.reader.consume(1);
+        let token = match first {
+            b'i' => {
+                let mut digits = Vec::new();
+                loop {
+                    match self.byte()? {
+                        b'e' if digits.last().is_some_and(|&last| is_digit(last)) => break,
+                        b'-' if digits.is_empty() => digits.push(b'-'),
+                        digit if is_digit(digit) => digits.push(digit),
+                        _ => return Err(ParseError::InvalidCharacter.into()),
+                    }
+                }
+                Token::Integer(digits)
+            },
+            b'l' => {
+                self.depth += 1;
+                Token::List
+            },
+            b'd' => {
+                self.depth += 1;
+                Token::Dict
+            },
+            b'e' if self.depth > 0 => {
+                self.depth -= 1;
+                Token::End
+            },
+            digit if is_digit(digit) => {
+                let mut length = u64::from(digit - b'0');
+                loop {
+                    match self.byte()? {
+                        b':' => break,
+                        digit if is_digit(digit) => {
+                            length = length.checked_mul(10)
+                                .and_then(|length| length.checked_add(u64::from(digit - b'0')))
+                                .ok_or(ParseError::InvalidLength)?;
+                        },
+                        _ => return Err(ParseError::InvalidCharacter.into()),
+                    }
+                }
+                self.remaining = length;
+                Token::Bytes(length)
+            },
+            _ => return Err(ParseError::InvalidCharacter.into()),
+        };
+        Ok(Some(token))
+    }
+
+    /// The unread part of the current byte string. Reading past the end of
+    /// the input is an `UnexpectedEof` error.
+    pub fn bytes(&mut self) -> ByteString<'_, R> {
+        ByteString { tokens: self }
+    }
+
+    /// Reads the whole current byte string into memory.
+    pub fn read_bytes(&mut self) -> StreamResult<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.bytes().read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// A `Read` over the current byte string of a `TokenReader`.
+pub struct ByteString<'a, R: 'a> {
+    tokens: &'a mut TokenReader<R>,
+}
+
+impl<'a, R: BufRead> Read for ByteString<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.tokens.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let limit = ::std::cmp::min(buf.len() as u64, self.tokens.remaining) as usize;
+        let read = self.tokens.reader.read(&mut buf[..limit])?;
+        if read == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "byte string is truncated"));
+        }
+        self.tokens.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+#[test]
+fn test_token_reader() {
+    let document = &b"d3:bigl10:0123456789e1:ni-42e5:skip!4:xxxxe"[..];
+    let mut tokens = TokenReader::new(document);
+    assert_eq!(tokens.next_token().unwrap(), Some(Token::Dict));
+    assert_eq!(tokens.next_token().unwrap(), Some(Token::Bytes(3)));
+    assert_eq!(tokens.read_bytes().unwrap(), b"big".to_vec());
+    assert_eq!(tokens.next_token().unwrap(), Some(Token::List));
+    assert_eq!(tokens.next_token().unwrap(), Some(Token::Bytes(10)));
+    let mut head = [0; 4];
+    tokens.bytes().read_exact(&mut head).unwrap();
+    assert_eq!(&head, b"0123");
+    let mut rest = Vec::new();
+    tokens.bytes().take(3).read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"456".to_vec());
+    assert_eq!(tokens.next_token().unwrap(), Some(Token::End));
+    assert_eq!(tokens.next_token().unwrap(), Some(Token::Bytes(1)));
+    assert_eq!(tokens.read_bytes().unwrap(), b"n".to_vec());
+    assert_eq!(tokens.next_token().unwrap(), Some(Token::Integer(b"-42".to_vec())));
+    assert_eq!(tokens.next_token().unwrap(), Some(Token::Bytes(5)));
+    assert_eq!(tokens.next_token().unwrap(), Some(Token::Bytes(4)));
+    assert_eq!(tokens.depth(), 1);
+    assert_eq!(tokens.next_token().unwrap(), Some(Token::End));
+    assert_eq!(tokens.next_token().unwrap(), None);
+
+    let mut truncated = TokenReader::new(&b"5:abc"[..]);
+    assert_eq!(truncated.next_token().unwrap(), Some(Token::Bytes(5)));
+    assert_eq!(truncated.read_bytes().map_err(|err| match err {
+        StreamError::Io(err) => err.kind(),
+        StreamError::Parse(_) => io::ErrorKind::Other,
+    }), Err(io::ErrorKind::UnexpectedEof));
+    assert!(TokenReader::new(&b"i-e"[..]).next_token().is_err());
+}
@@ -0,0 +1,294 @@
+//! A flat "tape" representation of a decoded document.
+//!
+//! Every value becomes one node in a single vector, in document order,
+//! with containers recording where their subtree ends. Byte strings and
+//! integers borrow from the input, so parsing makes one allocation for the
+//! whole document, and walking it touches memory in order. A `Cursor`
+//! navigates the tape.
+
+use std::ops::Range;
+
+use {ParseError, is_digit};
+use raw;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Integer,
+    Bytes,
+    List,
+    Dict,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Node {
+    kind: Kind,
+    /// Span of the whole encoded value.
+    start: usize,
+    end: usize,
+    /// Start of a byte string's contents or an integer's digits.
+    data: usize,
+    /// Index of the first node after this one's subtree.
+    next: usize,
+    /// Items of a list, or entries of a dictionary.
+    len: usize,
+}
+
+/// The parsed tape of one document. Dictionary entries are stored as a key
+/// node followed by the value's nodes.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Tape<'a> {
+    buf: &'a [u8],
+    nodes: Vec<Node>,
+}
+
+struct Frame {
+    node: usize,
+    /// For dictionaries: whether the next node is a key, and the last key.
+    expect_key: bool,
+    prev_key: Option<Range<usize>>,
+}
+
+impl<'a> Tape<'a> {
+    /// Parses the value at the start of `buf`.
+    pub fn parse(buf: &'a [u8]) -> Result<Tape<'a>, ParseError> {
+        let mut nodes: Vec<Node> = Vec::new();
+        let mut stack: Vec<Frame> = Vec::new();
+        let mut pos = 0;
+        loop {
+            let parent_is_dict = stack.last().map(|frame| nodes[frame.node].kind == Kind::Dict);
+            let expect_key = stack.last().is_some_and(|frame| frame.expect_key);
+            // A list may close anywhere, a dictionary only before a key.
+            let may_close = expect_key || parent_is_dict == Some(false);
+            match buf.get(pos) {
+                Some(&b'e') if may_close => {
+                    let frame = stack.pop().expect("stack is not empty");
+                    let next = nodes.len();
+                    let node = &mut nodes[frame.node];
+                    node.end = pos + 1;
+                    node.next = next;
+                    pos += 1;
+                },
+                Some(&b'e') if !stack.is_empty() => return Err(ParseError::InvalidCharacter),
+                Some(&val) => {
+                    if expect_key && !is_digit(val) {
+                        return Err(ParseError::InvalidCharacter);
+                    }
+                    let (kind, data, end) = match val {
+                        b'i' => {
+                            let end = raw::scan_integer(buf, pos)?;
+                            (Kind::Integer, pos + 1, Some(end))
+                        },
+                        b'l' => (Kind::List, pos + 1, None),
+                        b'd' => (Kind::Dict, pos + 1, None),
+                        val if is_digit(val) => {
+                            let span = raw::scan_bytea(buf, pos)?;
+                            (Kind::Bytes, span.start, Some(span.end))
+                        },
+                        _ => return Err(ParseError::InvalidCharacter),
+                    };
+                    if let Some(frame) = stack.last_mut() {
+                        if frame.expect_key {
+                            let key = data..end.expect("keys are byte strings");
+                            if let Some(ref prev) = frame.prev_key {
+                                if buf[key.clone()] < buf[prev.clone()] {
+                                    return Err(ParseError::OutOfOrderKey);
+                                }
+                            }
+                            frame.prev_key = Some(key);
+                        }
+                        if parent_is_dict == Some(false) || frame.expect_key {
+                            nodes[frame.node].len += 1;
+                        }
+                        if parent_is_dict == Some(true) {
+                            frame.expect_key = !frame.expect_key;
+                        }
+                    }
+                    let index = nodes.len();
+                    nodes.push(Node { kind, start: pos, end: 0, data, next: index + 1, len: 0 });
+                    match end {
+                        Some(end) => {
+                            nodes[index].end = end;
+                            pos = end;
+                        },
+                        None => {
+                            let expect_key = kind == Kind::Dict;
+                            stack.push(Frame { node: index, expect_key, prev_key: None });
+                            pos += 1;
+                        },
+                    }
+                },
+                None => return Err(ParseError::Truncated),
+            }
+            if stack.is_empty() {
+                return Ok(Tape { buf, nodes });
+            }
+        }
+    }
+
+    pub fn root(&self) -> Cursor<'_, 'a> {
+        Cursor { tape: self, index: 0 }
+    }
+
+    /// The number of nodes on the tape.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+/// A position on a `Tape`.
+#[derive(Clone, Copy, Debug)]
+pub struct Cursor<'t, 'a: 't> {
+    tape: &'t Tape<'a>,
+    index: usize,
+}
+
+impl<'t, 'a> Cursor<'t, 'a> {
+    fn node(&self) -> &'t Node {
+        &self.tape.nodes[self.index]
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.node().kind
+    }
+
+    /// The value's encoded bytes, exactly as in the input.
+    pub fn raw(&self) -> &'a [u8] {
+        let node = self.node();
+        &self.tape.buf[node.start..node.end]
+    }
+
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        let node = self.node();
+        match node.kind {
+            Kind::Bytes => Some(&self.tape.buf[node.data..node.end]),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&'a str> {
+        self.as_bytes().and_then(|buf| ::std::str::from_utf8(buf).ok())
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        let node = self.node();
+        match node.kind {
+            Kind::Integer => ::std::str::from_utf8(&self.tape.buf[node.data..node.end - 1])
+                .ok()?.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Items of a list or entries of a dictionary; 0 for other values.
+    pub fn len(&self) -> usize {
+        self.node().len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The children in order: list items, or alternating dictionary keys
+    /// and values.
+    fn children(&self) -> Children<'t, 'a> {
+        let node = self.node();
+        let end = match node.kind {
+            Kind::List | Kind::Dict => node.next,
+            _ => self.index + 1,
+        };
+        Children { tape: self.tape, index: self.index + 1, end }
+    }
+
+    /// The items of a list; empty for other values.
+    pub fn items(&self) -> Children<'t, 'a> {
+        match self.kind() {
+            Kind::List => self.children(),
+            _ => Children { tape: self.tape, index: 0, end: 0 },
+        }
+    }
+
+    /// The `(key, value)` entries of a dictionary; empty for other values.
+    pub fn entries(&self) -> Entries<'t, 'a> {
+        let children = match self.kind() {
+            Kind::Dict => self.children(),
+            _ => Children { tape: self.tape, index: 0, end: 0 },
+        };
+        Entries { children }
+    }
+
+    /// Looks a key up in a dictionary.
+    pub fn get(&self, key: &[u8]) -> Option<Cursor<'t, 'a>> {
+        self.entries().find(|&(probe, _)| probe == key).map(|(_, value)| value)
+    }
+
+    /// The `index`th item of a list.
+    pub fn index(&self, index: usize) -> Option<Cursor<'t, 'a>> {
+        self.items().nth(index)
+    }
+}
+
+/// Iterates over sibling nodes, skipping each one's subtree.
+pub struct Children<'t, 'a: 't> {
+    tape: &'t Tape<'a>,
+    index: usize,
+    end: usize,
+}
+
+impl<'t, 'a> Iterator for Children<'t, 'a> {
+    type Item = Cursor<'t, 'a>;
+
+    fn next(&mut self) -> Option<Cursor<'t, 'a>> {
+        if self.index >= self.end {
+            return None;
+        }
+        let cursor = Cursor { tape: self.tape, index: self.index };
+        self.index = self.tape.nodes[self.index].next;
+        Some(cursor)
+    }
+}
+
+pub struct Entries<'t, 'a: 't> {
+    children: Children<'t, 'a>,
+}
+
+impl<'t, 'a> Iterator for Entries<'t, 'a> {
+    type Item = (&'a [u8], Cursor<'t, 'a>);
+
+    fn next(&mut self) -> Option<(&'a [u8], Cursor<'t, 'a>)> {
+        let key = self.children.next()?;
+        let value = self.children.next()?;
+        Some((key.as_bytes().expect("keys are byte strings"), value))
+    }
+}
+
+#[test]
+fn test_tape() {
+    let buf = b"d1:ali1eli2ei3ee4:spame1:bd1:xi-1ee1:c0:e";
+    let tape = Tape::parse(buf).unwrap();
+    assert_eq!(tape.len(), 14);
+    let root = tape.root();
+    assert_eq!(root.kind(), Kind::Dict);
+    assert_eq!(root.len(), 3);
+    assert_eq!(root.raw(), &buf[..]);
+
+    let a = root.get(b"a").unwrap();
+    assert_eq!(a.len(), 3);
+    let items: Vec<&[u8]> = a.items().map(|item| item.raw()).collect();
+    assert_eq!(items, vec![&b"i1e"[..], b"li2ei3ee", b"4:spam"]);
+    assert_eq!(a.index(1).and_then(|inner| inner.index(1)).and_then(|c| c.as_i64()), Some(3));
+    assert_eq!(a.index(2).and_then(|c| c.as_str()), Some("spam"));
+    assert_eq!(root.get(b"b").and_then(|b| b.get(b"x")).and_then(|x| x.as_i64()), Some(-1));
+    assert_eq!(root.get(b"c").and_then(|c| c.as_bytes()), Some(&b""[..]));
+    assert!(root.get(b"d").is_none());
+    let keys: Vec<&[u8]> = root.entries().map(|(key, _)| key).collect();
+    assert_eq!(keys, vec![&b"a"[..], b"b", b"c"]);
+
+    assert_eq!(Tape::parse(b"i7e").unwrap().root().as_i64(), Some(7));
+    assert_eq!(Tape::parse(b"d1:ae"), Err(ParseError::InvalidCharacter));
+    assert_eq!(Tape::parse(b"di1e0:e"), Err(ParseError::InvalidCharacter));
+    assert_eq!(Tape::parse(b"d1:b0:1:a0:e"), Err(ParseError::OutOfOrderKey));
+    assert_eq!(Tape::parse(b"ll"), Err(ParseError::Truncated));
+}
@@ -0,0 +1,202 @@
+//! Documents with placeholders, for stamping out many similar documents.
+//!
+//! A template is an ordinary document in which some byte string values are
+//! placeholders: `{{name:int}}`, `{{name:bytes}}`, or `{{name}}` for any
+//! value. `Template::new` encodes everything around the placeholders once;
+//! after that, each document costs a `bind` per placeholder and a copy of
+//! the pieces, with no tree to build. Dictionary keys are always literal,
+//! so the key order is fixed when the template is made.
+
+use std::str;
+
+use {Bencode, bencode_to_vec};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Integer,
+    Bytes,
+    Any,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TemplateError {
+    /// A `{{...}}` string that is not a valid placeholder.
+    InvalidPlaceholder(String),
+    /// `bind` named a placeholder the template does not have.
+    Unknown(String),
+    /// `bind` gave a placeholder a value of the wrong kind.
+    WrongKind(String),
+    /// `encode` found a placeholder with no value bound.
+    Unbound(String),
+}
+
+#[derive(Clone, Debug)]
+struct Placeholder {
+    name: String,
+    kind: Kind,
+    /// The bound value, already encoded.
+    value: Option<Vec<u8>>,
+}
+
+/// A document compiled into literal runs of encoded bytes, with a
+/// placeholder between each pair of runs.
+#[derive(Clone, Debug)]
+pub struct Template {
+    placeholders: Vec<Placeholder>,
+    /// One more run than there are slots: `runs[0]`, `slots[0]`,
+    /// `runs[1]`, and so on.
+    runs: Vec<Vec<u8>>,
+    /// Indices into `placeholders`; a name used twice has two slots.
+    slots: Vec<usize>,
+}
+
+/// Reads a `{{name:kind}}` placeholder, or returns `None` for an ordinary
+/// byte string.
+fn parse_placeholder(buf: &[u8]) -> Result<Option<(&str, Kind)>, TemplateError> {
+    let inner = match buf.strip_prefix(b"{{").and_then(|rest| rest.strip_suffix(b"}}")) {
+        Some(inner) => inner,
+        None => return Ok(None),
+    };
+    let invalid = || {
+        TemplateError::InvalidPlaceholder(String::from_utf8_lossy(buf).into_owned())
+    };
+    let inner = str::from_utf8(inner).map_err(|_| invalid())?;
+    let (name, kind) = match inner.split_once(':') {
+        Some((name, "int")) => (name, Kind::Integer),
+        Some((name, "bytes")) => (name, Kind::Bytes),
+        Some(_) => return Err(invalid()),
+        None => (inner, Kind::Any),
+    };
+    if name.is_empty() {
+        return Err(invalid());
+    }
+    Ok(Some((name, kind)))
+}
+
+fn push_bytea(run: &mut Vec<u8>, buf: &[u8]) {
+    run.extend_from_slice(buf.len().to_string().as_bytes());
+    run.push(b':');
+    run.extend_from_slice(buf);
+}
+
+impl Template {
+    pub fn new(document: &Bencode) -> Result<Template, TemplateError> {
+        let mut template = Template {
+            placeholders: Vec::new(),
+            runs: vec![Vec::new()],
+            slots: Vec::new(),
+        };
+        template.compile(document)?;
+        Ok(template)
+    }
+
+    fn run(&mut self) -> &mut Vec<u8> {
+        self.runs.last_mut().expect("there is always a run")
+    }
+
+    fn compile(&mut self, value: &Bencode) -> Result<(), TemplateError> {
+        match *value {
+            Bencode::Bytes(ref buf) => match parse_placeholder(buf)? {
+                Some((name, kind)) => {
+                    // A name used twice must have the same kind both times.
+                    let index = match self.placeholders.iter().position(|p| p.name == name) {
+                        Some(index) if self.placeholders[index].kind == kind => index,
+                        Some(_) => return Err(TemplateError::InvalidPlaceholder(name.to_string())),
+                        None => {
+                            let name = name.to_string();
+                            self.placeholders.push(Placeholder { name, kind, value: None });
+                            self.placeholders.len() - 1
+                        },
+                    };
+                    self.slots.push(index);
+                    self.runs.push(Vec::new());
+                },
+                None => push_bytea(self.run(), buf),
+            },
+            Bencode::Integer(ref digits) => {
+                let run = self.run();
+                run.push(b'i');
+                run.extend_from_slice(digits);
+                run.push(b'e');
+            },
+            Bencode::Array(ref items) => {
+                self.run().push(b'l');
+                for item in items.iter() {
+                    self.compile(item)?;
+                }
+                self.run().push(b'e');
+            },
+            Bencode::Object(ref dict) => {
+                self.run().push(b'd');
+                for (key, item) in dict.iter() {
+                    push_bytea(self.run(), key);
+                    self.compile(item)?;
+                }
+                self.run().push(b'e');
+            },
+        }
+        Ok(())
+    }
+
+    /// Sets the value of every placeholder called `name`. The value stays
+    /// bound across `encode`s until it is bound again.
+    pub fn bind<V>(&mut self, name: &str, value: V) -> Result<&mut Template, TemplateError>
+        where
+            V: Into<Bencode> {
+
+        let placeholder = match self.placeholders.iter_mut().find(|p| p.name == name) {
+            Some(placeholder) => placeholder,
+            None => return Err(TemplateError::Unknown(name.to_string())),
+        };
+        let value = value.into();
+        match (placeholder.kind, &value) {
+            (Kind::Integer, &Bencode::Integer(_)) | (Kind::Bytes, &Bencode::Bytes(_)) => (),
+            (Kind::Any, _) => (),
+            _ => return Err(TemplateError::WrongKind(name.to_string())),
+        }
+        placeholder.value = Some(bencode_to_vec(&value));
+        Ok(self)
+    }
+
+    /// Appends the document, with the values bound so far, to `out`.
+    pub fn encode_into(&self, out: &mut Vec<u8>) -> Result<(), TemplateError> {
+        out.extend_from_slice(&self.runs[0]);
+        for (&slot, run) in self.slots.iter().zip(self.runs[1..].iter()) {
+            let placeholder = &self.placeholders[slot];
+            match placeholder.value {
+                Some(ref value) => out.extend_from_slice(value),
+                None => return Err(TemplateError::Unbound(placeholder.name.clone())),
+            }
+            out.extend_from_slice(run);
+        }
+        Ok(())
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, TemplateError> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out)?;
+        Ok(out)
+    }
+}
+
+#[test]
+fn test_template() {
+    let document = ::bdecode_slice(b"d8:completei0e8:interval12:{{interval}}5:peers\
+                                    15:{{peers:bytes}}9:peers_dupl15:{{peers:bytes}}ee").unwrap();
+    let mut template = Template::new(&document).unwrap();
+    assert_eq!(template.encode(), Err(TemplateError::Unbound("interval".to_string())));
+
+    template.bind("interval", 1800i64).unwrap()
+        .bind("peers", &b"\x7f\x00\x00\x01\x1a\xe1"[..]).unwrap();
+    let expected = &b"d8:completei0e8:intervali1800e5:peers6:\x7f\x00\x00\x01\x1a\xe1\
+                      9:peers_dupl6:\x7f\x00\x00\x01\x1a\xe1ee"[..];
+    assert_eq!(template.encode().unwrap(), expected);
+    template.bind("interval", 900i64).unwrap();
+    assert!(template.encode().unwrap().starts_with(b"d8:completei0e8:intervali900e"));
+
+    assert_eq!(template.bind("peers", 1i64).err(),
+               Some(TemplateError::WrongKind("peers".to_string())));
+    assert_eq!(template.bind("port", 1i64).err(), Some(TemplateError::Unknown("port".to_string())));
+    assert!(Template::new(&Bencode::from("{{x:float}}")).is_err());
+    assert_eq!(Template::new(&Bencode::from("{x}")).unwrap().encode().unwrap(), &b"3:{x}"[..]);
+}
@@ -0,0 +1,82 @@
+//! Round-trip assertions for tests, enabled by the `test-util` feature.
+//!
+//! Both helpers panic with the offset of the first difference and both
+//! encodings around it, which is easier to read than two long byte vectors.
+
+use Bencode;
+
+/// Bytes of context shown on either side of the first difference.
+const CONTEXT: usize = 16;
+
+fn render(buf: &[u8]) -> String {
+    let mut out = String::new();
+    for &byte in buf.iter() {
+        match byte {
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(byte as char),
+            _ => out.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    out
+}
+
+/// Describes how `actual` differs from `expected`, or `None` if it doesn't.
+fn diff(expected: &[u8], actual: &[u8]) -> Option<String> {
+    let offset = expected.iter().zip(actual.iter()).position(|(a, b)| a != b)
+        .unwrap_or(::std::cmp::min(expected.len(), actual.len()));
+    if offset == expected.len() && offset == actual.len() {
+        return None;
+    }
+    let window = |buf: &[u8]| {
+        let start = offset.saturating_sub(CONTEXT);
+        let end = ::std::cmp::min(buf.len(), offset + CONTEXT);
+        format!("{}{}{}", if start > 0 { "…" } else { "" }, render(&buf[start..end]),
+                if end < buf.len() { "…" } else { "" })
+    };
+    Some(format!("first difference at byte {} ({} vs {} bytes long)\n  \
+                  expected: {}\n    actual: {}",
+                 offset, expected.len(), actual.len(), window(expected), window(actual)))
+}
+
+/// Asserts that `buf` decodes and re-encodes to exactly the same bytes.
+pub fn assert_roundtrip(buf: &[u8]) {
+    let value = match ::bdecode_slice(buf) {
+        Ok(value) => value,
+        Err(err) => panic!("failed to decode {}: {:?}", render(buf), err),
+    };
+    if let Some(diff) = diff(buf, &::bencode_to_vec(&value)) {
+        panic!("re-encoding changed the document: {}", diff);
+    }
+}
+
+/// Asserts that `value` encodes canonically and decodes back unchanged.
+pub fn assert_canonical_roundtrip(value: &Bencode) {
+    let buf = ::bencode_to_vec(value);
+    if let Err(err) = ::validate_canonical(&buf) {
+        panic!("encoding is not canonical ({:?}): {}", err, render(&buf));
+    }
+    match ::bdecode_slice(&buf) {
+        Ok(ref decoded) if decoded == value => (),
+        Ok(decoded) => {
+            let diff = diff(&buf, &::bencode_to_vec(&decoded))
+                .unwrap_or_else(|| format!("{:?}\n    actual: {:?}", value, decoded));
+            panic!("decoding changed the value: {}", diff);
+        },
+        Err(err) => panic!("failed to decode {}: {:?}", render(&buf), err),
+    }
+}
+
+#[test]
+fn test_roundtrip_helpers() {
+    assert_roundtrip(b"d1:ali1e3:\x00\xffxe1:bd1:ci-2eee");
+    assert_canonical_roundtrip(&::bdecode_slice(b"d1:ali1eee").unwrap());
+
+    let failure = ::std::panic::catch_unwind(|| assert_roundtrip(b"d1:ai1e1:ai2ee")).unwrap_err();
+    let message = failure.downcast_ref::<String>().unwrap();
+    assert!(message.contains("first difference at byte 5 (14 vs 8 bytes long)"), "{}", message);
+    assert!(message.contains("expected: d1:ai1e1:ai2ee"), "{}", message);
+    assert!(message.contains("actual: d1:ai2ee"), "{}", message);
+
+    let integer = Bencode::Integer(b"007".to_vec());
+    assert!(::std::panic::catch_unwind(|| assert_canonical_roundtrip(&integer)).is_err());
+}
@@ -0,0 +1,102 @@
+//! Conformance samples with their expected outcomes, enabled by the
+//! `testvectors` feature.
+//!
+//! Each `Vector` pairs an input with what a decoder should make of it: a
+//! canonical document, a document that is well formed but not canonical
+//! (which lenient decoders accept and strict ones reject), or an invalid
+//! one. Errors are this crate's `ParseError`s; another implementation will
+//! usually only compare whether it fails. Every input is exactly one value
+//! or a malformed attempt at one, with nothing after it unless the vector
+//! is about trailing data.
+
+use ParseError;
+use ParseError::{InvalidCharacter, InvalidLength, OutOfOrderKey, Truncated};
+use self::Expected::{Canonical, Invalid, NonCanonical};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Expected {
+    Canonical,
+    /// Well formed, but not the canonical encoding of its value.
+    NonCanonical,
+    Invalid(ParseError),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Vector {
+    pub name: &'static str,
+    pub input: &'static [u8],
+    pub expected: Expected,
+}
+
+const fn vector(name: &'static str, input: &'static [u8], expected: Expected) -> Vector {
+    Vector { name, input, expected }
+}
+
+pub static VECTORS: &[Vector] = &[
+    // Integers.
+    vector("zero", b"i0e", Canonical),
+    vector("positive", b"i42e", Canonical),
+    vector("negative", b"i-42e", Canonical),
+    vector("i64 max", b"i9223372036854775807e", Canonical),
+    vector("i64 min", b"i-9223372036854775808e", Canonical),
+    vector("beyond 64 bits", b"i123456789012345678901234567890e", Canonical),
+    vector("leading zero", b"i03e", NonCanonical),
+    vector("negative zero", b"i-0e", NonCanonical),
+    vector("negative leading zero", b"i-03e", NonCanonical),
+    vector("empty integer", b"ie", Invalid(InvalidCharacter)),
+    vector("lone minus", b"i-e", Invalid(InvalidCharacter)),
+    vector("plus sign", b"i+1e", Invalid(InvalidCharacter)),
+    vector("double minus", b"i--1e", Invalid(InvalidCharacter)),
+    vector("inner minus", b"i1-1e", Invalid(InvalidCharacter)),
+    vector("fraction", b"i1.5e", Invalid(InvalidCharacter)),
+    vector("unterminated integer", b"i12", Invalid(Truncated)),
+    // Byte strings.
+    vector("empty string", b"0:", Canonical),
+    vector("string", b"4:spam", Canonical),
+    vector("binary string", b"3:\x00\xffe", Canonical),
+    vector("length with leading zero", b"04:spam", NonCanonical),
+    vector("short string", b"5:spam", Invalid(Truncated)),
+    vector("missing colon", b"4spam", Invalid(InvalidCharacter)),
+    vector("negative length", b"-1:a", Invalid(InvalidCharacter)),
+    vector("length overflow", b"99999999999999999999999:a", Invalid(InvalidLength)),
+    vector("bare length", b"12", Invalid(Truncated)),
+    // Lists.
+    vector("empty list", b"le", Canonical),
+    vector("list", b"li1e4:spame", Canonical),
+    vector("nested lists", b"llleee", Canonical),
+    vector("deep nesting", b"lllllllllllllllllllllllllllllllleeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee",
+           Canonical),
+    vector("unterminated list", b"li1e", Invalid(Truncated)),
+    vector("bad list item", b"lxe", Invalid(InvalidCharacter)),
+    // Dictionaries.
+    vector("empty dictionary", b"de", Canonical),
+    vector("dictionary", b"d3:bar4:spam3:fooi42ee", Canonical),
+    vector("nested dictionary", b"d1:ad1:bd1:ci1eeee", Canonical),
+    vector("keys in byte order", b"d1:Ai1e1:ai2ee", Canonical),
+    vector("prefix key first", b"d1:ai1e2:abi2ee", Canonical),
+    vector("keys out of order", b"d1:bi1e1:ai2ee", Invalid(OutOfOrderKey)),
+    vector("longer key first", b"d2:abi1e1:ai2ee", Invalid(OutOfOrderKey)),
+    vector("duplicate key", b"d1:ai1e1:ai2ee", NonCanonical),
+    vector("integer key", b"di1ei2ee", Invalid(InvalidCharacter)),
+    vector("list key", b"dlei1ee", Invalid(InvalidCharacter)),
+    vector("missing value", b"d1:ae", Invalid(InvalidCharacter)),
+    vector("unterminated dictionary", b"d1:ai1e", Invalid(Truncated)),
+    // Documents.
+    vector("empty input", b"", Invalid(Truncated)),
+    vector("unknown type", b"x", Invalid(InvalidCharacter)),
+    vector("stray end", b"e", Invalid(InvalidCharacter)),
+    vector("trailing data", b"i1ei2e", Invalid(InvalidCharacter)),
+];
+
+#[test]
+fn test_vectors() {
+    for vector in VECTORS.iter() {
+        let (lenient, strict) = match vector.expected {
+            Canonical => (Ok(()), Ok(())),
+            NonCanonical => (Ok(()), Err(ParseError::NonCanonical)),
+            Invalid(err) => (Err(err), Err(err)),
+        };
+        assert_eq!(::validate(vector.input), lenient, "{}", vector.name);
+        assert_eq!(::validate_canonical(vector.input), strict, "{}", vector.name);
+    }
+}
@@ -0,0 +1,1513 @@
+//! Typed model of `.torrent` metainfo documents (BEP 3).
+//!
+//! Keys the model does not know about are kept in `extra` maps, so a
+//! decoded document re-encodes to the same bytes as long as the input was
+//! canonical.
+
+#[cfg(any(feature = "sha1", feature = "sha256"))]
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::slice::ChunksExact;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use {Bencode, ParseError, bdecode, bencode};
+use fields::{put, take_bytes, take_dict, take_i64, take_list, take_string, take_u64};
+use file_tree::FileTree;
+use infohash::InfoHash;
+use raw;
+#[cfg(feature = "url")]
+use tracker_url::{UrlError, check_tracker_url};
+
+pub type Dict = BTreeMap<Vec<u8>, Bencode>;
+
+/// Length of each SHA-1 hash in the v1 `pieces` string.
+pub const PIECE_HASH_LEN: usize = 20;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MetainfoError {
+    Parse(ParseError),
+    /// A required key is absent.
+    Missing(&'static str),
+    /// A key holds a value of the wrong type or an unusable value.
+    Invalid(&'static str),
+}
+
+impl From<ParseError> for MetainfoError {
+    fn from(err: ParseError) -> MetainfoError {
+        MetainfoError::Parse(err)
+    }
+}
+
+/// A key that held a value of the wrong type.
+impl From<&'static str> for MetainfoError {
+    fn from(key: &'static str) -> MetainfoError {
+        MetainfoError::Invalid(key)
+    }
+}
+
+pub type MetainfoResult<T> = Result<T, MetainfoError>;
+
+fn require<T>(value: Option<T>, key: &'static str) -> MetainfoResult<T> {
+    value.ok_or(MetainfoError::Missing(key))
+}
+
+fn into_dict(value: Bencode, key: &'static str) -> MetainfoResult<Dict> {
+    match value {
+        Bencode::Object(map) => Ok(map),
+        _ => Err(MetainfoError::Invalid(key)),
+    }
+}
+
+fn decode_document(buf: &[u8]) -> MetainfoResult<Bencode> {
+    let mut stream = buf.iter().cloned().peekable();
+    Ok(bdecode(&mut stream)?)
+}
+
+fn encode_document(document: &Bencode) -> Vec<u8> {
+    let mut buf = Vec::new();
+    bencode(document, &mut buf).expect("writing to a Vec cannot fail");
+    buf
+}
+
+/// Reads a URL list that may be a single string or a list of strings,
+/// dropping empty entries. `None` if it is neither form or not UTF-8.
+fn url_list(value: &Bencode) -> Option<Vec<String>> {
+    let items = match *value {
+        Bencode::Bytes(_) => ::std::slice::from_ref(value),
+        Bencode::Array(ref items) => &items[..],
+        _ => return None,
+    };
+    let mut urls = Vec::with_capacity(items.len());
+    for item in items.iter() {
+        match *item {
+            Bencode::Bytes(ref buf) if buf.is_empty() => (),
+            Bencode::Bytes(ref buf) => urls.push(String::from_utf8(buf.clone()).ok()?),
+            _ => return None,
+        }
+    }
+    Some(urls)
+}
+
+/// Removes and returns a well-formed URL list; malformed values stay in
+/// `dict` untouched.
+fn take_url_list(dict: &mut Dict, key: &str) -> Vec<String> {
+    match dict.get(key.as_bytes()).and_then(url_list) {
+        Some(urls) => {
+            dict.remove(key.as_bytes());
+            urls
+        },
+        None => Vec::new(),
+    }
+}
+
+fn put_url_list(dict: &mut Dict, key: &str, urls: &[String]) {
+    if !urls.is_empty() {
+        let urls = urls.iter().map(|url| Bencode::from(&url[..])).collect();
+        put(dict, key, Some(Bencode::Array(urls)));
+    }
+}
+
+fn take_nodes(dict: &mut Dict) -> MetainfoResult<Vec<(String, u16)>> {
+    let invalid = MetainfoError::Invalid("nodes");
+    let mut nodes = Vec::new();
+    for node in take_list(dict, "nodes")?.unwrap_or_default() {
+        let pair = match node {
+            Bencode::Array(pair) => pair,
+            _ => return Err(invalid),
+        };
+        match (pair.first(), pair.get(1), pair.len()) {
+            (Some(Bencode::Bytes(host)), Some(port), 2) => {
+                let host = String::from_utf8(host.clone()).map_err(|_| invalid)?;
+                let port = port.as_u64()
+                    .filter(|&port| port <= u64::from(u16::MAX))
+                    .ok_or(invalid)?;
+                nodes.push((host, port as u16));
+            },
+            _ => return Err(invalid),
+        }
+    }
+    Ok(nodes)
+}
+
+fn is_padding_attr(attr: &Option<String>) -> bool {
+    attr.as_ref().is_some_and(|attr| attr.contains('p'))
+}
+
+/// An entry of the `files` list of a multi-file torrent.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FileEntry {
+    pub length: u64,
+    pub path: Vec<String>,
+    pub attr: Option<String>,
+    pub extra: Dict,
+}
+
+impl FileEntry {
+    pub fn from_bencode(value: Bencode) -> MetainfoResult<FileEntry> {
+        let mut dict = into_dict(value, "files")?;
+        let length = require(take_u64(&mut dict, "length")?, "length")?;
+        let mut path = Vec::new();
+        for component in require(take_list(&mut dict, "path")?, "path")? {
+            match component {
+                Bencode::Bytes(buf) => path.push(String::from_utf8(buf)
+                    .map_err(|_| MetainfoError::Invalid("path"))?),
+                _ => return Err(MetainfoError::Invalid("path")),
+            }
+        }
+        let attr = take_string(&mut dict, "attr")?;
+        Ok(FileEntry { length, path, attr, extra: dict })
+    }
+
+    /// Whether this is a BEP 47 padding file (`p` in `attr`).
+    pub fn is_padding(&self) -> bool {
+        is_padding_attr(&self.attr)
+    }
+
+    pub fn to_bencode(&self) -> Bencode {
+        let mut dict = self.extra.clone();
+        put(&mut dict, "attr", self.attr.as_ref().map(|s| &s[..]));
+        put(&mut dict, "length", Some(self.length));
+        let path = self.path.iter().map(|c| Bencode::from(&c[..])).collect();
+        put(&mut dict, "path", Some(Bencode::Array(path)));
+        Bencode::Object(dict)
+    }
+}
+
+/// A file of the torrent's content, with its path relative to the download
+/// directory. Single-file torrents yield one file named after the torrent.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TorrentFile {
+    pub path: PathBuf,
+    pub length: u64,
+    pub attr: Option<String>,
+}
+
+impl TorrentFile {
+    /// Whether this is a BEP 47 padding file (`p` in `attr`).
+    pub fn is_padding(&self) -> bool {
+        is_padding_attr(&self.attr)
+    }
+}
+
+/// Why a path component is unsafe to use on disk.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PathErrorKind {
+    Empty,
+    CurrentDir,
+    ParentDir,
+    /// Contains `/` or `\\`, which could form an absolute or nested path.
+    Separator,
+    /// Starts with a Windows drive prefix such as `C:`.
+    DrivePrefix,
+    NulByte,
+}
+
+/// An unsafe path component; `file` is the index into `files`, or into the
+/// flattened `file tree` of a v2-only torrent, or `None` for the torrent
+/// name.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PathError {
+    pub file: Option<usize>,
+    pub kind: PathErrorKind,
+}
+
+/// Checks that `component` names a single entry inside its parent
+/// directory.
+pub fn check_component(component: &str) -> Result<(), PathErrorKind> {
+    let bytes = component.as_bytes();
+    if bytes.is_empty() {
+        return Err(PathErrorKind::Empty);
+    }
+    if component == "." {
+        return Err(PathErrorKind::CurrentDir);
+    }
+    if component == ".." {
+        return Err(PathErrorKind::ParentDir);
+    }
+    if bytes.contains(&0) {
+        return Err(PathErrorKind::NulByte);
+    }
+    if bytes.iter().any(|&b| b == b'/' || b == b'\\') {
+        return Err(PathErrorKind::Separator);
+    }
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        return Err(PathErrorKind::DrivePrefix);
+    }
+    Ok(())
+}
+
+/// Rewrites `component` so that it passes `check_component`, replacing
+/// offending characters with `_`.
+pub fn sanitize_component(component: &str) -> String {
+    match check_component(component) {
+        Ok(()) => return component.to_string(),
+        Err(PathErrorKind::Empty) | Err(PathErrorKind::CurrentDir) => return "_".to_string(),
+        Err(PathErrorKind::ParentDir) => return "__".to_string(),
+        Err(_) => (),
+    }
+    let mut output: String = component.chars()
+        .map(|c| match c {
+            '\0' | '/' | '\\' => '_',
+            c => c,
+        })
+        .collect();
+    if check_component(&output) == Err(PathErrorKind::DrivePrefix) {
+        output.replace_range(1..2, "_");
+    }
+    output
+}
+
+/// Which BitTorrent protocol versions a torrent can be used with.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProtocolVersion {
+    V1,
+    V2,
+    /// Carries both v1 `pieces` and a v2 `file tree` describing the same
+    /// content.
+    Hybrid,
+}
+
+/// The `info` dictionary.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Info {
+    pub name: String,
+    pub piece_length: u64,
+    /// Concatenated v1 SHA-1 piece hashes; absent in v2-only torrents.
+    pub pieces: Option<Vec<u8>>,
+    /// BEP 52 `meta version`; 2 for v2 and hybrid torrents.
+    pub meta_version: Option<u64>,
+    /// BEP 52 `file tree`.
+    pub file_tree: Option<FileTree>,
+    /// Content length of a single-file torrent.
+    pub length: Option<u64>,
+    /// File list of a multi-file torrent.
+    pub files: Option<Vec<FileEntry>>,
+    /// BEP 47 attributes of a single-file torrent.
+    pub attr: Option<String>,
+    pub extra: Dict,
+}
+
+impl Info {
+    pub fn from_bencode(value: Bencode) -> MetainfoResult<Info> {
+        let mut dict = into_dict(value, "info")?;
+        let name = require(take_string(&mut dict, "name")?, "name")?;
+        let piece_length = require(take_u64(&mut dict, "piece length")?, "piece length")?;
+        let pieces = take_bytes(&mut dict, "pieces")?;
+        let meta_version = take_u64(&mut dict, "meta version")?;
+        let file_tree = match take_dict(&mut dict, "file tree")? {
+            Some(tree) => Some(FileTree::from_bencode(Bencode::Object(tree))?),
+            None => None,
+        };
+        let length = take_u64(&mut dict, "length")?;
+        let files = match take_list(&mut dict, "files")? {
+            Some(items) => Some(items.into_iter()
+                .map(FileEntry::from_bencode)
+                .collect::<MetainfoResult<Vec<_>>>()?),
+            None => None,
+        };
+        let attr = take_string(&mut dict, "attr")?;
+        let info = Info {
+            name, piece_length, pieces, meta_version, file_tree,
+            length, files, attr, extra: dict,
+        };
+
+        if !info.is_v1() && !info.is_v2() {
+            return Err(MetainfoError::Missing("pieces"));
+        }
+        // v2-only torrents describe their files in `file tree` alone.
+        if info.is_v1() || info.length.is_some() || info.files.is_some() {
+            match (info.length, info.files.is_some()) {
+                (None, false) => return Err(MetainfoError::Missing("length")),
+                (Some(_), true) => return Err(MetainfoError::Invalid("files")),
+                _ => (),
+            }
+        }
+        if info.checked_total_length().is_none() {
+            return Err(MetainfoError::Invalid("length"));
+        }
+        Ok(info)
+    }
+
+    pub fn to_bencode(&self) -> Bencode {
+        let mut dict = self.extra.clone();
+        put(&mut dict, "attr", self.attr.as_ref().map(|s| &s[..]));
+        if let Some(ref files) = self.files {
+            let files = files.iter().map(FileEntry::to_bencode).collect();
+            put(&mut dict, "files", Some(Bencode::Array(files)));
+        }
+        put(&mut dict, "file tree", self.file_tree.as_ref().map(FileTree::to_bencode));
+        put(&mut dict, "length", self.length);
+        put(&mut dict, "meta version", self.meta_version);
+        put(&mut dict, "name", Some(&self.name[..]));
+        put(&mut dict, "piece length", Some(self.piece_length));
+        put(&mut dict, "pieces", self.pieces.as_ref().map(|p| &p[..]));
+        Bencode::Object(dict)
+    }
+
+    /// Whether the torrent carries v1 piece hashes, either as `pieces` or
+    /// as the BEP 30 `root hash` of a merkle torrent.
+    pub fn is_v1(&self) -> bool {
+        self.pieces.is_some() || self.root_hash().is_some()
+    }
+
+    /// Whether the torrent carries a BEP 52 file tree.
+    pub fn is_v2(&self) -> bool {
+        self.meta_version == Some(2) && self.file_tree.is_some()
+    }
+
+    pub fn protocol_version(&self) -> Option<ProtocolVersion> {
+        match (self.is_v1(), self.is_v2()) {
+            (true, true) => Some(ProtocolVersion::Hybrid),
+            (true, false) => Some(ProtocolVersion::V1),
+            (false, true) => Some(ProtocolVersion::V2),
+            (false, false) => None,
+        }
+    }
+
+    pub fn is_multi_file(&self) -> bool {
+        self.files.is_some()
+    }
+
+    /// Iterates over the content files, for single- and multi-file
+    /// torrents alike.
+    pub fn files(&self) -> Files<'_> {
+        Files::new(self, false)
+    }
+
+    /// Like `files`, but with every path component passed through
+    /// `sanitize_component`, so the paths can be joined onto a download
+    /// directory without escaping it.
+    pub fn sanitized_files(&self) -> Files<'_> {
+        Files::new(self, true)
+    }
+
+    /// The file list of a v2-only torrent, flattened from `file tree`.
+    fn tree_files(&self) -> Option<Vec<FileEntry>> {
+        match self.file_tree {
+            Some(ref tree) if self.files.is_none() && self.length.is_none() =>
+                Some(tree.to_file_entries()),
+            _ => None,
+        }
+    }
+
+    /// Rejects names and file paths that are empty, contain `.`/`..`
+    /// components, separators or NUL bytes, or would be absolute.
+    pub fn validate_paths(&self) -> Result<(), PathError> {
+        check_component(&self.name)
+            .map_err(|kind| PathError { file: None, kind })?;
+        let tree_files = self.tree_files();
+        let files = self.files.as_ref().or(tree_files.as_ref());
+        for (index, entry) in files.iter().flat_map(|files| files.iter()).enumerate() {
+            if entry.path.is_empty() {
+                return Err(PathError { file: Some(index), kind: PathErrorKind::Empty });
+            }
+            for component in entry.path.iter() {
+                check_component(component)
+                    .map_err(|kind| PathError { file: Some(index), kind })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Iterates over the SHA-1 piece hashes, failing if `pieces` is not a
+    /// whole number of hashes.
+    pub fn pieces(&self) -> MetainfoResult<Pieces<'_>> {
+        let pieces = match self.pieces {
+            Some(ref pieces) => pieces,
+            None => return Err(MetainfoError::Missing("pieces")),
+        };
+        if !pieces.len().is_multiple_of(PIECE_HASH_LEN) {
+            return Err(MetainfoError::Invalid("pieces"));
+        }
+        Ok(Pieces { chunks: pieces.chunks_exact(PIECE_HASH_LEN) })
+    }
+
+    /// The number of v1 piece hashes.
+    pub fn piece_count(&self) -> usize {
+        self.pieces.as_ref().map_or(0, |pieces| pieces.len() / PIECE_HASH_LEN)
+    }
+
+    /// The length of piece `index`: `piece_length`, except for a shorter
+    /// final piece. `None` if the index is out of range.
+    pub fn piece_length_of(&self, index: usize) -> Option<u64> {
+        if index >= self.piece_count() || self.piece_length == 0 {
+            return None;
+        }
+        let start = (index as u64).checked_mul(self.piece_length)?;
+        let remaining = self.total_length().checked_sub(start)?;
+        match ::std::cmp::min(remaining, self.piece_length) {
+            0 => None,
+            length => Some(length),
+        }
+    }
+
+    /// Sum of all file lengths, padding included; this is the length the
+    /// pieces cover. `from_bencode` rejects torrents whose total does not
+    /// fit, and for others built by hand it saturates.
+    pub fn total_length(&self) -> u64 {
+        self.checked_total_length().unwrap_or(u64::MAX)
+    }
+
+    /// Like `total_length`, but `None` if the sum overflows.
+    pub fn checked_total_length(&self) -> Option<u64> {
+        match (&self.files, self.length, &self.file_tree) {
+            (Some(files), _, _) => files.iter().try_fold(0u64, |total, f| total.checked_add(f.length)),
+            (None, Some(length), _) => Some(length),
+            (None, None, Some(tree)) => tree.checked_total_length(),
+            (None, None, None) => Some(0),
+        }
+    }
+
+    /// Sum of the lengths of all non-padding files.
+    pub fn content_length(&self) -> u64 {
+        self.files().skip_padding().fold(0, |total, f| total.saturating_add(f.length))
+    }
+
+    /// BEP 27 `private` flag: peers must only come from the trackers.
+    pub fn is_private(&self) -> bool {
+        self.extra.get(&b"private"[..]).and_then(Bencode::as_i64) == Some(1)
+    }
+
+    /// Sets or clears `private`. Both change the infohash.
+    pub fn set_private(&mut self, private: bool) {
+        if private {
+            self.extra.insert(b"private".to_vec(), Bencode::from(1i64));
+        } else {
+            self.extra.remove(&b"private"[..]);
+        }
+    }
+
+    /// The `source` tag private trackers use to give cross-seeded torrents
+    /// distinct infohashes. `None` if absent or not UTF-8.
+    pub fn source(&self) -> Option<&str> {
+        match self.extra.get(&b"source"[..]) {
+            Some(Bencode::Bytes(buf)) => ::std::str::from_utf8(buf).ok(),
+            _ => None,
+        }
+    }
+
+    /// Sets or clears `source`. Both change the infohash.
+    pub fn set_source(&mut self, source: Option<&str>) {
+        match source {
+            Some(source) => self.extra.insert(b"source".to_vec(), Bencode::from(source)),
+            None => self.extra.remove(&b"source"[..]),
+        };
+    }
+
+    /// The BEP 30 `root hash` of a merkle torrent, which replaces `pieces`.
+    pub fn root_hash(&self) -> Option<[u8; PIECE_HASH_LEN]> {
+        match self.extra.get(&b"root hash"[..]) {
+            Some(Bencode::Bytes(buf)) if buf.len() == PIECE_HASH_LEN => {
+                let mut hash = [0; PIECE_HASH_LEN];
+                hash.copy_from_slice(buf);
+                Some(hash)
+            },
+            _ => None,
+        }
+    }
+
+    /// The BEP 38 `similar` infohashes listed in the info dictionary.
+    pub fn similar(&self) -> Vec<InfoHash> {
+        similar_in(&self.extra)
+    }
+
+    /// Replaces `similar`; an empty list removes it. Changes the infohash.
+    pub fn set_similar(&mut self, hashes: &[InfoHash]) {
+        set_list(&mut self.extra, b"similar", similar_list(hashes));
+    }
+
+    /// The BEP 38 `collections` named in the info dictionary.
+    pub fn collections(&self) -> Vec<String> {
+        collections_in(&self.extra)
+    }
+
+    /// Replaces `collections`; an empty list removes it. Changes the
+    /// infohash.
+    pub fn set_collections(&mut self, names: &[String]) {
+        set_list(&mut self.extra, b"collections", collections_list(names));
+    }
+
+    /// Sets or clears `root hash`. Both change the infohash.
+    pub fn set_root_hash(&mut self, root_hash: Option<&[u8; PIECE_HASH_LEN]>) {
+        match root_hash {
+            Some(hash) => self.extra.insert(b"root hash".to_vec(), Bencode::from(&hash[..])),
+            None => self.extra.remove(&b"root hash"[..]),
+        };
+    }
+}
+
+/// Reads a BEP 38 `similar` list, skipping entries that are not infohashes.
+fn similar_in(dict: &Dict) -> Vec<InfoHash> {
+    match dict.get(&b"similar"[..]) {
+        Some(Bencode::Array(items)) => items.iter()
+            .filter_map(|item| match *item {
+                Bencode::Bytes(ref buf) => InfoHash::from_slice(buf),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Reads a BEP 38 `collections` list, skipping entries that are not
+/// UTF-8 strings.
+fn collections_in(dict: &Dict) -> Vec<String> {
+    match dict.get(&b"collections"[..]) {
+        Some(Bencode::Array(items)) => items.iter()
+            .filter_map(|item| match *item {
+                Bencode::Bytes(ref buf) => String::from_utf8(buf.clone()).ok(),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Stores `items` under `key`, removing the key when there are none.
+fn set_list(dict: &mut Dict, key: &[u8], items: Vec<Bencode>) {
+    if items.is_empty() {
+        dict.remove(key);
+    } else {
+        dict.insert(key.to_vec(), Bencode::Array(items));
+    }
+}
+
+fn similar_list(hashes: &[InfoHash]) -> Vec<Bencode> {
+    hashes.iter().map(|hash| Bencode::from(hash.as_bytes())).collect()
+}
+
+fn collections_list(names: &[String]) -> Vec<Bencode> {
+    names.iter().map(|name| Bencode::from(&name[..])).collect()
+}
+
+pub struct Files<'a> {
+    info: &'a Info,
+    /// Flattened `file tree` entries, for v2-only torrents.
+    tree_files: Option<Vec<FileEntry>>,
+    index: usize,
+    sanitize: bool,
+    skip_padding: bool,
+}
+
+impl<'a> Files<'a> {
+    fn new(info: &'a Info, sanitize: bool) -> Files<'a> {
+        Files { info, tree_files: info.tree_files(), index: 0, sanitize, skip_padding: false }
+    }
+
+    fn entries(&self) -> Option<&[FileEntry]> {
+        match (&self.info.files, &self.tree_files) {
+            (Some(files), _) => Some(files),
+            (None, Some(files)) => Some(files),
+            (None, None) => None,
+        }
+    }
+
+    /// Whether a v2-only torrent holds one file stored under `name`.
+    fn single_tree_file(&self) -> bool {
+        self.tree_files.is_some() && self.info.file_tree.as_ref().is_some_and(FileTree::is_single_file)
+    }
+
+    /// Leaves BEP 47 padding files out of the iteration.
+    pub fn skip_padding(mut self) -> Files<'a> {
+        self.skip_padding = true;
+        self
+    }
+
+    fn component(&self, component: &str) -> String {
+        if self.sanitize {
+            sanitize_component(component)
+        } else {
+            component.to_string()
+        }
+    }
+
+    fn next_file(&mut self) -> Option<TorrentFile> {
+        let file = match self.entries() {
+            Some(files) if self.single_tree_file() => {
+                let entry = files.get(self.index)?;
+                TorrentFile {
+                    path: PathBuf::from(self.component(&self.info.name)),
+                    length: entry.length,
+                    attr: entry.attr.clone(),
+                }
+            },
+            Some(files) => {
+                let entry = files.get(self.index)?;
+                let mut path = PathBuf::from(self.component(&self.info.name));
+                for component in entry.path.iter() {
+                    path.push(self.component(component));
+                }
+                if self.sanitize && entry.path.is_empty() {
+                    path.push(sanitize_component(""));
+                }
+                TorrentFile {
+                    path,
+                    length: entry.length,
+                    attr: entry.attr.clone(),
+                }
+            },
+            None if self.index == 0 => TorrentFile {
+                path: PathBuf::from(self.component(&self.info.name)),
+                length: self.info.length.unwrap_or(0),
+                attr: self.info.attr.clone(),
+            },
+            None => return None,
+        };
+        self.index += 1;
+        Some(file)
+    }
+}
+
+impl<'a> Iterator for Files<'a> {
+    type Item = TorrentFile;
+
+    fn next(&mut self) -> Option<TorrentFile> {
+        loop {
+            let file = self.next_file()?;
+            if !(self.skip_padding && file.is_padding()) {
+                return Some(file);
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let total = self.entries().map_or(1, |files| files.len());
+        let remaining = total.saturating_sub(self.index);
+        if self.skip_padding {
+            (0, Some(remaining))
+        } else {
+            (remaining, Some(remaining))
+        }
+    }
+}
+
+pub struct Pieces<'a> {
+    chunks: ChunksExact<'a, u8>,
+}
+
+impl<'a> Iterator for Pieces<'a> {
+    type Item = [u8; PIECE_HASH_LEN];
+
+    fn next(&mut self) -> Option<[u8; PIECE_HASH_LEN]> {
+        let mut hash = [0u8; PIECE_HASH_LEN];
+        hash.copy_from_slice(self.chunks.next()?);
+        Some(hash)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chunks.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for Pieces<'a> {}
+
+/// BEP 12 `announce-list`: tiers of tracker URLs, tried tier by tier.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct AnnounceList {
+    /// The URLs as the torrent spells them. They are not required to be
+    /// valid, since published torrents often carry a few broken ones;
+    /// `normalize` checks them with `tracker_url`.
+    pub tiers: Vec<Vec<String>>,
+}
+
+impl AnnounceList {
+    /// Decodes a list of lists of UTF-8 strings. Anything else yields
+    /// `None`, and `Metainfo` then leaves the key untouched in `extra`.
+    pub fn from_bencode(value: &Bencode) -> Option<AnnounceList> {
+        let tiers = match *value {
+            Bencode::Array(ref tiers) => tiers,
+            _ => return None,
+        };
+        let mut out = Vec::with_capacity(tiers.len());
+        for tier in tiers.iter() {
+            let urls = match *tier {
+                Bencode::Array(ref urls) => urls,
+                _ => return None,
+            };
+            let mut tier = Vec::with_capacity(urls.len());
+            for url in urls.iter() {
+                match *url {
+                    Bencode::Bytes(ref buf) => tier.push(String::from_utf8(buf.clone()).ok()?),
+                    _ => return None,
+                }
+            }
+            out.push(tier);
+        }
+        Some(AnnounceList { tiers: out })
+    }
+
+    pub fn to_bencode(&self) -> Bencode {
+        Bencode::Array(self.tiers.iter()
+            .map(|tier| Bencode::Array(tier.iter().map(|url| Bencode::from(&url[..])).collect()))
+            .collect())
+    }
+
+    /// Whether there is no tracker in any tier.
+    pub fn is_empty(&self) -> bool {
+        self.tiers.iter().all(|tier| tier.is_empty())
+    }
+
+    /// All URLs in the order they should be tried.
+    pub fn flatten(&self) -> Vec<&str> {
+        self.tiers.iter().flat_map(|tier| tier.iter().map(|url| &url[..])).collect()
+    }
+
+    /// Drops repeated URLs, keeping the first occurrence, and any tiers
+    /// left empty.
+    pub fn dedup(&mut self) {
+        let mut seen = ::std::collections::BTreeSet::new();
+        for tier in self.tiers.iter_mut() {
+            tier.retain(|url| seen.insert(url.clone()));
+        }
+        self.tiers.retain(|tier| !tier.is_empty());
+    }
+
+    /// Shuffles the URLs within each tier, as BEP 12 asks clients to do on
+    /// load. `random_below(n)` must return a value in `0..n`.
+    pub fn shuffle<R: FnMut(usize) -> usize>(&mut self, mut random_below: R) {
+        for tier in self.tiers.iter_mut() {
+            for i in (1..tier.len()).rev() {
+                tier.swap(i, random_below(i + 1));
+            }
+        }
+    }
+
+    /// Normalizes every URL, dropping the invalid ones and any tiers left
+    /// empty, and returns what was dropped.
+    #[cfg(feature = "url")]
+    pub fn normalize(&mut self) -> Vec<UrlError> {
+        let mut errors = Vec::new();
+        for tier in self.tiers.iter_mut() {
+            let urls = ::std::mem::take(tier);
+            for url in urls {
+                match check_tracker_url(&url) {
+                    Ok(url) => tier.push(url),
+                    Err(err) => errors.push(err),
+                }
+            }
+        }
+        self.tiers.retain(|tier| !tier.is_empty());
+        errors
+    }
+
+    /// Moves a tracker that answered to the front of its tier.
+    pub fn promote(&mut self, tier: usize, index: usize) {
+        if let Some(tier) = self.tiers.get_mut(tier) {
+            if index < tier.len() {
+                let url = tier.remove(index);
+                tier.insert(0, url);
+            }
+        }
+    }
+}
+
+/// The protocol a seed URL speaks.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SeedKind {
+    /// BEP 19: plain HTTP/FTP file URLs, fetched with range requests.
+    WebSeed,
+    /// BEP 17: a script taking `info_hash` and `piece` query parameters.
+    HttpSeed,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Seed<'a> {
+    pub kind: SeedKind,
+    pub url: &'a str,
+}
+
+/// A decoded `.torrent` document.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Metainfo {
+    pub announce: Option<String>,
+    pub announce_list: Option<AnnounceList>,
+    /// BEP 19 web seeds from `url-list`, which may be a string or a list;
+    /// re-encoded as a list, or left out if empty.
+    pub url_list: Vec<String>,
+    /// BEP 17 seeds from `httpseeds`, handled like `url_list`.
+    pub httpseeds: Vec<String>,
+    /// DHT bootstrap nodes of a trackerless torrent, as `(host, port)`.
+    pub nodes: Vec<(String, u16)>,
+    pub info: Info,
+    /// The `info` dictionary exactly as `from_bytes` read it. The
+    /// infohashes are taken over these bytes while they still decode to
+    /// `info`, and over a re-encoding of `info` otherwise, as for torrents
+    /// built in code.
+    pub info_bytes: Option<Vec<u8>>,
+    pub comment: Option<String>,
+    pub created_by: Option<String>,
+    pub creation_date: Option<i64>,
+    pub extra: Dict,
+}
+
+impl Metainfo {
+    pub fn from_bencode(value: Bencode) -> MetainfoResult<Metainfo> {
+        let mut dict = into_dict(value, "metainfo")?;
+        let info = Info::from_bencode(require(take_dict(&mut dict, "info")?, "info")
+            .map(Bencode::Object)?)?;
+        let announce_list = dict.get(&b"announce-list"[..]).and_then(AnnounceList::from_bencode);
+        if announce_list.is_some() {
+            dict.remove(&b"announce-list"[..]);
+        }
+        let url_list = take_url_list(&mut dict, "url-list");
+        let httpseeds = take_url_list(&mut dict, "httpseeds");
+        let nodes = take_nodes(&mut dict)?;
+        Ok(Metainfo {
+            announce: take_string(&mut dict, "announce")?,
+            announce_list,
+            url_list,
+            httpseeds,
+            nodes,
+            info,
+            info_bytes: None,
+            comment: take_string(&mut dict, "comment")?,
+            created_by: take_string(&mut dict, "created by")?,
+            creation_date: take_i64(&mut dict, "creation date")?,
+            extra: dict,
+        })
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> MetainfoResult<Metainfo> {
+        let mut metainfo = Metainfo::from_bencode(decode_document(buf)?)?;
+        metainfo.info_bytes = raw::dict_value_span(buf, b"info")?.map(|span| buf[span].to_vec());
+        Ok(metainfo)
+    }
+
+    pub fn to_bencode(&self) -> Bencode {
+        let mut dict = self.extra.clone();
+        put(&mut dict, "announce", self.announce.as_ref().map(|s| &s[..]));
+        put(&mut dict, "announce-list", self.announce_list.as_ref().map(AnnounceList::to_bencode));
+        put(&mut dict, "comment", self.comment.as_ref().map(|s| &s[..]));
+        put(&mut dict, "created by", self.created_by.as_ref().map(|s| &s[..]));
+        put(&mut dict, "creation date", self.creation_date);
+        put(&mut dict, "info", Some(self.info.to_bencode()));
+        if !self.nodes.is_empty() {
+            let nodes = self.nodes.iter()
+                .map(|&(ref host, port)| Bencode::Array(vec![
+                    Bencode::from(&host[..]),
+                    Bencode::from(u64::from(port)),
+                ]))
+                .collect();
+            put(&mut dict, "nodes", Some(Bencode::Array(nodes)));
+        }
+        put_url_list(&mut dict, "httpseeds", &self.httpseeds);
+        put_url_list(&mut dict, "url-list", &self.url_list);
+        Bencode::Object(dict)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode_document(&self.to_bencode())
+    }
+
+    /// `creation date` as a point in time. `None` if absent or out of
+    /// `SystemTime`'s range.
+    pub fn creation_time(&self) -> Option<SystemTime> {
+        let seconds = self.creation_date?;
+        let offset = Duration::from_secs(seconds.unsigned_abs());
+        if seconds >= 0 {
+            UNIX_EPOCH.checked_add(offset)
+        } else {
+            UNIX_EPOCH.checked_sub(offset)
+        }
+    }
+
+    /// Stores `time` as whole Unix seconds, rounding towards the epoch.
+    pub fn set_creation_time(&mut self, time: SystemTime) {
+        self.creation_date = match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => Some(after.as_secs().min(i64::MAX as u64) as i64),
+            Err(err) => Some(-(err.duration().as_secs().min(i64::MAX as u64) as i64)),
+        };
+    }
+
+    /// A multi-line, human-readable overview, as printed by
+    /// `torrentinfo`-style tools.
+    pub fn summary(&self) -> Summary<'_> {
+        Summary { metainfo: self }
+    }
+
+    /// The bootstrap `nodes` given as IP addresses, in the form the DHT
+    /// code uses; entries naming hosts need resolving and are left out.
+    pub fn node_addrs(&self) -> Vec<SocketAddr> {
+        self.nodes.iter()
+            .filter_map(|&(ref host, port)| {
+                host.parse::<IpAddr>().ok().map(|ip| SocketAddr::new(ip, port))
+            })
+            .collect()
+    }
+
+    /// Every HTTP seed of either kind, web seeds first.
+    pub fn seeds(&self) -> Vec<Seed<'_>> {
+        let web = self.url_list.iter().map(|url| Seed { kind: SeedKind::WebSeed, url });
+        let http = self.httpseeds.iter().map(|url| Seed { kind: SeedKind::HttpSeed, url });
+        web.chain(http).collect()
+    }
+
+    /// The trackers to use: `announce-list` if it names any, otherwise a
+    /// single tier holding `announce`.
+    pub fn trackers(&self) -> AnnounceList {
+        match self.announce_list {
+            Some(ref list) if !list.is_empty() => list.clone(),
+            _ => AnnounceList {
+                tiers: self.announce.iter().map(|url| vec![url.clone()]).collect(),
+            },
+        }
+    }
+
+    /// Checks `announce` and every `announce-list` entry, returning the
+    /// invalid ones.
+    #[cfg(feature = "url")]
+    pub fn validate_trackers(&self) -> Vec<UrlError> {
+        let lists = self.announce_list.iter().flat_map(|list| list.flatten());
+        self.announce.iter().map(|url| &url[..]).chain(lists)
+            .filter_map(|url| check_tracker_url(url).err())
+            .collect()
+    }
+
+    /// Normalizes `announce` and `announce-list` in place, dropping and
+    /// returning the invalid entries.
+    #[cfg(feature = "url")]
+    pub fn normalize_trackers(&mut self) -> Vec<UrlError> {
+        let mut errors = Vec::new();
+        if let Some(url) = self.announce.take() {
+            match check_tracker_url(&url) {
+                Ok(url) => self.announce = Some(url),
+                Err(err) => errors.push(err),
+            }
+        }
+        if let Some(ref mut list) = self.announce_list {
+            errors.extend(list.normalize());
+        }
+        errors
+    }
+
+    /// The top-level BEP 38 `similar` infohashes, which unlike those in
+    /// the info dictionary can change without changing the infohash.
+    pub fn similar(&self) -> Vec<InfoHash> {
+        similar_in(&self.extra)
+    }
+
+    pub fn set_similar(&mut self, hashes: &[InfoHash]) {
+        set_list(&mut self.extra, b"similar", similar_list(hashes));
+    }
+
+    /// The top-level BEP 38 `collections`.
+    pub fn collections(&self) -> Vec<String> {
+        collections_in(&self.extra)
+    }
+
+    pub fn set_collections(&mut self, names: &[String]) {
+        set_list(&mut self.extra, b"collections", collections_list(names));
+    }
+
+    /// `similar` from the info dictionary and the top level, without
+    /// duplicates.
+    pub fn all_similar(&self) -> Vec<InfoHash> {
+        let mut all = self.info.similar();
+        for hash in self.similar() {
+            if !all.contains(&hash) {
+                all.push(hash);
+            }
+        }
+        all
+    }
+
+    /// `collections` from the info dictionary and the top level, without
+    /// duplicates.
+    pub fn all_collections(&self) -> Vec<String> {
+        let mut all = self.info.collections();
+        for name in self.collections() {
+            if !all.contains(&name) {
+                all.push(name);
+            }
+        }
+        all
+    }
+
+    /// The bytes the infohashes are taken over: `info_bytes` if they still
+    /// describe `info`, else its encoding.
+    #[cfg(any(feature = "sha1", feature = "sha256"))]
+    fn hashed_info(&self) -> Cow<'_, [u8]> {
+        if let Some(ref buf) = self.info_bytes {
+            let decoded = raw::decode_exact(buf).ok().and_then(|info| Info::from_bencode(info).ok());
+            if decoded.as_ref() == Some(&self.info) {
+                return Cow::Borrowed(buf);
+            }
+        }
+        Cow::Owned(encode_document(&self.info.to_bencode()))
+    }
+
+    /// The v1 infohash, or `None` for v2-only torrents.
+    #[cfg(feature = "sha1")]
+    pub fn info_hash_v1(&self) -> Option<InfoHash> {
+        if !self.info.is_v1() {
+            return None;
+        }
+        Some(InfoHash::V1(::sha1::Sha1::digest(&self.hashed_info())))
+    }
+
+    /// The v2 infohash, or `None` for v1-only torrents.
+    #[cfg(feature = "sha256")]
+    pub fn info_hash_v2(&self) -> Option<InfoHash> {
+        if !self.info.is_v2() {
+            return None;
+        }
+        Some(InfoHash::V2(::sha256::Sha256::digest(&self.hashed_info())))
+    }
+
+    /// Every infohash the torrent can be identified by: v1 first, then v2,
+    /// as far as the enabled hash features allow.
+    #[cfg(any(feature = "sha1", feature = "sha256"))]
+    pub fn info_hashes(&self) -> Vec<InfoHash> {
+        let mut hashes = Vec::new();
+        #[cfg(feature = "sha1")]
+        hashes.extend(self.info_hash_v1());
+        #[cfg(feature = "sha256")]
+        hashes.extend(self.info_hash_v2());
+        hashes
+    }
+}
+
+/// Whether `a` and `b` describe the same content, i.e. share a v1 or v2
+/// infohash. Trackers, comments, dates and other keys outside `info` are
+/// ignored, and a hybrid torrent matches its v1-only or v2-only
+/// counterpart. Only the hash versions enabled by features are compared.
+#[cfg(any(feature = "sha1", feature = "sha256"))]
+pub fn same_content(a: &Metainfo, b: &Metainfo) -> bool {
+    let b_hashes = b.info_hashes();
+    a.info_hashes().iter().any(|hash| b_hashes.contains(hash))
+}
+
+/// Formats a byte count with a binary unit, e.g. `1.5 MiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Display adapter returned by `Metainfo::summary`.
+pub struct Summary<'a> {
+    metainfo: &'a Metainfo,
+}
+
+impl<'a> fmt::Display for Summary<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let metainfo = self.metainfo;
+        let info = &metainfo.info;
+        writeln!(f, "name: {}", info.name)?;
+        #[cfg(any(feature = "sha1", feature = "sha256"))]
+        for hash in metainfo.info_hashes() {
+            let label = if hash.is_v1() { "v1" } else { "v2" };
+            writeln!(f, "info hash ({}): {}", label, hash)?;
+        }
+        let total = info.total_length();
+        writeln!(f, "size: {} ({} bytes)", format_size(total), total)?;
+        writeln!(f, "piece length: {}", format_size(info.piece_length))?;
+        if info.is_v1() {
+            writeln!(f, "pieces: {}", info.piece_count())?;
+        }
+        writeln!(f, "files: {}", info.files().skip_padding().count())?;
+        for (index, tier) in metainfo.trackers().tiers.iter().enumerate() {
+            for url in tier.iter() {
+                writeln!(f, "tracker (tier {}): {}", index, url)?;
+            }
+        }
+        for seed in metainfo.seeds() {
+            writeln!(f, "seed: {}", seed.url)?;
+        }
+
+        let mut flags = Vec::new();
+        match info.protocol_version() {
+            Some(ProtocolVersion::V1) => flags.push("v1"),
+            Some(ProtocolVersion::V2) => flags.push("v2"),
+            Some(ProtocolVersion::Hybrid) => flags.push("hybrid"),
+            None => (),
+        }
+        if info.is_private() {
+            flags.push("private");
+        }
+        if metainfo.announce.is_none() && metainfo.announce_list.is_none() {
+            flags.push("trackerless");
+        }
+        write!(f, "flags: {}", flags.join(", "))
+    }
+}
+
+#[cfg(test)]
+const MULTI_FILE: &[u8] = b"d8:announce18:http://t.example/a7:comment2:hi\
+    10:created by4:test13:creation datei1500000000e\
+    4:infod5:filesld6:lengthi3e4:pathl1:a5:b.txteed4:attr1:p6:lengthi5e4:pathl3:padeee\
+    4:name4:root12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaa7:privatei1eee";
+
+#[test]
+fn test_metainfo_roundtrip() {
+    let metainfo = Metainfo::from_bytes(MULTI_FILE).unwrap();
+    assert_eq!(metainfo.announce, Some("http://t.example/a".to_string()));
+    assert_eq!(metainfo.creation_date, Some(1500000000));
+    assert_eq!(metainfo.info.name, "root");
+    assert_eq!(metainfo.info.piece_length, 16384);
+    assert!(metainfo.info.is_private());
+    assert_eq!(metainfo.info.source(), None);
+    assert_eq!(metainfo.to_bytes(), MULTI_FILE);
+
+    assert_eq!(Metainfo::from_bytes(b"d4:infod4:name1:a12:piece lengthi1e6:pieces0:ee"),
+               Err(MetainfoError::Missing("length")));
+    assert_eq!(Metainfo::from_bytes(b"d4:infoi1ee"),
+               Err(MetainfoError::Invalid("info")));
+    assert_eq!(Metainfo::from_bytes(b"le"),
+               Err(MetainfoError::Invalid("metainfo")));
+}
+
+#[test]
+fn test_announce_list() {
+    let buf = b"d8:announce1:a13:announce-listll1:b1:ael1:cel1:bee\
+        4:infod6:lengthi1e4:name1:x12:piece lengthi1e6:pieces0:ee";
+    let metainfo = Metainfo::from_bytes(buf).unwrap();
+    let mut list = metainfo.trackers();
+    assert_eq!(list.flatten(), vec!["b", "a", "c", "b"]);
+    assert_eq!(metainfo.to_bytes(), &buf[..]);
+
+    list.dedup();
+    assert_eq!(list.tiers, vec![vec!["b".to_string(), "a".to_string()], vec!["c".to_string()]]);
+    list.shuffle(|_| 0);
+    assert_eq!(list.tiers[0], vec!["a", "b"]);
+    list.promote(0, 1);
+    assert_eq!(list.tiers[0], vec!["b", "a"]);
+
+    // Malformed lists are left alone; `announce` is the fallback.
+    let buf = b"d8:announce1:a13:announce-listl1:be\
+        4:infod6:lengthi1e4:name1:x12:piece lengthi1e6:pieces0:ee";
+    let metainfo = Metainfo::from_bytes(buf).unwrap();
+    assert_eq!(metainfo.announce_list, None);
+    assert_eq!(metainfo.trackers().flatten(), vec!["a"]);
+    assert_eq!(metainfo.to_bytes(), &buf[..]);
+}
+
+#[test]
+fn test_seeds() {
+    let info = "4:infod6:lengthi1e4:name1:x12:piece lengthi1e6:pieces0:e";
+    let single = format!("d{}8:url-list9:http://s/e", info);
+    let metainfo = Metainfo::from_bytes(single.as_bytes()).unwrap();
+    assert_eq!(metainfo.url_list, vec!["http://s/"]);
+    assert_eq!(metainfo.to_bytes(), format!("d{}8:url-listl9:http://s/ee", info).as_bytes());
+
+    let empty = format!("d{}8:url-list0:e", info);
+    let metainfo = Metainfo::from_bytes(empty.as_bytes()).unwrap();
+    assert!(metainfo.url_list.is_empty());
+
+    let both = format!("d9:httpseedsl9:http://h/e{}8:url-list9:http://s/e", info);
+    let metainfo = Metainfo::from_bytes(both.as_bytes()).unwrap();
+    assert_eq!(metainfo.seeds(), vec![
+        Seed { kind: SeedKind::WebSeed, url: "http://s/" },
+        Seed { kind: SeedKind::HttpSeed, url: "http://h/" },
+    ]);
+
+    let malformed = format!("d{}8:url-listi1ee", info);
+    let metainfo = Metainfo::from_bytes(malformed.as_bytes()).unwrap();
+    assert!(metainfo.url_list.is_empty());
+    assert_eq!(metainfo.to_bytes(), malformed.as_bytes());
+}
+
+#[cfg(feature = "sha1")]
+#[test]
+fn test_same_content() {
+    let a = Metainfo::from_bytes(MULTI_FILE).unwrap();
+    let mut b = a.clone();
+    b.announce = None;
+    b.comment = Some("other".to_string());
+    b.creation_date = None;
+    assert!(same_content(&a, &b));
+
+    b.info.set_source(Some("TRK"));
+    assert!(!same_content(&a, &b));
+}
+
+#[test]
+fn test_summary() {
+    assert_eq!(format_size(1023), "1023 B");
+    assert_eq!(format_size(1536 * 1024), "1.5 MiB");
+
+    let summary = Metainfo::from_bytes(MULTI_FILE).unwrap().summary().to_string();
+    assert!(summary.starts_with("name: root\n"));
+    assert!(summary.contains("size: 8 B (8 bytes)\npiece length: 16.0 KiB\npieces: 1\nfiles: 1\n"));
+    assert!(summary.contains("tracker (tier 0): http://t.example/a\n"));
+    assert!(summary.ends_with("flags: v1, private"));
+    #[cfg(feature = "sha1")]
+    assert!(summary.contains("info hash (v1): "));
+}
+
+#[test]
+fn test_creation_time() {
+    let mut metainfo = Metainfo::from_bytes(MULTI_FILE).unwrap();
+    let time = metainfo.creation_time().unwrap();
+    assert_eq!(time, UNIX_EPOCH + Duration::from_secs(1500000000));
+
+    metainfo.set_creation_time(UNIX_EPOCH - Duration::from_millis(1500));
+    assert_eq!(metainfo.creation_date, Some(-1));
+    assert_eq!(metainfo.creation_time(), Some(UNIX_EPOCH - Duration::from_secs(1)));
+    metainfo.set_creation_time(time + Duration::from_millis(999));
+    assert_eq!(metainfo.creation_date, Some(1500000000));
+}
+
+#[test]
+fn test_nodes() {
+    let info = "4:infod6:lengthi1e4:name1:x12:piece lengthi1e6:pieces0:e";
+    let buf = format!("d{}5:nodesll9:127.0.0.1i6881eel4:dht.i1eeee", info);
+    let metainfo = Metainfo::from_bytes(buf.as_bytes()).unwrap();
+    assert_eq!(metainfo.nodes, vec![("127.0.0.1".to_string(), 6881), ("dht.".to_string(), 1)]);
+    assert_eq!(metainfo.to_bytes(), buf.as_bytes());
+    assert_eq!(metainfo.node_addrs(), vec!["127.0.0.1:6881".parse::<SocketAddr>().unwrap()]);
+
+    for nodes in ["l9:127.0.0.1e", "ll1:ai65536eee", "ll1:ai1ei2eee", "li1ee"].iter() {
+        let buf = format!("d{}5:nodes{}e", info, nodes);
+        assert_eq!(Metainfo::from_bytes(buf.as_bytes()), Err(MetainfoError::Invalid("nodes")));
+    }
+}
+
+#[test]
+fn test_private_and_source() {
+    let mut info = Metainfo::from_bytes(MULTI_FILE).unwrap().info;
+    info.set_source(Some("TRK"));
+    assert_eq!(info.source(), Some("TRK"));
+    info.set_private(false);
+    assert!(!info.is_private());
+    assert!(!info.extra.contains_key(&b"private"[..]));
+
+    let encoded = encode_document(&info.to_bencode());
+    let decoded = Info::from_bencode(decode_document(&encoded).unwrap()).unwrap();
+    assert_eq!(decoded.source(), Some("TRK"));
+    info.set_source(None);
+    assert_eq!(info.source(), None);
+
+    assert_eq!(info.root_hash(), None);
+    info.set_root_hash(Some(&[7; PIECE_HASH_LEN]));
+    assert_eq!(info.root_hash(), Some([7; PIECE_HASH_LEN]));
+}
+
+#[test]
+fn test_similar_and_collections() {
+    let mut metainfo = Metainfo::from_bytes(MULTI_FILE).unwrap();
+    let a = InfoHash::V1([1; 20]);
+    let b = InfoHash::V2([2; 32]);
+    metainfo.info.set_similar(&[a]);
+    metainfo.set_similar(&[a, b]);
+    metainfo.info.set_collections(&["linux".to_string()]);
+    metainfo.set_collections(&["isos".to_string(), "linux".to_string()]);
+
+    let decoded = Metainfo::from_bytes(&metainfo.to_bytes()).unwrap();
+    assert_eq!(decoded.info.similar(), vec![a]);
+    assert_eq!(decoded.all_similar(), vec![a, b]);
+    assert_eq!(decoded.all_collections(), vec!["linux".to_string(), "isos".to_string()]);
+
+    metainfo.set_similar(&[]);
+    assert!(!metainfo.extra.contains_key(&b"similar"[..]));
+}
+
+#[test]
+fn test_info_files() {
+    let metainfo = Metainfo::from_bytes(MULTI_FILE).unwrap();
+    let files: Vec<TorrentFile> = metainfo.info.files().collect();
+    assert_eq!(files, vec![
+        TorrentFile {
+            path: ["root", "a", "b.txt"].iter().collect(),
+            length: 3,
+            attr: None,
+        },
+        TorrentFile {
+            path: ["root", "pad"].iter().collect(),
+            length: 5,
+            attr: Some("p".to_string()),
+        },
+    ]);
+    assert_eq!(metainfo.info.total_length(), 8);
+
+    let single = Info::from_bencode(decode_document(
+        b"d6:lengthi7e4:name5:x.iso12:piece lengthi1e6:pieces0:e").unwrap()).unwrap();
+    assert_eq!(single.files().count(), 1);
+    assert_eq!(single.files().next().map(|f| (f.path, f.length)),
+               Some((PathBuf::from("x.iso"), 7)));
+}
+
+#[test]
+fn test_path_sanitization() {
+    assert_eq!(check_component("ok.txt"), Ok(()));
+    assert_eq!(check_component(""), Err(PathErrorKind::Empty));
+    assert_eq!(check_component(".."), Err(PathErrorKind::ParentDir));
+    assert_eq!(check_component("/etc"), Err(PathErrorKind::Separator));
+    assert_eq!(check_component("a\\b"), Err(PathErrorKind::Separator));
+    assert_eq!(check_component("C:x"), Err(PathErrorKind::DrivePrefix));
+    assert_eq!(check_component("a\0b"), Err(PathErrorKind::NulByte));
+
+    assert_eq!(sanitize_component(".."), "__");
+    assert_eq!(sanitize_component("/etc/passwd"), "_etc_passwd");
+    assert_eq!(sanitize_component("C:evil"), "C_evil");
+    assert_eq!(sanitize_component(""), "_");
+
+    let mut metainfo = Metainfo::from_bytes(MULTI_FILE).unwrap();
+    assert_eq!(metainfo.info.validate_paths(), Ok(()));
+    metainfo.info.files.as_mut().unwrap()[1].path = vec!["..".to_string(), "x".to_string()];
+    assert_eq!(metainfo.info.validate_paths(),
+               Err(PathError { file: Some(1), kind: PathErrorKind::ParentDir }));
+    let paths: Vec<PathBuf> = metainfo.info.sanitized_files().map(|f| f.path).collect();
+    assert_eq!(paths, vec![["root", "a", "b.txt"].iter().collect::<PathBuf>(),
+                           ["root", "__", "x"].iter().collect()]);
+}
+
+#[test]
+fn test_padding_files() {
+    let metainfo = Metainfo::from_bytes(MULTI_FILE).unwrap();
+    let files = metainfo.info.files.as_ref().unwrap();
+    assert!(!files[0].is_padding() && files[1].is_padding());
+
+    let content: Vec<TorrentFile> = metainfo.info.files().skip_padding().collect();
+    assert_eq!(content.len(), 1);
+    assert_eq!(content[0].length, 3);
+    assert_eq!(metainfo.info.total_length(), 8);
+    assert_eq!(metainfo.info.content_length(), 3);
+}
+
+#[test]
+fn test_pieces() {
+    let mut info = Info::from_bencode(decode_document(
+        b"d6:lengthi40000e4:name1:a12:piece lengthi16384e6:pieces60:\
+          aaaaaaaaaaaaaaaaaaaabbbbbbbbbbbbbbbbbbbbcccccccccccccccccccce").unwrap()).unwrap();
+    assert_eq!(info.piece_count(), 3);
+    let pieces: Vec<[u8; 20]> = info.pieces().unwrap().collect();
+    assert_eq!(pieces, vec![[b'a'; 20], [b'b'; 20], [b'c'; 20]]);
+
+    assert_eq!(info.piece_length_of(0), Some(16384));
+    assert_eq!(info.piece_length_of(2), Some(40000 - 2 * 16384));
+    assert_eq!(info.piece_length_of(3), None);
+
+    info.pieces.as_mut().unwrap().pop();
+    assert!(info.pieces().is_err());
+}
+
+#[test]
+fn test_validate_tree_paths() {
+    let hostile = b"d4:infod9:file treed2:..d1:xd0:d6:lengthi3e11:pieces root32:\
+        rrrrrrrrrrrrrrrrrrrrrrrrrrrrrrrreeee12:meta versioni2e\
+        4:name1:a12:piece lengthi16384eee";
+    let metainfo = Metainfo::from_bytes(hostile).unwrap();
+    assert_eq!(metainfo.info.validate_paths(),
+               Err(PathError { file: Some(0), kind: PathErrorKind::ParentDir }));
+    assert!(Metainfo::from_bytes(HYBRID).unwrap().info.validate_paths().is_ok());
+}
+
+#[test]
+fn test_total_length_overflow() {
+    let file = b"d6:lengthi9000000000000000000e4:pathl1:aee";
+    let document = [&b"d5:filesl"[..], file, file, file,
+                    b"e4:name1:r12:piece lengthi16384e6:pieces0:e"].concat();
+    assert_eq!(Info::from_bencode(decode_document(&document).unwrap()),
+               Err(MetainfoError::Invalid("length")));
+
+    let mut info = Metainfo::from_bytes(MULTI_FILE).unwrap().info;
+    for file in info.files.as_mut().unwrap().iter_mut() {
+        file.length = u64::MAX;
+    }
+    assert_eq!(info.checked_total_length(), None);
+    assert_eq!(info.total_length(), u64::MAX);
+    assert_eq!(info.piece_length_of(0), Some(info.piece_length));
+}
+
+#[test]
+fn test_merkle_root_hash_only() {
+    let info = Info::from_bencode(decode_document(
+        b"d6:lengthi5e4:name1:a12:piece lengthi16384e9:root hash20:\
+          rrrrrrrrrrrrrrrrrrrre").unwrap()).unwrap();
+    assert_eq!(info.root_hash(), Some([b'r'; PIECE_HASH_LEN]));
+    assert_eq!(info.protocol_version(), Some(ProtocolVersion::V1));
+    assert_eq!(info.piece_count(), 0);
+}
+
+#[cfg(test)]
+const HYBRID: &[u8] = b"d4:infod9:file treed1:ad0:d6:lengthi3e11:pieces root32:\
+    rrrrrrrrrrrrrrrrrrrrrrrrrrrrrrrreee6:lengthi3e12:meta versioni2e\
+    4:name1:a12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+
+#[test]
+fn test_protocol_versions() {
+    let hybrid = Metainfo::from_bytes(HYBRID).unwrap();
+    assert_eq!(hybrid.info.protocol_version(), Some(ProtocolVersion::Hybrid));
+    assert_eq!(hybrid.to_bytes(), HYBRID);
+
+    let mut v2 = hybrid.clone();
+    v2.info.pieces = None;
+    v2.info.length = None;
+    let v2 = Metainfo::from_bytes(&v2.to_bytes()).unwrap();
+    assert_eq!(v2.info.protocol_version(), Some(ProtocolVersion::V2));
+    assert_eq!(v2.info.piece_count(), 0);
+    assert_eq!(v2.info.total_length(), 3);
+    let files: Vec<TorrentFile> = v2.info.files().collect();
+    assert_eq!(files, vec![TorrentFile { path: PathBuf::from("a"), length: 3, attr: None }]);
+
+    let v1 = Metainfo::from_bytes(MULTI_FILE).unwrap();
+    assert_eq!(v1.info.protocol_version(), Some(ProtocolVersion::V1));
+
+    assert_eq!(Metainfo::from_bytes(b"d4:infod6:lengthi1e4:name1:a12:piece lengthi1eee"),
+               Err(MetainfoError::Missing("pieces")));
+}
+
+#[cfg(all(feature = "sha1", feature = "sha256"))]
+#[test]
+fn test_hybrid_info_hashes() {
+    let hybrid = Metainfo::from_bytes(HYBRID).unwrap();
+    let v1 = ::infohash::info_hash_v1(HYBRID).unwrap();
+    let v2 = ::infohash::info_hash_v2(HYBRID).unwrap();
+    assert_eq!(hybrid.info_hashes(), vec![InfoHash::V1(v1), InfoHash::V2(v2)]);
+
+    let v1_only = Metainfo::from_bytes(MULTI_FILE).unwrap();
+    assert_eq!(v1_only.info_hashes().len(), 1);
+    assert_eq!(v1_only.info_hash_v2(), None);
+}
+
+#[cfg(feature = "sha1")]
+#[test]
+fn test_info_hash_of_original_bytes() {
+    // A leading zero makes the encoding non-canonical: re-encoding `info`
+    // would hash `i16384e` instead.
+    let buf = b"d4:infod6:lengthi1e4:name1:a12:piece lengthi016384e6:pieces20:AAAAAAAAAAAAAAAAAAAAee";
+    let mut metainfo = Metainfo::from_bytes(buf).unwrap();
+    let original = InfoHash::V1(::infohash::info_hash_v1(buf).unwrap());
+    assert_eq!(metainfo.info_hash_v1(), Some(original));
+
+    // Once `info` is edited the recorded bytes no longer apply.
+    metainfo.info.name = "b".to_string();
+    let edited = Metainfo::from_bytes(&metainfo.to_bytes()).unwrap();
+    assert!(metainfo.info_hash_v1() != Some(original));
+    assert_eq!(metainfo.info_hash_v1(), edited.info_hash_v1());
+}
+
+#[cfg(feature = "url")]
+#[test]
+fn test_normalize_trackers() {
+    use tracker_url::UrlErrorKind;
+
+    let mut metainfo = Metainfo::from_bytes(HYBRID).unwrap();
+    metainfo.announce = Some("HTTP://A.example:80/announce".to_string());
+    metainfo.announce_list = Some(AnnounceList {
+        tiers: vec![
+            vec!["udp://b.example:6969".to_string(), "udp://c.example".to_string()],
+            vec!["http://d.example/a http://e.example/a".to_string()],
+        ],
+    });
+    let errors = metainfo.validate_trackers();
+    let kinds: Vec<UrlErrorKind> = errors.iter().map(|err| err.kind).collect();
+    assert_eq!(kinds, vec![UrlErrorKind::InvalidPort, UrlErrorKind::Whitespace]);
+
+    assert_eq!(metainfo.normalize_trackers(), errors);
+    assert_eq!(metainfo.announce, Some("http://a.example/announce".to_string()));
+    assert_eq!(metainfo.trackers().flatten(), vec!["udp://b.example:6969"]);
+    assert!(metainfo.validate_trackers().is_empty());
+}
@@ -0,0 +1,146 @@
+//! Per-call events from the main entry points, enabled by the `trace`
+//! feature.
+//!
+//! Once a `Subscriber` is installed, `bdecode`, `bdecode_slice`,
+//! `Decoder::decode`, `bencode` and `bencode_to_vec` each report an `Event`
+//! as they return: the document's size and depth, how long the call took
+//! and, for failed decodes, the error and roughly where it happened. A
+//! subscriber can log the events or turn them into spans of whatever
+//! tracing system the application uses. With no subscriber installed a
+//! call costs one clock read.
+
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use {Bencode, ParseError, measure};
+use lexer::Lexer;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Operation {
+    Decode,
+    Encode,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Event {
+    pub operation: Operation,
+    /// The function called, e.g. `"bdecode_slice"`.
+    pub entry_point: &'static str,
+    /// Encoded size of the document. For a failed decode, the length of
+    /// the input if it was a slice, and 0 otherwise.
+    pub bytes: u64,
+    /// Nesting depth of the document; a bare integer or string has depth
+    /// 1. 0 for a failed decode.
+    pub depth: u64,
+    pub elapsed: Duration,
+    /// Why a decode failed.
+    pub error: Option<ParseError>,
+    /// The start of the token a failed decode stopped at, when the input
+    /// was a slice and the error is a syntax error.
+    pub error_offset: Option<usize>,
+    /// Whether an encode failed to write its output.
+    pub write_failed: bool,
+}
+
+pub trait Subscriber: Sync {
+    fn event(&self, event: &Event);
+}
+
+static SUBSCRIBER: OnceLock<&'static dyn Subscriber> = OnceLock::new();
+
+/// Installs the process-wide subscriber. Returns `false`, and leaves the
+/// existing one in place, if a subscriber is already installed.
+pub fn set_subscriber(subscriber: &'static dyn Subscriber) -> bool {
+    SUBSCRIBER.set(subscriber).is_ok()
+}
+
+/// Finds where lexing `buf` fails, which for syntax errors is where
+/// decoding failed too.
+fn error_offset(buf: &[u8]) -> Option<usize> {
+    let mut lexer = Lexer::new(buf);
+    match lexer.by_ref().find_map(Result::err) {
+        // The lexer's depth limit is lower than the decoders'.
+        Some(ParseError::TooDeep) | None => None,
+        Some(_) => Some(lexer.position()),
+    }
+}
+
+/// Reports one decode that started at `start`.
+pub(crate) fn decoded(entry_point: &'static str, input: Option<&[u8]>, start: Instant,
+                      result: &Result<Bencode, ParseError>) {
+    let subscriber = match SUBSCRIBER.get() {
+        Some(subscriber) => subscriber,
+        None => return,
+    };
+    let elapsed = start.elapsed();
+    let event = match *result {
+        Ok(ref value) => {
+            let (bytes, _, depth) = measure(value);
+            Event {
+                operation: Operation::Decode, entry_point, bytes, depth, elapsed,
+                error: None, error_offset: None, write_failed: false,
+            }
+        },
+        Err(error) => Event {
+            operation: Operation::Decode, entry_point,
+            bytes: input.map_or(0, |input| input.len() as u64),
+            depth: 0,
+            elapsed,
+            error: Some(error),
+            error_offset: input.and_then(error_offset),
+            write_failed: false,
+        },
+    };
+    subscriber.event(&event);
+}
+
+/// Reports one encode of `document` that started at `start`.
+pub(crate) fn encoded(entry_point: &'static str, start: Instant, document: &Bencode,
+                      write_failed: bool) {
+    let subscriber = match SUBSCRIBER.get() {
+        Some(subscriber) => subscriber,
+        None => return,
+    };
+    let elapsed = start.elapsed();
+    let (bytes, _, depth) = measure(document);
+    subscriber.event(&Event {
+        operation: Operation::Encode, entry_point, bytes, depth, elapsed,
+        error: None, error_offset: None, write_failed,
+    });
+}
+
+#[test]
+fn test_trace_events() {
+    use std::sync::Mutex;
+
+    struct Recorder(Mutex<Vec<Event>>);
+
+    impl Subscriber for Recorder {
+        fn event(&self, event: &Event) {
+            self.0.lock().unwrap().push(*event);
+        }
+    }
+
+    static RECORDER: Recorder = Recorder(Mutex::new(Vec::new()));
+    assert!(set_subscriber(&RECORDER));
+    assert!(!set_subscriber(&RECORDER));
+
+    // Other tests decode concurrently, so look for these calls by size.
+    let buf = b"d1:ali1e2:xye1:bi-123456789ee";
+    let value = ::bdecode_slice(buf).unwrap();
+    assert_eq!(::bencode_to_vec(&value), &buf[..]);
+    assert_eq!(::bdecode_slice(b"d1:ali1ei1xeee"), Err(ParseError::InvalidCharacter));
+
+    let events = RECORDER.0.lock().unwrap();
+    let find = |operation, bytes| {
+        events.iter().find(|event| event.operation == operation && event.bytes == bytes)
+            .expect("event recorded")
+    };
+    let decode = find(Operation::Decode, buf.len() as u64);
+    assert_eq!((decode.entry_point, decode.depth, decode.error), ("bdecode_slice", 3, None));
+    let encode = find(Operation::Encode, buf.len() as u64);
+    assert_eq!((encode.entry_point, encode.depth, encode.write_failed),
+               ("bencode_to_vec", 3, false));
+    let failed = find(Operation::Decode, 14);
+    assert_eq!((failed.error, failed.error_offset), (Some(ParseError::InvalidCharacter), Some(8)));
+}
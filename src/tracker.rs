@@ -0,0 +1,749 @@
+//! Tracker HTTP protocol documents (BEP 3).
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::time::Duration;
+
+use {Bencode, ParseError, bencode};
+use fields::{put, take_bytes, take_u64};
+use infohash::InfoHash;
+use peer_id::PeerId;
+use percent;
+use raw;
+use torrent::Dict;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TrackerError {
+    /// The tracker rejected the request with this `failure reason`.
+    Failure(String),
+    Parse(ParseError),
+    /// A required key is absent.
+    Missing(&'static str),
+    /// A key holds a value of the wrong type or an unusable value.
+    Invalid(&'static str),
+}
+
+impl From<ParseError> for TrackerError {
+    fn from(err: ParseError) -> TrackerError {
+        TrackerError::Parse(err)
+    }
+}
+
+/// A key that held a value of the wrong type.
+impl From<&'static str> for TrackerError {
+    fn from(key: &'static str) -> TrackerError {
+        TrackerError::Invalid(key)
+    }
+}
+
+pub type TrackerResult<T> = Result<T, TrackerError>;
+
+/// Trackers send text in any encoding, so unlike `fields::take_string`
+/// this does not reject invalid UTF-8.
+fn take_string(dict: &mut Dict, key: &'static str) -> TrackerResult<Option<String>> {
+    Ok(take_bytes(dict, key)?.map(|buf| String::from_utf8_lossy(&buf).into_owned()))
+}
+
+/// Reads an address sent either as text or in compact binary form (4 or
+/// 16 bytes).
+fn take_ip(dict: &mut Dict, key: &'static str) -> TrackerResult<Option<IpAddr>> {
+    let buf = match take_bytes(dict, key)? {
+        Some(buf) => buf,
+        None => return Ok(None),
+    };
+    if let Some(ip) = ::std::str::from_utf8(&buf).ok().and_then(|ip| ip.parse().ok()) {
+        return Ok(Some(ip));
+    }
+    match buf.len() {
+        4 => {
+            let mut octets = [0; 4];
+            octets.copy_from_slice(&buf);
+            Ok(Some(IpAddr::from(octets)))
+        },
+        16 => {
+            let mut octets = [0; 16];
+            octets.copy_from_slice(&buf);
+            Ok(Some(IpAddr::from(octets)))
+        },
+        _ => Err(TrackerError::Invalid(key)),
+    }
+}
+
+fn ip_octets(ip: IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    }
+}
+
+/// Decodes a response body, which must hold exactly one dictionary.
+fn decode_document(buf: &[u8]) -> TrackerResult<Dict> {
+    match raw::decode_exact(buf)? {
+        Bencode::Object(dict) => Ok(dict),
+        _ => Err(TrackerError::Invalid("response")),
+    }
+}
+
+/// Takes a response dictionary, turning a `failure reason` into an error.
+fn response_dict(value: Bencode) -> TrackerResult<Dict> {
+    let mut dict = match value {
+        Bencode::Object(dict) => dict,
+        _ => return Err(TrackerError::Invalid("response")),
+    };
+    match take_string(&mut dict, "failure reason")? {
+        Some(reason) => Err(TrackerError::Failure(reason)),
+        None => Ok(dict),
+    }
+}
+
+/// Encodes the response a tracker sends to reject a request.
+pub fn failure_response(reason: &str) -> Vec<u8> {
+    let mut dict = Dict::new();
+    put(&mut dict, "failure reason", Some(reason));
+    encode_document(&Bencode::Object(dict))
+}
+
+fn encode_document(document: &Bencode) -> Vec<u8> {
+    let mut buf = Vec::new();
+    bencode(document, &mut buf).expect("writing to a Vec cannot fail");
+    buf
+}
+
+/// Where a peer listens. BEP 3 lets the dictionary form give a DNS name
+/// in place of an address.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PeerAddr {
+    Ip(SocketAddr),
+    /// A host name and port, left for the caller to resolve.
+    Name(String, u16),
+}
+
+/// A peer from an announce response. The compact form carries no peer id.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Peer {
+    pub addr: PeerAddr,
+    pub peer_id: Option<PeerId>,
+}
+
+impl Peer {
+    /// The peer's address, if it was given as an IP address.
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        match self.addr {
+            PeerAddr::Ip(addr) => Some(addr),
+            PeerAddr::Name(..) => None,
+        }
+    }
+
+    fn from_bencode(value: Bencode) -> TrackerResult<Peer> {
+        let invalid = || TrackerError::Invalid("peers");
+        let mut dict = match value {
+            Bencode::Object(dict) => dict,
+            _ => return Err(invalid()),
+        };
+        let ip = take_string(&mut dict, "ip")?.filter(|ip| !ip.is_empty()).ok_or_else(invalid)?;
+        let port = take_u64(&mut dict, "port")?
+            .filter(|&port| port <= u64::from(u16::MAX))
+            .ok_or_else(invalid)? as u16;
+        Ok(Peer {
+            addr: match ip.parse::<IpAddr>() {
+                Ok(ip) => PeerAddr::Ip(SocketAddr::new(ip, port)),
+                Err(_) => PeerAddr::Name(ip, port),
+            },
+            peer_id: match take_bytes(&mut dict, "peer id")? {
+                Some(id) => Some(PeerId::from_slice(&id).ok_or_else(invalid)?),
+                None => None,
+            },
+        })
+    }
+
+    fn to_bencode(&self) -> Bencode {
+        let (ip, port) = match self.addr {
+            PeerAddr::Ip(addr) => (addr.ip().to_string(), addr.port()),
+            PeerAddr::Name(ref name, port) => (name.clone(), port),
+        };
+        let mut dict = Dict::new();
+        put(&mut dict, "ip", Some(ip));
+        put(&mut dict, "peer id", self.peer_id.as_ref().map(|id| &id.0[..]));
+        put(&mut dict, "port", Some(u64::from(port)));
+        Bencode::Object(dict)
+    }
+}
+
+/// Length of one BEP 23 compact IPv4 peer: address then port, big endian.
+pub const COMPACT_V4_LEN: usize = 6;
+
+/// Decodes a BEP 23 compact peer string.
+pub fn decode_compact_peers(buf: &[u8]) -> TrackerResult<Vec<SocketAddrV4>> {
+    if !buf.len().is_multiple_of(COMPACT_V4_LEN) {
+        return Err(TrackerError::Invalid("peers"));
+    }
+    Ok(buf.chunks(COMPACT_V4_LEN).map(|chunk| {
+        let ip = Ipv4Addr::new(chunk[0], chunk[1], chunk[2], chunk[3]);
+        SocketAddrV4::new(ip, u16::from_be_bytes([chunk[4], chunk[5]]))
+    }).collect())
+}
+
+/// Encodes peers as a BEP 23 compact peer string.
+pub fn encode_compact_peers(peers: &[SocketAddrV4]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(peers.len() * COMPACT_V4_LEN);
+    for peer in peers.iter() {
+        buf.extend_from_slice(&peer.ip().octets());
+        buf.extend_from_slice(&peer.port().to_be_bytes());
+    }
+    buf
+}
+
+/// Length of one BEP 7 compact IPv6 peer.
+pub const COMPACT_V6_LEN: usize = 18;
+
+/// Decodes a BEP 7 `peers6` string.
+pub fn decode_compact_peers6(buf: &[u8]) -> TrackerResult<Vec<SocketAddrV6>> {
+    if !buf.len().is_multiple_of(COMPACT_V6_LEN) {
+        return Err(TrackerError::Invalid("peers6"));
+    }
+    Ok(buf.chunks(COMPACT_V6_LEN).map(|chunk| {
+        let mut octets = [0u8; 16];
+        octets.copy_from_slice(&chunk[..16]);
+        let port = u16::from_be_bytes([chunk[16], chunk[17]]);
+        SocketAddrV6::new(Ipv6Addr::from(octets), port, 0, 0)
+    }).collect())
+}
+
+/// Encodes peers as a BEP 7 `peers6` string.
+pub fn encode_compact_peers6(peers: &[SocketAddrV6]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(peers.len() * COMPACT_V6_LEN);
+    for peer in peers.iter() {
+        buf.extend_from_slice(&peer.ip().octets());
+        buf.extend_from_slice(&peer.port().to_be_bytes());
+    }
+    buf
+}
+
+/// A successful response to an announce request.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct AnnounceResponse {
+    /// How long the client should wait between regular announces. Encoded
+    /// in whole seconds; fractions are dropped.
+    pub interval: Duration,
+    /// The shortest allowed wait between announces.
+    pub min_interval: Option<Duration>,
+    /// Number of seeders.
+    pub complete: Option<u64>,
+    /// Number of leechers.
+    pub incomplete: Option<u64>,
+    /// Opaque value to send back as `trackerid` on later announces.
+    pub tracker_id: Option<Vec<u8>>,
+    /// A message to show the user; the response is otherwise processed
+    /// normally.
+    pub warning_message: Option<String>,
+    pub peers: Vec<Peer>,
+    /// BEP 24 `external ip`: the client's address as the tracker sees it.
+    pub external_ip: Option<IpAddr>,
+    /// `ip`: the same, as some trackers send it in text form.
+    pub ip: Option<IpAddr>,
+    /// `ipv4` and `ipv6`: the client's address in each family, for
+    /// dual-stack clients (BEP 7).
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
+    pub extra: Dict,
+}
+
+impl AnnounceResponse {
+    /// Decodes a response; a `failure reason` yields
+    /// `TrackerError::Failure`.
+    pub fn from_bencode(value: Bencode) -> TrackerResult<AnnounceResponse> {
+        let mut dict = response_dict(value)?;
+        let interval = take_u64(&mut dict, "interval")?
+            .ok_or(TrackerError::Missing("interval"))?;
+        let mut peers = match dict.remove(&b"peers"[..]) {
+            None => Vec::new(),
+            Some(Bencode::Bytes(buf)) => decode_compact_peers(&buf)?
+                .into_iter()
+                .map(|addr| Peer { addr: PeerAddr::Ip(SocketAddr::V4(addr)), peer_id: None })
+                .collect(),
+            Some(Bencode::Array(peers)) => peers.into_iter()
+                .map(Peer::from_bencode)
+                .collect::<TrackerResult<Vec<_>>>()?,
+            Some(_) => return Err(TrackerError::Invalid("peers")),
+        };
+        if let Some(buf) = take_bytes(&mut dict, "peers6")? {
+            peers.extend(decode_compact_peers6(&buf)?.into_iter()
+                .map(|addr| Peer { addr: PeerAddr::Ip(SocketAddr::V6(addr)), peer_id: None }));
+        }
+        let ipv4 = match take_ip(&mut dict, "ipv4")? {
+            Some(IpAddr::V4(ip)) => Some(ip),
+            Some(IpAddr::V6(_)) => return Err(TrackerError::Invalid("ipv4")),
+            None => None,
+        };
+        let ipv6 = match take_ip(&mut dict, "ipv6")? {
+            Some(IpAddr::V6(ip)) => Some(ip),
+            Some(IpAddr::V4(_)) => return Err(TrackerError::Invalid("ipv6")),
+            None => None,
+        };
+        Ok(AnnounceResponse {
+            interval: Duration::from_secs(interval),
+            min_interval: take_u64(&mut dict, "min interval")?.map(Duration::from_secs),
+            complete: take_u64(&mut dict, "complete")?,
+            incomplete: take_u64(&mut dict, "incomplete")?,
+            tracker_id: take_bytes(&mut dict, "tracker id")?,
+            warning_message: take_string(&mut dict, "warning message")?,
+            peers,
+            external_ip: take_ip(&mut dict, "external ip")?,
+            ip: take_ip(&mut dict, "ip")?,
+            ipv4,
+            ipv6,
+            extra: dict,
+        })
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> TrackerResult<AnnounceResponse> {
+        AnnounceResponse::from_bencode(Bencode::Object(decode_document(buf)?))
+    }
+
+    /// Encodes the response. With `compact`, peer ids and peers given by
+    /// name are dropped, and IPv4 and IPv6 peers are packed into the
+    /// `peers` and `peers6` strings;
+    /// `peers6` is only emitted if there are IPv6 peers. `ip` is written
+    /// as text and the other address keys in binary form.
+    pub fn to_bencode(&self, compact: bool) -> Bencode {
+        let mut dict = self.extra.clone();
+        put(&mut dict, "external ip", self.external_ip.map(ip_octets));
+        put(&mut dict, "ip", self.ip.map(|ip| ip.to_string()));
+        put(&mut dict, "ipv4", self.ipv4.map(|ip| ip.octets().to_vec()));
+        put(&mut dict, "ipv6", self.ipv6.map(|ip| ip.octets().to_vec()));
+        put(&mut dict, "complete", self.complete);
+        put(&mut dict, "incomplete", self.incomplete);
+        put(&mut dict, "interval", Some(self.interval_secs()));
+        put(&mut dict, "min interval", self.min_interval_secs());
+        put(&mut dict, "tracker id", self.tracker_id.as_ref().map(|id| &id[..]));
+        put(&mut dict, "warning message", self.warning_message.as_ref().map(|s| &s[..]));
+        let peers = if compact {
+            Bencode::Bytes(encode_compact_peers(&self.peers_v4()))
+        } else {
+            Bencode::Array(self.peers.iter().map(Peer::to_bencode).collect())
+        };
+        put(&mut dict, "peers", Some(peers));
+        let peers6 = self.peers_v6();
+        if compact && !peers6.is_empty() {
+            put(&mut dict, "peers6", Some(Bencode::Bytes(encode_compact_peers6(&peers6))));
+        }
+        Bencode::Object(dict)
+    }
+
+    /// A response asking clients to announce every `interval`.
+    pub fn new(interval: Duration) -> AnnounceResponse {
+        AnnounceResponse { interval, ..AnnounceResponse::default() }
+    }
+
+    /// `interval` in seconds, as sent on the wire.
+    pub fn interval_secs(&self) -> u64 {
+        self.interval.as_secs()
+    }
+
+    /// `min interval` in seconds, as sent on the wire.
+    pub fn min_interval_secs(&self) -> Option<u64> {
+        self.min_interval.map(|interval| interval.as_secs())
+    }
+
+    /// The client's address as reported by the tracker, from whichever of
+    /// `external ip`, `ip`, `ipv4` and `ipv6` is present, in that order.
+    pub fn reported_ip(&self) -> Option<IpAddr> {
+        self.external_ip
+            .or(self.ip)
+            .or(self.ipv4.map(IpAddr::V4))
+            .or(self.ipv6.map(IpAddr::V6))
+    }
+
+    /// The IPv4 peers, as carried by the compact `peers` string.
+    pub fn peers_v4(&self) -> Vec<SocketAddrV4> {
+        self.peers.iter().filter_map(|peer| match peer.socket_addr() {
+            Some(SocketAddr::V4(addr)) => Some(addr),
+            _ => None,
+        }).collect()
+    }
+
+    /// The IPv6 peers, as carried by the compact `peers6` string.
+    pub fn peers_v6(&self) -> Vec<SocketAddrV6> {
+        self.peers.iter().filter_map(|peer| match peer.socket_addr() {
+            Some(SocketAddr::V6(addr)) => Some(addr),
+            _ => None,
+        }).collect()
+    }
+
+    pub fn to_bytes(&self, compact: bool) -> Vec<u8> {
+        encode_document(&self.to_bencode(compact))
+    }
+}
+
+/// Swarm statistics for one torrent in a scrape response. Trackers that
+/// leave a count out are read as reporting zero.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ScrapeStats {
+    /// Number of seeders.
+    pub complete: u64,
+    /// Number of completed downloads ever reported.
+    pub downloaded: u64,
+    /// Number of leechers.
+    pub incomplete: u64,
+    /// The torrent name, which some trackers include.
+    pub name: Option<String>,
+}
+
+impl ScrapeStats {
+    fn from_bencode(value: Bencode) -> TrackerResult<ScrapeStats> {
+        let mut dict = match value {
+            Bencode::Object(dict) => dict,
+            _ => return Err(TrackerError::Invalid("files")),
+        };
+        Ok(ScrapeStats {
+            complete: take_u64(&mut dict, "complete")?.unwrap_or(0),
+            downloaded: take_u64(&mut dict, "downloaded")?.unwrap_or(0),
+            incomplete: take_u64(&mut dict, "incomplete")?.unwrap_or(0),
+            name: take_string(&mut dict, "name")?,
+        })
+    }
+
+    fn to_bencode(&self) -> Bencode {
+        let mut dict = Dict::new();
+        put(&mut dict, "complete", Some(self.complete));
+        put(&mut dict, "downloaded", Some(self.downloaded));
+        put(&mut dict, "incomplete", Some(self.incomplete));
+        put(&mut dict, "name", self.name.as_ref().map(|s| &s[..]));
+        Bencode::Object(dict)
+    }
+}
+
+/// A response to a scrape request. `files` is keyed by the raw 20-byte
+/// infohash, not its hex form; trackers key v2 torrents by the truncated
+/// hash, so those keys read back as `InfoHash::V1`.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ScrapeResponse {
+    pub files: HashMap<InfoHash, ScrapeStats>,
+    /// `flags.min_request_interval`: seconds to wait between scrapes.
+    pub min_request_interval: Option<u64>,
+    pub extra: Dict,
+}
+
+impl ScrapeResponse {
+    /// Decodes a response; a `failure reason` yields
+    /// `TrackerError::Failure`.
+    pub fn from_bencode(value: Bencode) -> TrackerResult<ScrapeResponse> {
+        let mut dict = response_dict(value)?;
+        let mut files = HashMap::new();
+        match dict.remove(&b"files"[..]) {
+            None => (),
+            Some(Bencode::Object(entries)) => {
+                for (key, stats) in entries {
+                    let hash = InfoHash::from_slice(&key).ok_or(TrackerError::Invalid("files"))?;
+                    files.insert(hash, ScrapeStats::from_bencode(stats)?);
+                }
+            },
+            Some(_) => return Err(TrackerError::Invalid("files")),
+        }
+        let min_request_interval = match dict.get_mut(&b"flags"[..]) {
+            Some(Bencode::Object(flags)) => take_u64(flags, "min_request_interval")?,
+            _ => None,
+        };
+        if let Some(Bencode::Object(flags)) = dict.get(&b"flags"[..]) {
+            if flags.is_empty() {
+                dict.remove(&b"flags"[..]);
+            }
+        }
+        Ok(ScrapeResponse { files, min_request_interval, extra: dict })
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> TrackerResult<ScrapeResponse> {
+        ScrapeResponse::from_bencode(Bencode::Object(decode_document(buf)?))
+    }
+
+    pub fn to_bencode(&self) -> Bencode {
+        let mut dict = self.extra.clone();
+        let files = self.files.iter()
+            .map(|(hash, stats)| (hash.truncated().to_vec(), stats.to_bencode()))
+            .collect();
+        put(&mut dict, "files", Some(Bencode::Object(files)));
+        if let Some(interval) = self.min_request_interval {
+            let flags = dict.entry(b"flags".to_vec()).or_insert_with(|| Bencode::Object(Dict::new()));
+            if let Bencode::Object(ref mut flags) = *flags {
+                put(flags, "min_request_interval", Some(interval));
+            }
+        }
+        Bencode::Object(dict)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode_document(&self.to_bencode())
+    }
+}
+
+/// The `event` of an announce; regular announces carry none.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Event {
+    Started,
+    Stopped,
+    Completed,
+}
+
+impl Event {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Event::Started => "started",
+            Event::Stopped => "stopped",
+            Event::Completed => "completed",
+        }
+    }
+}
+
+/// The query parameters of an HTTP announce.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AnnounceRequest {
+    /// Sent in its 20-byte form, truncated for v2 torrents as BEP 52
+    /// specifies.
+    pub info_hash: InfoHash,
+    pub peer_id: PeerId,
+    pub port: u16,
+    pub uploaded: u64,
+    pub downloaded: u64,
+    pub left: u64,
+    /// Asks for the BEP 23 compact peer list.
+    pub compact: bool,
+    pub numwant: Option<u32>,
+    pub key: Option<String>,
+    /// The `tracker id` of an earlier response, sent as `trackerid`.
+    pub tracker_id: Option<Vec<u8>>,
+    pub event: Option<Event>,
+}
+
+impl AnnounceRequest {
+    pub fn new(info_hash: InfoHash, peer_id: PeerId, port: u16) -> AnnounceRequest {
+        AnnounceRequest {
+            info_hash,
+            peer_id,
+            port,
+            uploaded: 0,
+            downloaded: 0,
+            left: 0,
+            compact: true,
+            numwant: None,
+            key: None,
+            tracker_id: None,
+            event: None,
+        }
+    }
+
+    /// Renders the query string, without the leading `?`.
+    pub fn to_query(&self) -> String {
+        let mut query = format!("info_hash={}&peer_id={}&port={}&uploaded={}&downloaded={}&left={}\
+                                 &compact={}",
+                                percent::encode(&self.info_hash.truncated()),
+                                percent::encode(self.peer_id.as_bytes()),
+                                self.port, self.uploaded, self.downloaded, self.left,
+                                if self.compact { 1 } else { 0 });
+        if let Some(numwant) = self.numwant {
+            query.push_str(&format!("&numwant={}", numwant));
+        }
+        if let Some(ref key) = self.key {
+            query.push_str(&format!("&key={}", percent::encode_component(key.as_bytes())));
+        }
+        if let Some(ref tracker_id) = self.tracker_id {
+            query.push_str(&format!("&trackerid={}", percent::encode_component(tracker_id)));
+        }
+        if let Some(event) = self.event {
+            query.push_str(&format!("&event={}", event.as_str()));
+        }
+        query
+    }
+
+    /// Appends the query to an announce URL, which may already carry
+    /// parameters of its own.
+    pub fn to_url(&self, announce: &str) -> String {
+        let separator = if announce.contains('?') { '&' } else { '?' };
+        format!("{}{}{}", announce, separator, self.to_query())
+    }
+
+    /// Parses a query string (without the `?`) as received by a tracker.
+    /// Unknown parameters are ignored.
+    pub fn from_query(query: &str) -> TrackerResult<AnnounceRequest> {
+        fn number<T: ::std::str::FromStr>(value: &[u8], key: &'static str) -> TrackerResult<T> {
+            ::std::str::from_utf8(value).ok()
+                .and_then(|value| value.parse().ok())
+                .ok_or(TrackerError::Invalid(key))
+        }
+
+        let (mut info_hash, mut peer_id, mut port) = (None, None, None);
+        let mut request = AnnounceRequest::new(InfoHash::V1([0; 20]), PeerId([0; 20]), 0);
+        request.compact = false;
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = match pair.find('=') {
+                Some(index) => (&pair[..index], &pair[index + 1..]),
+                None => (pair, ""),
+            };
+            let value = percent::decode(value).map_err(|_| TrackerError::Invalid("query"))?;
+            match key {
+                "info_hash" => {
+                    info_hash = Some(InfoHash::from_slice(&value)
+                        .ok_or(TrackerError::Invalid("info_hash"))?);
+                },
+                "peer_id" => {
+                    peer_id = Some(PeerId::from_slice(&value)
+                        .ok_or(TrackerError::Invalid("peer_id"))?);
+                },
+                "port" => port = Some(number(&value, "port")?),
+                "uploaded" => request.uploaded = number(&value, "uploaded")?,
+                "downloaded" => request.downloaded = number(&value, "downloaded")?,
+                "left" => request.left = number(&value, "left")?,
+                "compact" => request.compact = value == b"1",
+                "numwant" => request.numwant = Some(number(&value, "numwant")?),
+                "key" => request.key = Some(String::from_utf8_lossy(&value).into_owned()),
+                "trackerid" => request.tracker_id = Some(value),
+                "event" => request.event = match &value[..] {
+                    b"started" => Some(Event::Started),
+                    b"stopped" => Some(Event::Stopped),
+                    b"completed" => Some(Event::Completed),
+                    b"" | b"empty" => None,
+                    _ => return Err(TrackerError::Invalid("event")),
+                },
+                _ => (),
+            }
+        }
+        request.info_hash = info_hash.ok_or(TrackerError::Missing("info_hash"))?;
+        request.peer_id = peer_id.ok_or(TrackerError::Missing("peer_id"))?;
+        request.port = port.ok_or(TrackerError::Missing("port"))?;
+        Ok(request)
+    }
+}
+
+#[test]
+fn test_compact_peers() {
+    let peers = vec![SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 0x1ae1),
+                     SocketAddrV4::new(Ipv4Addr::new(255, 0, 0, 9), 65535)];
+    let buf = encode_compact_peers(&peers);
+    assert_eq!(buf, b"\x01\x02\x03\x04\x1a\xe1\xff\x00\x00\x09\xff\xff".to_vec());
+    assert_eq!(decode_compact_peers(&buf), Ok(peers));
+    assert_eq!(decode_compact_peers(&buf[..5]), Err(TrackerError::Invalid("peers")));
+    assert_eq!(decode_compact_peers(b""), Ok(vec![]));
+}
+
+#[test]
+fn test_announce_response() {
+    let buf = b"d8:completei5e10:incompletei2e8:intervali1800e12:min intervali60e\
+        5:peers12:\x7f\x00\x00\x01\x1a\xe1\x0a\x00\x00\x02\x00\x50e";
+    let response = AnnounceResponse::from_bytes(buf).unwrap();
+    assert_eq!(response.interval, Duration::from_secs(1800));
+    assert_eq!(response.interval_secs(), 1800);
+    assert_eq!(response.min_interval, Some(Duration::from_secs(60)));
+    assert_eq!((response.complete, response.incomplete), (Some(5), Some(2)));
+    let addrs: Vec<SocketAddr> = response.peers.iter().filter_map(Peer::socket_addr).collect();
+    assert_eq!(addrs, vec!["127.0.0.1:6881".parse().unwrap(), "10.0.0.2:80".parse().unwrap()]);
+    assert_eq!(response.to_bytes(true), &buf[..]);
+
+    let dict_form = b"d8:intervali900e5:peersld2:ip3:::17:peer id20:-XX0001-abcdefghijkl4:porti1eeee";
+    let response = AnnounceResponse::from_bytes(dict_form).unwrap();
+    assert_eq!(response.peers, vec![Peer {
+        addr: PeerAddr::Ip("[::1]:1".parse().unwrap()),
+        peer_id: Some(PeerId(*b"-XX0001-abcdefghijkl")),
+    }]);
+    assert_eq!(response.to_bytes(false), &dict_form[..]);
+
+    let named = b"d8:intervali900e5:peersld2:ip12:peer.example4:porti6881eeee";
+    let response = AnnounceResponse::from_bytes(named).unwrap();
+    assert_eq!(response.peers[0].addr, PeerAddr::Name("peer.example".to_string(), 6881));
+    assert_eq!(response.peers[0].socket_addr(), None);
+    assert_eq!(response.to_bytes(false), &named[..]);
+
+    let mixed = AnnounceResponse {
+        interval: Duration::from_millis(1500),
+        peers: vec![Peer { addr: PeerAddr::Ip("[::2]:2".parse().unwrap()), peer_id: None },
+                    Peer { addr: PeerAddr::Ip("1.2.3.4:5".parse().unwrap()), peer_id: None },
+                    Peer { addr: PeerAddr::Name("peer.example".to_string(), 6), peer_id: None }],
+        ..AnnounceResponse::default()
+    };
+    let buf = mixed.to_bytes(true);
+    assert_eq!(&buf[..], &b"d8:intervali1e5:peers6:\x01\x02\x03\x04\x00\x05\
+        6:peers618:\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x02\x00\x02e"[..]);
+    let decoded = AnnounceResponse::from_bytes(&buf).unwrap();
+    assert_eq!(decoded.peers_v4(), mixed.peers_v4());
+    assert_eq!(decoded.peers_v6(), mixed.peers_v6());
+
+    assert_eq!(AnnounceResponse::from_bytes(b"de"), Err(TrackerError::Missing("interval")));
+    assert_eq!(AnnounceResponse::from_bytes(b"d8:intervali1e5:peers1:xe"),
+               Err(TrackerError::Invalid("peers")));
+}
+
+#[test]
+fn test_reported_addresses() {
+    let buf = b"d11:external ip4:\x01\x02\x03\x048:intervali1e2:ip7:5.6.7.8\
+        4:ipv64:\x01\x02\x03\x045:peers0:e";
+    assert_eq!(AnnounceResponse::from_bytes(buf), Err(TrackerError::Invalid("ipv6")));
+
+    let buf = b"d11:external ip16:\x20\x01\x0d\xb8\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\
+        8:intervali1e2:ip7:5.6.7.84:ipv44:\x01\x02\x03\x045:peers0:e";
+    let response = AnnounceResponse::from_bytes(buf).unwrap();
+    assert_eq!(response.external_ip, Some("2001:db8::1".parse().unwrap()));
+    assert_eq!(response.ip, Some("5.6.7.8".parse().unwrap()));
+    assert_eq!(response.ipv4, Some(Ipv4Addr::new(1, 2, 3, 4)));
+    assert_eq!(response.reported_ip(), response.external_ip);
+    assert_eq!(response.to_bytes(true), &buf[..]);
+}
+
+#[test]
+fn test_failure_and_warning() {
+    let buf = failure_response("unregistered torrent");
+    assert_eq!(&buf[..], &b"d14:failure reason20:unregistered torrente"[..]);
+    assert_eq!(AnnounceResponse::from_bytes(&buf),
+               Err(TrackerError::Failure("unregistered torrent".to_string())));
+    assert_eq!(AnnounceResponse::from_bytes(b"d14:failure reason3:\xffOKe"),
+               Err(TrackerError::Failure("\u{fffd}OK".to_string())));
+    assert_eq!(AnnounceResponse::from_bytes(b"d14:failure reasoni1ee"),
+               Err(TrackerError::Invalid("failure reason")));
+
+    let buf = b"d8:intervali60e5:peers0:15:warning message4:slowe";
+    let response = AnnounceResponse::from_bytes(buf).unwrap();
+    assert_eq!(response.warning_message, Some("slow".to_string()));
+    assert_eq!(response.to_bytes(true), &buf[..]);
+}
+
+#[test]
+fn test_scrape_response() {
+    let buf = b"d5:filesd20:aaaaaaaaaaaaaaaaaaaad8:completei3e10:downloadedi9e\
+        10:incompletei1eee5:flagsd20:min_request_intervali900eee";
+    let response = ScrapeResponse::from_bytes(buf).unwrap();
+    let stats = &response.files[&InfoHash::V1([b'a'; 20])];
+    assert_eq!((stats.complete, stats.downloaded, stats.incomplete), (3, 9, 1));
+    assert_eq!(response.min_request_interval, Some(900));
+    assert!(response.extra.is_empty());
+    assert_eq!(response.to_bytes(), &buf[..]);
+
+    assert_eq!(ScrapeResponse::from_bytes(b"d5:filesd3:abcdeee"),
+               Err(TrackerError::Invalid("files")));
+    let partial = ScrapeResponse::from_bytes(b"d5:filesd20:bbbbbbbbbbbbbbbbbbbbdeee").unwrap();
+    assert_eq!(partial.files[&InfoHash::V1([b'b'; 20])], ScrapeStats::default());
+}
+
+#[test]
+fn test_announce_request() {
+    let mut request = AnnounceRequest::new(InfoHash::V1([0xab; 20]),
+                                          PeerId(*b"-XX0001-abcdefghijkl"), 6881);
+    request.left = 100;
+    request.numwant = Some(50);
+    request.key = Some("k 1".to_string());
+    request.event = Some(Event::Started);
+
+    let query = request.to_query();
+    assert!(query.starts_with("info_hash=%AB%AB"));
+    assert!(query.ends_with("&port=6881&uploaded=0&downloaded=0&left=100&compact=1\
+                             &numwant=50&key=k%201&event=started"));
+    assert_eq!(AnnounceRequest::from_query(&query), Ok(request.clone()));
+    assert!(request.to_url("http://t.example/announce?passkey=x")
+        .starts_with("http://t.example/announce?passkey=x&info_hash="));
+
+    assert_eq!(AnnounceRequest::from_query("port=1"), Err(TrackerError::Missing("info_hash")));
+    let bad_port = query.replace("port=6881", "port=70000");
+    assert_eq!(AnnounceRequest::from_query(&bad_port), Err(TrackerError::Invalid("port")));
+
+    let v2 = AnnounceRequest::new(InfoHash::V2([0xcd; 32]), PeerId([b'p'; 20]), 6881);
+    let query = v2.to_query();
+    assert!(query.starts_with(&format!("info_hash={}&peer_id=", "%CD".repeat(20))));
+    assert_eq!(AnnounceRequest::from_query(&query).unwrap().info_hash,
+               InfoHash::V1([0xcd; 20]));
+}
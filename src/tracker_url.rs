@@ -0,0 +1,118 @@
+//! Validation and normalization of tracker URLs.
+
+use std::fmt;
+
+/// Schemes accepted for trackers: HTTP(S) (BEP 3), UDP (BEP 15) and
+/// WebSocket trackers used by WebTorrent.
+pub const ALLOWED_SCHEMES: [&str; 4] = ["http", "https", "udp", "wss"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UrlErrorKind {
+    Empty,
+    /// Whitespace inside the URL, usually two URLs run together.
+    Whitespace,
+    MissingScheme,
+    UnsupportedScheme,
+    MissingHost,
+    /// The port is not a number from 1 to 65535, or is missing from a
+    /// `udp` URL.
+    InvalidPort,
+}
+
+/// A tracker URL that failed validation.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct UrlError {
+    pub url: String,
+    pub kind: UrlErrorKind,
+}
+
+impl fmt::Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid tracker URL {:?}: {:?}", self.url, self.kind)
+    }
+}
+
+fn default_port(scheme: &str) -> Option<u16> {
+    match scheme {
+        "http" => Some(80),
+        "https" | "wss" => Some(443),
+        _ => None,
+    }
+}
+
+/// Checks a tracker URL and returns its normalized form: surrounding
+/// whitespace trimmed, scheme and host lowercased, and a default port
+/// dropped. The path and query are kept as they are.
+pub fn normalize_tracker_url(url: &str) -> Result<String, UrlErrorKind> {
+    let url = url.trim();
+    if url.is_empty() {
+        return Err(UrlErrorKind::Empty);
+    }
+    if url.chars().any(char::is_whitespace) {
+        return Err(UrlErrorKind::Whitespace);
+    }
+    let (scheme, rest) = match url.find("://") {
+        Some(index) => (url[..index].to_ascii_lowercase(), &url[index + 3..]),
+        None => return Err(UrlErrorKind::MissingScheme),
+    };
+    if !ALLOWED_SCHEMES.contains(&&scheme[..]) {
+        return Err(UrlErrorKind::UnsupportedScheme);
+    }
+
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (authority, tail) = rest.split_at(authority_end);
+    let (userinfo, host_port) = match authority.rfind('@') {
+        Some(index) => (&authority[..index + 1], &authority[index + 1..]),
+        None => ("", authority),
+    };
+    // A bracketed IPv6 literal may itself contain colons.
+    let port_start = match host_port.rfind(':') {
+        Some(index) if !host_port[index..].contains(']') => Some(index),
+        _ => None,
+    };
+    let (host, port) = match port_start {
+        Some(index) => (&host_port[..index], Some(&host_port[index + 1..])),
+        None => (host_port, None),
+    };
+    if host.is_empty() || host == "[]" {
+        return Err(UrlErrorKind::MissingHost);
+    }
+    let port = match port {
+        Some(port) => match port.parse::<u16>() {
+            Ok(0) | Err(_) => return Err(UrlErrorKind::InvalidPort),
+            Ok(port) => Some(port),
+        },
+        None if scheme == "udp" => return Err(UrlErrorKind::InvalidPort),
+        None => None,
+    };
+
+    let mut normalized = format!("{}://{}{}", scheme, userinfo, host.to_ascii_lowercase());
+    if let Some(port) = port.filter(|&port| Some(port) != default_port(&scheme)) {
+        normalized.push_str(&format!(":{}", port));
+    }
+    normalized.push_str(tail);
+    Ok(normalized)
+}
+
+/// Like `normalize_tracker_url`, but reporting the offending URL.
+pub fn check_tracker_url(url: &str) -> Result<String, UrlError> {
+    normalize_tracker_url(url).map_err(|kind| UrlError { url: url.to_string(), kind })
+}
+
+#[test]
+fn test_normalize_tracker_url() {
+    assert_eq!(normalize_tracker_url(" HTTP://Tracker.Example:80/announce?x=Y "),
+               Ok("http://tracker.example/announce?x=Y".to_string()));
+    assert_eq!(normalize_tracker_url("udp://[2001:DB8::1]:6969"),
+               Ok("udp://[2001:db8::1]:6969".to_string()));
+    assert_eq!(normalize_tracker_url("wss://t.example:8443/"),
+               Ok("wss://t.example:8443/".to_string()));
+
+    assert_eq!(normalize_tracker_url(""), Err(UrlErrorKind::Empty));
+    assert_eq!(normalize_tracker_url("http://a/x http://b/x"), Err(UrlErrorKind::Whitespace));
+    assert_eq!(normalize_tracker_url("tracker.example/announce"), Err(UrlErrorKind::MissingScheme));
+    assert_eq!(normalize_tracker_url("ftp://t.example/"), Err(UrlErrorKind::UnsupportedScheme));
+    assert_eq!(normalize_tracker_url("http:///announce"), Err(UrlErrorKind::MissingHost));
+    assert_eq!(normalize_tracker_url("udp://t.example/announce"), Err(UrlErrorKind::InvalidPort));
+    assert_eq!(normalize_tracker_url("http://t.example:99999/"), Err(UrlErrorKind::InvalidPort));
+}
@@ -0,0 +1,228 @@
+//! The `ut_metadata` extension (BEP 9): fetching the info dictionary from
+//! peers in 16 KiB pieces.
+
+use {Bencode, bencode, raw};
+use extension::{ExtensionError, ExtensionResult};
+#[cfg(any(feature = "sha1", feature = "sha256"))]
+use infohash::InfoHash;
+use torrent::Dict;
+
+/// Name of the extension in the BEP 10 handshake.
+pub const EXTENSION_NAME: &str = "ut_metadata";
+
+/// Size of every metadata piece but the last.
+pub const PIECE_LEN: usize = 16 * 1024;
+
+/// Largest `metadata_size` a `MetadataAssembler` accepts. Real info
+/// dictionaries are far smaller, and other clients use a similar cap.
+pub const MAX_METADATA_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Number of pieces metadata of `total_size` bytes is split into.
+pub fn piece_count(total_size: u64) -> usize {
+    total_size.div_ceil(PIECE_LEN as u64) as usize
+}
+
+/// Splits an encoded info dictionary into the pieces peers request.
+pub fn split_metadata(info: &[u8]) -> Vec<&[u8]> {
+    info.chunks(PIECE_LEN).collect()
+}
+
+fn expected_len(piece: u32, total_size: u64) -> Option<usize> {
+    let start = u64::from(piece) * PIECE_LEN as u64;
+    if start >= total_size {
+        return None;
+    }
+    Some(::std::cmp::min(total_size - start, PIECE_LEN as u64) as usize)
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MetadataMessage {
+    /// `msg_type` 0: asks for a piece.
+    Request { piece: u32 },
+    /// `msg_type` 1: a piece, whose bytes follow the bencoded header.
+    Data { piece: u32, total_size: u64, data: Vec<u8> },
+    /// `msg_type` 2: the peer will not send the piece.
+    Reject { piece: u32 },
+}
+
+impl MetadataMessage {
+    /// Decodes an extended message payload: the bencoded header and, for
+    /// data messages, the piece bytes after it. The length of a data piece
+    /// is checked against `total_size`.
+    pub fn from_bytes(buf: &[u8]) -> ExtensionResult<MetadataMessage> {
+        let (mut dict, end) = match raw::decode_value(buf, 0, 1)? {
+            (Bencode::Object(dict), end) => (dict, end),
+            _ => return Err(ExtensionError::Invalid("message")),
+        };
+        let trailing = &buf[end..];
+
+        let mut take_u64 = |key: &'static str| match dict.remove(key.as_bytes()) {
+            Some(value) => value.as_u64().ok_or(ExtensionError::Invalid(key)),
+            None => Err(ExtensionError::Invalid(key)),
+        };
+        let msg_type = take_u64("msg_type")?;
+        let piece = take_u64("piece")?;
+        if piece > u64::from(u32::MAX) {
+            return Err(ExtensionError::Invalid("piece"));
+        }
+        let piece = piece as u32;
+
+        let message = match msg_type {
+            0 => MetadataMessage::Request { piece },
+            1 => {
+                let total_size = take_u64("total_size")?;
+                if expected_len(piece, total_size) != Some(trailing.len()) {
+                    return Err(ExtensionError::Invalid("piece"));
+                }
+                return Ok(MetadataMessage::Data { piece, total_size, data: trailing.to_vec() });
+            },
+            2 => MetadataMessage::Reject { piece },
+            _ => return Err(ExtensionError::Invalid("msg_type")),
+        };
+        if !trailing.is_empty() {
+            return Err(ExtensionError::Invalid("message"));
+        }
+        Ok(message)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (msg_type, piece) = match *self {
+            MetadataMessage::Request { piece } => (0u64, piece),
+            MetadataMessage::Data { piece, .. } => (1, piece),
+            MetadataMessage::Reject { piece } => (2, piece),
+        };
+        let mut dict = Dict::new();
+        dict.insert(b"msg_type".to_vec(), Bencode::from(msg_type));
+        dict.insert(b"piece".to_vec(), Bencode::from(u64::from(piece)));
+        if let MetadataMessage::Data { total_size, .. } = *self {
+            dict.insert(b"total_size".to_vec(), Bencode::from(total_size));
+        }
+
+        let mut buf = Vec::new();
+        bencode(&Bencode::Object(dict), &mut buf).expect("writing to a Vec cannot fail");
+        if let MetadataMessage::Data { ref data, .. } = *self {
+            buf.extend_from_slice(data);
+        }
+        buf
+    }
+}
+
+/// Collects metadata pieces as they arrive.
+#[derive(Clone, Debug)]
+pub struct MetadataAssembler {
+    total_size: u64,
+    pieces: Vec<Option<Vec<u8>>>,
+}
+
+impl MetadataAssembler {
+    /// Starts collecting the `metadata_size` bytes a peer advertised.
+    /// Fails if that is over `MAX_METADATA_SIZE`.
+    pub fn new(total_size: u64) -> ExtensionResult<MetadataAssembler> {
+        if total_size > MAX_METADATA_SIZE {
+            return Err(ExtensionError::Invalid("metadata_size"));
+        }
+        Ok(MetadataAssembler { total_size, pieces: vec![None; piece_count(total_size)] })
+    }
+
+    /// Stores a received piece. Fails if the piece is out of range or has
+    /// the wrong length.
+    pub fn insert(&mut self, piece: u32, data: Vec<u8>) -> ExtensionResult<()> {
+        if expected_len(piece, self.total_size) != Some(data.len()) {
+            return Err(ExtensionError::Invalid("piece"));
+        }
+        self.pieces[piece as usize] = Some(data);
+        Ok(())
+    }
+
+    /// Indices of the pieces still to be requested.
+    pub fn missing(&self) -> Vec<u32> {
+        (0..self.pieces.len())
+            .filter(|&index| self.pieces[index].is_none())
+            .map(|index| index as u32)
+            .collect()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.pieces.iter().all(Option::is_some)
+    }
+
+    /// Joins the pieces, or returns `None` if some are still missing.
+    pub fn assemble(&self) -> Option<Vec<u8>> {
+        if !self.is_complete() {
+            return None;
+        }
+        let mut info = Vec::with_capacity(self.total_size as usize);
+        for piece in self.pieces.iter().flatten() {
+            info.extend_from_slice(piece);
+        }
+        Some(info)
+    }
+}
+
+/// Checks that assembled metadata hashes to `info_hash`. A hash whose
+/// algorithm is not enabled never matches.
+#[cfg(any(feature = "sha1", feature = "sha256"))]
+pub fn verify_metadata(info: &[u8], info_hash: &InfoHash) -> bool {
+    match *info_hash {
+        #[cfg(feature = "sha1")]
+        InfoHash::V1(ref hash) => ::sha1::Sha1::digest(info) == *hash,
+        #[cfg(not(feature = "sha1"))]
+        InfoHash::V1(_) => false,
+        #[cfg(feature = "sha256")]
+        InfoHash::V2(ref hash) => ::sha256::Sha256::digest(info) == *hash,
+        #[cfg(not(feature = "sha256"))]
+        InfoHash::V2(_) => false,
+    }
+}
+
+#[test]
+fn test_messages() {
+    let request = MetadataMessage::from_bytes(b"d8:msg_typei0e5:piecei0ee").unwrap();
+    assert_eq!(request, MetadataMessage::Request { piece: 0 });
+    assert_eq!(request.to_bytes(), b"d8:msg_typei0e5:piecei0ee".to_vec());
+
+    let data = b"d8:msg_typei1e5:piecei1e10:total_sizei16390ee123456";
+    let message = MetadataMessage::from_bytes(data).unwrap();
+    assert_eq!(message, MetadataMessage::Data { piece: 1, total_size: 16390, data: b"123456".to_vec() });
+    assert_eq!(message.to_bytes(), data.to_vec());
+
+    assert_eq!(MetadataMessage::from_bytes(b"d8:msg_typei1e5:piecei1e10:total_sizei16390ee12345"),
+               Err(ExtensionError::Invalid("piece")));
+    assert_eq!(MetadataMessage::from_bytes(b"d8:msg_typei2e5:piecei0eextra"),
+               Err(ExtensionError::Invalid("message")));
+    let deep = [&b"d1:x"[..], &vec![b'l'; 100_000]].concat();
+    assert_eq!(MetadataMessage::from_bytes(&deep),
+               Err(ExtensionError::Parse(::ParseError::TooDeep)));
+}
+
+#[test]
+fn test_assembler() {
+    let info: Vec<u8> = (0..40000u32).map(|x| x as u8).collect();
+    let pieces = split_metadata(&info);
+    assert_eq!(pieces.len(), piece_count(info.len() as u64));
+
+    let mut assembler = MetadataAssembler::new(info.len() as u64).unwrap();
+    assert_eq!(assembler.missing(), vec![0, 1, 2]);
+    assembler.insert(2, pieces[2].to_vec()).unwrap();
+    assert_eq!(assembler.insert(1, pieces[2].to_vec()), Err(ExtensionError::Invalid("piece")));
+    assert_eq!(assembler.insert(3, Vec::new()), Err(ExtensionError::Invalid("piece")));
+    assert_eq!(assembler.assemble(), None);
+    assembler.insert(0, pieces[0].to_vec()).unwrap();
+    assembler.insert(1, pieces[1].to_vec()).unwrap();
+    assert_eq!(assembler.assemble(), Some(info));
+
+    assert!(MetadataAssembler::new(MAX_METADATA_SIZE).is_ok());
+    for &size in [MAX_METADATA_SIZE + 1, u64::MAX].iter() {
+        assert_eq!(MetadataAssembler::new(size).err(),
+                   Some(ExtensionError::Invalid("metadata_size")));
+    }
+}
+
+#[cfg(feature = "sha1")]
+#[test]
+fn test_verify_metadata() {
+    let info = b"d4:name1:a12:piece lengthi16384e6:pieces0:e";
+    let hash = InfoHash::V1(::sha1::Sha1::digest(info));
+    assert!(verify_metadata(info, &hash));
+    assert!(!verify_metadata(b"de", &hash));
+}
@@ -0,0 +1,338 @@
+//! Linting of metainfo documents.
+//!
+//! `Metainfo::from_bencode` stops at the first problem it cannot work
+//! around; `validate_metainfo` instead walks the whole document and reports
+//! every finding, including ones the typed model tolerates.
+
+use Bencode;
+use torrent::{Dict, PIECE_HASH_LEN, PathErrorKind, check_component};
+
+/// Smallest piece length BEP 52 allows, and the smallest one clients
+/// expect in practice.
+pub const MIN_PIECE_LENGTH: u64 = 16 * 1024;
+/// Largest piece length accepted in strict mode.
+pub const MAX_PIECE_LENGTH: u64 = 64 * 1024 * 1024;
+
+/// How far piece length checks go beyond what the specifications require.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Strictness {
+    /// Only what the format requires: a positive length, and for v2 a
+    /// power of two of at least 16 KiB.
+    Lenient,
+    /// Also hold v1 torrents to the v2 rules and cap the length at
+    /// `MAX_PIECE_LENGTH`.
+    Strict,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PieceLengthError {
+    Zero,
+    NotPowerOfTwo,
+    TooSmall,
+    TooLarge,
+}
+
+/// Checks a piece length for a v1 (`v2 == false`) or v2/hybrid torrent.
+pub fn check_piece_length(piece_length: u64, v2: bool, strictness: Strictness)
+    -> Result<(), PieceLengthError> {
+
+    let strict = strictness == Strictness::Strict;
+    if piece_length == 0 {
+        return Err(PieceLengthError::Zero);
+    }
+    if (v2 || strict) && !piece_length.is_power_of_two() {
+        return Err(PieceLengthError::NotPowerOfTwo);
+    }
+    if (v2 || strict) && piece_length < MIN_PIECE_LENGTH {
+        return Err(PieceLengthError::TooSmall);
+    }
+    if strict && piece_length > MAX_PIECE_LENGTH {
+        return Err(PieceLengthError::TooLarge);
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ViolationKind {
+    /// A required key is absent.
+    Missing,
+    /// The value has the wrong bencode type.
+    WrongType,
+    /// A string that must be UTF-8 is not.
+    NotUtf8,
+    /// A length is negative or too large.
+    OutOfRange,
+    /// `pieces` is not a whole number of SHA-1 hashes.
+    PiecesLength,
+    PieceLength(PieceLengthError),
+    /// The number of piece hashes does not match the content length.
+    PieceCount,
+    /// `info` has both `length` and `files`.
+    LengthAndFiles,
+    /// `info` has neither `length` nor `files`.
+    NoLengthOrFiles,
+    /// A file path is an empty list.
+    EmptyPath,
+    /// A name or path component is unsafe.
+    Path(PathErrorKind),
+}
+
+/// One finding; `key` locates it, e.g. `info.files[3].path[0]`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Violation {
+    pub key: String,
+    pub kind: ViolationKind,
+}
+
+struct Validator {
+    strictness: Strictness,
+    violations: Vec<Violation>,
+}
+
+impl Validator {
+    fn report(&mut self, key: &str, kind: ViolationKind) {
+        self.violations.push(Violation { key: key.to_string(), kind });
+    }
+
+    fn string<'a>(&mut self, value: &'a Bencode, key: &str) -> Option<&'a str> {
+        match *value {
+            Bencode::Bytes(ref buf) => match ::std::str::from_utf8(buf) {
+                Ok(s) => Some(s),
+                Err(_) => {
+                    self.report(key, ViolationKind::NotUtf8);
+                    None
+                },
+            },
+            _ => {
+                self.report(key, ViolationKind::WrongType);
+                None
+            },
+        }
+    }
+
+    fn optional_string(&mut self, dict: &Dict, name: &str, prefix: &str) {
+        if let Some(value) = dict.get(name.as_bytes()) {
+            self.string(value, &format!("{}{}", prefix, name));
+        }
+    }
+
+    /// Checks for a non-negative integer.
+    fn length(&mut self, value: &Bencode, key: &str) -> Option<u64> {
+        match *value {
+            Bencode::Integer(_) => match value.as_u64() {
+                Some(length) => Some(length),
+                None => {
+                    self.report(key, ViolationKind::OutOfRange);
+                    None
+                },
+            },
+            _ => {
+                self.report(key, ViolationKind::WrongType);
+                None
+            },
+        }
+    }
+
+    fn component(&mut self, value: &Bencode, key: &str) {
+        if let Some(component) = self.string(value, key) {
+            if let Err(kind) = check_component(component) {
+                self.report(key, ViolationKind::Path(kind));
+            }
+        }
+    }
+
+    /// Checks a `files` entry and returns its length if valid.
+    fn file(&mut self, value: &Bencode, key: &str) -> Option<u64> {
+        let file = match *value {
+            Bencode::Object(ref file) => file,
+            _ => {
+                self.report(key, ViolationKind::WrongType);
+                return None;
+            },
+        };
+        let length = match file.get(&b"length"[..]) {
+            Some(length) => self.length(length, &format!("{}.length", key)),
+            None => {
+                self.report(&format!("{}.length", key), ViolationKind::Missing);
+                None
+            },
+        };
+        let path_key = format!("{}.path", key);
+        match file.get(&b"path"[..]) {
+            Some(Bencode::Array(components)) if components.is_empty() =>
+                self.report(&path_key, ViolationKind::EmptyPath),
+            Some(Bencode::Array(components)) => {
+                for (index, component) in components.iter().enumerate() {
+                    self.component(component, &format!("{}[{}]", path_key, index));
+                }
+            },
+            Some(_) => self.report(&path_key, ViolationKind::WrongType),
+            None => self.report(&path_key, ViolationKind::Missing),
+        }
+        self.optional_string(file, "attr", &format!("{}.", key));
+        length
+    }
+
+    fn info(&mut self, value: &Bencode) {
+        let info = match *value {
+            Bencode::Object(ref info) => info,
+            _ => return self.report("info", ViolationKind::WrongType),
+        };
+
+        match info.get(&b"name"[..]) {
+            Some(name) => self.component(name, "info.name"),
+            None => self.report("info.name", ViolationKind::Missing),
+        }
+        let is_v2 = info.get(&b"meta version"[..]).and_then(Bencode::as_u64) == Some(2)
+            && info.contains_key(&b"file tree"[..]);
+        let piece_length = match info.get(&b"piece length"[..]) {
+            Some(value) => match self.length(value, "info.piece length") {
+                Some(piece_length) => match check_piece_length(piece_length, is_v2, self.strictness) {
+                    Ok(()) => Some(piece_length),
+                    Err(err) => {
+                        self.report("info.piece length", ViolationKind::PieceLength(err));
+                        None
+                    },
+                },
+                None => None,
+            },
+            None => {
+                self.report("info.piece length", ViolationKind::Missing);
+                None
+            },
+        };
+
+        let mut piece_count = None;
+        match info.get(&b"pieces"[..]) {
+            Some(Bencode::Bytes(pieces)) if !pieces.len().is_multiple_of(PIECE_HASH_LEN) =>
+                self.report("info.pieces", ViolationKind::PiecesLength),
+            Some(Bencode::Bytes(pieces)) => piece_count = Some(pieces.len() / PIECE_HASH_LEN),
+            Some(_) => self.report("info.pieces", ViolationKind::WrongType),
+            None if !is_v2 && !info.contains_key(&b"root hash"[..]) =>
+                self.report("info.pieces", ViolationKind::Missing),
+            None => (),
+        }
+        match info.get(&b"root hash"[..]) {
+            Some(Bencode::Bytes(hash)) if hash.len() != PIECE_HASH_LEN =>
+                self.report("info.root hash", ViolationKind::OutOfRange),
+            Some(Bencode::Bytes(_)) | None => (),
+            Some(_) => self.report("info.root hash", ViolationKind::WrongType),
+        }
+
+        let length = info.get(&b"length"[..]);
+        let files = info.get(&b"files"[..]);
+        match (length, files) {
+            (Some(_), Some(_)) => self.report("info", ViolationKind::LengthAndFiles),
+            (None, None) if !is_v2 => self.report("info", ViolationKind::NoLengthOrFiles),
+            _ => (),
+        }
+        let mut total_length = None;
+        if let Some(length) = length {
+            total_length = self.length(length, "info.length");
+        }
+        match files {
+            Some(Bencode::Array(files)) => {
+                let mut total = Some(0u64);
+                for (index, file) in files.iter().enumerate() {
+                    let length = self.file(file, &format!("info.files[{}]", index));
+                    total = match (total, length) {
+                        (Some(total), Some(length)) => total.checked_add(length),
+                        _ => None,
+                    };
+                }
+                total_length = total;
+            },
+            Some(_) => self.report("info.files", ViolationKind::WrongType),
+            None => (),
+        }
+        self.optional_string(info, "attr", "info.");
+
+        if let (Some(count), Some(piece_length), Some(total)) =
+                (piece_count, piece_length, total_length) {
+            if count as u64 != total.div_ceil(piece_length) {
+                self.report("info.pieces", ViolationKind::PieceCount);
+            }
+        }
+    }
+}
+
+/// Checks `document` against the structure BEP 3 (and BEP 52 for v2
+/// torrents) requires and returns every violation found, in document
+/// order. Piece lengths are checked leniently.
+pub fn validate_metainfo(document: &Bencode) -> Vec<Violation> {
+    validate_metainfo_with(document, Strictness::Lenient)
+}
+
+/// `validate_metainfo` with a choice of piece length strictness.
+pub fn validate_metainfo_with(document: &Bencode, strictness: Strictness) -> Vec<Violation> {
+    let mut validator = Validator { strictness, violations: Vec::new() };
+    match *document {
+        Bencode::Object(ref dict) => {
+            validator.optional_string(dict, "announce", "");
+            validator.optional_string(dict, "comment", "");
+            validator.optional_string(dict, "created by", "");
+            if let Some(date) = dict.get(&b"creation date"[..]) {
+                if date.as_i64().is_none() {
+                    validator.report("creation date", ViolationKind::WrongType);
+                }
+            }
+            match dict.get(&b"info"[..]) {
+                Some(info) => validator.info(info),
+                None => validator.report("info", ViolationKind::Missing),
+            }
+        },
+        _ => validator.report("", ViolationKind::WrongType),
+    }
+    validator.violations
+}
+
+#[test]
+fn test_validate_metainfo() {
+    let decode = |buf: &[u8]| ::bdecode(&mut buf.iter().cloned().peekable()).unwrap();
+
+    let good = decode(b"d8:announce1:a4:infod5:filesld6:lengthi3e4:pathl1:aeee\
+        4:name4:root12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee");
+    assert_eq!(validate_metainfo(&good), vec![]);
+
+    let bad = decode(b"d8:announcei1e4:infod5:filesld4:pathl2:..eed6:lengthi1e4:pathleee\
+        6:lengthi1e4:name0:12:piece lengthi0e6:pieces3:abcee");
+    let violation = |key: &str, kind| Violation { key: key.to_string(), kind };
+    assert_eq!(validate_metainfo(&bad), vec![
+        violation("announce", ViolationKind::WrongType),
+        violation("info.name", ViolationKind::Path(PathErrorKind::Empty)),
+        violation("info.piece length", ViolationKind::PieceLength(PieceLengthError::Zero)),
+        violation("info.pieces", ViolationKind::PiecesLength),
+        violation("info", ViolationKind::LengthAndFiles),
+        violation("info.files[0].length", ViolationKind::Missing),
+        violation("info.files[0].path[0]", ViolationKind::Path(PathErrorKind::ParentDir)),
+        violation("info.files[1].path", ViolationKind::EmptyPath),
+    ]);
+
+    assert_eq!(validate_metainfo(&decode(b"le")), vec![violation("", ViolationKind::WrongType)]);
+
+    // BEP 30 merkle torrents carry `root hash` instead of `pieces`.
+    let merkle = decode(b"d4:infod6:lengthi3e4:name1:a12:piece lengthi16384e\
+        9:root hash20:aaaaaaaaaaaaaaaaaaaaee");
+    assert_eq!(validate_metainfo(&merkle), vec![]);
+    let merkle = decode(b"d4:infod6:lengthi3e4:name1:a12:piece lengthi16384e9:root hash1:aee");
+    assert_eq!(validate_metainfo(&merkle), vec![violation("info.root hash", ViolationKind::OutOfRange)]);
+}
+
+#[test]
+fn test_piece_length() {
+    use self::Strictness::*;
+
+    assert_eq!(check_piece_length(3, false, Lenient), Ok(()));
+    assert_eq!(check_piece_length(3, false, Strict), Err(PieceLengthError::NotPowerOfTwo));
+    assert_eq!(check_piece_length(8192, true, Lenient), Err(PieceLengthError::TooSmall));
+    assert_eq!(check_piece_length(1 << 30, true, Lenient), Ok(()));
+    assert_eq!(check_piece_length(1 << 30, true, Strict), Err(PieceLengthError::TooLarge));
+
+    let decode = |buf: &[u8]| ::bdecode(&mut buf.iter().cloned().peekable()).unwrap();
+    let document = decode(b"d4:infod6:lengthi40000e4:name1:a12:piece lengthi16384e\
+        6:pieces40:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaee");
+    assert_eq!(validate_metainfo_with(&document, Strict), vec![Violation {
+        key: "info.pieces".to_string(),
+        kind: ViolationKind::PieceCount,
+    }]);
+}
@@ -0,0 +1,206 @@
+//! Checking downloaded content against a torrent's v1 piece hashes.
+
+use std::cmp;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use sha1::Sha1;
+use torrent::{Info, PIECE_HASH_LEN};
+
+const READ_BUF_LEN: usize = 64 * 1024;
+
+struct PieceChecker<'a, F> {
+    info: &'a Info,
+    index: usize,
+    hasher: Sha1,
+    filled: u64,
+    damaged: bool,
+    results: Vec<bool>,
+    progress: F,
+}
+
+impl<'a, F> PieceChecker<'a, F>
+    where
+        F: FnMut(usize, bool) {
+
+    fn current_length(&self) -> Option<u64> {
+        self.info.piece_length_of(self.index)
+    }
+
+    /// Feeds `len` bytes of content into the current pieces. `None` stands
+    /// for bytes that could not be read, which damages every piece they
+    /// fall in.
+    fn advance(&mut self, data: Option<&[u8]>, mut len: u64) {
+        let mut offset = 0;
+        while len > 0 {
+            let piece_length = match self.current_length() {
+                Some(length) => length,
+                // More content than pieces; the validator reports this.
+                None => return,
+            };
+            let take = cmp::min(len, piece_length - self.filled);
+            match data {
+                Some(data) => self.hasher.update(&data[offset..offset + take as usize]),
+                None => self.damaged = true,
+            }
+            offset += take as usize;
+            len -= take;
+            self.filled += take;
+            if self.filled == piece_length {
+                self.finish_piece();
+            }
+        }
+    }
+
+    fn finish_piece(&mut self) {
+        let hasher = ::std::mem::take(&mut self.hasher);
+        let start = self.index * PIECE_HASH_LEN;
+        let pieces = self.info.pieces.as_ref().expect("checked by verify_pieces");
+        let expected = &pieces[start..start + PIECE_HASH_LEN];
+        let matched = !self.damaged && hasher.finish()[..] == *expected;
+
+        self.results.push(matched);
+        (self.progress)(self.index, matched);
+        self.index += 1;
+        self.filled = 0;
+        self.damaged = false;
+    }
+}
+
+fn feed_file<F>(checker: &mut PieceChecker<F>, path: &Path, length: u64) -> io::Result<()>
+    where
+        F: FnMut(usize, bool) {
+
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+            checker.advance(None, length);
+            return Ok(());
+        },
+        Err(err) => return Err(err),
+    };
+
+    let mut buf = vec![0u8; READ_BUF_LEN];
+    let mut remaining = length;
+    while remaining > 0 {
+        let want = cmp::min(remaining, buf.len() as u64) as usize;
+        let read = match file.read(&mut buf[..want]) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        checker.advance(Some(&buf[..read]), read as u64);
+        remaining -= read as u64;
+    }
+    // A short file damages whatever it should have covered.
+    checker.advance(None, remaining);
+    Ok(())
+}
+
+/// Hashes the content of `info` found under `root_dir` and reports, per
+/// piece, whether it matches. Pieces that span several files are assembled
+/// across file boundaries; missing or short files fail the pieces they
+/// cover, and BEP 47 padding files are taken to be zeros without touching
+/// the disk. `progress` is called with each piece index and result as
+/// soon as it is known.
+pub fn verify_pieces<P, F>(root_dir: P, info: &Info, progress: F) -> io::Result<Vec<bool>>
+    where
+        P: AsRef<Path>,
+        F: FnMut(usize, bool) {
+
+    if info.pieces().is_err() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  "missing or malformed v1 pieces"));
+    }
+
+    let mut checker = PieceChecker {
+        info,
+        index: 0,
+        hasher: Sha1::new(),
+        filled: 0,
+        damaged: false,
+        results: Vec::with_capacity(info.piece_count()),
+        progress,
+    };
+
+    let zeros = vec![0u8; READ_BUF_LEN];
+    for file in info.sanitized_files() {
+        if file.is_padding() {
+            let mut remaining = file.length;
+            while remaining > 0 {
+                let take = cmp::min(remaining, zeros.len() as u64);
+                checker.advance(Some(&zeros[..take as usize]), take);
+                remaining -= take;
+            }
+            continue;
+        }
+        feed_file(&mut checker, &root_dir.as_ref().join(&file.path), file.length)?;
+    }
+
+    // Pieces past the end of the content (or a trailing partial piece)
+    // cannot match.
+    if checker.filled > 0 {
+        checker.damaged = true;
+        checker.finish_piece();
+    }
+    while checker.results.len() < info.piece_count() {
+        checker.damaged = true;
+        checker.finish_piece();
+    }
+    Ok(checker.results)
+}
+
+#[test]
+fn test_verify_pieces() {
+    use std::fs;
+    use torrent::{FileEntry, Dict};
+
+    let root = ::std::env::temp_dir().join(format!("bencode-verify-{}", ::std::process::id()));
+    let content_dir = root.join("multi");
+    fs::create_dir_all(&content_dir).unwrap();
+    fs::write(content_dir.join("a"), vec![1u8; 10]).unwrap();
+    fs::write(content_dir.join("b"), vec![2u8; 9]).unwrap();
+
+    // Piece length 8 forces pieces to straddle the files and the padding.
+    let mut data = vec![1u8; 10];
+    data.extend(vec![0u8; 6]);
+    data.extend(vec![2u8; 9]);
+    let mut pieces = Vec::new();
+    for chunk in data.chunks(8) {
+        pieces.extend_from_slice(&Sha1::digest(chunk));
+    }
+    let entry = |name: &str, length, attr: Option<&str>| FileEntry {
+        length,
+        path: vec![name.to_string()],
+        attr: attr.map(|s| s.to_string()),
+        extra: Dict::new(),
+    };
+    let info = Info {
+        name: "multi".to_string(),
+        piece_length: 8,
+        pieces: Some(pieces),
+        meta_version: None,
+        file_tree: None,
+        length: None,
+        files: Some(vec![entry("a", 10, None), entry("pad", 6, Some("p")), entry("b", 9, None)]),
+        attr: None,
+        extra: Dict::new(),
+    };
+
+    let mut seen = Vec::new();
+    let results = verify_pieces(&root, &info, |index, ok| seen.push((index, ok))).unwrap();
+    assert_eq!(results, vec![true, true, true, true]);
+    assert_eq!(seen.len(), 4);
+
+    fs::write(content_dir.join("b"), vec![2u8; 5]).unwrap();
+    let results = verify_pieces(&root, &info, |_, _| ()).unwrap();
+    assert_eq!(results, vec![true, true, false, false]);
+
+    fs::remove_file(content_dir.join("a")).unwrap();
+    let results = verify_pieces(&root, &info, |_, _| ()).unwrap();
+    assert_eq!(results, vec![false, false, false, false]);
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,180 @@
+//! Scrubbing secrets from memory, enabled by the `zeroize` feature.
+//!
+//! Announce URLs of private trackers carry passkeys, and tracker requests
+//! and responses carry keys and tracker ids. `Zeroize` overwrites such
+//! data with zeros through volatile writes, which the optimizer may not
+//! remove, and `Zeroizing` does so when it is dropped. Buffers are cleared
+//! over their whole capacity, so bytes left behind by earlier truncation
+//! go too; copies made before, such as by reallocation, are out of reach.
+
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::sync::atomic::{self, Ordering};
+
+use Bencode;
+use torrent::{AnnounceList, Dict, Metainfo};
+use tracker::{AnnounceRequest, AnnounceResponse};
+
+pub trait Zeroize {
+    /// Overwrites the value's contents with zeros and leaves it empty.
+    fn zeroize(&mut self);
+}
+
+/// Writes `len` zero bytes at `ptr` in a way the compiler keeps.
+unsafe fn volatile_zero(ptr: *mut u8, len: usize) {
+    for offset in 0..len {
+        ptr::write_volatile(ptr.add(offset), 0);
+    }
+    atomic::compiler_fence(Ordering::SeqCst);
+}
+
+impl Zeroize for u8 {
+    fn zeroize(&mut self) {
+        // SAFETY: `self` is a valid, exclusive `u8`.
+        unsafe { volatile_zero(self, 1) }
+    }
+}
+
+impl<T: Zeroize> Zeroize for Vec<T> {
+    fn zeroize(&mut self) {
+        for item in self.iter_mut() {
+            item.zeroize();
+        }
+        self.clear();
+        let len = self.capacity() * mem::size_of::<T>();
+        // SAFETY: the allocation is `len` bytes long and, with the vector
+        // empty, holds no values that zeros could invalidate.
+        unsafe { volatile_zero(self.as_mut_ptr() as *mut u8, len) }
+    }
+}
+
+impl Zeroize for String {
+    fn zeroize(&mut self) {
+        // SAFETY: the string is empty, so valid UTF-8, once this returns.
+        unsafe { self.as_mut_vec().zeroize() }
+    }
+}
+
+impl<T: Zeroize> Zeroize for Option<T> {
+    fn zeroize(&mut self) {
+        if let Some(ref mut value) = *self {
+            value.zeroize();
+        }
+        *self = None;
+    }
+}
+
+impl Zeroize for Dict {
+    fn zeroize(&mut self) {
+        // Keys cannot be borrowed mutably in place, so the map is taken
+        // apart and each entry scrubbed on its way out.
+        for (mut key, mut value) in mem::take(self) {
+            key.zeroize();
+            value.zeroize();
+        }
+    }
+}
+
+impl Zeroize for Bencode {
+    fn zeroize(&mut self) {
+        match *self {
+            Bencode::Integer(ref mut buf) | Bencode::Bytes(ref mut buf) => buf.zeroize(),
+            Bencode::Array(ref mut items) => items.zeroize(),
+            Bencode::Object(ref mut dict) => dict.zeroize(),
+        }
+    }
+}
+
+impl Zeroize for AnnounceList {
+    fn zeroize(&mut self) {
+        self.tiers.zeroize();
+    }
+}
+
+/// Scrubs everything but `info`, which is public wherever the torrent is.
+impl Zeroize for Metainfo {
+    fn zeroize(&mut self) {
+        self.announce.zeroize();
+        self.announce_list.zeroize();
+        self.url_list.zeroize();
+        self.httpseeds.zeroize();
+        for node in self.nodes.iter_mut() {
+            node.0.zeroize();
+        }
+        self.nodes.clear();
+        self.comment.zeroize();
+        self.created_by.zeroize();
+        self.extra.zeroize();
+    }
+}
+
+impl Zeroize for AnnounceRequest {
+    fn zeroize(&mut self) {
+        self.key.zeroize();
+        self.tracker_id.zeroize();
+    }
+}
+
+impl Zeroize for AnnounceResponse {
+    fn zeroize(&mut self) {
+        self.tracker_id.zeroize();
+        self.warning_message.zeroize();
+    }
+}
+
+/// Holds a value and zeroizes it when dropped.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Zeroizing<T: Zeroize>(T);
+
+impl<T: Zeroize> Zeroizing<T> {
+    pub fn new(value: T) -> Zeroizing<T> {
+        Zeroizing(value)
+    }
+}
+
+impl<T: Zeroize> Deref for Zeroizing<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize> DerefMut for Zeroizing<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Zeroize> Drop for Zeroizing<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+#[test]
+fn test_zeroize() {
+    let mut value = ::bdecode_slice(b"d8:announce20:http://t/abcdef/anno4:listl3:keyi7eee").unwrap();
+    let secret = match value {
+        Bencode::Object(ref mut dict) => dict.get_mut(&b"announce"[..]).unwrap(),
+        _ => unreachable!(),
+    };
+    let mut buf = match mem::replace(secret, Bencode::Integer(Vec::new())) {
+        Bencode::Bytes(buf) => buf,
+        _ => unreachable!(),
+    };
+    buf.truncate(4);
+    let (ptr, capacity) = (buf.as_ptr(), buf.capacity());
+    buf.zeroize();
+    assert!(buf.is_empty());
+    // SAFETY: the allocation is still owned by `buf`.
+    assert!(unsafe { std::slice::from_raw_parts(ptr, capacity) }.iter().all(|&b| b == 0));
+
+    value.zeroize();
+    assert_eq!(value, Bencode::Object(Dict::new()));
+
+    let mut passkey = Zeroizing::new(String::from("passkey"));
+    passkey.push('!');
+    assert_eq!(&passkey[..], "passkey!");
+}